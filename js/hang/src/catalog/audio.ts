@@ -30,6 +30,9 @@ export const AudioSchema = z.object({
 
 	// The configuration of the audio track
 	config: AudioConfigSchema,
+
+	// A track listing the timestamp and group of each keyframe, used to seek.
+	index: z.optional(TrackSchema),
 });
 
 export type Audio = z.infer<typeof AudioSchema>;
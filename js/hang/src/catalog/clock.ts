@@ -0,0 +1,17 @@
+import { z } from "zod/v4-mini";
+
+// Maps media timestamps to wall clock time, used to measure the end-to-end latency.
+export const ClockSchema = z.object({
+	// A media timestamp in microseconds.
+	timestamp: z.number(),
+
+	// When the media at this timestamp was captured, in microseconds since the UNIX epoch (UTC).
+	utc: z.number(),
+});
+
+export type Clock = z.infer<typeof ClockSchema>;
+
+// Return when the media at the given timestamp (in microseconds) was captured, in milliseconds since the UNIX epoch.
+export function captureTime(clock: Clock, timestamp: number): number {
+	return (clock.utc + timestamp - clock.timestamp) / 1000;
+}
@@ -1,6 +1,9 @@
 export * from "./audio";
+export * from "./clock";
 export * from "./location";
+export * from "./metadata";
 export * from "./root";
+export * from "./text";
 export * from "./track";
 export * from "./video";
 export * from "./capabilities";
@@ -0,0 +1,25 @@
+import { z } from "zod/v4-mini";
+
+import { TrackSchema } from "./track";
+
+export const MetadataConfigSchema = z.object({
+	// Identifies the format of the events, ex. "https://aomedia.org/emsg/ID3" or "urn:scte:scte35:2013:bin".
+	scheme: z.string(),
+
+	// An optional sub-scheme, with a meaning defined by the scheme.
+	value: z.optional(z.string()),
+
+	// The MIME type of each event payload, ex. "application/json".
+	mimeType: z.optional(z.string()),
+});
+
+export const MetadataSchema = z.object({
+	// The MoQ track information.
+	track: TrackSchema,
+
+	// The configuration of the metadata track
+	config: MetadataConfigSchema,
+});
+
+export type Metadata = z.infer<typeof MetadataSchema>;
+export type MetadataConfig = z.infer<typeof MetadataConfigSchema>;
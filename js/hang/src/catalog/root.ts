@@ -4,17 +4,29 @@ import { z } from "zod/v4-mini";
 import { AudioSchema } from "./audio";
 import { CapabilitiesSchema } from "./capabilities";
 import { ChatSchema } from "./chat";
+import { ClockSchema } from "./clock";
 import { LocationSchema } from "./location";
+import { MetadataSchema } from "./metadata";
+import { TextSchema } from "./text";
 import { UserSchema } from "./user";
 import { VideoSchema } from "./video";
 
+// The layout of the header prefixed to each frame, missing for older publishers.
+export const ContainerSchema = z.enum(["legacy", "native"]);
+
+export type Container = z.infer<typeof ContainerSchema>;
+
 export const RootSchema = z.object({
+	container: z.optional(ContainerSchema),
 	video: z.optional(z.array(VideoSchema)),
 	audio: z.optional(z.array(AudioSchema)),
+	text: z.optional(z.array(TextSchema)),
+	metadata: z.optional(z.array(MetadataSchema)),
 	location: z.optional(LocationSchema),
 	user: z.optional(UserSchema),
 	chat: z.optional(ChatSchema),
 	capabilities: z.optional(CapabilitiesSchema),
+	clock: z.optional(ClockSchema),
 });
 
 export type Root = z.infer<typeof RootSchema>;
@@ -24,11 +36,12 @@ export function encode(root: Root): Uint8Array {
 	return encoder.encode(JSON.stringify(root));
 }
 
-export function decode(raw: Uint8Array): Root {
+export function decode(raw: Uint8Array, previous?: Root): Root {
 	const decoder = new TextDecoder();
 	const str = decoder.decode(raw);
 	try {
-		const json = JSON.parse(str);
+		let json = JSON.parse(str);
+		if (previous) json = mergePatch(previous, json);
 		return RootSchema.parse(json);
 	} catch (error) {
 		console.warn("invalid catalog", str);
@@ -36,8 +49,37 @@ export function decode(raw: Uint8Array): Root {
 	}
 }
 
-export async function fetch(track: Moq.TrackConsumer): Promise<Root | undefined> {
+// Apply a JSON merge patch (RFC 7386), returning a new value.
+export function mergePatch(target: unknown, patch: unknown): unknown {
+	if (typeof patch !== "object" || patch === null || Array.isArray(patch)) {
+		return patch;
+	}
+
+	const result: Record<string, unknown> =
+		typeof target === "object" && target !== null && !Array.isArray(target)
+			? { ...(target as Record<string, unknown>) }
+			: {};
+
+	for (const [key, value] of Object.entries(patch)) {
+		if (value === null) {
+			delete result[key];
+		} else {
+			result[key] = mergePatch(result[key], value);
+		}
+	}
+
+	return result;
+}
+
+// The first frame of each group is the full catalog, and any subsequent frames are merge patches.
+export async function fetch(track: Moq.TrackConsumer, previous?: Root): Promise<Root | undefined> {
 	const frame = await track.nextFrame();
 	if (!frame) return undefined;
+
+	if (frame.frame > 0) {
+		if (!previous) throw new Error("received catalog patch without a catalog");
+		return decode(frame.data, previous);
+	}
+
 	return decode(frame.data);
 }
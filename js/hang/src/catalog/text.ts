@@ -0,0 +1,31 @@
+import { z } from "zod/v4-mini";
+
+import { TrackSchema } from "./track";
+
+export const TextConfigSchema = z.object({
+	// The format of each cue payload: "wvtt" (ISO/IEC 14496-30) or "tx3g" (3GPP timed text).
+	codec: z.string(),
+
+	// The language of the text as a BCP 47 tag, ex. "en-US".
+	language: z.optional(z.string()),
+
+	// How the text is meant to be used, matching the HTML track kinds.
+	kind: z.optional(z.enum(["subtitles", "captions", "descriptions", "chapters"])),
+
+	// A human readable label, ex. "English (SDH)".
+	label: z.optional(z.string()),
+
+	// Any codec specific configuration, ex. the WebVTT file header.
+	description: z.optional(z.string()), // hex encoded
+});
+
+export const TextSchema = z.object({
+	// The MoQ track information.
+	track: TrackSchema,
+
+	// The configuration of the text track
+	config: TextConfigSchema,
+});
+
+export type Text = z.infer<typeof TextSchema>;
+export type TextConfig = z.infer<typeof TextConfigSchema>;
@@ -2,6 +2,22 @@ import { z } from "zod/v4-mini";
 
 import { TrackSchema } from "./track";
 
+// Mirrors VideoColorSpaceInit
+// https://w3c.github.io/webcodecs/#video-color-space-interface
+export const VideoColorSpaceSchema = z.object({
+	// ex. "bt709" or "bt2020"
+	primaries: z.optional(z.string()),
+
+	// ex. "bt709", "pq" or "hlg"
+	transfer: z.optional(z.string()),
+
+	// ex. "bt709" or "bt2020-ncl"
+	matrix: z.optional(z.string()),
+
+	// If true, the full range of values is used instead of the limited (video) range.
+	fullRange: z.optional(z.boolean()),
+});
+
 export const VideoConfigSchema = z.object({
 	// See: https://w3c.github.io/webcodecs/codec_registry.html
 	codec: z.string(),
@@ -21,8 +37,8 @@ export const VideoConfigSchema = z.object({
 	displayRatioWidth: z.optional(z.uint32()),
 	displayRatioHeight: z.optional(z.uint32()),
 
-	// The frame rate of the video in frames per second
-	framerate: z.optional(z.uint32()),
+	// The frame rate of the video in frames per second, which may be fractional (ex. 29.97)
+	framerate: z.optional(z.number()),
 
 	// The bitrate of the video in bits per second
 	// TODO: Support up to Number.MAX_SAFE_INTEGER
@@ -39,6 +55,10 @@ export const VideoConfigSchema = z.object({
 	// If true, the decoder will flip the video horizontally
 	// Default: false
 	flip: z.optional(z.boolean()),
+
+	// The color space of the video, used for HDR.
+	// If not provided, the decoder will use the values in the bitstream.
+	colorSpace: z.optional(VideoColorSpaceSchema),
 });
 
 // Mirrors VideoDecoderConfig
@@ -49,7 +69,14 @@ export const VideoSchema = z.object({
 
 	// The configuration of the video track
 	config: VideoConfigSchema,
+
+	// Tracks with the same rendition group are alternate encodings of the same source, ex. an ABR ladder.
+	renditionGroup: z.optional(z.string()),
+
+	// A track listing the timestamp and group of each keyframe, used to seek.
+	index: z.optional(TrackSchema),
 });
 
 export type Video = z.infer<typeof VideoSchema>;
 export type VideoConfig = z.infer<typeof VideoConfigSchema>;
+export type VideoColorSpace = z.infer<typeof VideoColorSpaceSchema>;
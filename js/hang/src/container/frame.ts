@@ -1,4 +1,5 @@
 import * as Moq from "@kixelated/moq";
+import type * as Catalog from "../catalog";
 import { getVint53, setVint53 } from "./vint";
 
 export interface FrameSource {
@@ -6,21 +7,56 @@ export interface FrameSource {
 	copyTo(buffer: Uint8Array): void;
 }
 
-export function encodeFrame(source: Uint8Array | FrameSource, timestamp: number): Uint8Array {
-	const data = new Uint8Array(8 + (source instanceof Uint8Array ? source.byteLength : source.byteLength));
-	const size = setVint53(data, timestamp).byteLength;
+// The header contains three varints, all in microseconds:
+// - The decode timestamp.
+// - The composition offset (presentation - decode), zigzag encoded as it may be negative.
+// - The duration plus one, or zero if unknown.
+// WebCodecs doesn't produce B-frames, so the encoder always writes a zero offset.
+export function encodeFrame(source: Uint8Array | FrameSource, timestamp: number, duration?: number): Uint8Array {
+	const data = new Uint8Array(17 + source.byteLength);
+
+	let size = setVint53(data, timestamp).byteLength;
+	size += setVint53(data.subarray(size), 0).byteLength;
+	size += setVint53(data.subarray(size), duration !== undefined ? duration + 1 : 0).byteLength;
+
 	if (source instanceof Uint8Array) {
 		data.set(source, size);
 	} else {
 		source.copyTo(data.subarray(size));
 	}
-	return data.subarray(0, (source instanceof Uint8Array ? source.byteLength : source.byteLength) + size);
+	return data.subarray(0, source.byteLength + size);
 }
 
 // NOTE: A keyframe is always the first frame in a group, so it's not encoded on the wire.
-export function decodeFrame(buffer: Uint8Array): { data: Uint8Array; timestamp: number } {
-	const [timestamp, data] = getVint53(buffer);
-	return { timestamp, data };
+// The returned timestamp is the presentation timestamp.
+// Catalogs without a container use the legacy header, which is only the presentation timestamp.
+export function decodeFrame(
+	buffer: Uint8Array,
+	container: Catalog.Container = "legacy",
+): {
+	data: Uint8Array;
+	timestamp: number;
+	decodeTimestamp: number;
+	duration?: number;
+} {
+	if (container === "legacy") {
+		const [timestamp, data] = getVint53(buffer);
+		return { data, timestamp, decodeTimestamp: timestamp };
+	}
+
+	const [decodeTimestamp, rest] = getVint53(buffer);
+	const [zigzag, rest2] = getVint53(rest);
+	const [duration, data] = getVint53(rest2);
+
+	// Arithmetic instead of bitwise operators, which are limited to 32-bits.
+	const offset = zigzag % 2 === 0 ? zigzag / 2 : -(zigzag + 1) / 2;
+
+	return {
+		data,
+		timestamp: decodeTimestamp + offset,
+		decodeTimestamp,
+		duration: duration > 0 ? duration - 1 : undefined,
+	};
 }
 
 export class FrameProducer {
@@ -43,7 +79,7 @@ export class FrameProducer {
 	}
 
 	consume(): FrameConsumer {
-		return new FrameConsumer(this.#track.consume());
+		return new FrameConsumer(this.#track.consume(), "native");
 	}
 
 	close() {
@@ -54,16 +90,18 @@ export class FrameProducer {
 
 export class FrameConsumer {
 	#track: Moq.TrackConsumer;
+	#container?: Catalog.Container;
 
-	constructor(track: Moq.TrackConsumer) {
+	constructor(track: Moq.TrackConsumer, container?: Catalog.Container) {
 		this.#track = track;
+		this.#container = container;
 	}
 
 	async decode(): Promise<{ data: Uint8Array; timestamp: number; keyframe: boolean } | undefined> {
 		const next = await this.#track.nextFrame();
 		if (!next) return undefined;
 
-		const { timestamp, data } = decodeFrame(next.data);
+		const { timestamp, data } = decodeFrame(next.data, this.#container);
 		return { data, timestamp, keyframe: next.frame === 0 };
 	}
 }
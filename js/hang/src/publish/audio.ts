@@ -160,7 +160,7 @@ export class Audio {
 					this.#groupTimestamp = frame.timestamp;
 				}
 
-				const buffer = Container.encodeFrame(frame, frame.timestamp);
+				const buffer = Container.encodeFrame(frame, frame.timestamp, frame.duration ?? undefined);
 				this.#group.writeFrame(buffer);
 			},
 			error: (err) => {
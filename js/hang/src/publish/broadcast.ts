@@ -198,6 +198,7 @@ export class Broadcast {
 		const video = this.video.catalog.get();
 
 		const catalog: Catalog.Root = {
+			container: "native",
 			video: video ? [video] : [],
 			audio: audio ? [audio] : [],
 			location: this.location.catalog.get(),
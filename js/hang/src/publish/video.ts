@@ -122,7 +122,7 @@ export class Video {
 					throw new Error("no keyframe");
 				}
 
-				const buffer = Container.encodeFrame(frame, frame.timestamp);
+				const buffer = Container.encodeFrame(frame, frame.timestamp, frame.duration ?? undefined);
 				this.#group.writeFrame(buffer);
 			},
 			error: (err: Error) => {
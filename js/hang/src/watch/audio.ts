@@ -180,6 +180,8 @@ export class Audio {
 		const broadcast = this.broadcast.get();
 		if (!broadcast) return;
 
+		// Older catalogs don't specify a container, so the frames use the legacy header.
+		const container = this.catalog.peek()?.container;
 		const sub = broadcast.subscribe(selected.track.name, selected.track.priority);
 		cleanup(() => sub.close());
 
@@ -201,7 +203,7 @@ export class Audio {
 				const frame = await sub.nextFrame();
 				if (!frame) break;
 
-				const decoded = Container.decodeFrame(frame.data);
+				const decoded = Container.decodeFrame(frame.data, container);
 
 				const chunk = new EncodedAudioChunk({
 					type: "key",
@@ -132,12 +132,15 @@ export class Broadcast {
 
 		(async () => {
 			try {
+				let current: Catalog.Root | undefined;
+
 				for (;;) {
-					const update = await Catalog.fetch(catalog);
+					const update = await Catalog.fetch(catalog, current);
 					if (!update) break;
 
 					console.debug("received catalog", this.path.peek(), update);
 
+					current = update;
 					this.#catalog.set(update);
 					this.status.set("live");
 				}
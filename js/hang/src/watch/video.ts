@@ -185,6 +185,9 @@ export class Video {
 		const broadcast = this.broadcast.get();
 		if (!broadcast) return;
 
+		// Older catalogs don't specify a container, so the frames use the legacy header.
+		const container = this.catalog.peek()?.container;
+
 		// We don't clear previous frames so we can seamlessly switch tracks.
 		const sub = broadcast.subscribe(selected.track.name, selected.track.priority);
 
@@ -217,6 +220,8 @@ export class Video {
 			...config,
 			description: config.description ? Buffer.from(config.description, "hex") : undefined,
 			optimizeForLatency: config.optimizeForLatency ?? true,
+			// The catalog uses strings, since the DOM types don't include every HDR value yet.
+			colorSpace: config.colorSpace as VideoColorSpaceInit | undefined,
 		});
 
 		(async () => {
@@ -225,7 +230,7 @@ export class Video {
 					const next = await sub.nextFrame();
 					if (!next) break;
 
-					const decoded = Container.decodeFrame(next.data);
+					const decoded = Container.decodeFrame(next.data, container);
 
 					const chunk = new EncodedVideoChunk({
 						type: next.frame === 0 ? "key" : "delta",
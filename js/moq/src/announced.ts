@@ -8,8 +8,25 @@ import { WatchConsumer, WatchProducer } from "./util/watch";
 export interface Announce {
 	path: string;
 	active: boolean;
+
+	// An optional blob provided by the publisher, such as a title.
+	metadata?: Uint8Array;
+}
+
+/**
+ * Optional settings used when publishing a broadcast.
+ *
+ * @public
+ */
+export interface PublishOptions {
+	// A small blob sent with the announcement, such as a title or participant name.
+	// It must not exceed MAX_METADATA bytes.
+	metadata?: Uint8Array;
 }
 
+// The largest metadata the remote will decode, matching the default string limit.
+export const MAX_METADATA = 4096;
+
 /**
  * Handles writing announcements to the announcement queue.
  *
@@ -103,6 +120,7 @@ export class AnnouncedConsumer {
 				return {
 					path: announce.path.slice(this.prefix.length),
 					active: announce.active,
+					metadata: announce.metadata,
 				};
 			}
 		}
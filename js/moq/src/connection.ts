@@ -1,5 +1,5 @@
 import { Buffer } from "buffer";
-import { AnnouncedConsumer } from "./announced";
+import { AnnouncedConsumer, type PublishOptions } from "./announced";
 import { BroadcastConsumer } from "./broadcast";
 import { Publisher } from "./publisher";
 import { Subscriber } from "./subscriber";
@@ -20,27 +20,41 @@ export class Connection {
 	// Use to receive/send session messages.
 	#session: Wire.Stream;
 
+	// The negotiated version, used to encode and decode messages.
+	#version: number;
+
 	// Module for contributing tracks.
 	#publisher: Publisher;
 
 	// Module for distributing tracks.
 	#subscriber: Subscriber;
 
+	// Serializes writes to the session stream.
+	#sessionWrite: Promise<void> = Promise.resolve();
+
+	// The smoothed PING/PONG round-trip time in milliseconds.
+	#rtt?: number;
+
+	// How often to send a PING, in milliseconds.
+	static readonly PING_INTERVAL = 1000;
+
 	/**
 	 * Creates a new Connection instance.
 	 * @param url - The URL of the connection
 	 * @param quic - The WebTransport session
 	 * @param session - The session stream
+	 * @param version - The version negotiated during the setup
 	 *
 	 * @internal
 	 */
-	private constructor(url: URL, quic: WebTransport, session: Wire.Stream) {
+	private constructor(url: URL, quic: WebTransport, session: Wire.Stream, version: number) {
 		this.url = url;
 		this.#quic = quic;
 		this.#session = session;
+		this.#version = version;
 
-		this.#publisher = new Publisher(this.#quic);
-		this.#subscriber = new Subscriber(this.#quic);
+		this.#publisher = new Publisher(this.#quic, version);
+		this.#subscriber = new Subscriber(this.#quic, version);
 
 		this.#run();
 	}
@@ -86,15 +100,15 @@ export class Connection {
 		const quic = new WebTransport(adjustedUrl, options);
 		await quic.ready;
 
-		const client = new Wire.SessionClient([Wire.CURRENT_VERSION]);
-		const stream = await Wire.Stream.open(quic, client);
+		const client = new Wire.SessionClient(Wire.SUPPORTED_VERSIONS);
+		const stream = await Wire.Stream.open(quic, client, Wire.CURRENT_VERSION);
 
 		const server = await Wire.SessionServer.decode(stream.reader);
-		if (server.version !== Wire.CURRENT_VERSION) {
+		if (!Wire.SUPPORTED_VERSIONS.includes(server.version)) {
 			throw new Error(`unsupported server version: ${server.version.toString()}`);
 		}
 
-		const conn = new Connection(adjustedUrl, quic, stream);
+		const conn = new Connection(adjustedUrl, quic, stream, server.version);
 
 		const cleanup = () => {
 			conn.close();
@@ -131,9 +145,11 @@ export class Connection {
 	/**
 	 * Publishes a broadcast to the connection.
 	 * @param broadcast - The broadcast to publish
+	 * @param options - Optional metadata sent with the announcement
+	 * @throws If the metadata is larger than MAX_METADATA
 	 */
-	publish(path: string, broadcast: BroadcastConsumer) {
-		this.#publisher.publish(path, broadcast);
+	publish(path: string, broadcast: BroadcastConsumer, options?: PublishOptions) {
+		this.#publisher.publish(path, broadcast, options);
 	}
 
 	/**
@@ -165,18 +181,74 @@ export class Connection {
 		return this.#subscriber.consume(broadcast);
 	}
 
+	/**
+	 * The smoothed round-trip time of PING/PONG messages on the session stream, in milliseconds.
+	 *
+	 * @remarks
+	 * Unlike the QUIC RTT, this includes any processing delay in the remote's MoQ implementation.
+	 * It only measures this hop: a relay answers pings itself, so the RTT to the origin through a relay chain is not included.
+	 * Returns undefined until the first PONG is received.
+	 */
+	get rtt(): number | undefined {
+		return this.#rtt;
+	}
+
+	#sessionEncode(msg: Wire.SessionMessage) {
+		this.#sessionWrite = this.#sessionWrite.then(() => msg.encode(this.#session.writer));
+		return this.#sessionWrite;
+	}
+
 	async #runSession() {
-		// Receive messages until the connection is closed.
-		for (;;) {
-			const msg = await Wire.SessionInfo.decode_maybe(this.#session.reader);
-			if (!msg) break;
-			// TODO use the session info
+		// Older versions don't support session messages, so we can't ping.
+		if (this.#version === Wire.Version.LITE_00) {
+			await this.#session.reader.closed();
+			return;
+		}
+
+		// Only one ping is outstanding at a time, so nothing accumulates if the remote never responds.
+		let pending: [number, number] | undefined;
+		let sequence = 0;
+
+		const ping = () => {
+			if (pending) return;
+			pending = [sequence, performance.now()];
+			this.#sessionEncode(new Wire.SessionPing(sequence++)).catch(() => clearInterval(interval));
+		};
+
+		ping();
+		const interval = setInterval(ping, Connection.PING_INTERVAL);
+
+		try {
+			// Receive messages until the connection is closed.
+			for (;;) {
+				const msg = await Wire.decodeSessionMessage(this.#session.reader);
+				if (!msg) break;
+
+				if (msg instanceof Wire.SessionPing) {
+					await this.#sessionEncode(new Wire.SessionPong(msg.sequence));
+				} else if (msg instanceof Wire.SessionPong) {
+					if (pending?.[0] !== msg.sequence) {
+						console.debug(`ignoring unexpected pong: sequence=${msg.sequence}`);
+						continue;
+					}
+
+					const sent = pending[1];
+					pending = undefined;
+
+					// Smooth the RTT like TCP (RFC 6298) so a single slow response doesn't cause a spike.
+					const sample = performance.now() - sent;
+					this.#rtt = this.#rtt === undefined ? sample : (this.#rtt * 7 + sample) / 8;
+				}
+				// TODO use the session info
+			}
+		} finally {
+			clearInterval(interval);
 		}
 	}
 
 	async #runBidis() {
 		for (;;) {
-			const next = await Wire.Stream.accept(this.#quic);
+			const next = await Wire.Stream.accept(this.#quic, this.#version);
 			if (!next) {
 				break;
 			}
@@ -212,7 +284,7 @@ export class Connection {
 
 	async #runUnis() {
 		for (;;) {
-			const next = await Wire.Reader.accept(this.#quic);
+			const next = await Wire.Reader.accept(this.#quic, this.#version);
 			if (!next) {
 				break;
 			}
@@ -1,4 +1,4 @@
-import { AnnouncedProducer } from "./announced";
+import { AnnouncedProducer, MAX_METADATA, type PublishOptions } from "./announced";
 import { BroadcastConsumer } from "./broadcast";
 import type { GroupConsumer } from "./group";
 import type { TrackConsumer } from "./track";
@@ -13,6 +13,9 @@ import * as Wire from "./wire";
 export class Publisher {
 	#quic: WebTransport;
 
+	// The negotiated version, used to encode and decode messages.
+	#version: number;
+
 	// TODO this will store every announce/unannounce message, which will grow unbounded.
 	// We should remove any cached announcements on unannounce, etc.
 	#announced = new AnnouncedProducer();
@@ -23,11 +26,13 @@ export class Publisher {
 	/**
 	 * Creates a new Publisher instance.
 	 * @param quic - The WebTransport session to use
+	 * @param version - The negotiated version, used to encode and decode messages
 	 *
 	 * @internal
 	 */
-	constructor(quic: WebTransport) {
+	constructor(quic: WebTransport, version: number) {
 		this.#quic = quic;
+		this.#version = version;
 	}
 
 	/**
@@ -42,17 +47,25 @@ export class Publisher {
 	/**
 	 * Publishes a broadcast with any associated tracks.
 	 * @param broadcast - The broadcast to publish
+	 * @param options - Optional metadata sent with the announcement
+	 * @throws If the metadata is larger than MAX_METADATA
 	 */
-	publish(path: string, broadcast: BroadcastConsumer) {
+	publish(path: string, broadcast: BroadcastConsumer, options?: PublishOptions) {
+		const size = options?.metadata?.byteLength ?? 0;
+		if (size > MAX_METADATA) {
+			throw new Error(`metadata too large: ${size} > ${MAX_METADATA}`);
+		}
+
 		this.#broadcasts.set(path, broadcast);
-		void this.#runPublish(path, broadcast);
+		void this.#runPublish(path, broadcast, options);
 	}
 
-	async #runPublish(path: string, broadcast: BroadcastConsumer) {
+	async #runPublish(path: string, broadcast: BroadcastConsumer, options?: PublishOptions) {
 		try {
 			this.#announced.write({
 				path,
 				active: true,
+				metadata: options?.metadata,
 			});
 
 			console.debug(`announce: broadcast=${path} active=true`);
@@ -89,8 +102,8 @@ export class Publisher {
 			const announcement = await consumer.next();
 			if (!announcement) break;
 
-			const wire = new Wire.Announce(announcement.path, announcement.active);
-			await wire.encode(stream.writer);
+			const wire = new Wire.Announce(announcement.path, announcement.active, announcement.metadata);
+			await wire.encode(stream.writer, this.#version);
 		}
 	}
 
@@ -173,7 +186,7 @@ export class Publisher {
 	async #runGroup(sub: bigint, group: GroupConsumer) {
 		const msg = new Wire.Group(sub, group.id);
 		try {
-			const stream = await Wire.Writer.open(this.#quic, msg);
+			const stream = await Wire.Writer.open(this.#quic, msg, this.#version);
 			try {
 				for (;;) {
 					const frame = await Promise.race([group.nextFrame(), stream.closed()]);
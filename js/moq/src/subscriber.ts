@@ -13,6 +13,9 @@ import * as Wire from "./wire";
 export class Subscriber {
 	#quic: WebTransport;
 
+	// The negotiated version, used to encode and decode messages.
+	#version: number;
+
 	// Our subscribed tracks.
 	#subscribes = new Map<bigint, TrackProducer>();
 	#subscribeNext = 0n;
@@ -23,11 +26,13 @@ export class Subscriber {
 	/**
 	 * Creates a new Subscriber instance.
 	 * @param quic - The WebTransport session to use
+	 * @param version - The negotiated version, used to encode and decode messages
 	 *
 	 * @internal
 	 */
-	constructor(quic: WebTransport) {
+	constructor(quic: WebTransport, version: number) {
 		this.#quic = quic;
+		this.#version = version;
 	}
 
 	/**
@@ -45,10 +50,10 @@ export class Subscriber {
 			const active = new Set<string>();
 
 			try {
-				const stream = await Wire.Stream.open(this.#quic, msg);
+				const stream = await Wire.Stream.open(this.#quic, msg, this.#version);
 
 				for (;;) {
-					const announce = await Wire.Announce.decode_maybe(stream.reader);
+					const announce = await Wire.Announce.decode_maybe(stream.reader, this.#version);
 					if (!announce) {
 						break;
 					}
@@ -56,7 +61,7 @@ export class Subscriber {
 					const full = prefix.concat(announce.suffix);
 
 					console.debug(`announced: broadcast=${full} active=${announce.active}`);
-					producer.write({ path: full, active: announce.active });
+					producer.write({ path: full, active: announce.active, metadata: announce.metadata });
 
 					// Just for logging
 					if (announce.active) {
@@ -129,7 +134,7 @@ export class Subscriber {
 
 		const msg = new Wire.Subscribe(id, path, track.name, track.priority);
 
-		const stream = await Wire.Stream.open(this.#quic, msg);
+		const stream = await Wire.Stream.open(this.#quic, msg, this.#version);
 		try {
 			await Wire.SubscribeOk.decode(stream.reader);
 			console.debug(`subscribe ok: id=${id} broadcast=${path} track=${track.name}`);
@@ -1,28 +1,46 @@
+import { Version } from "./session";
 import type { Reader, Writer } from "./stream";
 
 export class Announce {
 	suffix: string;
 	active: boolean;
 
-	constructor(suffix: string, active: boolean) {
+	// An optional blob provided by the publisher, only sent when active.
+	metadata?: Uint8Array;
+
+	constructor(suffix: string, active: boolean, metadata?: Uint8Array) {
 		this.suffix = suffix;
 		this.active = active;
+		this.metadata = metadata;
 	}
 
-	async encode(w: Writer) {
+	async encode(w: Writer, version: number) {
 		await w.u53(this.active ? 1 : 0);
 		await w.string(this.suffix);
+
+		if (this.active && version !== Version.LITE_00) {
+			const metadata = this.metadata ?? new Uint8Array();
+			await w.u53(metadata.length);
+			await w.write(metadata);
+		}
 	}
 
-	static async decode(r: Reader): Promise<Announce> {
+	static async decode(r: Reader, version: number): Promise<Announce> {
 		const active = (await r.u53()) === 1;
 		const suffix = await r.string();
-		return new Announce(suffix, active);
+
+		if (!active || version === Version.LITE_00) {
+			return new Announce(suffix, active);
+		}
+
+		const size = await r.u53();
+		const metadata = size > 0 ? await r.read(size) : undefined;
+		return new Announce(suffix, active, metadata);
 	}
 
-	static async decode_maybe(r: Reader): Promise<Announce | undefined> {
+	static async decode_maybe(r: Reader, version: number): Promise<Announce | undefined> {
 		if (await r.done()) return;
-		return await Announce.decode(r);
+		return await Announce.decode(r, version);
 	}
 }
 
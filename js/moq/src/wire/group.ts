@@ -1,23 +1,44 @@
+import { Version } from "./session";
 import type { Reader, Writer } from "./stream";
 
 export class Group {
 	subscribe: bigint;
 	sequence: number;
 
+	// The publisher's priority boost for this group.
+	priority: number;
+
+	// The index of the first frame, non-zero when resuming a group.
+	frameOffset: number;
+
 	static StreamID = 0x0;
 
-	constructor(subscribe: bigint, sequence: number) {
+	constructor(subscribe: bigint, sequence: number, priority = 0, frameOffset = 0) {
 		this.subscribe = subscribe;
 		this.sequence = sequence;
+		this.priority = priority;
+		this.frameOffset = frameOffset;
 	}
 
-	async encode(w: Writer) {
+	async encode(w: Writer, version: number) {
 		await w.u62(this.subscribe);
 		await w.u53(this.sequence);
+
+		if (version !== Version.LITE_00) {
+			await w.u8(this.priority);
+			await w.u53(this.frameOffset);
+		}
 	}
 
-	static async decode(r: Reader): Promise<Group> {
-		return new Group(await r.u62(), await r.u53());
+	static async decode(r: Reader, version: number): Promise<Group> {
+		const subscribe = await r.u62();
+		const sequence = await r.u53();
+
+		if (version === Version.LITE_00) {
+			return new Group(subscribe, sequence);
+		}
+
+		return new Group(subscribe, sequence, await r.u8(), await r.u53());
 	}
 }
 
@@ -11,9 +11,14 @@ export const Version = {
 	FORK_03: 0xff0bad03,
 	FORK_04: 0xff0bad04,
 	LITE_00: 0xff0dad00,
+	// Adds subscription filters, resumption, group priorities, session messages and announce metadata.
+	LITE_01: 0xff0dad01,
 } as const;
 
-export const CURRENT_VERSION = Version.LITE_00;
+export const CURRENT_VERSION = Version.LITE_01;
+
+// Every supported version, in preferred order.
+export const SUPPORTED_VERSIONS: number[] = [Version.LITE_01, Version.LITE_00];
 
 export class Extensions {
 	entries: Map<bigint, Uint8Array>;
@@ -119,6 +124,8 @@ export class SessionServer {
 }
 
 export class SessionInfo {
+	static Type = 0;
+
 	bitrate: number;
 
 	constructor(bitrate: number) {
@@ -126,6 +133,7 @@ export class SessionInfo {
 	}
 
 	async encode(w: Writer) {
+		await w.u53(SessionInfo.Type);
 		await w.u53(this.bitrate);
 	}
 
@@ -133,9 +141,63 @@ export class SessionInfo {
 		const bitrate = await r.u53();
 		return new SessionInfo(bitrate);
 	}
+}
+
+// Request a SessionPong with the same sequence number, used to measure the RTT.
+export class SessionPing {
+	static Type = 1;
+
+	sequence: number;
+
+	constructor(sequence: number) {
+		this.sequence = sequence;
+	}
+
+	async encode(w: Writer) {
+		await w.u53(SessionPing.Type);
+		await w.u53(this.sequence);
+	}
+
+	static async decode(r: Reader): Promise<SessionPing> {
+		return new SessionPing(await r.u53());
+	}
+}
+
+// Sent in response to a SessionPing as soon as possible.
+export class SessionPong {
+	static Type = 2;
+
+	sequence: number;
+
+	constructor(sequence: number) {
+		this.sequence = sequence;
+	}
+
+	async encode(w: Writer) {
+		await w.u53(SessionPong.Type);
+		await w.u53(this.sequence);
+	}
+
+	static async decode(r: Reader): Promise<SessionPong> {
+		return new SessionPong(await r.u53());
+	}
+}
 
-	static async decode_maybe(r: Reader): Promise<SessionInfo | undefined> {
-		if (await r.done()) return;
-		return await SessionInfo.decode(r);
+// Sent by either side on the session stream after the setup.
+export type SessionMessage = SessionInfo | SessionPing | SessionPong;
+
+export async function decodeSessionMessage(r: Reader): Promise<SessionMessage | undefined> {
+	if (await r.done()) return;
+
+	const typ = await r.u53();
+	switch (typ) {
+		case SessionInfo.Type:
+			return await SessionInfo.decode(r);
+		case SessionPing.Type:
+			return await SessionPing.decode(r);
+		case SessionPong.Type:
+			return await SessionPong.decode(r);
+		default:
+			throw new Error(`unknown session message: ${typ.toString()}`);
 	}
 }
@@ -24,7 +24,7 @@ export class Stream {
 		this.reader = new Reader(props.readable);
 	}
 
-	static async accept(quic: WebTransport): Promise<[StreamBi, Stream] | undefined> {
+	static async accept(quic: WebTransport, version: number): Promise<[StreamBi, Stream] | undefined> {
 		const reader =
 			quic.incomingBidirectionalStreams.getReader() as ReadableStreamDefaultReader<WebTransportBidirectionalStream>;
 		const next = await reader.read();
@@ -40,7 +40,7 @@ export class Stream {
 		} else if (typ === Wire.AnnounceInterest.StreamID) {
 			msg = await Wire.AnnounceInterest.decode(stream.reader);
 		} else if (typ === Wire.Subscribe.StreamID) {
-			msg = await Wire.Subscribe.decode(stream.reader);
+			msg = await Wire.Subscribe.decode(stream.reader, version);
 		} else {
 			throw new Error(`unknown stream type: ${typ.toString()}`);
 		}
@@ -48,21 +48,22 @@ export class Stream {
 		return [msg, stream];
 	}
 
-	static async open(quic: WebTransport, msg: StreamBi, priority?: number): Promise<Stream> {
+	static async open(quic: WebTransport, msg: StreamBi, version: number, priority?: number): Promise<Stream> {
 		const stream = new Stream(await quic.createBidirectionalStream({ sendOrder: priority }));
 
 		if (msg instanceof Wire.SessionClient) {
 			await stream.writer.u8(Wire.SessionClient.StreamID);
+			await msg.encode(stream.writer);
 		} else if (msg instanceof Wire.AnnounceInterest) {
 			await stream.writer.u8(Wire.AnnounceInterest.StreamID);
+			await msg.encode(stream.writer);
 		} else if (msg instanceof Wire.Subscribe) {
 			await stream.writer.u8(Wire.Subscribe.StreamID);
+			await msg.encode(stream.writer, version);
 		} else {
 			throw new Error("invalid message type");
 		}
 
-		await msg.encode(stream.writer);
-
 		return stream;
 	}
 
@@ -211,7 +212,7 @@ export class Reader {
 		return this.#reader.closed;
 	}
 
-	static async accept(quic: WebTransport): Promise<[StreamUni, Reader] | undefined> {
+	static async accept(quic: WebTransport, version: number): Promise<[StreamUni, Reader] | undefined> {
 		const reader = quic.incomingUnidirectionalStreams.getReader() as ReadableStreamDefaultReader<
 			ReadableStream<Uint8Array>
 		>;
@@ -224,7 +225,7 @@ export class Reader {
 
 		const typ = await stream.u8();
 		if (typ === Wire.Group.StreamID) {
-			msg = await Wire.Group.decode(stream);
+			msg = await Wire.Group.decode(stream, version);
 		} else {
 			throw new Error(`unknown stream type: ${typ.toString()}`);
 		}
@@ -305,7 +306,7 @@ export class Writer {
 		this.#writer.abort(reason).catch(() => void 0);
 	}
 
-	static async open(quic: WebTransport, msg: StreamUni): Promise<Writer> {
+	static async open(quic: WebTransport, msg: StreamUni, version: number): Promise<Writer> {
 		const writable = (await quic.createUnidirectionalStream()) as WritableStream<Uint8Array>;
 		const stream = new Writer(writable);
 
@@ -315,7 +316,7 @@ export class Writer {
 			throw new Error("invalid message type");
 		}
 
-		await msg.encode(stream);
+		await msg.encode(stream, version);
 
 		return stream;
 	}
@@ -1,3 +1,4 @@
+import { Version } from "./session";
 import type { Reader, Writer } from "./stream";
 
 export class SubscribeUpdate {
@@ -22,33 +23,97 @@ export class SubscribeUpdate {
 	}
 }
 
-export class Subscribe extends SubscribeUpdate {
+// Optionally skip groups or frames, applied by the publisher.
+export interface SubscribeFilter {
+	// Only deliver the first frame of each group.
+	firstFrame: boolean;
+
+	// Only deliver groups with a sequence number divisible by this value, or 0 for all groups.
+	nthGroup: bigint;
+
+	// Skip groups within this many microseconds of the previously delivered group, or 0 for all groups.
+	minInterval: bigint;
+}
+
+// Resume a partially received group after reconnecting.
+export interface SubscribeResume {
+	// The sequence number of the group.
+	group: bigint;
+
+	// The index of the next frame within the group.
+	frame: bigint;
+}
+
+export class Subscribe {
 	id: bigint;
 	broadcast: string;
 	track: string;
+	priority: number;
+	filter: SubscribeFilter;
+
+	// Abort groups older than this many microseconds, or 0 for no limit.
+	maxAge: bigint;
+
+	// Skip groups before this sequence number and frames before the index within it.
+	resume?: SubscribeResume;
 
 	static StreamID = 0x2;
 
-	constructor(id: bigint, broadcast: string, track: string, priority: number) {
-		super(priority);
+	constructor(
+		id: bigint,
+		broadcast: string,
+		track: string,
+		priority: number,
+		filter?: SubscribeFilter,
+		maxAge?: bigint,
+		resume?: SubscribeResume,
+	) {
 		this.id = id;
 		this.broadcast = broadcast;
 		this.track = track;
+		this.priority = priority;
+		this.filter = filter ?? { firstFrame: false, nthGroup: 0n, minInterval: 0n };
+		this.maxAge = maxAge ?? 0n;
+		this.resume = resume;
 	}
 
-	override async encode(w: Writer) {
+	async encode(w: Writer, version: number) {
 		await w.u62(this.id);
 		await w.string(this.broadcast);
 		await w.string(this.track);
-		await super.encode(w);
+		await w.u53(this.priority);
+
+		// Older versions don't support filters, max age, or resumption.
+		if (version === Version.LITE_00) return;
+
+		await w.u8(this.filter.firstFrame ? 1 : 0);
+		await w.u62(this.filter.nthGroup);
+		await w.u62(this.filter.minInterval);
+		await w.u62(this.maxAge);
+		await w.u62(this.resume ? this.resume.group + 1n : 0n);
+		await w.u62(this.resume?.frame ?? 0n);
 	}
 
-	static override async decode(r: Reader): Promise<Subscribe> {
+	static async decode(r: Reader, version: number): Promise<Subscribe> {
 		const id = await r.u62();
 		const broadcast = await r.string();
 		const track = await r.string();
-		const update = await SubscribeUpdate.decode(r);
-		return new Subscribe(id, broadcast, track, update.priority);
+		const priority = await r.u53();
+
+		if (version === Version.LITE_00) {
+			return new Subscribe(id, broadcast, track, priority);
+		}
+
+		const filter = {
+			firstFrame: (await r.u8()) !== 0,
+			nthGroup: await r.u62(),
+			minInterval: await r.u62(),
+		};
+		const maxAge = await r.u62();
+		const resumeGroup = await r.u62();
+		const resumeFrame = await r.u62();
+		const resume = resumeGroup > 0n ? { group: resumeGroup - 1n, frame: resumeFrame } : undefined;
+		return new Subscribe(id, broadcast, track, priority, filter, maxAge, resume);
 	}
 }
 
@@ -0,0 +1,90 @@
+use std::path::PathBuf;
+
+use hang::moq_lite;
+use hang::{catalog::Catalog, BroadcastConsumer};
+use moq_lite::archive::{Pacing, Recorder};
+use moq_lite::{BroadcastProducer, Session, Track};
+use url::Url;
+
+/// Record a remote broadcast to an archive, discovering tracks via the catalog.
+pub async fn record(config: moq_native::ClientConfig, url: Url, output: Option<PathBuf>) -> anyhow::Result<()> {
+	let client = config.init()?;
+
+	tracing::info!(%url, "connecting");
+
+	let session = client.connect(url).await?;
+	let session = Session::connect(session).await?;
+
+	// The path is relative to the URL, so it's empty because we only consume one broadcast.
+	let mut broadcast = BroadcastConsumer::new(session.consume(""));
+
+	let mut recorder = match output {
+		Some(path) => Recorder::new(broadcast.inner.clone(), tokio::fs::File::create(path).await?),
+		None => Recorder::new(broadcast.inner.clone(), tokio::io::stdout()),
+	};
+
+	recorder.track(&Track {
+		name: Catalog::DEFAULT_NAME.to_string(),
+		priority: 100,
+		..Default::default()
+	});
+
+	loop {
+		tokio::select! {
+			catalog = broadcast.catalog.next() => {
+				let Some(catalog) = catalog? else { break };
+
+				for video in &catalog.video {
+					recorder.track(&video.track);
+				}
+
+				for audio in &catalog.audio {
+					recorder.track(&audio.track);
+				}
+			}
+			// On ctrl-c, stop recording; closing the session ends each track so the archive can be finished.
+			_ = tokio::signal::ctrl_c() => break,
+		}
+	}
+
+	session.close(moq_lite::Error::Cancel);
+	recorder.finish().await?;
+
+	Ok(())
+}
+
+/// Replay an archive and publish it to the remote.
+pub async fn replay(
+	config: moq_native::ClientConfig,
+	url: Url,
+	input: Option<PathBuf>,
+	pacing: Pacing,
+) -> anyhow::Result<()> {
+	let client = config.init()?;
+
+	tracing::info!(%url, "connecting");
+
+	let session = client.connect(url).await?;
+	let mut session = Session::connect(session).await?;
+
+	let mut broadcast = BroadcastProducer::new();
+	session.publish("", broadcast.consume());
+
+	tokio::select! {
+		res = async {
+			match input {
+				Some(path) => moq_lite::archive::replay(tokio::fs::File::open(path).await?, &mut broadcast, pacing).await,
+				None => moq_lite::archive::replay(tokio::io::stdin(), &mut broadcast, pacing).await,
+			}
+		} => res?,
+		_ = tokio::signal::ctrl_c() => {},
+		_ = session.closed() => return Err(session.closed().await.into()),
+	}
+
+	session.close(moq_lite::Error::Cancel);
+
+	// Give it a chance to close.
+	tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+	Ok(())
+}
@@ -1,14 +1,17 @@
 use anyhow::Context;
-use hang::cmaf::Import;
+use hang::cmaf::Export;
 use hang::moq_lite;
 use hang::{BroadcastConsumer, BroadcastProducer};
 use moq_lite::Session;
-use tokio::io::AsyncRead;
+use tokio::io::{AsyncRead, AsyncWrite};
 use url::Url;
 
+use crate::{import, ImportConfig};
+
 pub async fn client<T: AsyncRead + Unpin>(
 	config: moq_native::ClientConfig,
 	url: Url,
+	format: ImportConfig,
 	input: &mut T,
 ) -> anyhow::Result<()> {
 	let producer = BroadcastProducer::new();
@@ -19,7 +22,7 @@ pub async fn client<T: AsyncRead + Unpin>(
 	// Connect to the remote and start parsing stdin in parallel.
 	tokio::select! {
 		res = connect(client, url, consumer) => res,
-		res = publish(producer, input) => res,
+		res = import(producer, format, input) => res,
 	}
 }
 
@@ -47,17 +50,34 @@ async fn connect(client: moq_native::Client, url: Url, consumer: BroadcastConsum
 	}
 }
 
-async fn publish<T: AsyncRead + Unpin>(producer: BroadcastProducer, input: &mut T) -> anyhow::Result<()> {
-	let mut import = Import::new(producer);
+/// Subscribe to a remote broadcast and write it as fMP4 to the output.
+pub async fn subscribe<T: AsyncWrite + Unpin>(
+	config: moq_native::ClientConfig,
+	url: Url,
+	output: T,
+) -> anyhow::Result<()> {
+	let client = config.init()?;
 
-	import
-		.init_from(input)
-		.await
-		.context("failed to initialize cmaf from input")?;
+	tracing::info!(%url, "connecting");
+
+	let session = client.connect(url).await?;
+	let session = Session::connect(session).await?;
 
-	tracing::info!("initialized");
+	// The path is relative to the URL, so it's empty because we only consume one broadcast.
+	let broadcast = BroadcastConsumer::new(session.consume(""));
 
-	import.read_from(input).await?;
+	let export = Export::init(broadcast, output)
+		.await
+		.context("failed to initialize cmaf from catalog")?;
+
+	tracing::info!(catalog = ?export.catalog(), "initialized");
 
-	Ok(())
+	tokio::select! {
+		res = export.run() => Ok(res?),
+		// On ctrl-c, close the session and exit.
+		_ = tokio::signal::ctrl_c() => {
+			session.close(moq_lite::Error::Cancel);
+			Ok(())
+		}
+	}
 }
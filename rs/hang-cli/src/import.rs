@@ -0,0 +1,138 @@
+use anyhow::Context;
+use clap::{Args, ValueEnum};
+use hang::{annexb, cmaf, mp4, mpegts, ogg, webm, BroadcastProducer};
+use std::{path::PathBuf, time::Duration};
+use tokio::io::AsyncRead;
+
+/// The format of the media read from stdin.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Format {
+	/// Fragmented MP4 (CMAF).
+	Fmp4,
+	/// MPEG-TS containing H.264/H.265 and AAC.
+	Ts,
+	/// A raw H.264 (Annex-B) byte stream.
+	H264,
+	/// A raw H.265 (Annex-B) byte stream.
+	H265,
+	/// Ogg containing Opus.
+	Ogg,
+	/// WebM (Matroska) containing VP8/VP9/AV1/H.264 and Opus/AAC.
+	Webm,
+	/// A progressive (non-fragmented) MP4 file, read from `--input` since it must be seekable.
+	Mp4,
+}
+
+#[derive(Args, Clone)]
+pub struct ImportConfig {
+	/// The format of the media read from stdin.
+	#[arg(long, value_enum, default_value = "fmp4")]
+	pub format: Format,
+
+	/// The framerate of a raw H.264/H.265 stream, overriding any timing info in the stream.
+	#[arg(long)]
+	pub framerate: Option<f64>,
+
+	/// Read the media from the given file instead of stdin, required for mp4.
+	#[arg(long)]
+	pub input: Option<PathBuf>,
+
+	/// Start an mp4 file at the given position, in seconds.
+	#[arg(long)]
+	pub start: Option<f64>,
+
+	/// Publish an mp4 file as fast as possible instead of in real-time.
+	#[arg(long)]
+	pub fast: bool,
+
+	/// Anchor the media timestamps to the wall clock in the catalog, so viewers can measure the latency.
+	///
+	/// This assumes the input is live, so it shouldn't be combined with --fast.
+	#[arg(long)]
+	pub clock: bool,
+}
+
+/// Import media from the input into the broadcast until EOF.
+pub async fn import<T: AsyncRead + Unpin>(
+	producer: BroadcastProducer,
+	config: ImportConfig,
+	input: &mut T,
+) -> anyhow::Result<()> {
+	let producer = match config.clock {
+		true => producer.with_clock(),
+		false => producer,
+	};
+
+	if let Format::Mp4 = config.format {
+		return import_mp4(producer, config).await;
+	}
+
+	match &config.input {
+		Some(path) => {
+			let mut file = tokio::fs::File::open(path).await.context("failed to open input")?;
+			import_stream(producer, config, &mut file).await
+		}
+		None => import_stream(producer, config, input).await,
+	}
+}
+
+async fn import_stream<T: AsyncRead + Unpin>(
+	producer: BroadcastProducer,
+	config: ImportConfig,
+	input: &mut T,
+) -> anyhow::Result<()> {
+	match config.format {
+		Format::Fmp4 => {
+			let mut import = cmaf::Import::new(producer);
+
+			import
+				.init_from(input)
+				.await
+				.context("failed to initialize cmaf from input")?;
+
+			tracing::info!("initialized");
+
+			import.read_from(input).await?;
+		}
+		Format::Ts => mpegts::Import::new(producer).read_from(input).await?,
+		Format::Ogg => ogg::Import::new(producer).read_from(input).await?,
+		Format::Webm => webm::Import::new(producer).read_from(input).await?,
+		Format::H264 | Format::H265 => {
+			let codec = match config.format {
+				Format::H264 => annexb::Codec::H264,
+				_ => annexb::Codec::H265,
+			};
+
+			let mut import = annexb::Import::new(producer, codec);
+			if let Some(framerate) = config.framerate {
+				import = import.with_framerate(framerate);
+			}
+
+			import.read_from(input).await?;
+		}
+		Format::Mp4 => unreachable!("mp4 requires a seekable input"),
+	}
+
+	Ok(())
+}
+
+// A progressive MP4 is read from a file because the moov may be at the end.
+async fn import_mp4(producer: BroadcastProducer, config: ImportConfig) -> anyhow::Result<()> {
+	let path = config.input.context("mp4 must be read from a file with --input")?;
+	let file = tokio::fs::File::open(path).await.context("failed to open input")?;
+
+	let mut import = mp4::Import::open(producer, file)
+		.await
+		.context("failed to initialize mp4 from input")?
+		.with_realtime(!config.fast);
+
+	tracing::info!(duration = ?import.duration(), "initialized");
+
+	if let Some(start) = config.start {
+		import.seek(Duration::from_secs_f64(start));
+	}
+
+	import.run().await?;
+
+	Ok(())
+}
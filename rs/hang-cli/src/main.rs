@@ -1,9 +1,13 @@
+mod archive;
 mod client;
+mod import;
 mod server;
 
 use std::path::PathBuf;
 
+use archive::*;
 use client::*;
+use import::*;
 use server::*;
 
 use clap::{Parser, Subcommand};
@@ -27,6 +31,10 @@ pub enum Command {
 		/// Optionally serve static files from the given directory.
 		#[arg(long)]
 		dir: Option<PathBuf>,
+
+		/// The format of the media read from stdin.
+		#[command(flatten)]
+		import: ImportConfig,
 	},
 	Publish {
 		/// The MoQ client configuration.
@@ -42,6 +50,49 @@ pub enum Command {
 		/// - If `https` is used, then A WebTransport connection is made via QUIC to the provided host/port.
 		///   The path is used to identify the broadcast, with the rest of the URL (ex. query/fragment) currently ignored.
 		url: Url,
+
+		/// The format of the media read from stdin.
+		#[command(flatten)]
+		import: ImportConfig,
+	},
+	/// Subscribe to a broadcast and write it to stdout as fragmented MP4, ex. to pipe into ffmpeg.
+	Subscribe {
+		/// The MoQ client configuration.
+		#[command(flatten)]
+		config: moq_native::ClientConfig,
+
+		/// The URL of the broadcast to subscribe to.
+		url: Url,
+	},
+	/// Record a broadcast to an archive that can be replayed later.
+	Record {
+		/// The MoQ client configuration.
+		#[command(flatten)]
+		config: moq_native::ClientConfig,
+
+		/// The URL of the broadcast to record.
+		url: Url,
+
+		/// Write the archive to the given file instead of stdout.
+		#[arg(long)]
+		output: Option<PathBuf>,
+	},
+	/// Publish a broadcast from an archive created by `record`.
+	Replay {
+		/// The MoQ client configuration.
+		#[command(flatten)]
+		config: moq_native::ClientConfig,
+
+		/// The URL to publish the broadcast.
+		url: Url,
+
+		/// Read the archive from the given file instead of stdin.
+		#[arg(long)]
+		input: Option<PathBuf>,
+
+		/// Replay as fast as possible instead of with the original timing.
+		#[arg(long)]
+		fast: bool,
 	},
 }
 
@@ -51,7 +102,21 @@ async fn main() -> anyhow::Result<()> {
 	cli.log.init();
 
 	match cli.command {
-		Command::Serve { config, dir } => server(config, dir, &mut tokio::io::stdin()).await,
-		Command::Publish { config, url } => client(config, url, &mut tokio::io::stdin()).await,
+		Command::Serve { config, dir, import } => server(config, dir, import, &mut tokio::io::stdin()).await,
+		Command::Publish { config, url, import } => client(config, url, import, &mut tokio::io::stdin()).await,
+		Command::Subscribe { config, url } => subscribe(config, url, tokio::io::stdout()).await,
+		Command::Record { config, url, output } => record(config, url, output).await,
+		Command::Replay {
+			config,
+			url,
+			input,
+			fast,
+		} => {
+			let pacing = match fast {
+				true => hang::moq_lite::archive::Pacing::Fast,
+				false => hang::moq_lite::archive::Pacing::Original,
+			};
+			replay(config, url, input, pacing).await
+		}
 	}
 }
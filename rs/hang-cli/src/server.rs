@@ -3,7 +3,7 @@ use axum::handler::HandlerWithoutStateExt;
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
 use axum::{http::Method, routing::get, Router};
-use hang::{cmaf, moq_lite};
+use hang::moq_lite;
 use hang::{BroadcastConsumer, BroadcastProducer};
 use moq_lite::web_transport;
 use std::net::SocketAddr;
@@ -12,9 +12,12 @@ use tokio::io::AsyncRead;
 use tower_http::cors::{Any, CorsLayer};
 use tower_http::services::ServeDir;
 
+use crate::{import, ImportConfig};
+
 pub async fn server<T: AsyncRead + Unpin>(
 	config: moq_native::ServerConfig,
 	public: Option<PathBuf>,
+	format: ImportConfig,
 	input: &mut T,
 ) -> anyhow::Result<()> {
 	let mut listen = config.listen.unwrap_or("[::]:443".parse().unwrap());
@@ -32,7 +35,7 @@ pub async fn server<T: AsyncRead + Unpin>(
 
 	tokio::select! {
 		res = accept(server, consumer) => res,
-		res = publish(producer, input) => res,
+		res = import(producer, format, input) => res,
 		res = web(listen, fingerprints, public) => res,
 	}
 }
@@ -65,22 +68,6 @@ async fn accept(mut server: moq_native::Server, consumer: BroadcastConsumer) ->
 	Ok(())
 }
 
-async fn publish<T: AsyncRead + Unpin>(producer: BroadcastProducer, input: &mut T) -> anyhow::Result<()> {
-	let mut import = cmaf::Import::new(producer);
-
-	import
-		.init_from(input)
-		.await
-		.context("failed to initialize cmaf from input")?;
-
-	tracing::info!("initialized");
-
-	import.read_from(input).await?;
-
-	Ok(())
-}
-
-// Run a HTTP server using Axum to serve the certificate fingerprint.
 async fn web(bind: SocketAddr, fingerprints: Vec<String>, public: Option<PathBuf>) -> anyhow::Result<()> {
 	// Get the first certificate's fingerprint.
 	// TODO serve all of them so we can support multiple signature algorithms.
@@ -172,8 +172,11 @@ impl HangSrc {
 
 		gst::info!(CAT, "catalog: {:?}", catalog);
 
+		let container = catalog.container;
+
 		for video in catalog.video {
 			let mut track = broadcast.subscribe(&video.track);
+			track.set_container(container);
 
 			let caps = match video.config.codec {
 				hang::catalog::VideoCodec::H264(_) => {
@@ -253,6 +256,7 @@ impl HangSrc {
 
 		for audio in catalog.audio {
 			let mut track = broadcast.subscribe(&audio.track);
+			track.set_container(container);
 
 			let caps = match &audio.config.codec {
 				hang::catalog::AudioCodec::AAC(_aac) => {
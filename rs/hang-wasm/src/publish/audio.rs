@@ -22,6 +22,7 @@ impl PublishAudio {
 		let track = moq_lite::Track {
 			name: format!("audio_{}", id),
 			priority: 2,
+			..Default::default()
 		}
 		.produce();
 
@@ -52,6 +53,8 @@ impl PublishAudio {
 
 			self.track.write(hang::Frame {
 				timestamp: frame.timestamp,
+				decode_timestamp: None,
+				duration: None,
 				keyframe: frame.keyframe,
 				payload: frame.payload,
 			});
@@ -86,6 +89,7 @@ impl PublishAudio {
 					channel_count: config.channel_count,
 					bitrate: self.config.bitrate.map(|b| b as _),
 				},
+				index: None,
 			};
 
 			broadcast.add_audio(self.track.consume(), info);
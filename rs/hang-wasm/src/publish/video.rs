@@ -22,6 +22,7 @@ impl PublishVideo {
 		let track = moq_lite::Track {
 			name: format!("video_{}", id),
 			priority: 1,
+			..Default::default()
 		}
 		.produce();
 
@@ -114,6 +115,8 @@ impl PublishVideo {
 
 			self.track.write(hang::Frame {
 				timestamp: frame.timestamp,
+				decode_timestamp: None,
+				duration: None,
 				keyframe: frame.keyframe,
 				payload: frame.payload,
 			});
@@ -138,7 +141,10 @@ impl PublishVideo {
 					rotation: None,
 					flip: None,
 					optimize_for_latency: None,
+					color_space: None,
 				},
+				rendition_group: None,
+				index: None,
 			};
 
 			broadcast.add_video(self.track.consume(), info);
@@ -42,7 +42,8 @@ impl Audio {
 			}
 		}
 
-		let track = broadcast.subscribe(&audio.track);
+		let mut track = broadcast.subscribe(&audio.track);
+		track.set_container(catalog.container);
 
 		// TODO handle the error instead of ignoring it.
 		let track = AudioTrack::new(track, audio.clone()).ok()?;
@@ -63,7 +63,8 @@ impl Video {
 			}
 		}
 
-		let track = broadcast.subscribe(&video.track);
+		let mut track = broadcast.subscribe(&video.track);
+		track.set_container(catalog.container);
 
 		// TODO handle the error instead of ignoring it.
 		let video = VideoTrack::new(track, video.clone()).ok()?;
@@ -0,0 +1,102 @@
+use super::{Error, Result};
+
+/// Remove the emulation prevention bytes (0x000003) from a NAL unit.
+pub fn rbsp(nal: &[u8]) -> Vec<u8> {
+	let mut out = Vec::with_capacity(nal.len());
+	let mut zeros = 0;
+
+	for &byte in nal {
+		if zeros >= 2 && byte == 0x03 {
+			zeros = 0;
+			continue;
+		}
+
+		zeros = if byte == 0 { zeros + 1 } else { 0 };
+		out.push(byte);
+	}
+
+	out
+}
+
+/// Reads bits MSB first, including the Exp-Golomb codes used by parameter sets.
+pub struct BitReader<'a> {
+	data: &'a [u8],
+	pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+	pub fn new(data: &'a [u8]) -> Self {
+		Self { data, pos: 0 }
+	}
+
+	pub fn bit(&mut self) -> Result<bool> {
+		let byte = self.data.get(self.pos / 8).ok_or(Error::Truncated)?;
+		let bit = (byte >> (7 - self.pos % 8)) & 1;
+		self.pos += 1;
+		Ok(bit == 1)
+	}
+
+	pub fn bits(&mut self, count: u32) -> Result<u32> {
+		assert!(count <= 32);
+
+		let mut value = 0u32;
+		for _ in 0..count {
+			value = (value << 1) | self.bit()? as u32;
+		}
+
+		Ok(value)
+	}
+
+	pub fn skip(&mut self, count: usize) -> Result<()> {
+		self.pos += count;
+		match self.pos <= self.data.len() * 8 {
+			true => Ok(()),
+			false => Err(Error::Truncated),
+		}
+	}
+
+	/// Read an unsigned Exp-Golomb code.
+	pub fn ue(&mut self) -> Result<u32> {
+		let mut zeros = 0;
+		while !self.bit()? {
+			zeros += 1;
+			if zeros > 31 {
+				return Err(Error::InvalidNal);
+			}
+		}
+
+		Ok(((1u64 << zeros) - 1 + self.bits(zeros)? as u64) as u32)
+	}
+
+	/// Read a signed Exp-Golomb code.
+	pub fn se(&mut self) -> Result<i32> {
+		let value = self.ue()? as i64;
+		Ok(match value % 2 {
+			0 => -(value / 2),
+			_ => (value + 1) / 2,
+		} as i32)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn exp_golomb() {
+		// 1, 010, 011, 00100, 00101
+		let data = [0b1010_0110, 0b0100_0010, 0b1000_0000];
+		let mut bits = BitReader::new(&data);
+		assert_eq!(bits.ue().unwrap(), 0);
+		assert_eq!(bits.ue().unwrap(), 1);
+		assert_eq!(bits.ue().unwrap(), 2);
+		assert_eq!(bits.se().unwrap(), 2);
+		assert_eq!(bits.se().unwrap(), -2);
+		assert!(bits.ue().is_err());
+	}
+
+	#[test]
+	fn emulation_prevention() {
+		assert_eq!(rbsp(&[0, 0, 3, 1, 0, 0, 3, 0, 3]), [0, 0, 1, 0, 0, 0, 3]);
+	}
+}
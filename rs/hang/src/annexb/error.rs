@@ -0,0 +1,25 @@
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("mp4 error: {0}")]
+	Mp4(#[from] mp4_atom::Error),
+
+	#[error("truncated NAL unit")]
+	Truncated,
+
+	#[error("invalid NAL unit")]
+	InvalidNal,
+
+	#[error("invalid SPS")]
+	InvalidSps,
+
+	#[error("missing framerate")]
+	MissingFramerate,
+
+	#[error("B-frames are not supported without timestamps")]
+	BFrames,
+
+	#[error("io error: {0}")]
+	Io(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
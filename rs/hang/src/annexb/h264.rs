@@ -0,0 +1,347 @@
+use bytes::{Bytes, BytesMut};
+use mp4_atom::Atom;
+
+use super::{rbsp, BitReader, Error, Result};
+use crate::catalog::{VideoColorSpace, VideoConfig, H264};
+
+pub const NAL_IDR: u8 = 5;
+pub const NAL_SPS: u8 = 7;
+pub const NAL_PPS: u8 = 8;
+pub const NAL_AUD: u8 = 9;
+
+pub fn nal_type(nal: &[u8]) -> Option<u8> {
+	Some(nal.first()? & 0x1f)
+}
+
+/// The fields we care about from an H.264 sequence parameter set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sps {
+	pub profile: u8,
+	pub constraints: u8,
+	pub level: u8,
+	pub width: u32,
+	pub height: u32,
+
+	// From the VUI timing info, if present.
+	pub framerate: Option<f64>,
+
+	// From the VUI colour description, if present.
+	pub color: Option<VideoColorSpace>,
+}
+
+impl Sps {
+	pub fn parse(nal: &[u8]) -> Result<Self> {
+		if nal_type(nal) != Some(NAL_SPS) {
+			return Err(Error::InvalidSps);
+		}
+
+		let rbsp = rbsp(&nal[1..]);
+		let mut bits = BitReader::new(&rbsp);
+
+		let profile = bits.bits(8)? as u8;
+		let constraints = bits.bits(8)? as u8;
+		let level = bits.bits(8)? as u8;
+		let _sps_id = bits.ue()?;
+
+		let mut chroma_format_idc = 1;
+		let mut separate_colour_plane = false;
+
+		if matches!(
+			profile,
+			100 | 110 | 122 | 244 | 44 | 83 | 86 | 118 | 128 | 138 | 139 | 134 | 135
+		) {
+			chroma_format_idc = bits.ue()?;
+			if chroma_format_idc == 3 {
+				separate_colour_plane = bits.bit()?;
+			}
+
+			let _bit_depth_luma_minus8 = bits.ue()?;
+			let _bit_depth_chroma_minus8 = bits.ue()?;
+			let _qpprime_y_zero_transform_bypass = bits.bit()?;
+
+			if bits.bit()? {
+				// seq_scaling_matrix_present_flag
+				let count = if chroma_format_idc == 3 { 12 } else { 8 };
+				for i in 0..count {
+					if bits.bit()? {
+						skip_scaling_list(&mut bits, if i < 6 { 16 } else { 64 })?;
+					}
+				}
+			}
+		}
+
+		let _log2_max_frame_num_minus4 = bits.ue()?;
+
+		match bits.ue()? {
+			0 => {
+				let _log2_max_pic_order_cnt_lsb_minus4 = bits.ue()?;
+			}
+			1 => {
+				let _delta_pic_order_always_zero = bits.bit()?;
+				let _offset_for_non_ref_pic = bits.se()?;
+				let _offset_for_top_to_bottom_field = bits.se()?;
+				for _ in 0..bits.ue()? {
+					let _offset_for_ref_frame = bits.se()?;
+				}
+			}
+			_ => {}
+		}
+
+		let _max_num_ref_frames = bits.ue()?;
+		let _gaps_in_frame_num_value_allowed = bits.bit()?;
+
+		let width_in_mbs = bits.ue()? + 1;
+		let height_in_map_units = bits.ue()? + 1;
+
+		let frame_mbs_only = bits.bit()?;
+		if !frame_mbs_only {
+			let _mb_adaptive_frame_field = bits.bit()?;
+		}
+
+		let _direct_8x8_inference = bits.bit()?;
+
+		let mut crop = [0; 4];
+		if bits.bit()? {
+			for crop in crop.iter_mut() {
+				*crop = bits.ue()?;
+			}
+		}
+
+		// Table 6-1: the cropping units depend on the chroma subsampling.
+		let field_factor = 2 - frame_mbs_only as u32;
+		let (crop_x, crop_y) = match (chroma_format_idc, separate_colour_plane) {
+			(0, _) | (3, true) => (1, field_factor),
+			(1, _) => (2, 2 * field_factor),
+			(2, _) => (2, field_factor),
+			_ => (1, field_factor),
+		};
+
+		let width = (width_in_mbs * 16)
+			.checked_sub(crop_x * (crop[0] + crop[1]))
+			.ok_or(Error::InvalidSps)?;
+		let height = (field_factor * height_in_map_units * 16)
+			.checked_sub(crop_y * (crop[2] + crop[3]))
+			.ok_or(Error::InvalidSps)?;
+
+		let (framerate, color) = match bits.bit()? {
+			true => Self::vui(&mut bits)?,
+			false => (None, None),
+		};
+
+		Ok(Self {
+			profile,
+			constraints,
+			level,
+			width,
+			height,
+			framerate,
+			color,
+		})
+	}
+
+	// Parse the VUI up until the timing info, returning the framerate and colour description.
+	fn vui(bits: &mut BitReader) -> Result<(Option<f64>, Option<VideoColorSpace>)> {
+		if bits.bit()? {
+			// aspect_ratio_info_present_flag
+			if bits.bits(8)? == 255 {
+				// Extended_SAR
+				bits.skip(32)?;
+			}
+		}
+
+		if bits.bit()? {
+			// overscan_info_present_flag
+			bits.skip(1)?;
+		}
+
+		let mut color = None;
+
+		if bits.bit()? {
+			// video_signal_type_present_flag
+			let _video_format = bits.bits(3)?;
+			let full_range = bits.bit()?;
+
+			// Without a colour description, the values are unspecified (2).
+			let (primaries, transfer, matrix) = match bits.bit()? {
+				true => (bits.bits(8)? as u8, bits.bits(8)? as u8, bits.bits(8)? as u8),
+				false => (2, 2, 2),
+			};
+
+			color = Some(VideoColorSpace::from_cicp(primaries, transfer, matrix, full_range));
+		}
+
+		if bits.bit()? {
+			// chroma_loc_info_present_flag
+			bits.ue()?;
+			bits.ue()?;
+		}
+
+		if !bits.bit()? {
+			// timing_info_present_flag
+			return Ok((None, color));
+		}
+
+		let num_units_in_tick = bits.bits(32)?;
+		let time_scale = bits.bits(32)?;
+
+		if num_units_in_tick == 0 || time_scale == 0 {
+			return Ok((None, color));
+		}
+
+		// Each frame is two fields, hence the factor of two.
+		let framerate = time_scale as f64 / (2 * num_units_in_tick as u64) as f64;
+		Ok((Some(framerate), color))
+	}
+}
+
+fn skip_scaling_list(bits: &mut BitReader, size: usize) -> Result<()> {
+	let mut last = 8;
+	let mut next = 8;
+
+	for _ in 0..size {
+		if next != 0 {
+			let delta = bits.se()?;
+			next = (last + delta + 256) % 256;
+		}
+
+		if next != 0 {
+			last = next;
+		}
+	}
+
+	Ok(())
+}
+
+/// Build a video config from the SPS and PPS, using an avcC box as the description.
+pub fn config(sps: &[u8], pps: &[u8]) -> Result<VideoConfig> {
+	let parsed = Sps::parse(sps)?;
+
+	let avcc = mp4_atom::Avcc::new(sps, pps)?;
+	let mut description = BytesMut::new();
+	avcc.encode_body(&mut description)?;
+
+	Ok(VideoConfig {
+		codec: H264 {
+			profile: parsed.profile,
+			constraints: parsed.constraints,
+			level: parsed.level,
+		}
+		.into(),
+		description: Some(description.freeze()),
+		coded_width: Some(parsed.width),
+		coded_height: Some(parsed.height),
+		display_ratio_width: None,
+		display_ratio_height: None,
+		bitrate: None,
+		framerate: parsed.framerate,
+		rotation: None,
+		flip: None,
+		optimize_for_latency: None,
+		color_space: parsed.color,
+	})
+}
+
+pub fn is_vcl(nal: &[u8]) -> bool {
+	matches!(nal_type(nal), Some(1..=5))
+}
+
+// Section 7.4.1.2.3: the order of NAL units within an access unit.
+pub fn is_first(nal: &[u8]) -> bool {
+	match nal_type(nal) {
+		// first_mb_in_slice == 0, which is a single 1 bit when Exp-Golomb coded.
+		Some(1..=5) => nal.get(1).is_some_and(|b| b & 0x80 != 0),
+		Some(6..=9 | 14..=18) => true,
+		_ => false,
+	}
+}
+
+/// Returns true if the access unit contains an IDR slice.
+pub fn is_keyframe(nals: &[Bytes]) -> bool {
+	nals.iter().any(|nal| nal_type(nal) == Some(NAL_IDR))
+}
+
+/// Returns true if this is a bi-predictive (B) slice, which may be presented out of decode order.
+pub fn is_bslice(nal: &[u8]) -> Result<bool> {
+	if !is_vcl(nal) {
+		return Ok(false);
+	}
+
+	let rbsp = rbsp(&nal[1..]);
+	let mut bits = BitReader::new(&rbsp);
+
+	let _first_mb_in_slice = bits.ue()?;
+	let slice_type = bits.ue()?;
+
+	// Section 7.4.3: slice types 5-9 are the same as 0-4.
+	Ok(slice_type % 5 == 1)
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+	use super::*;
+
+	// Baseline, 1280x720, no cropping.
+	pub const SPS_720P: &[u8] = &[0x67, 0x42, 0xc0, 0x1f, 0xda, 0x01, 0x40, 0x16, 0xe4];
+	// High, 1920x1080 cropped from 1088, with VUI timing for 29.97fps.
+	pub const SPS_1080P: &[u8] = &[
+		0x67, 0x64, 0x00, 0x28, 0xac, 0xd9, 0x40, 0x78, 0x02, 0x27, 0xe5, 0xc0, 0x5a, 0x80, 0x80, 0x80, 0xa0, 0x00,
+		0x00, 0x7d, 0x20, 0x00, 0x1d, 0x4c, 0x10, 0x80,
+	];
+	// The 720p SPS with a VUI colour description for BT.2020 PQ, full range.
+	pub const SPS_HDR: &[u8] = &[
+		0x67, 0x42, 0xc0, 0x1f, 0xda, 0x01, 0x40, 0x16, 0xe9, 0xb8, 0x48, 0x80, 0x49,
+	];
+	pub const PPS: &[u8] = &[0x68, 0xce, 0x3c, 0x80];
+
+	#[test]
+	fn sps() {
+		let sps = Sps::parse(SPS_720P).unwrap();
+		assert_eq!((sps.profile, sps.constraints, sps.level), (0x42, 0xc0, 0x1f));
+		assert_eq!((sps.width, sps.height), (1280, 720));
+		assert_eq!(sps.framerate, None);
+
+		let sps = Sps::parse(SPS_1080P).unwrap();
+		assert_eq!((sps.profile, sps.level), (0x64, 0x28));
+		assert_eq!((sps.width, sps.height), (1920, 1080));
+		assert_eq!(sps.framerate, Some(60000.0 / 2002.0));
+	}
+
+	#[test]
+	fn color() {
+		let sps = Sps::parse(SPS_HDR).unwrap();
+		assert_eq!((sps.width, sps.height), (1280, 720));
+		assert_eq!(sps.framerate, None);
+		assert_eq!(
+			sps.color,
+			Some(VideoColorSpace {
+				primaries: Some("bt2020".to_string()),
+				transfer: Some("pq".to_string()),
+				matrix: Some("bt2020-ncl".to_string()),
+				full_range: Some(true),
+			})
+		);
+
+		let config = config(SPS_HDR, PPS).unwrap();
+		assert_eq!(config.color_space, sps.color);
+	}
+
+	#[test]
+	fn bslice() {
+		// Non-IDR slices with slice_type B, P and B (all slices in the picture).
+		assert!(is_bslice(&[0x01, 0xa0]).unwrap());
+		assert!(!is_bslice(&[0x41, 0xc0]).unwrap());
+		assert!(is_bslice(&[0x01, 0x9c]).unwrap());
+		assert!(!is_bslice(SPS_720P).unwrap());
+	}
+
+	#[test]
+	fn description() {
+		let config = config(SPS_720P, PPS).unwrap();
+		assert_eq!(config.codec.to_string(), "avc1.42c01f");
+
+		let mut description = config.description.unwrap();
+		let avcc = mp4_atom::Avcc::decode_body(&mut description).unwrap();
+		assert_eq!(avcc.sequence_parameter_sets, [SPS_720P]);
+		assert_eq!(avcc.picture_parameter_sets, [PPS]);
+	}
+}
@@ -0,0 +1,420 @@
+use std::collections::HashMap;
+
+use bytes::{Bytes, BytesMut};
+use mp4_atom::Atom;
+
+use super::{rbsp, BitReader, Error, Result};
+use crate::catalog::{VideoConfig, H265};
+
+pub const NAL_VPS: u8 = 32;
+pub const NAL_SPS: u8 = 33;
+pub const NAL_PPS: u8 = 34;
+pub const NAL_AUD: u8 = 35;
+
+pub fn nal_type(nal: &[u8]) -> Option<u8> {
+	Some((nal.first()? >> 1) & 0x3f)
+}
+
+/// The general profile, tier and level, shared by the VPS and SPS.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileTierLevel {
+	pub profile_space: u8,
+	pub tier_flag: bool,
+	pub profile_idc: u8,
+	pub profile_compatibility_flags: [u8; 4],
+	pub constraint_flags: [u8; 6],
+	pub level_idc: u8,
+}
+
+impl ProfileTierLevel {
+	fn parse(bits: &mut BitReader, max_sub_layers_minus1: u8) -> Result<Self> {
+		let profile_space = bits.bits(2)? as u8;
+		let tier_flag = bits.bit()?;
+		let profile_idc = bits.bits(5)? as u8;
+		let profile_compatibility_flags = bits.bits(32)?.to_be_bytes();
+
+		let mut constraint_flags = [0; 6];
+		for flag in constraint_flags.iter_mut() {
+			*flag = bits.bits(8)? as u8;
+		}
+
+		let level_idc = bits.bits(8)? as u8;
+
+		let mut sub_layers = Vec::with_capacity(max_sub_layers_minus1 as usize);
+		for _ in 0..max_sub_layers_minus1 {
+			let profile_present = bits.bit()?;
+			let level_present = bits.bit()?;
+			sub_layers.push((profile_present, level_present));
+		}
+
+		if max_sub_layers_minus1 > 0 {
+			for _ in max_sub_layers_minus1..8 {
+				let _reserved_zero_2bits = bits.bits(2)?;
+			}
+		}
+
+		for (profile_present, level_present) in sub_layers {
+			if profile_present {
+				bits.skip(88)?;
+			}
+
+			if level_present {
+				bits.skip(8)?;
+			}
+		}
+
+		Ok(Self {
+			profile_space,
+			tier_flag,
+			profile_idc,
+			profile_compatibility_flags,
+			constraint_flags,
+			level_idc,
+		})
+	}
+}
+
+/// The fields we care about from an H.265 video parameter set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vps {
+	// From the VPS timing info, if present.
+	pub framerate: Option<f64>,
+}
+
+impl Vps {
+	pub fn parse(nal: &[u8]) -> Result<Self> {
+		if nal_type(nal) != Some(NAL_VPS) || nal.len() < 2 {
+			return Err(Error::InvalidNal);
+		}
+
+		let rbsp = rbsp(&nal[2..]);
+		let mut bits = BitReader::new(&rbsp);
+
+		let _vps_id = bits.bits(4)?;
+		let _base_layer_internal = bits.bit()?;
+		let _base_layer_available = bits.bit()?;
+		let _max_layers_minus1 = bits.bits(6)?;
+		let max_sub_layers_minus1 = bits.bits(3)? as u8;
+		let _temporal_id_nesting = bits.bit()?;
+		let _reserved_0xffff_16bits = bits.bits(16)?;
+
+		ProfileTierLevel::parse(&mut bits, max_sub_layers_minus1)?;
+
+		let ordering_info_present = bits.bit()?;
+		let first = if ordering_info_present {
+			0
+		} else {
+			max_sub_layers_minus1
+		};
+		for _ in first..=max_sub_layers_minus1 {
+			let _max_dec_pic_buffering_minus1 = bits.ue()?;
+			let _max_num_reorder_pics = bits.ue()?;
+			let _max_latency_increase_plus1 = bits.ue()?;
+		}
+
+		let max_layer_id = bits.bits(6)? as usize;
+		let num_layer_sets_minus1 = bits.ue()? as usize;
+		bits.skip(num_layer_sets_minus1 * (max_layer_id + 1))?;
+
+		if !bits.bit()? {
+			// vps_timing_info_present_flag
+			return Ok(Self { framerate: None });
+		}
+
+		let num_units_in_tick = bits.bits(32)?;
+		let time_scale = bits.bits(32)?;
+
+		let framerate = match num_units_in_tick {
+			0 => None,
+			_ => Some(time_scale as f64 / num_units_in_tick as f64),
+		};
+
+		Ok(Self { framerate })
+	}
+}
+
+/// The fields we care about from an H.265 picture parameter set, needed to parse slice headers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pps {
+	pub id: u32,
+	pub num_extra_slice_header_bits: u8,
+}
+
+impl Pps {
+	pub fn parse(nal: &[u8]) -> Result<Self> {
+		if nal_type(nal) != Some(NAL_PPS) || nal.len() < 2 {
+			return Err(Error::InvalidNal);
+		}
+
+		let rbsp = rbsp(&nal[2..]);
+		let mut bits = BitReader::new(&rbsp);
+
+		let id = bits.ue()?;
+		let _sps_id = bits.ue()?;
+		let _dependent_slice_segments_enabled = bits.bit()?;
+		let _output_flag_present = bits.bit()?;
+		let num_extra_slice_header_bits = bits.bits(3)? as u8;
+
+		Ok(Self {
+			id,
+			num_extra_slice_header_bits,
+		})
+	}
+}
+
+/// The fields we care about from an H.265 sequence parameter set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sps {
+	pub profile: ProfileTierLevel,
+
+	pub max_sub_layers: u8,
+	pub temporal_id_nesting: bool,
+
+	pub chroma_format_idc: u8,
+	pub bit_depth_luma_minus8: u8,
+	pub bit_depth_chroma_minus8: u8,
+
+	pub width: u32,
+	pub height: u32,
+}
+
+impl Sps {
+	pub fn parse(nal: &[u8]) -> Result<Self> {
+		if nal_type(nal) != Some(NAL_SPS) || nal.len() < 2 {
+			return Err(Error::InvalidSps);
+		}
+
+		let rbsp = rbsp(&nal[2..]);
+		let mut bits = BitReader::new(&rbsp);
+
+		let _vps_id = bits.bits(4)?;
+		let max_sub_layers_minus1 = bits.bits(3)? as u8;
+		let temporal_id_nesting = bits.bit()?;
+
+		let profile = ProfileTierLevel::parse(&mut bits, max_sub_layers_minus1)?;
+
+		let _sps_id = bits.ue()?;
+
+		let chroma_format_idc = bits.ue()?;
+		let separate_colour_plane = chroma_format_idc == 3 && bits.bit()?;
+
+		let width = bits.ue()?;
+		let height = bits.ue()?;
+
+		let mut window = [0; 4];
+		if bits.bit()? {
+			for offset in window.iter_mut() {
+				*offset = bits.ue()?;
+			}
+		}
+
+		let bit_depth_luma_minus8 = bits.ue()? as u8;
+		let bit_depth_chroma_minus8 = bits.ue()? as u8;
+
+		// Table 6-1: the conformance window is in units of chroma samples.
+		let (unit_x, unit_y) = match (chroma_format_idc, separate_colour_plane) {
+			(1, _) => (2, 2),
+			(2, _) => (2, 1),
+			_ => (1, 1),
+		};
+
+		let width = width
+			.checked_sub(unit_x * (window[0] + window[1]))
+			.ok_or(Error::InvalidSps)?;
+		let height = height
+			.checked_sub(unit_y * (window[2] + window[3]))
+			.ok_or(Error::InvalidSps)?;
+
+		Ok(Self {
+			profile,
+			max_sub_layers: max_sub_layers_minus1 + 1,
+			temporal_id_nesting,
+			chroma_format_idc: chroma_format_idc as u8,
+			bit_depth_luma_minus8,
+			bit_depth_chroma_minus8,
+			width,
+			height,
+		})
+	}
+}
+
+/// Build a video config from the VPS, SPS and PPS, using an hvcC box as the description.
+///
+/// The parameter sets are also expected in-band, so this uses hev1 instead of hvc1.
+pub fn config(vps: &[u8], sps: &[u8], pps: &[u8]) -> Result<VideoConfig> {
+	let parsed = Sps::parse(sps)?;
+	let profile = &parsed.profile;
+
+	// The framerate is optional, so don't fail if the VPS can't be parsed.
+	let framerate = Vps::parse(vps).ok().and_then(|vps| vps.framerate);
+
+	let array = |nal_unit_type, nal: &[u8]| mp4_atom::HvcCArray {
+		completeness: true,
+		nal_unit_type,
+		nalus: vec![nal.to_vec()],
+	};
+
+	let hvcc = mp4_atom::Hvcc {
+		configuration_version: 1,
+		general_profile_space: profile.profile_space,
+		general_tier_flag: profile.tier_flag,
+		general_profile_idc: profile.profile_idc,
+		general_profile_compatibility_flags: profile.profile_compatibility_flags,
+		general_constraint_indicator_flags: profile.constraint_flags,
+		general_level_idc: profile.level_idc,
+		chroma_format_idc: parsed.chroma_format_idc,
+		bit_depth_luma_minus8: parsed.bit_depth_luma_minus8,
+		bit_depth_chroma_minus8: parsed.bit_depth_chroma_minus8,
+		num_temporal_layers: parsed.max_sub_layers,
+		temporal_id_nested: parsed.temporal_id_nesting,
+		length_size_minus_one: 3,
+		arrays: vec![array(NAL_VPS, vps), array(NAL_SPS, sps), array(NAL_PPS, pps)],
+		..Default::default()
+	};
+
+	let mut description = BytesMut::new();
+	hvcc.encode_body(&mut description)?;
+
+	Ok(VideoConfig {
+		codec: H265 {
+			in_band: true,
+			profile_space: profile.profile_space,
+			profile_idc: profile.profile_idc,
+			profile_compatibility_flags: profile.profile_compatibility_flags,
+			tier_flag: profile.tier_flag,
+			level_idc: profile.level_idc,
+			constraint_flags: profile.constraint_flags,
+		}
+		.into(),
+		description: Some(description.freeze()),
+		coded_width: Some(parsed.width),
+		coded_height: Some(parsed.height),
+		display_ratio_width: None,
+		display_ratio_height: None,
+		bitrate: None,
+		framerate,
+		rotation: None,
+		flip: None,
+		optimize_for_latency: None,
+		color_space: None,
+	})
+}
+
+pub fn is_vcl(nal: &[u8]) -> bool {
+	matches!(nal_type(nal), Some(0..=31))
+}
+
+// Section 7.4.2.4.4: the order of NAL units within an access unit.
+pub fn is_first(nal: &[u8]) -> bool {
+	match nal_type(nal) {
+		// first_slice_segment_in_pic_flag
+		Some(0..=31) => nal.get(2).is_some_and(|b| b & 0x80 != 0),
+		Some(32..=35 | 39 | 41..=44 | 48..=55) => true,
+		_ => false,
+	}
+}
+
+/// Returns true if the access unit contains an IRAP (BLA, IDR or CRA) slice.
+pub fn is_keyframe(nals: &[Bytes]) -> bool {
+	nals.iter().any(|nal| matches!(nal_type(nal), Some(16..=23)))
+}
+
+/// Returns true if this is a bi-predictive (B) slice, which may be presented out of decode order.
+///
+/// Only the first slice segment of a picture is checked, as the others require the SPS to parse.
+/// A slice referencing an unknown PPS returns false.
+pub fn is_bslice(nal: &[u8], pps: &HashMap<u32, Pps>) -> Result<bool> {
+	let Some(nal_type @ 0..=31) = nal_type(nal) else {
+		return Ok(false);
+	};
+
+	if nal.len() < 2 {
+		return Err(Error::InvalidNal);
+	}
+
+	let rbsp = rbsp(&nal[2..]);
+	let mut bits = BitReader::new(&rbsp);
+
+	if !bits.bit()? {
+		// first_slice_segment_in_pic_flag
+		return Ok(false);
+	}
+
+	if (16..=23).contains(&nal_type) {
+		let _no_output_of_prior_pics = bits.bit()?;
+	}
+
+	let Some(pps) = pps.get(&bits.ue()?) else {
+		return Ok(false);
+	};
+
+	bits.skip(pps.num_extra_slice_header_bits as usize)?;
+	let slice_type = bits.ue()?;
+
+	// Section 7.4.7.1: 0 is B, 1 is P and 2 is I.
+	Ok(slice_type == 0)
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+	use super::*;
+
+	// Parameter sets from a libheif (x265) encoded image.
+	pub const VPS: &[u8] = &[
+		0x40, 0x01, 0x0c, 0x01, 0xff, 0xff, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00, 0x03, 0x00, 0x00,
+		0x03, 0x00, 0x78, 0x99, 0x8a, 0x02, 0x40,
+	];
+	pub const SPS: &[u8] = &[
+		0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00, 0x03, 0x00, 0x00, 0x03, 0x00, 0x78,
+		0xa0, 0x02, 0x80, 0x80, 0x35, 0x9f, 0x59, 0x66, 0x62, 0xa4, 0x91, 0x26, 0xbf, 0xfc, 0x1a, 0xb0, 0x1a, 0xac,
+		0x04, 0x00, 0x00, 0x03, 0x00, 0x04, 0x00, 0x00, 0x03, 0x00, 0x64, 0x20,
+	];
+	// A VPS with timing info for 25fps.
+	pub const VPS_25FPS: &[u8] = &[
+		0x40, 0x01, 0x0c, 0x01, 0xff, 0xff, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00, 0x03, 0x00, 0x00,
+		0x03, 0x00, 0x5d, 0x97, 0x03, 0x00, 0x00, 0x03, 0x00, 0x01, 0x00, 0x00, 0x03, 0x00, 0x19, 0x50,
+	];
+	pub const PPS: &[u8] = &[0x44, 0x01, 0xc1, 0x72, 0xb6, 0x62, 0x40];
+
+	#[test]
+	fn sps() {
+		let sps = Sps::parse(SPS).unwrap();
+		assert_eq!(sps.profile.profile_idc, 1);
+		assert_eq!(sps.profile.profile_compatibility_flags, [0x60, 0, 0, 0]);
+		assert_eq!(sps.profile.constraint_flags, [0x90, 0, 0, 0, 0, 0]);
+		assert_eq!(sps.profile.level_idc, 0x78);
+		assert_eq!(sps.chroma_format_idc, 1);
+		assert_eq!((sps.width, sps.height), (1280, 854));
+	}
+
+	#[test]
+	fn bslice() {
+		let pps = Pps::parse(PPS).unwrap();
+		assert_eq!((pps.id, pps.num_extra_slice_header_bits), (0, 0));
+		let pps = HashMap::from([(pps.id, pps)]);
+
+		// TRAIL_R slices with slice_type B, P, followed by a slice referencing an unknown PPS.
+		assert!(is_bslice(&[0x02, 0x01, 0xe0], &pps).unwrap());
+		assert!(!is_bslice(&[0x02, 0x01, 0xd0], &pps).unwrap());
+		assert!(!is_bslice(&[0x02, 0x01, 0xa0], &pps).unwrap());
+		assert!(!is_bslice(VPS, &pps).unwrap());
+	}
+
+	#[test]
+	fn vps() {
+		assert_eq!(Vps::parse(VPS).unwrap().framerate, None);
+		assert_eq!(Vps::parse(VPS_25FPS).unwrap().framerate, Some(25.0));
+	}
+
+	#[test]
+	fn description() {
+		let config = config(VPS, SPS, PPS).unwrap();
+		assert_eq!(config.codec.to_string(), "hev1.1.60.L120.90");
+
+		let mut description = config.description.unwrap();
+		let hvcc = mp4_atom::Hvcc::decode_body(&mut description).unwrap();
+		assert_eq!(hvcc.general_profile_idc, 1);
+		assert_eq!(hvcc.arrays.len(), 3);
+		assert_eq!(hvcc.arrays[1].nalus, [SPS]);
+	}
+}
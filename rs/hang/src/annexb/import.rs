@@ -0,0 +1,342 @@
+use std::collections::HashMap;
+
+use bytes::{Buf, Bytes, BytesMut};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use super::{find_start_code, h264, h265, trim, Codec, Error, Result, VideoTrack};
+use crate::model::{BroadcastProducer, Timestamp};
+
+/// Converts a raw H.264/H.265 Annex-B byte stream -> Karp
+///
+/// The byte stream doesn't contain timestamps, so they're derived from the framerate.
+/// This is either provided with [Self::with_framerate] or signaled in the VUI/VPS timing info.
+///
+/// The access units are in decode order, so B-frames are rejected with [Error::BFrames] as they would be presented out of order.
+pub struct Import {
+	// Any partial NAL unit in the input buffer
+	buffer: BytesMut,
+
+	// True if the buffer starts with a start code, otherwise it only contains the tail of any leading junk.
+	started: bool,
+
+	// The broadcast being produced
+	broadcast: BroadcastProducer,
+
+	codec: Codec,
+	video: VideoTrack,
+
+	// The NAL units in the current access unit.
+	access_unit: Vec<Bytes>,
+
+	// The configured framerate, or the one signaled in the parameter sets.
+	framerate: Option<f64>,
+	configured: bool,
+
+	// The number of access units so far, used to compute the timestamp.
+	count: u64,
+
+	// The H.265 picture parameter sets, needed to parse slice headers.
+	pps: HashMap<u32, h265::Pps>,
+}
+
+impl Import {
+	pub fn new(broadcast: BroadcastProducer, codec: Codec) -> Self {
+		let name = match codec {
+			Codec::H264 => "h264",
+			Codec::H265 => "h265",
+		};
+
+		Self {
+			buffer: BytesMut::new(),
+			started: false,
+			broadcast,
+			codec,
+			video: VideoTrack::new(codec, name.to_string()),
+			access_unit: Vec::new(),
+			framerate: None,
+			configured: false,
+			count: 0,
+			pps: HashMap::new(),
+		}
+	}
+
+	/// Use the provided framerate instead of any timing info in the byte stream.
+	pub fn with_framerate(mut self, framerate: f64) -> Self {
+		self.video = self.video.with_framerate(framerate);
+		self.framerate = Some(framerate);
+		self.configured = true;
+		self
+	}
+
+	pub fn parse(&mut self, data: &[u8]) -> Result<()> {
+		// The existing buffer was already searched, except for a start code spanning the new data.
+		let mut from = self.buffer.len().saturating_sub(2);
+		let mut start = None;
+
+		if self.started {
+			from = from.max(3);
+			start = Some(0);
+		}
+
+		self.buffer.extend_from_slice(data);
+
+		// Every NAL unit followed by a start code is complete.
+		let mut nals = Vec::new();
+		while let Some(end) = find_start_code(&self.buffer, from) {
+			if let Some(start) = start {
+				nals.push(start + 3..end);
+			}
+
+			start = Some(end);
+			from = end + 3;
+		}
+
+		let Some(start) = start else {
+			// Skip any data before the first start code, keeping enough in case one spans the next chunk.
+			let junk = self.buffer.len().saturating_sub(2);
+			self.buffer.advance(junk);
+			return Ok(());
+		};
+
+		// Keep the last (partial) NAL unit, including its start code.
+		let buffer = self.buffer.split_to(start).freeze();
+		self.started = true;
+
+		for range in nals {
+			self.nal(trim(buffer.slice(range)))?;
+		}
+
+		Ok(())
+	}
+
+	// Read the media from a stream until EOF, flushing the last access unit at the end.
+	pub async fn read_from<T: AsyncRead + Unpin>(&mut self, input: &mut T) -> Result<()> {
+		let mut buffer = BytesMut::new();
+
+		while input.read_buf(&mut buffer).await? > 0 {
+			self.parse(&buffer)?;
+			buffer.clear();
+		}
+
+		self.flush()
+	}
+
+	/// Write the last access unit, which is only known to be complete at the end of the stream.
+	pub fn flush(&mut self) -> Result<()> {
+		let buffer = std::mem::take(&mut self.buffer).freeze();
+		if std::mem::take(&mut self.started) {
+			self.nal(trim(buffer.slice(3..)))?;
+		}
+
+		self.write()
+	}
+
+	fn nal(&mut self, nal: Bytes) -> Result<()> {
+		if nal.is_empty() {
+			return Ok(());
+		}
+
+		let has_vcl = self.access_unit.iter().any(|nal| self.codec.is_vcl(nal));
+		if has_vcl && self.codec.is_first(&nal) {
+			self.write()?;
+		}
+
+		if !self.configured {
+			let framerate = match (self.codec, self.codec_nal_type(&nal)) {
+				(Codec::H264, Some(h264::NAL_SPS)) => h264::Sps::parse(&nal)?.framerate,
+				(Codec::H265, Some(h265::NAL_VPS)) => h265::Vps::parse(&nal)?.framerate,
+				_ => None,
+			};
+
+			if framerate.is_some() {
+				self.framerate = framerate;
+			}
+		}
+
+		if self.codec == Codec::H265 && h265::nal_type(&nal) == Some(h265::NAL_PPS) {
+			let pps = h265::Pps::parse(&nal)?;
+			self.pps.insert(pps.id, pps);
+		}
+
+		self.access_unit.push(nal);
+
+		Ok(())
+	}
+
+	fn codec_nal_type(&self, nal: &[u8]) -> Option<u8> {
+		match self.codec {
+			Codec::H264 => h264::nal_type(nal),
+			Codec::H265 => h265::nal_type(nal),
+		}
+	}
+
+	// Write the current access unit.
+	fn write(&mut self) -> Result<()> {
+		let nals = std::mem::take(&mut self.access_unit);
+		if nals.is_empty() {
+			return Ok(());
+		}
+
+		let Some(framerate) = self.framerate else {
+			if self.codec.is_keyframe(&nals) {
+				return Err(Error::MissingFramerate);
+			}
+
+			tracing::trace!("waiting for keyframe");
+			return Ok(());
+		};
+
+		// The timestamps are assigned in decode order, which would be wrong for reordered frames.
+		// Only the first slice of each picture is checked, which is enough for any encoder in practice.
+		for nal in nals.iter().filter(|nal| self.codec.is_first(nal)) {
+			let bslice = match self.codec {
+				Codec::H264 => h264::is_bslice(nal)?,
+				Codec::H265 => h265::is_bslice(nal, &self.pps)?,
+			};
+
+			if bslice {
+				return Err(Error::BFrames);
+			}
+		}
+
+		let micros = self.count as f64 * 1_000_000.0 / framerate;
+		let timestamp = Timestamp::from_micros(micros.round() as u64);
+		self.count += 1;
+
+		self.video.write_nals(&mut self.broadcast, timestamp, None, nals)?;
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::annexb::h264::test::{PPS, SPS_1080P, SPS_720P};
+	use futures::FutureExt;
+
+	fn stream(sps: &[u8]) -> Vec<u8> {
+		let nals: [&[u8]; 6] = [
+			&[0x09, 0xf0],
+			sps,
+			PPS,
+			&[0x65, 0x88, 0x84, 0x00, 0x00, 0x03, 0x01],
+			// Two slices in the same picture, followed by the next picture.
+			&[0x41, 0x9a, 0x02],
+			&[0x41, 0x00, 0x03],
+		];
+
+		let mut data: Vec<u8> = nals.iter().flat_map(|nal| [&[0, 0, 0, 1], *nal].concat()).collect();
+		data.extend_from_slice(&[0, 0, 1, 0x41, 0x9a, 0x04, 0x00]);
+		data
+	}
+
+	#[tokio::test]
+	async fn vui_timing() {
+		let broadcast = BroadcastProducer::new();
+		let mut consumer = broadcast.consume();
+
+		let mut import = Import::new(broadcast, Codec::H264);
+		for chunk in stream(SPS_1080P).chunks(5) {
+			import.parse(chunk).unwrap();
+		}
+		import.flush().unwrap();
+
+		let catalog = consumer.catalog.next().now_or_never().unwrap().unwrap().unwrap();
+		let video = &catalog.video[0];
+		assert_eq!(video.config.codec.to_string(), "avc1.640028");
+		assert_eq!(video.config.coded_width, Some(1920));
+		assert_eq!(video.config.coded_height, Some(1080));
+		assert_eq!(video.config.framerate, Some(60000.0 / 2002.0));
+
+		let mut track = consumer.subscribe(&video.track);
+
+		let frame = track.read().now_or_never().unwrap().unwrap().unwrap();
+		assert!(frame.keyframe);
+		assert_eq!(frame.timestamp, Timestamp::ZERO);
+		assert_eq!(
+			frame.payload.len(),
+			4 * 3 + SPS_1080P.len() + PPS.len() + 7,
+			"AUD should be removed"
+		);
+
+		let frame = track.read().now_or_never().unwrap().unwrap().unwrap();
+		assert!(!frame.keyframe);
+		assert_eq!(frame.timestamp, Timestamp::from_micros(33_367));
+		assert_eq!(
+			&frame.payload[..],
+			&[0, 0, 0, 3, 0x41, 0x9a, 0x02, 0, 0, 0, 3, 0x41, 0x00, 0x03]
+		);
+
+		// The trailing zero is removed from the last NAL.
+		let frame = track.read().now_or_never().unwrap().unwrap().unwrap();
+		assert_eq!(frame.timestamp, Timestamp::from_micros(66_733));
+		assert_eq!(&frame.payload[..], &[0, 0, 0, 3, 0x41, 0x9a, 0x04]);
+	}
+
+	#[tokio::test]
+	async fn framerate() {
+		// There's no timing info in this SPS.
+		let mut import = Import::new(BroadcastProducer::new(), Codec::H264);
+		assert!(matches!(import.parse(&stream(SPS_720P)), Err(Error::MissingFramerate)));
+
+		let broadcast = BroadcastProducer::new();
+		let mut consumer = broadcast.consume();
+
+		let mut import = Import::new(broadcast, Codec::H264).with_framerate(25.0);
+		import.parse(&stream(SPS_720P)).unwrap();
+		import.flush().unwrap();
+
+		let catalog = consumer.catalog.next().now_or_never().unwrap().unwrap().unwrap();
+		assert_eq!(catalog.video[0].config.framerate, Some(25.0));
+
+		let mut track = consumer.subscribe(&catalog.video[0].track);
+		track.read().now_or_never().unwrap().unwrap().unwrap();
+
+		let frame = track.read().now_or_never().unwrap().unwrap().unwrap();
+		assert_eq!(frame.timestamp, Timestamp::from_millis(40));
+	}
+
+	#[tokio::test]
+	async fn incremental() {
+		let broadcast = BroadcastProducer::new();
+		let mut consumer = broadcast.consume();
+
+		// Any data before the first start code is ignored, even if it's split across chunks.
+		let mut data = vec![0xff, 0, 0, 0xff, 0];
+		data.extend(stream(SPS_1080P));
+
+		let mut import = Import::new(broadcast, Codec::H264);
+		for chunk in data.chunks(1) {
+			import.parse(chunk).unwrap();
+		}
+		import.flush().unwrap();
+
+		let catalog = consumer.catalog.next().now_or_never().unwrap().unwrap().unwrap();
+		let mut track = consumer.subscribe(&catalog.video[0].track);
+
+		let frame = track.read().now_or_never().unwrap().unwrap().unwrap();
+		assert_eq!(frame.payload.len(), 4 * 3 + SPS_1080P.len() + PPS.len() + 7);
+
+		let frame = track.read().now_or_never().unwrap().unwrap().unwrap();
+		assert_eq!(
+			&frame.payload[..],
+			&[0, 0, 0, 3, 0x41, 0x9a, 0x02, 0, 0, 0, 3, 0x41, 0x00, 0x03]
+		);
+
+		let frame = track.read().now_or_never().unwrap().unwrap().unwrap();
+		assert_eq!(&frame.payload[..], &[0, 0, 0, 3, 0x41, 0x9a, 0x04]);
+	}
+
+	#[tokio::test]
+	async fn bframes() {
+		let mut data = stream(SPS_1080P);
+
+		// A B-slice, which would need to be presented before the previous picture.
+		data.extend_from_slice(&[0, 0, 1, 0x01, 0xa0, 0x04]);
+
+		let mut import = Import::new(BroadcastProducer::new(), Codec::H264);
+		import.parse(&data).unwrap();
+		assert!(matches!(import.flush(), Err(Error::BFrames)));
+	}
+}
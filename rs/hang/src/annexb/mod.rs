@@ -0,0 +1,124 @@
+//! Helpers for H.264 and H.265 Annex-B byte streams, where NAL units are separated by start codes.
+mod bits;
+mod error;
+mod import;
+mod video;
+
+pub mod h264;
+pub mod h265;
+
+use bits::*;
+pub use error::*;
+pub use import::*;
+pub use video::*;
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// The codec of an Annex-B byte stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+	H264,
+	H265,
+}
+
+impl Codec {
+	/// Returns true if the access unit contains a keyframe (IDR for H.264, IRAP for H.265).
+	pub fn is_keyframe(&self, nals: &[Bytes]) -> bool {
+		match self {
+			Self::H264 => h264::is_keyframe(nals),
+			Self::H265 => h265::is_keyframe(nals),
+		}
+	}
+
+	/// Returns true if the NAL unit contains a slice.
+	pub fn is_vcl(&self, nal: &[u8]) -> bool {
+		match self {
+			Self::H264 => h264::is_vcl(nal),
+			Self::H265 => h265::is_vcl(nal),
+		}
+	}
+
+	/// Returns true if the NAL unit would start a new access unit, if the current one already contains a slice.
+	pub fn is_first(&self, nal: &[u8]) -> bool {
+		match self {
+			Self::H264 => h264::is_first(nal),
+			Self::H265 => h265::is_first(nal),
+		}
+	}
+
+	/// Returns true if the NAL unit is an access unit delimiter.
+	pub fn is_aud(&self, nal: &[u8]) -> bool {
+		match self {
+			Self::H264 => h264::nal_type(nal) == Some(h264::NAL_AUD),
+			Self::H265 => h265::nal_type(nal) == Some(h265::NAL_AUD),
+		}
+	}
+}
+
+/// Returns the offset of the next 3-byte start code, starting the search at `from`.
+pub fn find_start_code(data: &[u8], from: usize) -> Option<usize> {
+	data.get(from..)?
+		.windows(3)
+		.position(|window| window == [0, 0, 1])
+		.map(|pos| from + pos)
+}
+
+/// Remove any trailing zeros from a NAL unit.
+///
+/// NAL units never end with a zero byte, so these are either part of a 4-byte start code or trailing_zero_8bits.
+pub fn trim(mut nal: Bytes) -> Bytes {
+	let len = nal.iter().rposition(|&b| b != 0).map(|i| i + 1).unwrap_or(0);
+	nal.truncate(len);
+	nal
+}
+
+/// Split an Annex-B byte stream into NAL units, without the start codes.
+///
+/// Any data before the first start code is ignored.
+pub fn split(data: &Bytes) -> Vec<Bytes> {
+	let mut nals = Vec::new();
+	let mut start = match find_start_code(data, 0) {
+		Some(start) => start + 3,
+		None => return nals,
+	};
+
+	while let Some(end) = find_start_code(data, start) {
+		nals.push(trim(data.slice(start..end)));
+		start = end + 3;
+	}
+
+	nals.push(trim(data.slice(start..)));
+	nals.retain(|nal| !nal.is_empty());
+	nals
+}
+
+/// Convert NAL units into the length-prefixed format used by avcC/hvcC, with 4 byte lengths.
+pub fn length_prefixed(nals: &[Bytes]) -> Bytes {
+	let size = nals.iter().map(|nal| 4 + nal.len()).sum();
+	let mut buf = BytesMut::with_capacity(size);
+
+	for nal in nals {
+		buf.put_u32(nal.len() as u32);
+		buf.put_slice(nal);
+	}
+
+	buf.freeze()
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn split_nals() {
+		let data = Bytes::from_static(&[
+			0xff, 0, 0, 0, 1, 0x67, 0x42, 0, 0, 1, 0x68, 0xce, 0, 0, 0, 1, 0x65, 0x88, 0, 0,
+		]);
+		let nals = split(&data);
+		assert_eq!(nals, [&[0x67, 0x42][..], &[0x68, 0xce], &[0x65, 0x88]]);
+
+		let prefixed = length_prefixed(&nals);
+		assert_eq!(&prefixed[..6], &[0, 0, 0, 2, 0x67, 0x42]);
+		assert_eq!(prefixed.len(), 18);
+	}
+}
@@ -0,0 +1,137 @@
+use bytes::Bytes;
+use moq_lite::Track;
+
+use super::{h264, h265, length_prefixed, split, Codec, Result};
+use crate::catalog::{Video, VideoConfig};
+use crate::model::{BroadcastProducer, Frame, Timestamp, TrackProducer};
+
+/// Converts Annex-B access units into hang frames.
+///
+/// The track is created on the first keyframe, once the parameter sets are known.
+pub struct VideoTrack {
+	codec: Codec,
+	name: String,
+	track: Option<TrackProducer>,
+
+	// The latest parameter sets.
+	vps: Option<Bytes>,
+	sps: Option<Bytes>,
+	pps: Option<Bytes>,
+
+	// Overrides the framerate signaled in the parameter sets.
+	framerate: Option<f64>,
+}
+
+impl VideoTrack {
+	pub fn new(codec: Codec, name: String) -> Self {
+		Self {
+			codec,
+			name,
+			track: None,
+			vps: None,
+			sps: None,
+			pps: None,
+			framerate: None,
+		}
+	}
+
+	/// Use the provided framerate in the catalog instead of any VUI timing info.
+	pub fn with_framerate(mut self, framerate: f64) -> Self {
+		self.framerate = Some(framerate);
+		self
+	}
+
+	/// Write an access unit, returning true if it was written as a keyframe.
+	///
+	/// The decode timestamp should be provided if it differs from the presentation timestamp (ex. B-frames).
+	/// Any frames before the first keyframe are dropped.
+	pub fn write(
+		&mut self,
+		broadcast: &mut BroadcastProducer,
+		timestamp: Timestamp,
+		decode_timestamp: Option<Timestamp>,
+		data: Bytes,
+	) -> Result<bool> {
+		self.write_nals(broadcast, timestamp, decode_timestamp, split(&data))
+	}
+
+	/// Write an access unit that has already been split into NAL units.
+	pub fn write_nals(
+		&mut self,
+		broadcast: &mut BroadcastProducer,
+		timestamp: Timestamp,
+		decode_timestamp: Option<Timestamp>,
+		mut nals: Vec<Bytes>,
+	) -> Result<bool> {
+		for nal in &nals {
+			let param = match self.codec {
+				Codec::H264 => match h264::nal_type(nal) {
+					Some(h264::NAL_SPS) => &mut self.sps,
+					Some(h264::NAL_PPS) => &mut self.pps,
+					_ => continue,
+				},
+				Codec::H265 => match h265::nal_type(nal) {
+					Some(h265::NAL_VPS) => &mut self.vps,
+					Some(h265::NAL_SPS) => &mut self.sps,
+					Some(h265::NAL_PPS) => &mut self.pps,
+					_ => continue,
+				},
+			};
+
+			param.replace(nal.clone());
+		}
+
+		// Access unit delimiters are not allowed in the length-prefixed format.
+		nals.retain(|nal| !self.codec.is_aud(nal));
+
+		let keyframe = self.codec.is_keyframe(&nals);
+
+		if self.track.is_none() {
+			if !keyframe {
+				tracing::trace!(name = ?self.name, "waiting for keyframe");
+				return Ok(false);
+			}
+
+			let Some(mut config) = self.config()? else {
+				tracing::warn!(name = ?self.name, "missing parameter sets before keyframe");
+				return Ok(false);
+			};
+
+			if self.framerate.is_some() {
+				config.framerate = self.framerate;
+			}
+
+			let track = Track {
+				name: self.name.clone(),
+				priority: 2,
+				..Default::default()
+			};
+
+			self.track = Some(broadcast.create_video(Video {
+				track,
+				config,
+				rendition_group: None,
+				index: None,
+			}));
+		}
+
+		let track = self.track.as_mut().expect("track was created");
+		track.write(Frame {
+			timestamp,
+			decode_timestamp,
+			duration: None,
+			keyframe,
+			payload: length_prefixed(&nals),
+		});
+
+		Ok(keyframe)
+	}
+
+	fn config(&self) -> Result<Option<VideoConfig>> {
+		Ok(match (self.codec, &self.vps, &self.sps, &self.pps) {
+			(Codec::H264, _, Some(sps), Some(pps)) => Some(h264::config(sps, pps)?),
+			(Codec::H265, Some(vps), Some(sps), Some(pps)) => Some(h265::config(vps, sps, pps)?),
+			_ => None,
+		})
+	}
+}
@@ -18,6 +18,10 @@ pub struct Audio {
 
 	// The configuration of the audio track
 	pub config: AudioConfig,
+
+	/// A track listing the timestamp and group of each keyframe, used to seek, see [crate::IndexProducer].
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub index: Option<moq_lite::Track>,
 }
 
 #[serde_with::serde_as]
@@ -0,0 +1,95 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::model::Timestamp;
+
+/// Maps media timestamps to wall clock time, used to measure latency and align broadcasts.
+///
+/// The publisher updates this periodically to correct for drift between the media and wall clocks.
+/// The accuracy depends on both sides having a synchronized clock, ex. via NTP.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Clock {
+	/// A media timestamp in microseconds.
+	pub timestamp: u64,
+
+	/// When the media at this timestamp was captured, in microseconds since the UNIX epoch (UTC).
+	pub utc: u64,
+}
+
+impl Clock {
+	pub fn new(timestamp: Timestamp, captured: SystemTime) -> Self {
+		let utc = captured.duration_since(UNIX_EPOCH).unwrap_or_default();
+
+		Self {
+			timestamp: timestamp.as_micros() as u64,
+			utc: utc.as_micros() as u64,
+		}
+	}
+
+	/// Anchor the media timestamp to the current time, ex. when it was just captured.
+	pub fn now(timestamp: Timestamp) -> Self {
+		Self::new(timestamp, SystemTime::now())
+	}
+
+	/// Return when the media at the given timestamp was captured.
+	pub fn capture_time(&self, timestamp: Timestamp) -> SystemTime {
+		let anchor = UNIX_EPOCH + Duration::from_micros(self.utc);
+		let timestamp = timestamp.as_micros() as u64;
+
+		match timestamp.checked_sub(self.timestamp) {
+			Some(ahead) => anchor + Duration::from_micros(ahead),
+			None => anchor - Duration::from_micros(self.timestamp - timestamp),
+		}
+	}
+
+	/// Return the media timestamp captured at the given time, or zero if it's before the start of the media.
+	pub fn timestamp(&self, captured: SystemTime) -> Timestamp {
+		let anchor = UNIX_EPOCH + Duration::from_micros(self.utc);
+		let timestamp = Timestamp::from_micros(self.timestamp);
+
+		match captured.duration_since(anchor) {
+			Ok(ahead) => timestamp + ahead,
+			Err(err) => timestamp.saturating_sub(err.duration()),
+		}
+	}
+
+	/// Convert a timestamp to the timeline of another broadcast, returning the timestamp captured at the same time.
+	pub fn align(&self, timestamp: Timestamp, other: &Clock) -> Timestamp {
+		other.timestamp(self.capture_time(timestamp))
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn convert() {
+		let captured = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+		let clock = Clock::new(Timestamp::from_secs(10), captured);
+
+		assert_eq!(
+			clock.capture_time(Timestamp::from_secs(12)),
+			captured + Duration::from_secs(2)
+		);
+		assert_eq!(
+			clock.capture_time(Timestamp::from_secs(9)),
+			captured - Duration::from_secs(1)
+		);
+		assert_eq!(
+			clock.timestamp(captured + Duration::from_millis(1500)),
+			Timestamp::from_millis(11_500)
+		);
+		assert_eq!(clock.timestamp(captured - Duration::from_secs(20)), Timestamp::ZERO);
+
+		// Another broadcast that started 3 seconds later.
+		let other = Clock::new(Timestamp::ZERO, captured + Duration::from_secs(3));
+		assert_eq!(clock.align(Timestamp::from_secs(15), &other), Timestamp::from_secs(2));
+		assert_eq!(other.align(Timestamp::from_secs(2), &clock), Timestamp::from_secs(15));
+
+		let json = serde_json::to_string(&clock).unwrap();
+		assert_eq!(json, r#"{"timestamp":10000000,"utc":1700000000000000}"#);
+	}
+}
@@ -0,0 +1,22 @@
+use serde::{Deserialize, Serialize};
+
+/// The layout of the header prefixed to each frame, used by every track in the catalog.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum Container {
+	/// The presentation timestamp in microseconds.
+	///
+	/// Used when the catalog doesn't specify a container, ex. by older publishers and archives.
+	Legacy,
+
+	/// The decode timestamp, composition offset and duration; see [crate::Frame].
+	#[default]
+	Native,
+}
+
+impl Container {
+	// Catalogs without a container predate the field.
+	pub(crate) fn legacy() -> Self {
+		Self::Legacy
+	}
+}
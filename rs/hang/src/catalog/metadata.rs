@@ -0,0 +1,31 @@
+use serde::{Deserialize, Serialize};
+
+/// A timed metadata track, such as ID3 tags, SCTE-35 splices or JSON events.
+///
+/// Each frame is an [crate::Event], aligned with the media timestamps.
+#[serde_with::serde_as]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Metadata {
+	// Generic information about the track
+	pub track: moq_lite::Track,
+
+	// The configuration of the metadata track
+	pub config: MetadataConfig,
+}
+
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MetadataConfig {
+	// Identifies the format of the events, ex. "https://aomedia.org/emsg/ID3" or "urn:scte:scte35:2013:bin".
+	pub scheme: String,
+
+	// An optional sub-scheme, with a meaning defined by the scheme.
+	#[serde(default)]
+	pub value: Option<String>,
+
+	// The MIME type of each event payload, ex. "application/json".
+	#[serde(default)]
+	pub mime_type: Option<String>,
+}
@@ -1,9 +1,19 @@
 mod audio;
+mod clock;
+mod container;
 mod location;
+mod metadata;
+mod patch;
 mod root;
+mod text;
 mod video;
 
 pub use audio::*;
+pub use clock::*;
+pub use container::*;
 pub use location::*;
+pub use metadata::*;
+pub use patch::*;
 pub use root::*;
+pub use text::*;
 pub use video::*;
@@ -0,0 +1,87 @@
+//! JSON merge patches (RFC 7386), used to send catalog updates without repeating the entire catalog.
+use serde_json::{Map, Value};
+
+/// Apply a merge patch to the target in place.
+pub fn merge_patch(target: &mut Value, patch: &Value) {
+	let Value::Object(patch) = patch else {
+		*target = patch.clone();
+		return;
+	};
+
+	if !target.is_object() {
+		*target = Value::Object(Map::new());
+	}
+
+	let target = target.as_object_mut().expect("just set to an object");
+
+	for (key, value) in patch {
+		if value.is_null() {
+			target.remove(key);
+		} else {
+			merge_patch(target.entry(key).or_insert(Value::Null), value);
+		}
+	}
+}
+
+/// Compute a merge patch that converts `old` into `new`, or None if they're identical.
+///
+/// NOTE: Merge patches can't set a value to null, so any nulls in `new` are removed instead.
+pub fn merge_diff(old: &Value, new: &Value) -> Option<Value> {
+	let (Value::Object(old), Value::Object(new)) = (old, new) else {
+		return (old != new).then(|| new.clone());
+	};
+
+	let mut patch = Map::new();
+
+	for key in old.keys() {
+		if !new.contains_key(key) {
+			patch.insert(key.clone(), Value::Null);
+		}
+	}
+
+	for (key, value) in new {
+		let diff = match old.get(key) {
+			Some(previous) => merge_diff(previous, value),
+			None => Some(value.clone()),
+		};
+
+		if let Some(diff) = diff {
+			patch.insert(key.clone(), diff);
+		}
+	}
+
+	(!patch.is_empty()).then_some(Value::Object(patch))
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use serde_json::json;
+
+	#[test]
+	fn rfc7386() {
+		let mut target = json!({"a": "b", "c": {"d": "e", "f": "g"}});
+		merge_patch(&mut target, &json!({"a": "z", "c": {"f": null}}));
+		assert_eq!(target, json!({"a": "z", "c": {"d": "e"}}));
+
+		// Arrays are replaced entirely.
+		let mut target = json!({"a": [1, 2]});
+		merge_patch(&mut target, &json!({"a": [3]}));
+		assert_eq!(target, json!({"a": [3]}));
+	}
+
+	#[test]
+	fn diff() {
+		let old = json!({"a": 1, "b": {"c": 2, "d": 3}, "e": [1]});
+		let new = json!({"a": 1, "b": {"c": 4}, "e": [1, 2], "f": true});
+
+		let patch = merge_diff(&old, &new).unwrap();
+		assert_eq!(patch, json!({"b": {"c": 4, "d": null}, "e": [1, 2], "f": true}));
+
+		let mut target = old.clone();
+		merge_patch(&mut target, &patch);
+		assert_eq!(target, new);
+
+		assert!(merge_diff(&new, &new).is_none());
+	}
+}
@@ -1,29 +1,49 @@
 //! This module contains the structs and functions for the MoQ catalog format
 use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures::FutureExt;
 
 /// The catalog format is a JSON file that describes the tracks available in a broadcast.
 use serde::{Deserialize, Serialize};
 
-use crate::catalog::{Audio, Video};
+use crate::catalog::{Audio, Clock, Container, Metadata, Text, Video};
+use crate::model::Timestamp;
 use crate::Result;
 
-use super::Location;
+use super::{merge_diff, merge_patch, Location};
 
 /// A catalog track, created by a broadcaster to describe the tracks available in a broadcast.
 #[serde_with::serde_as]
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
 #[serde(default, rename_all = "camelCase")]
 pub struct Catalog {
+	/// The layout of each frame header, defaulting to [Container::Legacy] when missing.
+	#[serde(default = "Container::legacy")]
+	pub container: Container,
+
 	#[serde(default, skip_serializing_if = "Vec::is_empty")]
 	pub video: Vec<Video>,
 
 	#[serde(default, skip_serializing_if = "Vec::is_empty")]
 	pub audio: Vec<Audio>,
 
+	/// Subtitles, captions and other timed text.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub text: Vec<Text>,
+
+	/// Timed metadata, such as ID3 tags, SCTE-35 splices or JSON events.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub metadata: Vec<Metadata>,
+
 	/// A location track, used to indicate the desired position of the broadcaster from -1 to 1.
 	/// This is primarily used for audio panning but can also be used for video.
 	#[serde(default, skip_serializing_if = "Option::is_none")]
 	pub location: Option<Location>,
+
+	/// Maps media timestamps to wall clock time, used to measure the end-to-end latency.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub clock: Option<Clock>,
 }
 
 impl Catalog {
@@ -58,10 +78,26 @@ impl Catalog {
 		Ok(serde_json::to_writer(writer, self)?)
 	}
 
+	/// Return the video tracks in a rendition group, in ascending order of bitrate and resolution.
+	///
+	/// Tracks without a bitrate are last, in ascending order of resolution; see [crate::catalog::VideoConfig::quality].
+	pub fn renditions(&self, group: &str) -> Vec<Video> {
+		let mut renditions: Vec<Video> = self
+			.video
+			.iter()
+			.filter(|video| video.rendition_group.as_deref() == Some(group))
+			.cloned()
+			.collect();
+
+		renditions.sort_by_key(|video| video.config.quality());
+		renditions
+	}
+
 	pub fn produce(self) -> CatalogProducer {
 		let track = moq_lite::Track {
 			name: Catalog::DEFAULT_NAME.to_string(),
 			priority: 100,
+			..Default::default()
 		}
 		.produce();
 
@@ -69,16 +105,37 @@ impl Catalog {
 	}
 }
 
+// How often to update the clock in the catalog, correcting for drift.
+const CLOCK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Produces the catalog track.
+///
+/// Each group starts with the full catalog, followed by JSON merge patches (RFC 7386) for any updates.
+/// A new group is started once the patches are larger than the full catalog, so late joiners don't download too much.
 #[derive(Clone)]
 pub struct CatalogProducer {
 	pub track: moq_lite::TrackProducer,
 	current: Arc<Mutex<Catalog>>,
+	published: Arc<Mutex<Published>>,
+}
+
+#[derive(Default)]
+struct Published {
+	group: Option<moq_lite::GroupProducer>,
+
+	// The catalog as of the last frame, used to compute the next patch.
+	json: serde_json::Value,
+
+	// The size of the full catalog and the patches in the current group.
+	full: usize,
+	patches: usize,
 }
 
 impl CatalogProducer {
 	pub fn new(track: moq_lite::TrackProducer, init: Catalog) -> Self {
 		Self {
 			current: Arc::new(Mutex::new(init)),
+			published: Default::default(),
 			track,
 		}
 	}
@@ -93,19 +150,67 @@ impl CatalogProducer {
 		current.audio.push(audio);
 	}
 
+	pub fn add_text(&mut self, text: Text) {
+		let mut current = self.current.lock().unwrap();
+		current.text.push(text);
+	}
+
+	pub fn add_metadata(&mut self, metadata: Metadata) {
+		let mut current = self.current.lock().unwrap();
+		current.metadata.push(metadata);
+	}
+
 	pub fn set_location(&mut self, location: Option<Location>) {
 		let mut current = self.current.lock().unwrap();
 		current.location = location;
 	}
 
+	pub fn set_clock(&mut self, clock: Option<Clock>) {
+		let mut current = self.current.lock().unwrap();
+		current.clock = clock;
+	}
+
+	/// Anchor the timestamp to the current time, publishing it if the previous anchor is old enough.
+	pub(crate) fn report_clock(&mut self, timestamp: Timestamp) {
+		let now = SystemTime::now();
+
+		{
+			let mut current = self.current.lock().unwrap();
+			let recent = current.clock.is_some_and(|clock| {
+				let reported = UNIX_EPOCH + Duration::from_micros(clock.utc);
+				now.duration_since(reported)
+					.is_ok_and(|elapsed| elapsed < CLOCK_INTERVAL)
+			});
+
+			if recent {
+				return;
+			}
+
+			current.clock = Some(Clock::new(timestamp, now));
+		}
+
+		self.publish();
+	}
+
+	// Entries are matched by track, so they can be removed after the config has been updated.
 	pub fn remove_video(&mut self, video: &Video) {
 		let mut current = self.current.lock().unwrap();
-		current.video.retain(|v| v != video);
+		current.video.retain(|v| v.track != video.track);
 	}
 
 	pub fn remove_audio(&mut self, audio: &Audio) {
 		let mut current = self.current.lock().unwrap();
-		current.audio.retain(|a| a != audio);
+		current.audio.retain(|a| a.track != audio.track);
+	}
+
+	pub fn remove_text(&mut self, text: &Text) {
+		let mut current = self.current.lock().unwrap();
+		current.text.retain(|t| t.track != text.track);
+	}
+
+	pub fn remove_metadata(&mut self, metadata: &Metadata) {
+		let mut current = self.current.lock().unwrap();
+		current.metadata.retain(|m| m.track != metadata.track);
 	}
 
 	// Just grab a lock to the current catalog, so you can update it manually.
@@ -113,15 +218,44 @@ impl CatalogProducer {
 		self.current.lock().unwrap()
 	}
 
-	/// Publish any changes to the catalog.
+	/// Publish any changes to the catalog, as a merge patch if possible.
 	pub fn publish(&mut self) {
 		let current = self.current.lock().unwrap();
-		let mut group = self.track.append_group();
 
 		// TODO decide if this should return an error, or be impossible to fail
-		let frame = current.to_string().expect("invalid catalog");
+		let json = serde_json::to_value(&*current).expect("invalid catalog");
+
+		let mut published = self.published.lock().unwrap();
+		let published = &mut *published;
+
+		if let Some(group) = published.group.as_mut() {
+			let Some(patch) = merge_diff(&published.json, &json) else {
+				// Nothing changed.
+				return;
+			};
+
+			let frame = patch.to_string();
+			if published.patches + frame.len() <= published.full {
+				published.patches += frame.len();
+				published.json = json;
+				group.write_frame(frame);
+				return;
+			}
+		}
+
+		if let Some(group) = published.group.take() {
+			group.finish();
+		}
+
+		let frame = json.to_string();
+		let mut group = self.track.append_group();
+
+		published.full = frame.len();
+		published.patches = 0;
+		published.json = json;
+
 		group.write_frame(frame);
-		group.finish();
+		published.group = Some(group);
 	}
 
 	pub fn consume(&self) -> CatalogConsumer {
@@ -129,6 +263,10 @@ impl CatalogProducer {
 	}
 
 	pub fn finish(self) {
+		if let Some(group) = self.published.lock().unwrap().group.take() {
+			group.finish();
+		}
+
 		self.track.finish();
 	}
 }
@@ -144,6 +282,7 @@ impl Default for CatalogProducer {
 		let track = moq_lite::Track {
 			name: Catalog::DEFAULT_NAME.to_string(),
 			priority: 100,
+			..Default::default()
 		}
 		.produce();
 
@@ -151,15 +290,23 @@ impl Default for CatalogProducer {
 	}
 }
 
+/// Consumes the catalog track, applying any merge patches to return the full catalog.
 #[derive(Clone)]
 pub struct CatalogConsumer {
 	pub track: moq_lite::TrackConsumer,
 	group: Option<moq_lite::GroupConsumer>,
+
+	// The catalog as of the last frame in the current group.
+	current: Option<serde_json::Value>,
 }
 
 impl CatalogConsumer {
 	pub fn new(track: moq_lite::TrackConsumer) -> Self {
-		Self { track, group: None }
+		Self {
+			track,
+			group: None,
+			current: None,
+		}
 	}
 
 	pub async fn next(&mut self) -> Result<Option<Catalog>> {
@@ -168,8 +315,9 @@ impl CatalogConsumer {
 				res = self.track.next_group() => {
 					match res? {
 						Some(group) => {
-							// Use the new group.
+							// Use the new group, which starts with the full catalog.
 							self.group = Some(group);
+							self.current = None;
 						}
 						None => {
 							// The track has ended, so we should return None.
@@ -178,14 +326,36 @@ impl CatalogConsumer {
 					}
 				},
 				Some(frame) = async { self.group.as_mut()?.read_frame().await.transpose() } => {
-					self.group.take(); // We don't support deltas yet
-					let catalog = Catalog::from_slice(&frame?)?;
-					return Ok(Some(catalog));
+					self.apply(&frame?)?;
+
+					// Apply any patches that are already available, so late joiners skip the intermediate catalogs.
+					while let Some(frame) = self.group.as_mut().and_then(|group| group.read_frame().now_or_never()) {
+						match frame? {
+							Some(frame) => self.apply(&frame)?,
+							None => break,
+						}
+					}
+
+					let current = self.current.clone().expect("frame was applied");
+					return Ok(Some(serde_json::from_value(current)?));
 				}
 			}
 		}
 	}
 
+	fn apply(&mut self, frame: &[u8]) -> Result<()> {
+		let frame: serde_json::Value = serde_json::from_slice(frame)?;
+
+		match self.current.as_mut() {
+			// Any subsequent frames are merge patches.
+			Some(current) => merge_patch(current, &frame),
+			// The first frame in each group is the full catalog.
+			None => self.current = Some(frame),
+		};
+
+		Ok(())
+	}
+
 	pub async fn closed(&self) -> Result<()> {
 		Ok(self.track.closed().await?)
 	}
@@ -204,9 +374,140 @@ mod test {
 
 	use super::*;
 
+	fn video(name: &str) -> Video {
+		Video {
+			track: Track {
+				name: name.to_string(),
+				priority: 1,
+				..Default::default()
+			},
+			config: VideoConfig {
+				codec: H264 {
+					profile: 0x64,
+					constraints: 0x00,
+					level: 0x1f,
+				}
+				.into(),
+				description: None,
+				coded_width: Some(1280),
+				coded_height: Some(720),
+				display_ratio_width: None,
+				display_ratio_height: None,
+				bitrate: None,
+				framerate: None,
+				optimize_for_latency: None,
+				color_space: None,
+				rotation: None,
+				flip: None,
+			},
+			rendition_group: None,
+			index: None,
+		}
+	}
+
+	fn next(consumer: &mut CatalogConsumer) -> Catalog {
+		consumer.next().now_or_never().unwrap().unwrap().unwrap()
+	}
+
+	#[tokio::test]
+	async fn patches() {
+		let mut producer = Catalog::default().produce();
+		let mut consumer = producer.consume();
+
+		producer.add_video(video("a"));
+		producer.add_video(video("b"));
+		producer.publish();
+		assert_eq!(next(&mut consumer).video, [video("a"), video("b")]);
+
+		producer.set_location(Some(Location {
+			handle: Some(1),
+			..Default::default()
+		}));
+		producer.publish();
+		assert_eq!(next(&mut consumer).location.unwrap().handle, Some(1));
+
+		producer.update().location.as_mut().unwrap().handle = Some(2);
+		producer.publish();
+		assert_eq!(next(&mut consumer).location.unwrap().handle, Some(2));
+
+		// Arrays are replaced entirely, so this patch contains the remaining video.
+		producer.remove_video(&video("a"));
+		producer.publish();
+		let catalog = next(&mut consumer);
+		assert_eq!(catalog.video, [video("b")]);
+		assert_eq!(catalog.location.unwrap().handle, Some(2));
+
+		// Publishing without any changes is a no-op.
+		producer.publish();
+		assert!(consumer.next().now_or_never().is_none());
+
+		// The updates were sent as patches in the same group.
+		let mut track = producer.track.consume();
+		let mut group = track.next_group().now_or_never().unwrap().unwrap().unwrap();
+		assert_eq!(group.info.sequence, 0);
+
+		let full = group.read_frame().now_or_never().unwrap().unwrap().unwrap();
+		assert_eq!(Catalog::from_slice(&full).unwrap().video.len(), 2);
+
+		let patch = group.read_frame().now_or_never().unwrap().unwrap().unwrap();
+		let patch: serde_json::Value = serde_json::from_slice(&patch).unwrap();
+		assert_eq!(patch["location"]["handle"], 1);
+		assert!(patch.get("video").is_none());
+
+		let patch = group.read_frame().now_or_never().unwrap().unwrap().unwrap();
+		let patch: serde_json::Value = serde_json::from_slice(&patch).unwrap();
+		assert_eq!(patch, serde_json::json!({"location": {"handle": 2}}));
+	}
+
+	#[tokio::test]
+	async fn late_join() {
+		let mut producer = Catalog::default().produce();
+
+		producer.add_video(video("a"));
+		producer.publish();
+		producer.add_video(video("b"));
+		producer.publish();
+		producer.remove_video(&video("a"));
+		producer.set_location(Some(Default::default()));
+		producer.publish();
+
+		// A late joiner gets the merged catalog immediately.
+		let mut consumer = producer.consume();
+		let catalog = next(&mut consumer);
+		assert_eq!(catalog.video, [video("b")]);
+		assert!(catalog.location.is_some());
+		assert!(consumer.next().now_or_never().is_none());
+	}
+
+	#[tokio::test]
+	async fn new_group() {
+		let mut producer = Catalog::default().produce();
+		let mut consumer = producer.consume();
+
+		producer.add_video(video("a"));
+		producer.publish();
+		next(&mut consumer);
+
+		// Keep changing the catalog until the patches are larger than the full catalog.
+		for i in 0..20 {
+			producer.update().video[0].config.bitrate = Some(i);
+			producer.publish();
+			assert_eq!(next(&mut consumer).video[0].config.bitrate, Some(i));
+		}
+
+		let mut track = producer.track.consume();
+		let group = track.next_group().now_or_never().unwrap().unwrap().unwrap();
+		assert!(group.info.sequence > 0);
+
+		// A late joiner still gets the latest catalog.
+		let mut consumer = producer.consume();
+		assert_eq!(next(&mut consumer).video[0].config.bitrate, Some(19));
+	}
+
 	#[test]
 	fn simple() {
 		let mut encoded = r#"{
+			"container": "native",
 			"video": [
 				{
 					"track": {
@@ -246,6 +547,7 @@ mod test {
 				track: Track {
 					name: "video".to_string(),
 					priority: 1,
+					..Default::default()
 				},
 				config: VideoConfig {
 					codec: H264 {
@@ -262,14 +564,18 @@ mod test {
 					bitrate: Some(6_000_000),
 					framerate: Some(30.0),
 					optimize_for_latency: None,
+					color_space: None,
 					rotation: None,
 					flip: None,
 				},
+				rendition_group: None,
+				index: None,
 			}],
 			audio: vec![Audio {
 				track: Track {
 					name: "audio".to_string(),
 					priority: 2,
+					..Default::default()
 				},
 				config: AudioConfig {
 					codec: Opus,
@@ -278,6 +584,7 @@ mod test {
 					bitrate: Some(128_000),
 					description: None,
 				},
+				index: None,
 			}],
 			..Default::default()
 		};
@@ -288,4 +595,13 @@ mod test {
 		let output = decoded.to_string().expect("failed to encode");
 		assert_eq!(encoded, output, "wrong encoded output");
 	}
+
+	#[test]
+	fn legacy() {
+		// Catalogs from older publishers don't have a container, so their frames use the old header.
+		let catalog = Catalog::from_str(r#"{"video":[]}"#).expect("failed to decode");
+		assert_eq!(catalog.container, Container::Legacy);
+
+		assert_eq!(Catalog::default().container, Container::Native);
+	}
 }
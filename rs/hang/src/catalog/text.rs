@@ -0,0 +1,87 @@
+use std::str::FromStr;
+
+use bytes::Bytes;
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+use serde_with::{hex::Hex, DisplayFromStr};
+
+use crate::Error;
+
+/// A timed text track, such as subtitles or captions.
+///
+/// Each frame is a [crate::Cue], with the payload in the format of the codec.
+#[serde_with::serde_as]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Text {
+	// Generic information about the track
+	pub track: moq_lite::Track,
+
+	// The configuration of the text track
+	pub config: TextConfig,
+}
+
+#[serde_with::serde_as]
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TextConfig {
+	// The format of each cue payload.
+	#[serde_as(as = "DisplayFromStr")]
+	pub codec: TextCodec,
+
+	// The language of the text as a BCP 47 tag, ex. "en-US".
+	#[serde(default)]
+	pub language: Option<String>,
+
+	// How the text is meant to be used, matching the HTML track kinds.
+	#[serde(default)]
+	pub kind: TextKind,
+
+	// A human readable label, ex. "English (SDH)".
+	#[serde(default)]
+	pub label: Option<String>,
+
+	// Any codec specific configuration, ex. the WebVTT file header or the tx3g sample entry.
+	#[serde(default)]
+	#[serde_as(as = "Option<Hex>")]
+	pub description: Option<Bytes>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Display)]
+pub enum TextCodec {
+	/// WebVTT cues, each payload is an ISO/IEC 14496-30 sample (vttc/vtte boxes).
+	#[display("wvtt")]
+	WebVtt,
+
+	/// 3GPP timed text, each payload is a tx3g sample (length prefixed text + modifiers).
+	#[display("tx3g")]
+	Tx3g,
+
+	#[display("{_0}")]
+	Unknown(String),
+}
+
+impl FromStr for TextCodec {
+	type Err = Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Ok(match s {
+			"wvtt" => Self::WebVtt,
+			"tx3g" => Self::Tx3g,
+			_ => Self::Unknown(s.to_string()),
+		})
+	}
+}
+
+/// The HTML track kind.
+/// https://html.spec.whatwg.org/multipage/media.html#attr-track-kind
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TextKind {
+	#[default]
+	Subtitles,
+	Captions,
+	Descriptions,
+	Chapters,
+}
@@ -0,0 +1,66 @@
+use serde::{Deserialize, Serialize};
+
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+/// VideoColorSpaceInit from WebCodecs
+/// https://w3c.github.io/webcodecs/#video-color-space-interface
+pub struct VideoColorSpace {
+	/// The color primaries, ex. "bt709" or "bt2020".
+	#[serde(default)]
+	pub primaries: Option<String>,
+
+	/// The transfer characteristics, ex. "bt709", "pq" or "hlg".
+	#[serde(default)]
+	pub transfer: Option<String>,
+
+	/// The matrix coefficients, ex. "bt709" or "bt2020-ncl".
+	#[serde(default)]
+	pub matrix: Option<String>,
+
+	/// If true, the full range of values is used instead of the limited (video) range.
+	#[serde(default)]
+	pub full_range: Option<bool>,
+}
+
+impl VideoColorSpace {
+	/// Convert the ITU-T H.273 code points used by the colr box, the H.264 VUI and vpcC.
+	///
+	/// Any values without a WebCodecs equivalent, including "unspecified", are left as None.
+	pub fn from_cicp(primaries: u8, transfer: u8, matrix: u8, full_range: bool) -> Self {
+		let primaries = match primaries {
+			1 => Some("bt709"),
+			5 => Some("bt470bg"),
+			6 => Some("smpte170m"),
+			9 => Some("bt2020"),
+			12 => Some("smpte432"),
+			_ => None,
+		};
+
+		let transfer = match transfer {
+			1 => Some("bt709"),
+			6 => Some("smpte170m"),
+			8 => Some("linear"),
+			13 => Some("iec61966-2-1"),
+			16 => Some("pq"),
+			18 => Some("hlg"),
+			_ => None,
+		};
+
+		let matrix = match matrix {
+			0 => Some("rgb"),
+			1 => Some("bt709"),
+			5 => Some("bt470bg"),
+			6 => Some("smpte170m"),
+			9 => Some("bt2020-ncl"),
+			_ => None,
+		};
+
+		Self {
+			primaries: primaries.map(Into::into),
+			transfer: transfer.map(Into::into),
+			matrix: matrix.map(Into::into),
+			full_range: Some(full_range),
+		}
+	}
+}
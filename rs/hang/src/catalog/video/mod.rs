@@ -1,11 +1,13 @@
 mod av1;
 mod codec;
+mod color;
 mod h264;
 mod h265;
 mod vp9;
 
 pub use av1::*;
 pub use codec::*;
+pub use color::*;
 pub use h264::*;
 pub use h265::*;
 pub use vp9::*;
@@ -25,6 +27,16 @@ pub struct Video {
 
 	/// The configuration of the video track
 	pub config: VideoConfig,
+
+	/// Tracks with the same rendition group are alternate encodings of the same source, ex. an ABR ladder.
+	///
+	/// A consumer should pick one track from each group, using the bitrate and resolution in the config.
+	#[serde(default)]
+	pub rendition_group: Option<String>,
+
+	/// A track listing the timestamp and group of each keyframe, used to seek, see [crate::IndexProducer].
+	#[serde(default)]
+	pub index: Option<moq_lite::Track>,
 }
 
 #[serde_with::serde_as]
@@ -61,7 +73,12 @@ pub struct VideoConfig {
 	pub display_ratio_width: Option<u32>,
 	pub display_ratio_height: Option<u32>,
 
-	// TODO color space
+	/// The color space of the media, used to render HDR correctly.
+	///
+	/// If not provided, the decoder uses the color space in the bitstream, or assumes bt709.
+	#[serde(default)]
+	pub color_space: Option<VideoColorSpace>,
+
 	/// The maximum bitrate of the video track, if known.
 	#[serde(default)]
 	pub bitrate: Option<u64>,
@@ -86,3 +103,13 @@ pub struct VideoConfig {
 	#[serde(default)]
 	pub flip: Option<bool>,
 }
+
+impl VideoConfig {
+	/// A sort key for comparing renditions: the bitrate, then the number of pixels.
+	///
+	/// Renditions without a bitrate can't be compared by bitrate, so they sort after the rest by the number of pixels.
+	pub fn quality(&self) -> (bool, u64, u64) {
+		let pixels = self.coded_width.unwrap_or_default() as u64 * self.coded_height.unwrap_or_default() as u64;
+		(self.bitrate.is_none(), self.bitrate.unwrap_or_default(), pixels)
+	}
+}
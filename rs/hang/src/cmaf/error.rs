@@ -9,6 +9,12 @@ pub enum Error {
 	#[error("karp error: {0}")]
 	Karp(#[from] crate::Error),
 
+	#[error("annexb error: {0}")]
+	Annexb(#[from] crate::annexb::Error),
+
+	#[error("ogg error: {0}")]
+	Ogg(#[from] crate::ogg::Error),
+
 	#[error("missing tracks")]
 	MissingTracks,
 
@@ -30,6 +36,9 @@ pub enum Error {
 	#[error("unsupported codec: {0}")]
 	UnsupportedCodec(String),
 
+	#[error("missing description")]
+	MissingDescription,
+
 	#[error("missing codec")]
 	MissingCodec,
 
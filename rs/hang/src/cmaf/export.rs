@@ -1,27 +1,1253 @@
-use super::Result;
-use tokio::io::AsyncWrite;
+use super::{text::empty_sample, Error, Result};
+use crate::annexb;
+use crate::catalog::{Audio, AudioCodec, Catalog, Container, Text, TextCodec, Video, VideoCodec};
+use crate::model::{BroadcastConsumer, Cue, Frame, GroupConsumer, Timestamp};
+use crate::ogg::OpusHead;
+use bytes::Bytes;
+use mp4_atom::{Atom, Decode, Encode};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+use web_async::spawn;
 
-use crate::{catalog, media};
+// All timestamps are in microseconds, so use that as the timescale for every track.
+const TIMESCALE: u64 = 1_000_000;
 
-// Converts Karp -> fMP4
+// https://chromium.googlesource.com/chromium/src/media/+/master/formats/mp4/track_run_iterator.cc#177
+const SAMPLE_FLAGS_KEYFRAME: u32 = 0x0200_0000; // kSampleDependsOnNoOther
+const SAMPLE_FLAGS_DELTA: u32 = 0x0101_0000; // kSampleDependsOnOthers | kSampleIsNonSyncSample
+
+/// Converts Karp -> fMP4
+///
+/// The init segment is produced from the first catalog, so any tracks added later are ignored.
 pub struct Export<W: AsyncWrite + Unpin> {
 	output: W,
-	broadcast: media::BroadcastConsumer,
+	broadcast: BroadcastConsumer,
+	catalog: Catalog,
+
+	// The tracks in the init segment, in order of their track ID.
+	tracks: Vec<ExportTrack>,
+
+	// The mfhd sequence number of the next fragment.
+	sequence: u32,
+}
+
+struct ExportTrack {
+	track: moq_lite::Track,
+
+	// Set for text tracks, whose frames are cues.
+	text: Option<TextCodec>,
+
+	// Set for H.264/H.265 tracks without a description, whose frames are Annex-B with in-band parameter sets.
+	annexb: Option<annexb::Codec>,
+}
+
+// A group of samples for a single track, written as a moof + mdat pair.
+struct Fragment {
+	track_id: u32,
+
+	// The decode timestamp of the first sample.
+	base: Timestamp,
+	samples: Vec<Sample>,
+}
+
+struct Sample {
+	duration: u32,
+
+	// The presentation timestamp minus the decode timestamp, which is negative for some B-frames.
+	offset: i32,
+
+	keyframe: bool,
+	payload: Bytes,
+}
+
+impl Fragment {
+	// The sample durations are the difference between decode timestamps, unless the frame has its own.
+	// The last frame uses the first decode timestamp of the next group, or the previous duration at the end of the track.
+	fn new(track_id: u32, frames: Vec<Frame>, next: Option<Timestamp>, prev: u32) -> Self {
+		let mut durations: Vec<u32> = frames
+			.windows(2)
+			.map(|pair| match pair[0].duration {
+				Some(duration) => micros(duration),
+				None => micros(pair[1].dts().saturating_sub(pair[0].dts())),
+			})
+			.collect();
+
+		let last = &frames[frames.len() - 1];
+		let last = match (last.duration, next) {
+			(Some(duration), _) => micros(duration),
+			(None, Some(next)) => micros(next.saturating_sub(last.dts())),
+			(None, None) => durations.last().copied().unwrap_or(prev),
+		};
+		durations.push(last);
+
+		Self {
+			track_id,
+			base: frames[0].dts(),
+			samples: frames
+				.into_iter()
+				.zip(durations)
+				.map(|(frame, duration)| Sample {
+					duration,
+					offset: (frame.timestamp.as_micros() as i64 - frame.dts().as_micros() as i64)
+						.clamp(i32::MIN as i64, i32::MAX as i64) as i32,
+					keyframe: frame.keyframe,
+					payload: frame.payload,
+				})
+				.collect(),
+		}
+	}
 }
 
-// TODO
 impl<W: AsyncWrite + Unpin> Export<W> {
-	pub async fn init(input: moq_lite::BroadcastConsumer, output: W) -> Result<Self> {
-		let broadcast = media::BroadcastConsumer::load(input).await?;
-		Ok(Self { broadcast, output })
+	/// Wait for the catalog, then write the init segment (ftyp + moov) to the output.
+	///
+	/// H.264/H.265 tracks without a description also wait for a keyframe, using its parameter sets instead.
+	pub async fn init(mut broadcast: BroadcastConsumer, mut output: W) -> Result<Self> {
+		let catalog = broadcast.catalog.next().await?.ok_or(Error::Closed)?;
+
+		let mut tracks = Vec::new();
+
+		// The encoded trak atoms, since mp4-atom can't encode text tracks.
+		let mut traks = Vec::new();
+
+		for video in &catalog.video {
+			let track_id = tracks.len() as u32 + 1;
+
+			let annexb = match (&video.config.codec, &video.config.description) {
+				(VideoCodec::H264(_), None) => Some(annexb::Codec::H264),
+				(VideoCodec::H265(_), None) => Some(annexb::Codec::H265),
+				_ => None,
+			};
+
+			match annexb {
+				Some(codec) => {
+					let mut video = video.clone();
+					video.config.description =
+						Some(Self::describe(&broadcast, &video, catalog.container, codec).await?);
+					Self::init_video(track_id, &video)?.encode(&mut traks)?;
+				}
+				None => Self::init_video(track_id, video)?.encode(&mut traks)?,
+			}
+
+			tracks.push(ExportTrack {
+				track: video.track.clone(),
+				text: None,
+				annexb,
+			});
+		}
+
+		for audio in &catalog.audio {
+			let track_id = tracks.len() as u32 + 1;
+			Self::init_audio(track_id, audio)?.encode(&mut traks)?;
+			tracks.push(ExportTrack {
+				track: audio.track.clone(),
+				text: None,
+				annexb: None,
+			});
+		}
+
+		for text in &catalog.text {
+			let track_id = tracks.len() as u32 + 1;
+			Self::init_text(track_id, text, &mut traks)?;
+			tracks.push(ExportTrack {
+				track: text.track.clone(),
+				text: Some(text.config.codec.clone()),
+				annexb: None,
+			});
+		}
+
+		if tracks.is_empty() {
+			return Err(Error::MissingTracks);
+		}
+
+		let count = tracks.len() as u32;
+
+		let ftyp = mp4_atom::Ftyp {
+			major_brand: b"iso6".into(),
+			minor_version: 0,
+			compatible_brands: vec![b"iso6".into(), b"cmfc".into(), b"mp41".into()],
+		};
+
+		let mvhd = mp4_atom::Mvhd {
+			next_track_id: count + 1,
+			..Default::default()
+		};
+
+		let mvex = mp4_atom::Mvex {
+			mehd: None,
+			trex: (1..=count)
+				.map(|track_id| mp4_atom::Trex {
+					track_id,
+					default_sample_description_index: 1,
+					..Default::default()
+				})
+				.collect(),
+		};
+
+		let mut moov = Vec::new();
+		mvhd.encode(&mut moov)?;
+		mvex.encode(&mut moov)?;
+		moov.extend_from_slice(&traks);
+
+		let mut buffer = Vec::new();
+		ftyp.encode(&mut buffer)?;
+		encode_box(&mut buffer, b"moov", &moov)?;
+
+		output.write_all(&buffer).await?;
+		output.flush().await?;
+
+		Ok(Self {
+			output,
+			broadcast,
+			catalog,
+			tracks,
+			sequence: 1,
+		})
+	}
+
+	// Build the avcC/hvcC from the parameter sets at the start of the latest group.
+	async fn describe(
+		broadcast: &BroadcastConsumer,
+		video: &Video,
+		container: Container,
+		codec: annexb::Codec,
+	) -> Result<Bytes> {
+		let mut track = broadcast.subscribe(&video.track);
+		track.set_container(container);
+
+		let frame = track.read().await?.ok_or(Error::Closed)?;
+		let nals = annexb::split(&frame.payload);
+
+		let config = match codec {
+			annexb::Codec::H264 => {
+				let find = |kind| nals.iter().find(|nal| annexb::h264::nal_type(nal) == Some(kind));
+				let sps = find(annexb::h264::NAL_SPS).ok_or(Error::MissingDescription)?;
+				let pps = find(annexb::h264::NAL_PPS).ok_or(Error::MissingDescription)?;
+				annexb::h264::config(sps, pps)?
+			}
+			annexb::Codec::H265 => {
+				let find = |kind| nals.iter().find(|nal| annexb::h265::nal_type(nal) == Some(kind));
+				let vps = find(annexb::h265::NAL_VPS).ok_or(Error::MissingDescription)?;
+				let sps = find(annexb::h265::NAL_SPS).ok_or(Error::MissingDescription)?;
+				let pps = find(annexb::h265::NAL_PPS).ok_or(Error::MissingDescription)?;
+				annexb::h265::config(vps, sps, pps)?
+			}
+		};
+
+		config.description.ok_or(Error::MissingDescription)
+	}
+
+	fn init_trak(track_id: u32, handler: &[u8; 4], name: &str, minf: mp4_atom::Minf) -> mp4_atom::Trak {
+		mp4_atom::Trak {
+			tkhd: mp4_atom::Tkhd {
+				track_id,
+				enabled: true,
+				..Default::default()
+			},
+			edts: None,
+			meta: None,
+			mdia: mp4_atom::Mdia {
+				mdhd: mp4_atom::Mdhd {
+					timescale: TIMESCALE as u32,
+					language: "und".to_string(),
+					..Default::default()
+				},
+				hdlr: mp4_atom::Hdlr {
+					handler: handler.into(),
+					name: name.to_string(),
+				},
+				minf,
+			},
+		}
+	}
+
+	fn init_minf(codec: mp4_atom::Codec) -> mp4_atom::Minf {
+		mp4_atom::Minf {
+			dinf: mp4_atom::Dinf {
+				dref: mp4_atom::Dref {
+					urls: vec![mp4_atom::Url::default()],
+				},
+			},
+			stbl: mp4_atom::Stbl {
+				stsd: mp4_atom::Stsd { codecs: vec![codec] },
+				stco: Some(Default::default()),
+				..Default::default()
+			},
+			..Default::default()
+		}
+	}
+
+	fn init_video(track_id: u32, video: &Video) -> Result<mp4_atom::Trak> {
+		let config = &video.config;
+
+		let visual = mp4_atom::Visual {
+			data_reference_index: 1,
+			width: config.coded_width.unwrap_or_default() as _,
+			height: config.coded_height.unwrap_or_default() as _,
+			..Default::default()
+		};
+
+		let codec = match &config.codec {
+			VideoCodec::H264(_) => {
+				let description = config.description.as_ref().ok_or(Error::MissingDescription)?;
+				let avcc = mp4_atom::Avcc::decode_body(&mut description.as_ref())?;
+
+				mp4_atom::Avc1 {
+					visual: visual.clone(),
+					avcc,
+					..Default::default()
+				}
+				.into()
+			}
+			VideoCodec::H265(h265) => {
+				let description = config.description.as_ref().ok_or(Error::MissingDescription)?;
+				let hvcc = mp4_atom::Hvcc::decode_body(&mut description.as_ref())?;
+
+				match h265.in_band {
+					true => mp4_atom::Hev1 {
+						visual: visual.clone(),
+						hvcc,
+						..Default::default()
+					}
+					.into(),
+					false => mp4_atom::Hvc1 {
+						visual: visual.clone(),
+						hvcc,
+						..Default::default()
+					}
+					.into(),
+				}
+			}
+			VideoCodec::AV1(av1) => {
+				let av1c = match &config.description {
+					Some(description) => mp4_atom::Av1c::decode_body(&mut description.as_ref())?,
+					None => mp4_atom::Av1c {
+						seq_profile: av1.profile,
+						seq_level_idx_0: av1.level,
+						seq_tier_0: av1.tier == 'H',
+						high_bitdepth: av1.bitdepth > 8,
+						twelve_bit: av1.bitdepth == 12,
+						monochrome: av1.mono_chrome,
+						chroma_subsampling_x: av1.chroma_subsampling_x,
+						chroma_subsampling_y: av1.chroma_subsampling_y,
+						chroma_sample_position: av1.chroma_sample_position,
+						..Default::default()
+					},
+				};
+
+				mp4_atom::Av01 {
+					visual: visual.clone(),
+					av1c,
+					..Default::default()
+				}
+				.into()
+			}
+			VideoCodec::VP9(vp9) => mp4_atom::Vp09 {
+				visual: visual.clone(),
+				vpcc: mp4_atom::VpcC {
+					profile: vp9.profile,
+					level: vp9.level,
+					bit_depth: vp9.bit_depth,
+					chroma_subsampling: vp9.chroma_subsampling,
+					video_full_range_flag: vp9.full_range,
+					color_primaries: vp9.color_primaries,
+					transfer_characteristics: vp9.transfer_characteristics,
+					matrix_coefficients: vp9.matrix_coefficients,
+					codec_initialization_data: Vec::new(),
+				},
+			}
+			.into(),
+			VideoCodec::VP8 => mp4_atom::Vp08 {
+				visual: visual.clone(),
+				vpcc: mp4_atom::VpcC {
+					bit_depth: 8,
+					chroma_subsampling: 1,
+					..Default::default()
+				},
+			}
+			.into(),
+			VideoCodec::Unknown(codec) => return Err(Error::UnsupportedCodec(codec.clone())),
+		};
+
+		let mut trak = Self::init_trak(
+			track_id,
+			b"vide",
+			"VideoHandler",
+			mp4_atom::Minf {
+				vmhd: Some(Default::default()),
+				..Self::init_minf(codec)
+			},
+		);
+
+		trak.tkhd.width = mp4_atom::FixedPoint::new(visual.width, 0);
+		trak.tkhd.height = mp4_atom::FixedPoint::new(visual.height, 0);
+
+		Ok(trak)
 	}
 
-	pub async fn run(self) -> Result<()> {
-		todo!();
+	fn init_audio(track_id: u32, audio: &Audio) -> Result<mp4_atom::Trak> {
+		let config = &audio.config;
+
+		let sample = mp4_atom::Audio {
+			data_reference_index: 1,
+			channel_count: config.channel_count as _,
+			sample_size: 16,
+			// The fixed point sample rate can't represent rates above 65535 Hz.
+			sample_rate: mp4_atom::FixedPoint::new(u16::try_from(config.sample_rate).unwrap_or_default(), 0),
+		};
+
+		let codec = match &config.codec {
+			AudioCodec::AAC(aac) => {
+				let dec_specific = match &config.description {
+					Some(description) => mp4_atom::esds::DecoderSpecific::decode(&mut description.as_ref())?,
+					None => mp4_atom::esds::DecoderSpecific {
+						profile: aac.profile,
+						freq_index: aac_frequency_index(config.sample_rate)
+							.ok_or_else(|| Error::UnsupportedCodec(format!("AAC at {}Hz", config.sample_rate)))?,
+						chan_conf: config.channel_count as _,
+					},
+				};
+
+				let bitrate = config.bitrate.unwrap_or_default().try_into().unwrap_or(u32::MAX);
+
+				mp4_atom::Mp4a {
+					audio: sample,
+					esds: mp4_atom::Esds {
+						es_desc: mp4_atom::esds::EsDescriptor {
+							es_id: track_id as _,
+							dec_config: mp4_atom::esds::DecoderConfig {
+								object_type_indication: 0x40, // Audio ISO/IEC 14496-3
+								stream_type: 0x05,            // AudioStream
+								max_bitrate: bitrate,
+								avg_bitrate: bitrate,
+								dec_specific,
+								..Default::default()
+							},
+							..Default::default()
+						},
+					},
+					btrt: None,
+					taic: None,
+				}
+				.into()
+			}
+			AudioCodec::Opus => {
+				// The description is the OpusHead, which has the pre-skip and output gain.
+				let head = config.description.clone().map(OpusHead::parse).transpose()?;
+
+				mp4_atom::Opus {
+					audio: sample,
+					dops: mp4_atom::Dops {
+						output_channel_count: config.channel_count as _,
+						pre_skip: head.as_ref().map_or(0, |head| head.pre_skip),
+						input_sample_rate: head.as_ref().map_or(config.sample_rate, |head| head.input_sample_rate),
+						output_gain: head.as_ref().map_or(0, |head| head.output_gain),
+					},
+				}
+				.into()
+			}
+			AudioCodec::Unknown(codec) => return Err(Error::UnsupportedCodec(codec.clone())),
+		};
+
+		let mut trak = Self::init_trak(
+			track_id,
+			b"soun",
+			"SoundHandler",
+			mp4_atom::Minf {
+				smhd: Some(Default::default()),
+				..Self::init_minf(codec)
+			},
+		);
+
+		trak.tkhd.volume = mp4_atom::FixedPoint::new(1, 0);
+
+		Ok(trak)
+	}
+
+	// mp4-atom doesn't support the wvtt sample entry or the sthd/nmhd media headers, so the trak is encoded by hand.
+	fn init_text(track_id: u32, text: &Text, buffer: &mut Vec<u8>) -> Result<()> {
+		let config = &text.config;
+
+		let mut entry = Vec::new();
+		let (handler, media_header) = match &config.codec {
+			// ISO/IEC 14496-30
+			TextCodec::WebVtt => {
+				let mut wvtt = vec![0; 6]; // reserved
+				wvtt.extend_from_slice(&1u16.to_be_bytes()); // data_reference_index
+
+				let header = config.description.as_deref().unwrap_or(b"WEBVTT");
+				encode_box(&mut wvtt, b"vttC", header)?;
+				encode_box(&mut entry, b"wvtt", &wvtt)?;
+
+				(b"subt", b"sthd")
+			}
+			// 3GPP TS 26.245
+			TextCodec::Tx3g => {
+				let mut tx3g = match &config.description {
+					Some(description) => mp4_atom::Tx3g::decode_body(&mut description.as_ref())?,
+					None => mp4_atom::Tx3g::default(),
+				};
+				tx3g.data_reference_index = 1;
+				tx3g.encode(&mut entry)?;
+
+				(b"sbtl", b"nmhd")
+			}
+			TextCodec::Unknown(codec) => return Err(Error::UnsupportedCodec(codec.clone())),
+		};
+
+		let mut stsd = 0u32.to_be_bytes().to_vec(); // version and flags
+		stsd.extend_from_slice(&1u32.to_be_bytes()); // entry_count
+		stsd.extend_from_slice(&entry);
+
+		let mut stbl = Vec::new();
+		encode_box(&mut stbl, b"stsd", &stsd)?;
+		mp4_atom::Stts::default().encode(&mut stbl)?;
+		mp4_atom::Stsc::default().encode(&mut stbl)?;
+		mp4_atom::Stsz::default().encode(&mut stbl)?;
+		mp4_atom::Stco::default().encode(&mut stbl)?;
+
+		let mut minf = Vec::new();
+		encode_box(&mut minf, media_header, &[0; 4])?; // version and flags
+		mp4_atom::Dinf {
+			dref: mp4_atom::Dref {
+				urls: vec![mp4_atom::Url::default()],
+			},
+		}
+		.encode(&mut minf)?;
+		encode_box(&mut minf, b"stbl", &stbl)?;
+
+		// The mdhd only supports ISO 639-2 codes.
+		let language = config
+			.language
+			.clone()
+			.filter(|language| language.len() == 3 && language.bytes().all(|c| c.is_ascii_lowercase()))
+			.unwrap_or_else(|| "und".to_string());
+
+		let mut mdia = Vec::new();
+		mp4_atom::Mdhd {
+			timescale: TIMESCALE as u32,
+			language,
+			..Default::default()
+		}
+		.encode(&mut mdia)?;
+		mp4_atom::Hdlr {
+			handler: handler.into(),
+			name: "SubtitleHandler".to_string(),
+		}
+		.encode(&mut mdia)?;
+		encode_box(&mut mdia, b"minf", &minf)?;
+
+		let mut trak = Vec::new();
+		mp4_atom::Tkhd {
+			track_id,
+			enabled: true,
+			..Default::default()
+		}
+		.encode(&mut trak)?;
+		encode_box(&mut trak, b"mdia", &mdia)?;
+
+		encode_box(buffer, b"trak", &trak)
+	}
+
+	/// Write a moof + mdat fragment for each group until all tracks have ended.
+	pub async fn run(mut self) -> Result<()> {
+		let (tx, mut rx) = mpsc::channel(self.tracks.len());
+		let container = self.catalog.container;
+
+		for (index, track) in self.tracks.iter().enumerate() {
+			let track_id = index as u32 + 1;
+			let consumer = self.broadcast.inner.subscribe(&track.track);
+			let text = track.text.clone();
+			let annexb = track.annexb;
+			let tx = tx.clone();
+
+			spawn(async move {
+				let res = match text {
+					Some(codec) => Self::run_text(track_id, codec, consumer, container, tx.clone()).await,
+					None => Self::run_track(track_id, consumer, container, annexb, tx.clone()).await,
+				};
+				if let Err(err) = res {
+					tx.send(Err(err)).await.ok();
+				}
+			});
+		}
+
+		// Drop our sender so the channel closes once all tracks have ended.
+		drop(tx);
+
+		while let Some(fragment) = rx.recv().await {
+			let buffer = self.encode(fragment?)?;
+			self.output.write_all(&buffer).await?;
+			self.output.flush().await?;
+		}
+
 		Ok(())
 	}
 
-	pub fn catalog(&self) -> &catalog::Broadcast {
-		self.broadcast.catalog()
+	// Read each group into a fragment, writing it as soon as the duration of its last frame is known.
+	// That's when the group ends if the frame has its own duration, otherwise when the next group starts.
+	async fn run_track(
+		track_id: u32,
+		mut track: moq_lite::TrackConsumer,
+		container: Container,
+		annexb: Option<annexb::Codec>,
+		fragments: mpsc::Sender<Result<Fragment>>,
+	) -> Result<()> {
+		let mut buffered: Option<Vec<Frame>> = None;
+
+		// The duration of the last sample written, repeated if there's nothing better at the end of the track.
+		let mut prev = 0;
+
+		while let Some(group) = track.next_group().await? {
+			let mut group = GroupConsumer::new(group).with_container(container);
+			let mut frames = Vec::new();
+
+			while let Some(mut frame) = group.read_frame().await? {
+				if let Some(codec) = annexb {
+					frame.payload = length_prefixed(codec, &frame.payload);
+				}
+
+				if let Some(buffered) = buffered.take() {
+					let fragment = Fragment::new(track_id, buffered, Some(frame.dts()), prev);
+					prev = fragment.samples.last().map_or(prev, |sample| sample.duration);
+					fragments.send(Ok(fragment)).await.map_err(|_| Error::Closed)?;
+				}
+
+				frames.push(frame);
+			}
+
+			match frames.last() {
+				Some(last) if last.duration.is_some() => {
+					let fragment = Fragment::new(track_id, frames, None, prev);
+					prev = fragment.samples.last().map_or(prev, |sample| sample.duration);
+					fragments.send(Ok(fragment)).await.map_err(|_| Error::Closed)?;
+				}
+				Some(_) => buffered = Some(frames),
+				None => {}
+			}
+		}
+
+		if let Some(frames) = buffered {
+			let fragment = Fragment::new(track_id, frames, None, prev);
+			fragments.send(Ok(fragment)).await.map_err(|_| Error::Closed)?;
+		}
+
+		Ok(())
+	}
+
+	// Cues know their own duration, so each group is written immediately.
+	// An empty sample fills any gap since the previous cue, so the timeline is contiguous.
+	async fn run_text(
+		track_id: u32,
+		codec: TextCodec,
+		mut track: moq_lite::TrackConsumer,
+		container: Container,
+		fragments: mpsc::Sender<Result<Fragment>>,
+	) -> Result<()> {
+		let mut end: Option<Timestamp> = None;
+
+		while let Some(group) = track.next_group().await? {
+			let mut group = GroupConsumer::new(group).with_container(container);
+			let mut base = None;
+			let mut samples = Vec::new();
+
+			while let Some(frame) = group.read_frame().await? {
+				let cue = Cue::decode(frame)?;
+
+				if let Some(end) = end.filter(|end| *end < cue.start) {
+					base.get_or_insert(end);
+					samples.push(Sample {
+						duration: micros(cue.start - end),
+						offset: 0,
+						keyframe: true,
+						payload: empty_sample(&codec),
+					});
+				}
+
+				base.get_or_insert(cue.start);
+				samples.push(Sample {
+					duration: micros(cue.end.saturating_sub(cue.start)),
+					offset: 0,
+					keyframe: true,
+					payload: cue.payload,
+				});
+
+				end = Some(cue.end);
+			}
+
+			if let Some(base) = base {
+				let fragment = Fragment {
+					track_id,
+					base,
+					samples,
+				};
+
+				fragments.send(Ok(fragment)).await.map_err(|_| Error::Closed)?;
+			}
+		}
+
+		Ok(())
+	}
+
+	fn encode(&mut self, fragment: Fragment) -> Result<Vec<u8>> {
+		let sequence_number = self.sequence;
+		self.sequence += 1;
+
+		let samples = &fragment.samples;
+
+		let mut traf = Vec::new();
+
+		mp4_atom::Tfhd {
+			track_id: fragment.track_id,
+			..Default::default()
+		}
+		.encode(&mut traf)?;
+
+		mp4_atom::Tfdt {
+			base_media_decode_time: fragment.base.as_micros() as u64,
+		}
+		.encode(&mut traf)?;
+
+		// NOTE: mp4_atom::Trun always writes the first sample flags, even when the flag isn't set.
+		// Until that's fixed upstream, we encode the trun by hand.
+		// The composition offsets are only written if there are B-frames, using version 1 if any are negative.
+		let offsets = samples.iter().any(|sample| sample.offset != 0);
+		let header: u32 = match (offsets, samples.iter().any(|sample| sample.offset < 0)) {
+			(false, _) => 0x0000_0701,    // version 0; data offset, duration, size, flags
+			(true, false) => 0x0000_0f01, // version 0; data offset, duration, size, flags, composition offset
+			(true, true) => 0x0100_0f01,  // version 1; signed composition offsets
+		};
+
+		let mut trun = Vec::new();
+		trun.extend_from_slice(&header.to_be_bytes());
+		trun.extend_from_slice(&(samples.len() as u32).to_be_bytes());
+		let data_offset = trun.len();
+		trun.extend_from_slice(&0i32.to_be_bytes()); // filled in below
+
+		for sample in samples {
+			let flags = match sample.keyframe {
+				true => SAMPLE_FLAGS_KEYFRAME,
+				false => SAMPLE_FLAGS_DELTA,
+			};
+
+			let size: u32 = sample.payload.len().try_into().map_err(|_| Error::InvalidSize)?;
+
+			trun.extend_from_slice(&sample.duration.to_be_bytes());
+			trun.extend_from_slice(&size.to_be_bytes());
+			trun.extend_from_slice(&flags.to_be_bytes());
+
+			if offsets {
+				trun.extend_from_slice(&sample.offset.to_be_bytes());
+			}
+		}
+
+		let data_offset = traf.len() + 8 + data_offset;
+		encode_box(&mut traf, b"trun", &trun)?;
+
+		let mut moof = Vec::new();
+		mp4_atom::Mfhd { sequence_number }.encode(&mut moof)?;
+		let data_offset = moof.len() + 8 + data_offset;
+		encode_box(&mut moof, b"traf", &traf)?;
+
+		let mut buffer = Vec::new();
+		let data_offset = 8 + data_offset;
+		encode_box(&mut buffer, b"moof", &moof)?;
+
+		// The sample data starts right after the mdat header, relative to the start of the moof.
+		let start: i32 = (buffer.len() + 8).try_into().map_err(|_| Error::InvalidSize)?;
+		buffer[data_offset..data_offset + 4].copy_from_slice(&start.to_be_bytes());
+
+		let size = 8 + samples.iter().map(|sample| sample.payload.len()).sum::<usize>();
+		let size: u32 = size.try_into().map_err(|_| Error::InvalidSize)?;
+		buffer.extend_from_slice(&size.to_be_bytes());
+		buffer.extend_from_slice(b"mdat");
+
+		for sample in samples {
+			buffer.extend_from_slice(&sample.payload);
+		}
+
+		Ok(buffer)
+	}
+
+	pub fn catalog(&self) -> &Catalog {
+		&self.catalog
+	}
+}
+
+fn encode_box(buffer: &mut Vec<u8>, kind: &[u8; 4], body: &[u8]) -> Result<()> {
+	let size: u32 = (8 + body.len()).try_into().map_err(|_| Error::InvalidSize)?;
+	buffer.extend_from_slice(&size.to_be_bytes());
+	buffer.extend_from_slice(kind);
+	buffer.extend_from_slice(body);
+	Ok(())
+}
+
+// Convert an Annex-B frame into the length-prefixed format used by MP4.
+// AUDs aren't allowed, and the H.264 parameter sets are only in the avcC as avc1 doesn't allow them in-band.
+fn length_prefixed(codec: annexb::Codec, payload: &Bytes) -> Bytes {
+	let mut nals = annexb::split(payload);
+	nals.retain(|nal| {
+		let parameters = codec == annexb::Codec::H264
+			&& matches!(
+				annexb::h264::nal_type(nal),
+				Some(annexb::h264::NAL_SPS | annexb::h264::NAL_PPS)
+			);
+		!codec.is_aud(nal) && !parameters
+	});
+
+	annexb::length_prefixed(&nals)
+}
+
+fn micros(duration: std::time::Duration) -> u32 {
+	duration.as_micros().try_into().unwrap_or(u32::MAX)
+}
+
+// ISO/IEC 14496-3 sampling frequency index.
+fn aac_frequency_index(sample_rate: u32) -> Option<u8> {
+	const RATES: [u32; 13] = [
+		96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000, 7350,
+	];
+
+	RATES
+		.iter()
+		.position(|rate| *rate == sample_rate)
+		.map(|index| index as u8)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::catalog::{AudioConfig, TextConfig, VideoConfig, H264};
+	use crate::cmaf::Import;
+	use crate::model::BroadcastProducer;
+	use crate::webvtt;
+	use bytes::Bytes;
+	use moq_lite::Track;
+
+	fn avcc() -> Bytes {
+		let avcc = mp4_atom::Avcc {
+			configuration_version: 1,
+			avc_profile_indication: 0x64,
+			profile_compatibility: 0x00,
+			avc_level_indication: 0x1f,
+			length_size: 4,
+			sequence_parameter_sets: vec![vec![0x67, 0x64, 0x00, 0x1f]],
+			picture_parameter_sets: vec![vec![0x68, 0xee, 0x3c, 0x80]],
+			ext: None,
+		};
+
+		let mut description = Vec::new();
+		avcc.encode_body(&mut description).unwrap();
+		description.into()
+	}
+
+	fn video(description: Option<Bytes>) -> Video {
+		Video {
+			track: Track {
+				name: "video".to_string(),
+				priority: 1,
+				..Default::default()
+			},
+			config: VideoConfig {
+				codec: H264 {
+					profile: 0x64,
+					constraints: 0x00,
+					level: 0x1f,
+				}
+				.into(),
+				description,
+				coded_width: Some(1280),
+				coded_height: Some(720),
+				display_ratio_width: None,
+				display_ratio_height: None,
+				bitrate: None,
+				framerate: None,
+				optimize_for_latency: None,
+				color_space: None,
+				rotation: None,
+				flip: None,
+			},
+			rendition_group: None,
+			index: None,
+		}
+	}
+
+	// Read a single box from the stream, failing instead of blocking forever.
+	async fn read_box(reader: &mut tokio::io::DuplexStream) -> Vec<u8> {
+		use tokio::io::AsyncReadExt;
+
+		let read = async {
+			let mut buf = vec![0; 8];
+			reader.read_exact(&mut buf).await.unwrap();
+
+			let size = u32::from_be_bytes(buf[..4].try_into().unwrap()) as usize;
+			buf.resize(size, 0);
+			reader.read_exact(&mut buf[8..]).await.unwrap();
+
+			buf
+		};
+
+		tokio::time::timeout(std::time::Duration::from_secs(5), read)
+			.await
+			.expect("timed out")
+	}
+
+	#[tokio::test]
+	async fn roundtrip() {
+		let mut broadcast = BroadcastProducer::new();
+		let mut track = broadcast.create_video(video(Some(avcc())));
+
+		let mut output = Vec::new();
+		let export = Export::init(broadcast.consume(), &mut output).await.unwrap();
+		assert_eq!(export.catalog().video.len(), 1);
+
+		// Subscribe to the tracks before writing any frames.
+		let (res, _) = tokio::join!(export.run(), async move {
+			for i in 0..6u64 {
+				track.write(Frame {
+					timestamp: Timestamp::from_millis(i * 33),
+					decode_timestamp: None,
+					duration: None,
+					keyframe: i % 3 == 0,
+					payload: Bytes::from(vec![i as u8; 10]),
+				});
+
+				// Give the exporter a chance to read the group before it's replaced by a newer one.
+				tokio::task::yield_now().await;
+			}
+
+			track.finish();
+		});
+		res.unwrap();
+
+		let mut buf = output.as_slice();
+		assert!(matches!(
+			mp4_atom::Any::decode(&mut buf).unwrap(),
+			mp4_atom::Any::Ftyp(_)
+		));
+		assert!(matches!(
+			mp4_atom::Any::decode(&mut buf).unwrap(),
+			mp4_atom::Any::Moov(_)
+		));
+
+		// Each group is a fragment, with the last duration of the track repeated.
+		for (sequence, group) in [(1, 0..3u64), (2, 3..6u64)] {
+			let start = buf.len();
+			let moof = mp4_atom::Moof::decode(&mut buf).unwrap();
+			let moof_size = start - buf.len();
+			let mdat = mp4_atom::Mdat::decode(&mut buf).unwrap();
+
+			assert_eq!(moof.mfhd.sequence_number, sequence);
+
+			let traf = &moof.traf[0];
+			assert_eq!(traf.tfhd.track_id, 1);
+			assert_eq!(traf.tfdt.as_ref().unwrap().base_media_decode_time, group.start * 33_000);
+
+			let trun = traf.trun.as_ref().unwrap();
+			assert_eq!(trun.data_offset, Some(moof_size as i32 + 8));
+			assert_eq!(trun.entries.len(), 3);
+			assert_eq!(trun.entries[0].flags, Some(SAMPLE_FLAGS_KEYFRAME));
+			assert_eq!(trun.entries[1].flags, Some(SAMPLE_FLAGS_DELTA));
+
+			for entry in &trun.entries {
+				assert_eq!(entry.duration, Some(33_000));
+				assert_eq!(entry.size, Some(10));
+			}
+
+			let expected: Vec<u8> = group.flat_map(|i| [i as u8; 10]).collect();
+			assert_eq!(mdat.data, expected);
+		}
+
+		assert!(buf.is_empty());
+
+		// Make sure the init segment can be imported again.
+		let imported = BroadcastProducer::new();
+		let mut catalog = imported.consume().catalog;
+		Import::new(imported).parse(&output).unwrap();
+
+		let catalog = catalog.next().await.unwrap().unwrap();
+		assert_eq!(catalog.video.len(), 1);
+		assert_eq!(catalog.video[0].config.description, Some(avcc()));
+		assert_eq!(catalog.video[0].config.coded_width, Some(1280));
+	}
+
+	#[tokio::test]
+	async fn bframes() {
+		let mut broadcast = BroadcastProducer::new();
+		let mut track = broadcast.create_video(video(Some(avcc())));
+
+		// I P B B in decode order, with the presentation delayed by a frame.
+		let frames = [(33, 0), (132, 33), (66, 66), (99, 99)].map(|(pts, dts)| Frame {
+			timestamp: Timestamp::from_millis(pts),
+			decode_timestamp: Some(Timestamp::from_millis(dts)),
+			duration: Some(Timestamp::from_millis(33)),
+			keyframe: dts == 0,
+			payload: Bytes::from(vec![dts as u8; 4]),
+		});
+
+		let mut output = Vec::new();
+		let export = Export::init(broadcast.consume(), &mut output).await.unwrap();
+
+		let (res, _) = tokio::join!(export.run(), async {
+			for frame in frames.clone() {
+				track.write(frame);
+			}
+
+			// Give the exporter a chance to read the group before the track is closed.
+			tokio::task::yield_now().await;
+
+			track.finish();
+		});
+		res.unwrap();
+
+		let mut buf = output.as_slice();
+		mp4_atom::Ftyp::decode(&mut buf).unwrap();
+		mp4_atom::Moov::decode(&mut buf).unwrap();
+
+		let moof = mp4_atom::Moof::decode(&mut buf).unwrap();
+		mp4_atom::Mdat::decode(&mut buf).unwrap();
+		assert!(buf.is_empty());
+
+		let traf = &moof.traf[0];
+		assert_eq!(traf.tfdt.as_ref().unwrap().base_media_decode_time, 0);
+
+		let trun = traf.trun.as_ref().unwrap();
+		let cts: Vec<_> = trun.entries.iter().map(|entry| entry.cts).collect();
+		assert_eq!(cts, [Some(33_000), Some(99_000), Some(0), Some(0)]);
+		assert!(trun.entries.iter().all(|entry| entry.duration == Some(33_000)));
+
+		// Importing the file again restores both timestamps.
+		let imported = BroadcastProducer::new();
+		let mut consumer = imported.consume();
+		let mut import = Import::new(imported);
+		import.parse(&output).unwrap();
+
+		let catalog = consumer.catalog.next().await.unwrap().unwrap();
+		let mut track = consumer.subscribe(&catalog.video[0].track);
+
+		for expected in frames {
+			let frame = track.read().await.unwrap().unwrap();
+			assert_eq!(frame.timestamp, expected.timestamp);
+			assert_eq!(frame.dts(), expected.dts());
+			assert_eq!(frame.duration, expected.duration);
+			assert_eq!(frame.payload, expected.payload);
+		}
+	}
+
+	#[tokio::test]
+	async fn text() {
+		let mut broadcast = BroadcastProducer::new();
+		let mut track = broadcast.create_text(Text {
+			track: Track {
+				name: "text".to_string(),
+				priority: 1,
+				..Default::default()
+			},
+			config: TextConfig {
+				codec: TextCodec::WebVtt,
+				language: Some("eng".to_string()),
+				kind: Default::default(),
+				label: None,
+				description: None,
+			},
+		});
+
+		let cue = |start: u64, text: &str| Cue {
+			start: Timestamp::from_secs(start),
+			end: Timestamp::from_secs(start + 1),
+			payload: webvtt::encode_sample(&[webvtt::VttCue {
+				payload: text.to_string(),
+				..Default::default()
+			}]),
+		};
+
+		let mut output = Vec::new();
+		let export = Export::init(broadcast.consume(), &mut output).await.unwrap();
+
+		let (res, _) = tokio::join!(export.run(), async move {
+			for (start, text) in [(1, "hello"), (3, "world")] {
+				track.write(cue(start, text).encode());
+				tokio::task::yield_now().await;
+			}
+
+			track.finish();
+		});
+		res.unwrap();
+
+		let mut buf = output.as_slice();
+		mp4_atom::Ftyp::decode(&mut buf).unwrap();
+		let moov = mp4_atom::Moov::decode(&mut buf).unwrap();
+
+		let trak = &moov.trak[0];
+		assert_eq!(trak.mdia.hdlr.handler, b"subt".into());
+		assert_eq!(trak.mdia.mdhd.language, "eng");
+		assert_eq!(
+			trak.mdia.minf.stbl.stsd.codecs,
+			[mp4_atom::Codec::Unknown(b"wvtt".into())]
+		);
+
+		// The second fragment starts with an empty sample to fill the gap.
+		for (base, durations) in [(1, vec![1]), (2, vec![1, 1])] {
+			let moof = mp4_atom::Moof::decode(&mut buf).unwrap();
+			mp4_atom::Mdat::decode(&mut buf).unwrap();
+
+			let traf = &moof.traf[0];
+			assert_eq!(traf.tfdt.as_ref().unwrap().base_media_decode_time, base * 1_000_000);
+
+			let trun = traf.trun.as_ref().unwrap();
+			let expected: Vec<_> = durations.into_iter().map(|d| Some(d * 1_000_000)).collect();
+			assert_eq!(trun.entries.iter().map(|e| e.duration).collect::<Vec<_>>(), expected);
+		}
+
+		assert!(buf.is_empty());
+
+		// Import the file again, skipping the empty sample.
+		let imported = BroadcastProducer::new();
+		let mut consumer = imported.consume();
+		let mut import = Import::new(imported);
+		import.parse(&output).unwrap();
+
+		let catalog = consumer.catalog.next().await.unwrap().unwrap();
+		let text = &catalog.text[0];
+		assert_eq!(text.config.codec, TextCodec::WebVtt);
+		assert_eq!(text.config.language.as_deref(), Some("eng"));
+
+		let mut track = consumer.subscribe(&text.track);
+		let imported = Cue::decode(track.read().await.unwrap().unwrap()).unwrap();
+		let expected = cue(3, "world");
+		assert_eq!(imported.start, expected.start);
+		assert_eq!(imported.end, expected.end);
+		assert_eq!(imported.payload, expected.payload);
+	}
+
+	#[tokio::test]
+	async fn flush() {
+		let mut broadcast = BroadcastProducer::new();
+		let mut track = broadcast.create_video(video(Some(avcc())));
+
+		let (writer, mut reader) = tokio::io::duplex(1 << 16);
+		let export = Export::init(broadcast.consume(), writer).await.unwrap();
+		tokio::spawn(export.run());
+
+		read_box(&mut reader).await; // ftyp
+		read_box(&mut reader).await; // moov
+
+		for i in 0..4u64 {
+			track.write(Frame {
+				timestamp: Timestamp::from_millis(i * 40),
+				decode_timestamp: None,
+				duration: None,
+				keyframe: i % 3 == 0,
+				payload: Bytes::from(vec![i as u8; 10]),
+			});
+
+			// Give the exporter a chance to read the group before it's replaced by a newer one.
+			tokio::task::yield_now().await;
+		}
+
+		// The first group is written once the next group starts, without waiting for the track to end.
+		let moof = mp4_atom::Moof::decode(&mut read_box(&mut reader).await.as_slice()).unwrap();
+		let mdat = mp4_atom::Mdat::decode(&mut read_box(&mut reader).await.as_slice()).unwrap();
+
+		let traf = &moof.traf[0];
+		assert_eq!(traf.tfdt.as_ref().unwrap().base_media_decode_time, 0);
+
+		// The duration of the last frame comes from the next group.
+		let trun = traf.trun.as_ref().unwrap();
+		assert!(trun.entries.iter().all(|entry| entry.duration == Some(40_000)));
+		assert_eq!(trun.entries.len(), 3);
+
+		let expected: Vec<u8> = (0..3u8).flat_map(|i| [i; 10]).collect();
+		assert_eq!(mdat.data, expected);
+	}
+
+	#[tokio::test]
+	async fn opus() {
+		let mut broadcast = BroadcastProducer::new();
+		let head = OpusHead::new(2, 312, 44_100, 0);
+
+		broadcast.create_audio(Audio {
+			track: Track {
+				name: "audio".to_string(),
+				priority: 2,
+				..Default::default()
+			},
+			config: AudioConfig {
+				codec: AudioCodec::Opus,
+				sample_rate: 48_000,
+				channel_count: 2,
+				bitrate: None,
+				description: Some(head.raw.clone()),
+			},
+			index: None,
+		});
+
+		let mut output = Vec::new();
+		Export::init(broadcast.consume(), &mut output).await.unwrap();
+
+		let mut buf = output.as_slice();
+		mp4_atom::Ftyp::decode(&mut buf).unwrap();
+		let moov = mp4_atom::Moov::decode(&mut buf).unwrap();
+
+		// The dOps is built from the OpusHead in the description.
+		let mp4_atom::Codec::Opus(opus) = &moov.trak[0].mdia.minf.stbl.stsd.codecs[0] else {
+			panic!("expected opus");
+		};
+		assert_eq!(opus.dops.pre_skip, 312);
+		assert_eq!(opus.dops.input_sample_rate, 44_100);
+
+		// Importing the init segment again restores the OpusHead.
+		let imported = BroadcastProducer::new();
+		let mut catalog = imported.consume().catalog;
+		Import::new(imported).parse(&output).unwrap();
+
+		let catalog = catalog.next().await.unwrap().unwrap();
+		assert_eq!(catalog.audio[0].config.description, Some(head.raw));
+	}
+
+	#[tokio::test]
+	async fn annexb() {
+		use crate::annexb::h264::test::{PPS, SPS_720P};
+
+		// The parameter sets are only in-band, so the catalog has no description.
+		let mut broadcast = BroadcastProducer::new();
+		let mut track = broadcast.create_video(video(None));
+
+		let nals: [&[u8]; 4] = [&[0x09, 0xf0], SPS_720P, PPS, &[0x65, 0x88, 0x84]];
+		let keyframe: Vec<u8> = nals.iter().flat_map(|nal| [&[0, 0, 0, 1], *nal].concat()).collect();
+
+		track.write(Frame {
+			timestamp: Timestamp::ZERO,
+			decode_timestamp: None,
+			duration: Some(Timestamp::from_millis(33)),
+			keyframe: true,
+			payload: keyframe.into(),
+		});
+
+		let mut output = Vec::new();
+		let export = Export::init(broadcast.consume(), &mut output).await.unwrap();
+
+		let (res, _) = tokio::join!(export.run(), async {
+			tokio::task::yield_now().await;
+			track.finish();
+		});
+		res.unwrap();
+
+		let mut buf = output.as_slice();
+		mp4_atom::Ftyp::decode(&mut buf).unwrap();
+		let moov = mp4_atom::Moov::decode(&mut buf).unwrap();
+
+		// The avcC is built from the parameter sets in the keyframe.
+		let mp4_atom::Codec::Avc1(avc1) = &moov.trak[0].mdia.minf.stbl.stsd.codecs[0] else {
+			panic!("expected avc1");
+		};
+		assert_eq!(avc1.avcc.sequence_parameter_sets, [SPS_720P]);
+		assert_eq!(avc1.avcc.picture_parameter_sets, [PPS]);
+
+		// The sample is length-prefixed, without the AUD or parameter sets.
+		mp4_atom::Moof::decode(&mut buf).unwrap();
+		let mdat = mp4_atom::Mdat::decode(&mut buf).unwrap();
+		assert_eq!(mdat.data, [0, 0, 0, 3, 0x65, 0x88, 0x84]);
+		assert!(buf.is_empty());
 	}
 }
@@ -1,9 +1,16 @@
-use super::{Error, Result};
-use crate::catalog::{Audio, AudioCodec, AudioConfig, Video, VideoCodec, VideoConfig, AAC, AV1, H264, H265, VP9};
-use crate::model::{BroadcastProducer, Frame, Timestamp, TrackProducer};
+use super::{text::is_empty_sample, Error, Result};
+use crate::annexb::h264;
+use crate::catalog::{
+	Audio, AudioCodec, AudioConfig, Metadata, MetadataConfig, Text, TextCodec, TextConfig, TextKind, Video, VideoCodec,
+	VideoColorSpace, VideoConfig, AAC, AV1, H264, H265, VP9,
+};
+use crate::model::{BroadcastProducer, Cue, Event, EventProducer, Frame, Timestamp, TrackProducer};
+use crate::ogg::OpusHead;
 use bytes::{Bytes, BytesMut};
 use moq_lite::Track;
-use mp4_atom::{Any, AsyncReadFrom, Atom, DecodeMaybe, Mdat, Moof, Moov, Tfdt, Trak, Trun};
+use mp4_atom::{
+	Any, AsyncReadFrom, Atom, DecodeMaybe, Emsg, EmsgTimestamp, Encode, Mdat, Moof, Moov, Tfdt, Trak, Trun,
+};
 use std::{collections::HashMap, time::Duration};
 use tokio::io::{AsyncRead, AsyncReadExt};
 
@@ -21,6 +28,22 @@ pub struct Import {
 	// The timestamp of the last keyframe for each track
 	last_keyframe: HashMap<u32, Timestamp>,
 
+	// The codec of each text track, whose samples are written as cues.
+	text: HashMap<u32, TextCodec>,
+
+	// The catalog entry for each video and audio track, updated with the measured framerate and bitrate.
+	video: HashMap<u32, Video>,
+	audio: HashMap<u32, Audio>,
+
+	// The samples observed for each video and audio track since the last measurement.
+	stats: HashMap<u32, Stats>,
+
+	// A timed metadata track for each emsg scheme and value.
+	metadata: HashMap<(String, String), EventProducer>,
+
+	// Any emsg (v0) boxes that are relative to the start of the next fragment.
+	emsg: Vec<Emsg>,
+
 	// The moov atom at the start of the file.
 	moov: Option<Moov>,
 
@@ -36,6 +59,12 @@ impl Import {
 			broadcast,
 			tracks: HashMap::default(),
 			last_keyframe: HashMap::default(),
+			text: HashMap::default(),
+			video: HashMap::default(),
+			audio: HashMap::default(),
+			stats: HashMap::default(),
+			metadata: HashMap::default(),
+			emsg: Vec::new(),
 			moov: None,
 			moof: None,
 			moof_size: 0,
@@ -84,13 +113,21 @@ impl Import {
 			let track = match handler.as_ref() {
 				b"vide" => {
 					let track = Self::init_video(trak)?;
+					self.video.insert(track_id, track.clone());
+					self.stats.insert(track_id, Stats::new(trak.mdia.mdhd.timescale as u64));
 					self.broadcast.create_video(track)
 				}
 				b"soun" => {
 					let track = Self::init_audio(trak)?;
+					self.audio.insert(track_id, track.clone());
+					self.stats.insert(track_id, Stats::new(trak.mdia.mdhd.timescale as u64));
 					self.broadcast.create_audio(track)
 				}
-				b"sbtl" => return Err(Error::UnsupportedTrack("subtitle")),
+				b"sbtl" | b"subt" | b"text" => {
+					let track = Self::init_text(trak)?;
+					self.text.insert(track_id, track.config.codec.clone());
+					self.broadcast.create_text(track)
+				}
 				_ => return Err(Error::UnsupportedTrack("unknown")),
 			};
 
@@ -102,11 +139,15 @@ impl Import {
 		Ok(())
 	}
 
-	fn init_video(trak: &Trak) -> Result<Video> {
+	pub(crate) fn init_video(trak: &Trak) -> Result<Video> {
 		let name = format!("video{}", trak.tkhd.track_id);
 		let stsd = &trak.mdia.minf.stbl.stsd;
 
-		let track = Track { name, priority: 2 };
+		let track = Track {
+			name,
+			priority: 2,
+			..Default::default()
+		};
 
 		let codec = match stsd.codecs.len() {
 			0 => return Err(Error::MissingCodec),
@@ -114,13 +155,19 @@ impl Import {
 			_ => return Err(Error::MultipleCodecs),
 		};
 
-		let track = match codec {
+		let mut track = match codec {
 			mp4_atom::Codec::Avc1(avc1) => {
 				let avcc = &avc1.avcc;
 
 				let mut description = BytesMut::new();
 				avcc.encode_body(&mut description)?;
 
+				// The VUI may contain the framerate and color space, if the colr box is missing.
+				let sps = avcc
+					.sequence_parameter_sets
+					.first()
+					.and_then(|sps| h264::Sps::parse(sps).ok());
+
 				Video {
 					track,
 					config: VideoConfig {
@@ -133,19 +180,35 @@ impl Import {
 						}
 						.into(),
 						description: Some(description.freeze()),
-						// TODO: populate these fields
-						framerate: None,
-						bitrate: None,
+						framerate: sps.as_ref().and_then(|sps| sps.framerate),
+						bitrate: bitrate(avc1.btrt.as_ref()),
 						rotation: None,
 						flip: None,
 						display_ratio_width: None,
 						display_ratio_height: None,
 						optimize_for_latency: None,
+						color_space: color_space(avc1.colr.as_ref()).or_else(|| sps?.color),
 					},
+					rendition_group: None,
+					index: None,
 				}
 			}
-			mp4_atom::Codec::Hev1(hev1) => Self::init_h265(track, true, &hev1.hvcc, &hev1.visual)?,
-			mp4_atom::Codec::Hvc1(hvc1) => Self::init_h265(track, false, &hvc1.hvcc, &hvc1.visual)?,
+			mp4_atom::Codec::Hev1(hev1) => Self::init_h265(
+				track,
+				true,
+				&hev1.hvcc,
+				&hev1.visual,
+				hev1.btrt.as_ref(),
+				hev1.colr.as_ref(),
+			)?,
+			mp4_atom::Codec::Hvc1(hvc1) => Self::init_h265(
+				track,
+				false,
+				&hvc1.hvcc,
+				&hvc1.visual,
+				hvc1.btrt.as_ref(),
+				hvc1.colr.as_ref(),
+			)?,
 			mp4_atom::Codec::Vp08(vp08) => Video {
 				track,
 				config: VideoConfig {
@@ -153,7 +216,6 @@ impl Import {
 					description: Default::default(),
 					coded_width: Some(vp08.visual.width as _),
 					coded_height: Some(vp08.visual.height as _),
-					// TODO: populate these fields
 					framerate: None,
 					bitrate: None,
 					rotation: None,
@@ -161,7 +223,10 @@ impl Import {
 					display_ratio_width: None,
 					display_ratio_height: None,
 					optimize_for_latency: None,
+					color_space: None,
 				},
+				rendition_group: None,
+				index: None,
 			},
 			mp4_atom::Codec::Vp09(vp09) => {
 				// https://github.com/gpac/mp4box.js/blob/325741b592d910297bf609bc7c400fc76101077b/src/box-codecs.js#L238
@@ -184,20 +249,49 @@ impl Import {
 						description: Default::default(),
 						coded_width: Some(vp09.visual.width as _),
 						coded_height: Some(vp09.visual.height as _),
-						// TODO: populate these fields
 						display_ratio_width: None,
 						display_ratio_height: None,
 						rotation: None,
 						flip: None,
 						optimize_for_latency: None,
+						color_space: Some(VideoColorSpace::from_cicp(
+							vpcc.color_primaries,
+							vpcc.transfer_characteristics,
+							vpcc.matrix_coefficients,
+							vpcc.video_full_range_flag,
+						)),
 						bitrate: None,
 						framerate: None,
 					},
+					rendition_group: None,
+					index: None,
 				}
 			}
 			mp4_atom::Codec::Av01(av01) => {
 				let av1c = &av01.av1c;
 
+				// The codec string includes the color information, which defaults to BT.709.
+				let defaults = AV1::default();
+				let (color_primaries, transfer_characteristics, matrix_coefficients, full_range) = match &av01.colr {
+					Some(mp4_atom::Colr::Nclx {
+						colour_primaries,
+						transfer_characteristics,
+						matrix_coefficients,
+						full_range_flag,
+					}) => (
+						*colour_primaries as u8,
+						*transfer_characteristics as u8,
+						*matrix_coefficients as u8,
+						*full_range_flag,
+					),
+					_ => (
+						defaults.color_primaries,
+						defaults.transfer_characteristics,
+						defaults.matrix_coefficients,
+						defaults.full_range,
+					),
+				};
+
 				Video {
 					track,
 					config: VideoConfig {
@@ -214,33 +308,48 @@ impl Import {
 							chroma_subsampling_x: av1c.chroma_subsampling_x,
 							chroma_subsampling_y: av1c.chroma_subsampling_y,
 							chroma_sample_position: av1c.chroma_sample_position,
-							// TODO HDR stuff?
-							..Default::default()
+							color_primaries,
+							transfer_characteristics,
+							matrix_coefficients,
+							full_range,
+							..defaults
 						}
 						.into(),
 						description: Default::default(),
 						coded_width: Some(av01.visual.width as _),
 						coded_height: Some(av01.visual.height as _),
-						// TODO: populate these fields
 						display_ratio_width: None,
 						display_ratio_height: None,
 						rotation: None,
 						flip: None,
 						optimize_for_latency: None,
-						bitrate: None,
+						color_space: color_space(av01.colr.as_ref()),
+						bitrate: bitrate(av01.btrt.as_ref()),
 						framerate: None,
 					},
+					rendition_group: None,
+					index: None,
 				}
 			}
 			mp4_atom::Codec::Unknown(unknown) => return Err(Error::UnsupportedCodec(unknown.to_string())),
 			_ => return Err(Error::UnsupportedCodec("unknown".to_string())),
 		};
 
+		// The tkhd matrix applies to every codec.
+		(track.config.rotation, track.config.flip) = orientation(&trak.tkhd.matrix);
+
 		Ok(track)
 	}
 
 	// There's two almost identical hvcc atoms in the wild.
-	fn init_h265(track: Track, in_band: bool, hvcc: &mp4_atom::Hvcc, visual: &mp4_atom::Visual) -> Result<Video> {
+	fn init_h265(
+		track: Track,
+		in_band: bool,
+		hvcc: &mp4_atom::Hvcc,
+		visual: &mp4_atom::Visual,
+		btrt: Option<&mp4_atom::Btrt>,
+		colr: Option<&mp4_atom::Colr>,
+	) -> Result<Video> {
 		let mut description = BytesMut::new();
 		hvcc.encode_body(&mut description)?;
 
@@ -260,23 +369,29 @@ impl Import {
 				description: Some(description.freeze()),
 				coded_width: Some(visual.width as _),
 				coded_height: Some(visual.height as _),
-				// TODO: populate these fields
-				bitrate: None,
+				bitrate: bitrate(btrt),
 				framerate: None,
 				display_ratio_width: None,
 				display_ratio_height: None,
 				rotation: None,
 				flip: None,
 				optimize_for_latency: None,
+				color_space: color_space(colr),
 			},
+			rendition_group: None,
+			index: None,
 		})
 	}
 
-	fn init_audio(trak: &Trak) -> Result<Audio> {
+	pub(crate) fn init_audio(trak: &Trak) -> Result<Audio> {
 		let name = format!("audio{}", trak.tkhd.track_id);
 		let stsd = &trak.mdia.minf.stbl.stsd;
 
-		let track = Track { name, priority: 2 };
+		let track = Track {
+			name,
+			priority: 2,
+			..Default::default()
+		};
 
 		let codec = match stsd.codecs.len() {
 			0 => return Err(Error::MissingCodec),
@@ -295,6 +410,10 @@ impl Import {
 
 				let bitrate = desc.avg_bitrate.max(desc.max_bitrate);
 
+				// The AudioSpecificConfig, without the descriptor header.
+				let mut description = BytesMut::new();
+				desc.dec_specific.encode(&mut description)?;
+
 				Audio {
 					track,
 					config: AudioConfig {
@@ -305,8 +424,9 @@ impl Import {
 						sample_rate: mp4a.audio.sample_rate.integer() as _,
 						channel_count: mp4a.audio.channel_count as _,
 						bitrate: Some(bitrate.into()),
-						description: None, // TODO?
+						description: Some(description.freeze()),
 					},
+					index: None,
 				}
 			}
 			mp4_atom::Codec::Opus(opus) => {
@@ -317,8 +437,18 @@ impl Import {
 						sample_rate: opus.audio.sample_rate.integer() as _,
 						channel_count: opus.audio.channel_count as _,
 						bitrate: None,
-						description: None, // TODO?
+						// Convert the dOps back into the OpusHead, so the pre-skip isn't lost.
+						description: Some(
+							OpusHead::new(
+								opus.dops.output_channel_count,
+								opus.dops.pre_skip,
+								opus.dops.input_sample_rate,
+								opus.dops.output_gain,
+							)
+							.raw,
+						),
 					},
+					index: None,
 				}
 			}
 			mp4_atom::Codec::Unknown(unknown) => return Err(Error::UnsupportedCodec(unknown.to_string())),
@@ -328,6 +458,50 @@ impl Import {
 		Ok(track)
 	}
 
+	fn init_text(trak: &Trak) -> Result<Text> {
+		let name = format!("text{}", trak.tkhd.track_id);
+		let stsd = &trak.mdia.minf.stbl.stsd;
+
+		let track = Track {
+			name,
+			priority: 2,
+			..Default::default()
+		};
+
+		let codec = match stsd.codecs.len() {
+			0 => return Err(Error::MissingCodec),
+			1 => &stsd.codecs[0],
+			_ => return Err(Error::MultipleCodecs),
+		};
+
+		let (codec, description) = match codec {
+			mp4_atom::Codec::Tx3g(tx3g) => {
+				let mut description = BytesMut::new();
+				tx3g.encode_body(&mut description)?;
+				(TextCodec::Tx3g, Some(description.freeze()))
+			}
+			// TODO mp4-atom doesn't parse the wvtt sample entry, so the vttC header is lost.
+			mp4_atom::Codec::Unknown(kind) if kind == &b"wvtt".into() => (TextCodec::WebVtt, None),
+			mp4_atom::Codec::Unknown(unknown) => return Err(Error::UnsupportedCodec(unknown.to_string())),
+			_ => return Err(Error::UnsupportedCodec("unknown".to_string())),
+		};
+
+		// The mdhd contains an ISO 639-2 code, which is also a valid BCP 47 tag.
+		let language =
+			Some(trak.mdia.mdhd.language.clone()).filter(|language| language != "und" && !language.is_empty());
+
+		Ok(Text {
+			track,
+			config: TextConfig {
+				codec,
+				language,
+				kind: TextKind::Subtitles,
+				label: None,
+				description,
+			},
+		})
+	}
+
 	// Read the media from a stream until processing the moov atom.
 	pub async fn init_from<T: AsyncRead + Unpin>(&mut self, input: &mut T) -> Result<()> {
 		let _ftyp = mp4_atom::Ftyp::read_from(input).await?;
@@ -369,6 +543,11 @@ impl Import {
 				self.moof = Some(moof);
 				self.moof_size = size;
 			}
+			Any::Emsg(emsg) => match emsg.presentation_time {
+				EmsgTimestamp::Absolute(time) => self.event(&emsg, time),
+				// We need the tfdt of the next fragment.
+				EmsgTimestamp::Relative(_) => self.emsg.push(emsg),
+			},
 			Any::Mdat(mdat) => {
 				// Extract the samples from the mdat atom.
 				let header_size = size - mdat.data.len();
@@ -383,12 +562,77 @@ impl Import {
 		Ok(())
 	}
 
+	// The decode time of the first sample in the fragment.
+	fn start(moov: &Moov, moof: &Moof) -> Option<Timestamp> {
+		let traf = moof.traf.first()?;
+		let tfdt = traf.tfdt.as_ref()?;
+		let trak = moov.trak.iter().find(|trak| trak.tkhd.track_id == traf.tfhd.track_id)?;
+
+		let timescale = trak.mdia.mdhd.timescale as u64;
+		Some(Timestamp::from_micros(rescale(
+			tfdt.base_media_decode_time,
+			timescale,
+			1_000_000,
+		)))
+	}
+
+	// Write an emsg to the metadata track for its scheme, creating it if needed.
+	fn event(&mut self, emsg: &Emsg, time: u64) {
+		if emsg.timescale == 0 {
+			tracing::warn!(?emsg, "skipping emsg with zero timescale");
+			return;
+		}
+
+		let timescale = emsg.timescale as u64;
+		let name = format!("metadata{}", self.metadata.len());
+
+		let key = (emsg.scheme_id_uri.clone(), emsg.value.clone());
+		let track = self.metadata.entry(key).or_insert_with(|| {
+			self.broadcast.create_metadata(Metadata {
+				track: Track {
+					name,
+					priority: 2,
+					..Default::default()
+				},
+				config: MetadataConfig {
+					scheme: emsg.scheme_id_uri.clone(),
+					value: Some(emsg.value.clone()).filter(|value| !value.is_empty()),
+					mime_type: None,
+				},
+			})
+		});
+
+		let duration = match emsg.event_duration {
+			// All ones means an unknown duration.
+			u32::MAX => None,
+			duration => Some(Timestamp::from_micros(rescale(duration as u64, timescale, 1_000_000))),
+		};
+
+		track.write(Event {
+			timestamp: Timestamp::from_micros(rescale(time, timescale, 1_000_000)),
+			duration,
+			id: emsg.id as u64,
+			payload: emsg.message_data.clone().into(),
+		});
+	}
+
 	// Extract all frames out of an mdat atom.
 	fn extract(&mut self, mdat: Mdat, header_size: usize) -> Result<()> {
 		let mdat = Bytes::from(mdat.data);
-		let moov = self.moov.as_ref().ok_or(Error::MissingBox(Moov::KIND))?;
 		let moof = self.moof.take().ok_or(Error::MissingBox(Moof::KIND))?;
 
+		// Relative emsg boxes use the timescale of the emsg, so convert the fragment start to the same units.
+		if let Some(start) = self.moov.as_ref().and_then(|moov| Self::start(moov, &moof)) {
+			for emsg in std::mem::take(&mut self.emsg) {
+				if let EmsgTimestamp::Relative(delta) = emsg.presentation_time {
+					let time = rescale(start.as_micros() as u64, 1_000_000, emsg.timescale as u64);
+					self.event(&emsg, time + delta as u64);
+				}
+			}
+		}
+
+		let moov = self.moov.as_ref().ok_or(Error::MissingBox(Moov::KIND))?;
+
 		// Keep track of the minimum and maximum timestamp so we can scold the user.
 		// Ideally these should both be the same value.
 		let mut min_timestamp = None;
@@ -454,6 +698,28 @@ impl Import {
 					return Err(Error::InvalidOffset);
 				}
 
+				let payload = mdat.slice(offset..(offset + size));
+
+				if let Some(codec) = self.text.get(&track_id) {
+					// Empty samples fill the gaps between cues, so they're not sent.
+					if !is_empty_sample(codec, &payload) {
+						let end = Timestamp::from_micros(1_000_000 * (pts + duration as u64) / timescale);
+						track.write(
+							Cue {
+								start: timestamp,
+								end,
+								payload,
+							}
+							.encode(),
+						);
+					}
+
+					// Cues are sparse, so they're not included in the latency warning.
+					dts += duration as u64;
+					offset += size;
+					continue;
+				}
+
 				let keyframe = if trak.mdia.hdlr.handler == b"vide".into() {
 					// https://chromium.googlesource.com/chromium/src/media/+/master/formats/mp4/track_run_iterator.cc#177
 					let keyframe = (flags >> 24) & 0x3 == 0x2; // kSampleDependsOnNoOther
@@ -481,10 +747,16 @@ impl Import {
 					self.last_keyframe.insert(track_id, timestamp);
 				}
 
-				let payload = mdat.slice(offset..(offset + size));
+				if let Some(stats) = self.stats.get_mut(&track_id) {
+					stats.samples += 1;
+					stats.bytes += size as u64;
+					stats.duration += duration as u64;
+				}
 
 				let frame = Frame {
 					timestamp,
+					decode_timestamp: (dts != pts).then(|| Timestamp::from_micros(1_000_000 * dts / timescale)),
+					duration: Some(Timestamp::from_micros(1_000_000 * duration as u64 / timescale)),
 					keyframe,
 					payload,
 				};
@@ -510,6 +782,351 @@ impl Import {
 			}
 		}
 
+		self.measure();
+
 		Ok(())
 	}
+
+	// Update the catalog with the framerate and bitrate of each track, once enough samples have been observed.
+	fn measure(&mut self) {
+		for (track_id, stats) in self.stats.iter_mut() {
+			// Wait for at least a second of media, so a single large keyframe doesn't skew the bitrate.
+			if stats.duration < stats.timescale {
+				continue;
+			}
+
+			let seconds = stats.duration as f64 / stats.timescale as f64;
+			let bitrate = stats.bytes as f64 * 8.0 / seconds;
+			let framerate = (stats.samples as f64 / seconds * 1000.0).round() / 1000.0;
+			*stats = Stats::new(stats.timescale);
+
+			if let Some(video) = self.video.get_mut(track_id) {
+				let config = &mut video.config;
+
+				let mut changed = false;
+				if differs(config.framerate, framerate, 0.01) {
+					config.framerate = Some(framerate);
+					changed = true;
+				}
+				if differs(config.bitrate.map(|b| b as f64), bitrate, 0.1) {
+					config.bitrate = Some(bitrate as u64);
+					changed = true;
+				}
+
+				if changed {
+					self.broadcast.update_video(video.clone());
+				}
+			} else if let Some(audio) = self.audio.get_mut(track_id) {
+				if differs(audio.config.bitrate.map(|b| b as f64), bitrate, 0.1) {
+					audio.config.bitrate = Some(bitrate as u64);
+					self.broadcast.update_audio(audio.clone());
+				}
+			}
+		}
+	}
+}
+
+// The samples observed for a track, in the track timescale.
+struct Stats {
+	timescale: u64,
+	samples: u64,
+	bytes: u64,
+	duration: u64,
+}
+
+impl Stats {
+	fn new(timescale: u64) -> Self {
+		Self {
+			timescale,
+			samples: 0,
+			bytes: 0,
+			duration: 0,
+		}
+	}
+}
+
+// Returns true if the value is unknown or has changed by more than the given fraction.
+fn differs(old: Option<f64>, new: f64, threshold: f64) -> bool {
+	match old {
+		Some(old) if old > 0.0 => ((new - old) / old).abs() > threshold,
+		_ => true,
+	}
+}
+
+// Use the larger of the maximum and average bitrate from the btrt box, if provided.
+fn bitrate(btrt: Option<&mp4_atom::Btrt>) -> Option<u64> {
+	let btrt = btrt?;
+	Some(btrt.max_bitrate.max(btrt.avg_bitrate) as u64).filter(|bitrate| *bitrate > 0)
+}
+
+// Convert an nclx colr box into a color space, ignoring ICC profiles.
+fn color_space(colr: Option<&mp4_atom::Colr>) -> Option<VideoColorSpace> {
+	match colr? {
+		mp4_atom::Colr::Nclx {
+			colour_primaries,
+			transfer_characteristics,
+			matrix_coefficients,
+			full_range_flag,
+		} => Some(VideoColorSpace::from_cicp(
+			*colour_primaries as u8,
+			*transfer_characteristics as u8,
+			*matrix_coefficients as u8,
+			*full_range_flag,
+		)),
+		_ => None,
+	}
+}
+
+// Decompose the tkhd matrix into a clockwise rotation in degrees, applied after an optional horizontal flip.
+// Both are None for the identity matrix.
+fn orientation(matrix: &mp4_atom::Matrix) -> (Option<f64>, Option<bool>) {
+	// The rotation and scale are 16.16 fixed point, but only the ratios matter.
+	let (a, b, c, d) = (matrix.a as f64, matrix.b as f64, matrix.c as f64, matrix.d as f64);
+
+	// A negative determinant means the matrix contains a reflection.
+	let flip = a * d - b * c < 0.0;
+	let (a, b) = if flip { (-a, -b) } else { (a, b) };
+
+	let rotation = b.atan2(a).to_degrees().round().rem_euclid(360.0);
+
+	(Some(rotation).filter(|r| *r != 0.0), Some(flip).filter(|f| *f))
+}
+
+// Convert a time between timescales, without overflowing for large absolute times.
+fn rescale(time: u64, from: u64, to: u64) -> u64 {
+	(time as u128 * to as u128 / from.max(1) as u128) as u64
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use futures::FutureExt;
+	use mp4_atom::Encode;
+
+	fn init() -> Vec<u8> {
+		let avc1 = mp4_atom::Avc1 {
+			visual: mp4_atom::Visual {
+				width: 1280,
+				height: 720,
+				..Default::default()
+			},
+			avcc: mp4_atom::Avcc::new(&[0x67, 0x64, 0x00, 0x1f], &[0x68, 0xee, 0x3c, 0x80]).unwrap(),
+			btrt: Some(mp4_atom::Btrt {
+				buffer_size_db: 0,
+				max_bitrate: 2_000_000,
+				avg_bitrate: 1_000_000,
+			}),
+			colr: Some(mp4_atom::Colr::new(9, 16, 9, false).unwrap()),
+			..Default::default()
+		};
+
+		let trak = mp4_atom::Trak {
+			tkhd: mp4_atom::Tkhd {
+				track_id: 1,
+				matrix: rotate(90),
+				..Default::default()
+			},
+			mdia: mp4_atom::Mdia {
+				mdhd: mp4_atom::Mdhd {
+					timescale: 1000,
+					..Default::default()
+				},
+				hdlr: mp4_atom::Hdlr {
+					handler: b"vide".into(),
+					name: String::new(),
+				},
+				minf: mp4_atom::Minf {
+					stbl: mp4_atom::Stbl {
+						stsd: mp4_atom::Stsd {
+							codecs: vec![avc1.into()],
+						},
+						..Default::default()
+					},
+					..Default::default()
+				},
+			},
+			..Default::default()
+		};
+
+		let moov = mp4_atom::Moov {
+			trak: vec![trak],
+			mvex: Some(mp4_atom::Mvex {
+				mehd: None,
+				trex: vec![mp4_atom::Trex {
+					track_id: 1,
+					default_sample_description_index: 1,
+					..Default::default()
+				}],
+			}),
+			..Default::default()
+		};
+
+		let mut data = Vec::new();
+		moov.encode(&mut data).unwrap();
+		data
+	}
+
+	// A clockwise rotation in 16.16 fixed point, for multiples of 90 degrees.
+	fn rotate(degrees: i32) -> mp4_atom::Matrix {
+		let (cos, sin) = match degrees {
+			0 => (1, 0),
+			90 => (0, 1),
+			180 => (-1, 0),
+			270 => (0, -1),
+			_ => unreachable!(),
+		};
+
+		mp4_atom::Matrix {
+			a: cos << 16,
+			b: sin << 16,
+			c: -sin << 16,
+			d: cos << 16,
+			..Default::default()
+		}
+	}
+
+	fn fragment(tfdt: u64) -> Vec<u8> {
+		let mut traf = Vec::new();
+		mp4_atom::Tfhd {
+			track_id: 1,
+			..Default::default()
+		}
+		.encode(&mut traf)
+		.unwrap();
+		mp4_atom::Tfdt {
+			base_media_decode_time: tfdt,
+		}
+		.encode(&mut traf)
+		.unwrap();
+
+		// NOTE: mp4_atom::Trun always writes the first sample flags, so it's encoded by hand.
+		// A single sample with a duration and size, at the start of the mdat.
+		let trun: [u32; 6] = [24, u32::from_be_bytes(*b"trun"), 0x0000_0300, 1, 40, 4];
+		traf.extend(trun.iter().flat_map(|v| v.to_be_bytes()));
+
+		let mut moof = Vec::new();
+		mp4_atom::Mfhd { sequence_number: 1 }.encode(&mut moof).unwrap();
+		moof.extend_from_slice(&(traf.len() as u32 + 8).to_be_bytes());
+		moof.extend_from_slice(b"traf");
+		moof.extend(traf);
+
+		let mut data = Vec::new();
+		data.extend_from_slice(&(moof.len() as u32 + 8).to_be_bytes());
+		data.extend_from_slice(b"moof");
+		data.extend(moof);
+		mp4_atom::Mdat { data: vec![0; 4] }.encode(&mut data).unwrap();
+		data
+	}
+
+	fn emsg(presentation_time: EmsgTimestamp, id: u32) -> Vec<u8> {
+		let emsg = Emsg {
+			timescale: 90_000,
+			presentation_time,
+			event_duration: 90_000,
+			id,
+			scheme_id_uri: "urn:scte:scte35:2013:bin".to_string(),
+			value: String::new(),
+			message_data: vec![0xfc, id as u8],
+		};
+
+		let mut data = Vec::new();
+		emsg.encode(&mut data).unwrap();
+		data
+	}
+
+	#[tokio::test]
+	async fn emsg_events() {
+		let broadcast = BroadcastProducer::new();
+		let mut consumer = broadcast.consume();
+
+		let mut import = Import::new(broadcast);
+		import.parse(&init()).unwrap();
+
+		// An absolute event is written immediately.
+		import.parse(&emsg(EmsgTimestamp::Absolute(180_000), 1)).unwrap();
+
+		let catalog = consumer.catalog.next().now_or_never().unwrap().unwrap().unwrap();
+		let metadata = &catalog.metadata[0];
+		assert_eq!(metadata.config.scheme, "urn:scte:scte35:2013:bin");
+		assert_eq!(metadata.config.value, None);
+
+		let mut events: crate::EventConsumer = consumer.subscribe(&metadata.track).into();
+		let event = events.read().now_or_never().unwrap().unwrap().unwrap();
+		assert_eq!(
+			event,
+			Event {
+				timestamp: Timestamp::from_secs(2),
+				duration: Some(Timestamp::from_secs(1)),
+				id: 1,
+				payload: vec![0xfc, 1].into(),
+			}
+		);
+
+		// A relative event is written once the next fragment starts, at 5s + 0.5s.
+		import.parse(&emsg(EmsgTimestamp::Relative(45_000), 2)).unwrap();
+		assert!(events.read().now_or_never().is_none());
+
+		import.parse(&fragment(5000)).unwrap();
+
+		let event = events.read().now_or_never().unwrap().unwrap().unwrap();
+		assert_eq!(event.timestamp, Timestamp::from_millis(5500));
+		assert_eq!(event.id, 2);
+	}
+
+	#[test]
+	fn orientation() {
+		assert_eq!(super::orientation(&rotate(0)), (None, None));
+		assert_eq!(super::orientation(&rotate(90)), (Some(90.0), None));
+		assert_eq!(super::orientation(&rotate(180)), (Some(180.0), None));
+		assert_eq!(super::orientation(&rotate(270)), (Some(270.0), None));
+
+		// Flip horizontally, then rotate.
+		let mut matrix = rotate(90);
+		matrix.a = -matrix.a;
+		matrix.b = -matrix.b;
+		assert_eq!(super::orientation(&matrix), (Some(90.0), Some(true)));
+
+		let mut matrix = rotate(0);
+		matrix.a = -matrix.a;
+		assert_eq!(super::orientation(&matrix), (None, Some(true)));
+	}
+
+	#[tokio::test]
+	async fn measure() {
+		let broadcast = BroadcastProducer::new();
+		let mut consumer = broadcast.consume();
+
+		let mut import = Import::new(broadcast);
+		import.parse(&init()).unwrap();
+
+		let catalog = consumer.catalog.next().now_or_never().unwrap().unwrap().unwrap();
+		let config = &catalog.video[0].config;
+		assert_eq!(config.rotation, Some(90.0));
+		assert_eq!(config.flip, None);
+		assert_eq!(config.bitrate, Some(2_000_000));
+		assert_eq!(config.framerate, None);
+		assert_eq!(
+			config.color_space,
+			Some(VideoColorSpace {
+				primaries: Some("bt2020".to_string()),
+				transfer: Some("pq".to_string()),
+				matrix: Some("bt2020-ncl".to_string()),
+				full_range: Some(false),
+			})
+		);
+
+		// Each fragment is a single 40ms sample of 4 bytes, so nothing is measured until 1s.
+		for i in 0..24 {
+			import.parse(&fragment(i * 40)).unwrap();
+		}
+		assert!(consumer.catalog.next().now_or_never().is_none());
+
+		import.parse(&fragment(24 * 40)).unwrap();
+
+		let catalog = consumer.catalog.next().now_or_never().unwrap().unwrap().unwrap();
+		let config = &catalog.video[0].config;
+		assert_eq!(config.framerate, Some(25.0));
+		assert_eq!(config.bitrate, Some(800));
+		assert_eq!(config.rotation, Some(90.0));
+	}
 }
@@ -1,7 +1,8 @@
 mod error;
-//mod export;
+mod export;
 mod import;
+mod text;
 
 pub use error::*;
-//pub use export::*;
+pub use export::*;
 pub use import::*;
@@ -0,0 +1,21 @@
+use crate::catalog::TextCodec;
+use crate::webvtt;
+use bytes::Bytes;
+
+// An empty sample, used to fill the gaps between cues.
+pub(super) fn empty_sample(codec: &TextCodec) -> Bytes {
+	match codec {
+		TextCodec::WebVtt => Bytes::from_static(&webvtt::EMPTY_SAMPLE),
+		// A tx3g sample starts with the length of the text.
+		TextCodec::Tx3g => Bytes::from_static(&[0, 0]),
+		TextCodec::Unknown(_) => Bytes::new(),
+	}
+}
+
+pub(super) fn is_empty_sample(codec: &TextCodec, sample: &[u8]) -> bool {
+	match codec {
+		TextCodec::WebVtt => webvtt::decode_sample(sample).is_ok_and(|cues| cues.is_empty()),
+		TextCodec::Tx3g => sample.len() < 2 || sample[..2] == [0, 0],
+		TextCodec::Unknown(_) => sample.is_empty(),
+	}
+}
@@ -32,6 +32,9 @@ pub enum Error {
 	#[error("unsupported codec")]
 	UnsupportedCodec,
 
+	#[error("not retained by the publisher")]
+	NoHistory,
+
 	#[error("expected int")]
 	ExpectedInt(#[from] std::num::ParseIntError),
 
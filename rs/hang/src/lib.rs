@@ -1,9 +1,15 @@
 mod error;
 mod model;
 
+pub mod annexb;
 pub mod catalog;
 pub mod cmaf;
 pub mod feedback;
+pub mod mp4;
+pub mod mpegts;
+pub mod ogg;
+pub mod webm;
+pub mod webvtt;
 
 // export the moq-lite version in use
 pub use moq_lite;
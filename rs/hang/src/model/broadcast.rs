@@ -1,5 +1,6 @@
-use crate::catalog::{Audio, Catalog, CatalogConsumer, CatalogProducer, Video};
-use crate::model::{TrackConsumer, TrackProducer};
+use crate::catalog::{Audio, Catalog, CatalogConsumer, CatalogProducer, Clock, Metadata, Text, Video};
+use crate::model::{EventProducer, IndexProducer, RenditionConsumer, SyncConsumer, TrackConsumer, TrackProducer};
+use crate::Result;
 use moq_lite::Track;
 use web_async::spawn;
 
@@ -8,6 +9,12 @@ use web_async::spawn;
 pub struct BroadcastProducer {
 	catalog: CatalogProducer,
 	pub inner: moq_lite::BroadcastProducer,
+
+	// Report the wall clock time of keyframes written to video and audio tracks.
+	clock: bool,
+
+	// Publish an index track for video and audio tracks, so consumers can seek.
+	index: bool,
 }
 
 impl Default for BroadcastProducer {
@@ -22,7 +29,34 @@ impl BroadcastProducer {
 		let mut inner = moq_lite::BroadcastProducer::new();
 		inner.insert(catalog.consume().track);
 
-		Self { catalog, inner }
+		Self {
+			catalog,
+			inner,
+			clock: false,
+			index: false,
+		}
+	}
+
+	/// Periodically anchor the timestamp of video and audio keyframes to the current time in the catalog.
+	///
+	/// This should only be used for live media, as the timestamps are assumed to have just been captured.
+	pub fn with_clock(mut self) -> Self {
+		self.clock = true;
+		self
+	}
+
+	/// Publish an index track alongside each video and audio track, listed in the catalog so consumers can seek.
+	///
+	/// This is only useful if the tracks also retain older groups, see [TrackProducer::set_history].
+	pub fn with_index(mut self) -> Self {
+		self.index = true;
+		self
+	}
+
+	/// Set the mapping from media timestamps to wall clock time, for publishers that know the capture time.
+	pub fn set_clock(&mut self, clock: Clock) {
+		self.catalog.set_clock(Some(clock));
+		self.catalog.publish();
 	}
 
 	pub fn consume(&self) -> BroadcastConsumer {
@@ -60,18 +94,104 @@ impl BroadcastProducer {
 		});
 	}
 
-	pub fn create_video(&mut self, video: Video) -> TrackProducer {
-		let producer: TrackProducer = video.track.clone().produce().into();
+	/// Add a text track to the broadcast, where each frame is a [crate::Cue].
+	pub fn add_text(&mut self, track: TrackConsumer, info: Text) {
+		self.inner.insert(track.inner.clone());
+		self.catalog.add_text(info.clone());
+		self.catalog.publish();
+
+		let mut this = self.clone();
+		spawn(async move {
+			let _ = track.closed().await;
+			this.catalog.remove_text(&info);
+			this.catalog.publish();
+		});
+	}
+
+	/// Add a timed metadata track to the broadcast, where each frame is an [crate::Event].
+	pub fn add_metadata(&mut self, track: TrackConsumer, info: Metadata) {
+		self.inner.insert(track.inner.clone());
+		self.catalog.add_metadata(info.clone());
+		self.catalog.publish();
+
+		let mut this = self.clone();
+		spawn(async move {
+			let _ = track.closed().await;
+			this.catalog.remove_metadata(&info);
+			this.catalog.publish();
+		});
+	}
+
+	/// Replace the catalog entry for an existing video track, ex. after measuring the bitrate.
+	pub fn update_video(&mut self, info: Video) {
+		if let Some(video) = self.catalog.update().video.iter_mut().find(|v| v.track == info.track) {
+			// Keep the index track created by [Self::create_video].
+			let index = info.index.or_else(|| video.index.take());
+			*video = Video { index, ..info };
+		}
+
+		self.catalog.publish();
+	}
+
+	/// Replace the catalog entry for an existing audio track, ex. after measuring the bitrate.
+	pub fn update_audio(&mut self, info: Audio) {
+		if let Some(audio) = self.catalog.update().audio.iter_mut().find(|a| a.track == info.track) {
+			// Keep the index track created by [Self::create_audio].
+			let index = info.index.or_else(|| audio.index.take());
+			*audio = Audio { index, ..info };
+		}
+
+		self.catalog.publish();
+	}
+
+	/// Create a video track, along with an index track if enabled via [Self::with_index].
+	pub fn create_video(&mut self, mut video: Video) -> TrackProducer {
+		let (producer, index) = self.produce_media(&video.track);
+		video.index = index;
 		self.add_video(producer.consume(), video);
 		producer
 	}
 
-	pub fn create_audio(&mut self, audio: Audio) -> TrackProducer {
-		let producer: TrackProducer = audio.track.clone().produce().into();
+	/// Create an audio track, along with an index track if enabled via [Self::with_index].
+	pub fn create_audio(&mut self, mut audio: Audio) -> TrackProducer {
+		let (producer, index) = self.produce_media(&audio.track);
+		audio.index = index;
 		self.add_audio(producer.consume(), audio);
 		producer
 	}
 
+	fn produce_media(&mut self, track: &Track) -> (TrackProducer, Option<Track>) {
+		let producer: TrackProducer = track.clone().produce().into();
+
+		let (producer, index) = match self.index {
+			true => {
+				let index = IndexProducer::track(track);
+				let producer = producer.with_index(IndexProducer::new(self.inner.create(index.clone())));
+				(producer, Some(index))
+			}
+			false => (producer, None),
+		};
+
+		let producer = match self.clock {
+			true => producer.with_clock(self.catalog.clone()),
+			false => producer,
+		};
+
+		(producer, index)
+	}
+
+	pub fn create_text(&mut self, text: Text) -> TrackProducer {
+		let producer: TrackProducer = text.track.clone().produce().into();
+		self.add_text(producer.consume(), text);
+		producer
+	}
+
+	pub fn create_metadata(&mut self, metadata: Metadata) -> EventProducer {
+		let producer: TrackProducer = metadata.track.clone().produce().into();
+		self.add_metadata(producer.consume(), metadata);
+		producer.into()
+	}
+
 	/*
 	// Given a producer, publish the location track and update the catalog accordingly.
 	// If a handle is provided, then it can be used by peers to update our position.
@@ -116,6 +236,7 @@ impl BroadcastConsumer {
 		let catalog = Track {
 			name: Catalog::DEFAULT_NAME.to_string(),
 			priority: 100,
+			..Default::default()
 		};
 		let catalog = inner.subscribe(&catalog).into();
 
@@ -125,7 +246,17 @@ impl BroadcastConsumer {
 	/// Subscribe to a track, wrapping it in a [TrackConsumer].
 	/// If you don't want the wrapper, then use [self.inner.subscribe] instead.
 	pub fn subscribe(&self, track: &Track) -> TrackConsumer {
-		self.inner.subscribe(track).into()
+		TrackConsumer::new(self.inner.subscribe(track)).with_broadcast(self.inner.clone())
+	}
+
+	/// Subscribe to a rendition group, switching between the tracks automatically.
+	pub fn subscribe_renditions(&self, renditions: Vec<Video>) -> Result<RenditionConsumer> {
+		RenditionConsumer::new(self.inner.clone(), renditions)
+	}
+
+	/// Read multiple tracks with a shared latency target, interleaving the frames in decode order.
+	pub fn subscribe_sync(&self) -> SyncConsumer {
+		SyncConsumer::new(self.inner.clone())
 	}
 }
 
@@ -0,0 +1,56 @@
+use moq_lite::coding::*;
+
+use derive_more::Debug;
+
+use crate::model::{Frame, Timestamp};
+use crate::{Error, Result};
+
+/// A timed text cue, such as a subtitle or caption.
+///
+/// A cue is sent as a keyframe so it starts a new group.
+/// The frame timestamp is the start of the cue, and the payload is prefixed with the duration in microseconds.
+#[derive(Clone, Debug)]
+pub struct Cue {
+	pub start: Timestamp,
+	pub end: Timestamp,
+
+	/// The cue in the format of the track's [crate::catalog::TextCodec].
+	#[debug("{}", payload.len())]
+	pub payload: Bytes,
+}
+
+impl Cue {
+	/// Encode the cue as a frame, to be written to a [crate::TrackProducer].
+	pub fn encode(&self) -> Frame {
+		let duration = self.end.saturating_sub(self.start).as_micros() as u64;
+
+		let mut payload = BytesMut::with_capacity(duration.encode_size() + self.payload.len());
+		duration.encode(&mut payload);
+		payload.extend_from_slice(&self.payload);
+
+		Frame {
+			timestamp: self.start,
+			decode_timestamp: None,
+			duration: None,
+			keyframe: true,
+			payload: payload.freeze(),
+		}
+	}
+
+	/// Decode a cue from a frame read from a [crate::TrackConsumer].
+	pub fn decode(frame: Frame) -> Result<Self> {
+		let mut payload = frame.payload;
+		let duration = u64::decode(&mut payload)?;
+
+		let end = frame
+			.timestamp
+			.checked_add(Timestamp::from_micros(duration))
+			.ok_or(Error::InvalidFrame)?;
+
+		Ok(Self {
+			start: frame.timestamp,
+			end,
+			payload,
+		})
+	}
+}
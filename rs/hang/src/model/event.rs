@@ -0,0 +1,123 @@
+use moq_lite::coding::*;
+
+use derive_more::Debug;
+
+use crate::model::{Frame, Timestamp, TrackConsumer, TrackProducer};
+use crate::Result;
+
+/// A timed metadata event, such as an ID3 tag or SCTE-35 splice.
+///
+/// An event is sent as a keyframe so it starts a new group.
+/// The frame timestamp is the presentation time, and the payload is prefixed with the duration and ID.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Event {
+	/// The presentation time of the event, aligned with the media timestamps.
+	pub timestamp: Timestamp,
+
+	/// How long the event lasts, or None if unknown.
+	pub duration: Option<Timestamp>,
+
+	/// Identifies the event, so repeated events can be ignored.
+	pub id: u64,
+
+	/// The event in the format of the track's [crate::catalog::MetadataConfig].
+	#[debug("{}", payload.len())]
+	pub payload: Bytes,
+}
+
+impl Event {
+	/// Encode the event as a frame.
+	pub fn encode(&self) -> Frame {
+		// Zero means an unknown duration, so the duration is offset by one.
+		let duration = self.duration.map(|d| d.as_micros() as u64 + 1).unwrap_or_default();
+
+		let mut payload = BytesMut::with_capacity(duration.encode_size() + self.id.encode_size() + self.payload.len());
+		duration.encode(&mut payload);
+		self.id.encode(&mut payload);
+		payload.extend_from_slice(&self.payload);
+
+		Frame {
+			timestamp: self.timestamp,
+			decode_timestamp: None,
+			duration: None,
+			keyframe: true,
+			payload: payload.freeze(),
+		}
+	}
+
+	/// Decode an event from a frame.
+	pub fn decode(frame: Frame) -> Result<Self> {
+		let mut payload = frame.payload;
+
+		let duration = match u64::decode(&mut payload)? {
+			0 => None,
+			micros => Some(Timestamp::from_micros(micros - 1)),
+		};
+		let id = u64::decode(&mut payload)?;
+
+		Ok(Self {
+			timestamp: frame.timestamp,
+			duration,
+			id,
+			payload,
+		})
+	}
+}
+
+/// Produces a timed metadata track, writing each [Event] as a new group.
+#[derive(Clone)]
+pub struct EventProducer {
+	pub track: TrackProducer,
+}
+
+impl EventProducer {
+	pub fn new(track: TrackProducer) -> Self {
+		Self { track }
+	}
+
+	pub fn write(&mut self, event: Event) {
+		self.track.write(event.encode());
+	}
+
+	pub fn consume(&self) -> EventConsumer {
+		EventConsumer::new(self.track.consume())
+	}
+
+	pub fn finish(self) {
+		self.track.finish();
+	}
+}
+
+impl From<TrackProducer> for EventProducer {
+	fn from(track: TrackProducer) -> Self {
+		Self::new(track)
+	}
+}
+
+/// Consumes a timed metadata track, decoding each [Event].
+pub struct EventConsumer {
+	pub track: TrackConsumer,
+}
+
+impl EventConsumer {
+	pub fn new(track: TrackConsumer) -> Self {
+		Self { track }
+	}
+
+	pub async fn read(&mut self) -> Result<Option<Event>> {
+		match self.track.read().await? {
+			Some(frame) => Ok(Some(Event::decode(frame)?)),
+			None => Ok(None),
+		}
+	}
+
+	pub async fn closed(&self) -> Result<()> {
+		self.track.closed().await
+	}
+}
+
+impl From<TrackConsumer> for EventConsumer {
+	fn from(track: TrackConsumer) -> Self {
+		Self::new(track)
+	}
+}
@@ -2,13 +2,149 @@ use moq_lite::coding::*;
 
 use derive_more::Debug;
 
+use crate::catalog::Container;
+use crate::Result;
+
 pub type Timestamp = std::time::Duration;
 
+/// A frame of media, prefixed on the wire by a header containing the timing information.
+///
+/// With [Container::Native], the header consists of three varints, all in microseconds:
+/// - The decode timestamp.
+/// - The composition offset (presentation - decode), zigzag encoded as it may be negative.
+/// - The duration plus one, or zero if unknown.
+///
+/// With [Container::Legacy], the header is only the presentation timestamp.
 #[derive(Clone, Debug)]
 pub struct Frame {
+	/// The presentation timestamp.
 	pub timestamp: Timestamp,
+
+	/// The decode timestamp, if different from the presentation timestamp (ex. B-frames).
+	///
+	/// Frames are written in decode order, so this never decreases within a track.
+	pub decode_timestamp: Option<Timestamp>,
+
+	/// How long the frame is presented, if known.
+	pub duration: Option<Timestamp>,
+
 	pub keyframe: bool,
 
 	#[debug("{}", payload.len())]
 	pub payload: Bytes,
 }
+
+impl Frame {
+	/// The decode timestamp, which is the presentation timestamp unless there are B-frames.
+	pub fn dts(&self) -> Timestamp {
+		self.decode_timestamp.unwrap_or(self.timestamp)
+	}
+
+	pub(crate) fn encode_header(&self) -> Bytes {
+		let dts = self.dts().as_micros() as u64;
+		let offset = zigzag(self.timestamp.as_micros() as i64 - dts as i64);
+		let duration = self.duration.map(|d| d.as_micros() as u64 + 1).unwrap_or_default();
+
+		let mut header = BytesMut::with_capacity(dts.encode_size() + offset.encode_size() + duration.encode_size());
+		dts.encode(&mut header);
+		offset.encode(&mut header);
+		duration.encode(&mut header);
+		header.freeze()
+	}
+
+	pub(crate) fn decode(mut payload: Bytes, keyframe: bool, container: Container) -> Result<Self> {
+		if container == Container::Legacy {
+			let timestamp = u64::decode(&mut payload)?;
+
+			return Ok(Self {
+				timestamp: Timestamp::from_micros(timestamp),
+				decode_timestamp: None,
+				duration: None,
+				keyframe,
+				payload,
+			});
+		}
+
+		let dts = u64::decode(&mut payload)?;
+		let offset = unzigzag(u64::decode(&mut payload)?);
+		let duration = match u64::decode(&mut payload)? {
+			0 => None,
+			micros => Some(Timestamp::from_micros(micros - 1)),
+		};
+
+		let pts = dts.saturating_add_signed(offset);
+
+		Ok(Self {
+			timestamp: Timestamp::from_micros(pts),
+			decode_timestamp: (offset != 0).then(|| Timestamp::from_micros(dts)),
+			duration,
+			keyframe,
+			payload,
+		})
+	}
+}
+
+fn zigzag(value: i64) -> u64 {
+	((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+	(value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn header() {
+		let frame = |timestamp: u64, decode_timestamp: Option<u64>, duration: Option<u64>| Frame {
+			timestamp: Timestamp::from_millis(timestamp),
+			decode_timestamp: decode_timestamp.map(Timestamp::from_millis),
+			duration: duration.map(Timestamp::from_millis),
+			keyframe: false,
+			payload: Bytes::from_static(b"hi"),
+		};
+
+		// A B-frame presented before a later decode time, and a negative offset.
+		for frame in [
+			frame(100, None, None),
+			frame(100, Some(66), Some(33)),
+			frame(66, Some(100), None),
+		] {
+			let mut buffer = BytesMut::from(frame.encode_header().as_ref());
+			buffer.extend_from_slice(&frame.payload);
+
+			let decoded = Frame::decode(buffer.freeze(), false, Container::Native).unwrap();
+			assert_eq!(decoded.timestamp, frame.timestamp);
+			assert_eq!(decoded.decode_timestamp, frame.decode_timestamp);
+			assert_eq!(decoded.duration, frame.duration);
+			assert_eq!(decoded.payload, frame.payload);
+		}
+
+		// Without B-frames, the header is just the timestamp plus two zero bytes.
+		assert_eq!(frame(1, None, None).encode_header().as_ref(), [0x43, 0xe8, 0, 0]);
+	}
+
+	#[test]
+	fn legacy() {
+		// The original header is just the presentation timestamp.
+		let payload = Bytes::from_static(&[0x43, 0xe8, b'h', b'i']);
+
+		let frame = Frame::decode(payload, true, Container::Legacy).unwrap();
+		assert_eq!(frame.timestamp, Timestamp::from_millis(1));
+		assert_eq!(frame.decode_timestamp, None);
+		assert_eq!(frame.duration, None);
+		assert_eq!(frame.payload, "hi");
+	}
+
+	#[test]
+	fn zigzag() {
+		for value in [0, 1, -1, 33_000, -33_000, i64::MAX, i64::MIN] {
+			assert_eq!(unzigzag(super::zigzag(value)), value);
+		}
+
+		assert_eq!(super::zigzag(-1), 1);
+		assert_eq!(super::zigzag(1), 2);
+	}
+}
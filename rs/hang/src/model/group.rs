@@ -1,10 +1,9 @@
 use std::collections::VecDeque;
 
+use crate::catalog::Container;
 use crate::model::{Frame, Timestamp};
 use crate::Result;
 
-use moq_lite::coding::Decode;
-
 pub struct GroupConsumer {
 	// The group.
 	group: moq_lite::GroupConsumer,
@@ -15,8 +14,11 @@ pub struct GroupConsumer {
 	// The any buffered frames in the group.
 	buffered: VecDeque<Frame>,
 
-	// The max timestamp in the group
+	// The max decode timestamp in the group
 	max_timestamp: Option<Timestamp>,
+
+	// The layout of each frame header.
+	container: Container,
 }
 
 impl GroupConsumer {
@@ -26,9 +28,16 @@ impl GroupConsumer {
 			index: 0,
 			buffered: VecDeque::new(),
 			max_timestamp: None,
+			container: Container::default(),
 		}
 	}
 
+	/// Decode frames using the given header layout, from the catalog.
+	pub fn with_container(mut self, container: Container) -> Self {
+		self.container = container;
+		self
+	}
+
 	pub async fn read_frame(&mut self) -> Result<Option<Frame>> {
 		if let Some(frame) = self.buffered.pop_front() {
 			Ok(Some(frame))
@@ -38,22 +47,15 @@ impl GroupConsumer {
 	}
 
 	async fn read_frame_unbuffered(&mut self) -> Result<Option<Frame>> {
-		let mut payload = match self.group.read_frame().await? {
+		let payload = match self.group.read_frame().await? {
 			Some(payload) => payload,
 			None => return Ok(None),
 		};
 
-		let micros = u64::decode(&mut payload)?;
-		let timestamp = Timestamp::from_micros(micros);
-
-		let frame = Frame {
-			keyframe: (self.index == 0),
-			timestamp,
-			payload,
-		};
+		let frame = Frame::decode(payload, self.index == 0, self.container)?;
 
 		self.index += 1;
-		self.max_timestamp = Some(self.max_timestamp.unwrap_or_default().max(timestamp));
+		self.max_timestamp = Some(self.max_timestamp.unwrap_or_default().max(frame.dts()));
 
 		Ok(Some(frame))
 	}
@@ -76,6 +78,7 @@ impl GroupConsumer {
 		}
 	}
 
+	/// The maximum decode timestamp read so far.
 	pub fn max_timestamp(&self) -> Option<Timestamp> {
 		self.max_timestamp
 	}
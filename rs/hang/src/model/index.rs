@@ -0,0 +1,221 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use futures::FutureExt;
+use moq_lite::coding::*;
+
+use crate::model::Timestamp;
+use crate::{Error, Result};
+
+/// Produces an index track, listing the decode timestamp and group sequence of each keyframe in a media track.
+///
+/// Each group starts with a snapshot of every keyframe still retained by the publisher, followed by a frame per new keyframe.
+/// Each new keyframe also lists the oldest keyframe still retained, so consumers can drop older ones.
+/// A new group is started once the new keyframes outnumber the snapshot, so each keyframe costs a constant amount on average.
+/// Consumers only need the latest group to map a timestamp to a group, see [crate::TrackConsumer::seek].
+#[derive(Clone)]
+pub struct IndexProducer {
+	pub track: moq_lite::TrackProducer,
+
+	keyframes: BTreeMap<Timestamp, u64>,
+
+	// The current group, along with the size of its snapshot and the number of keyframes appended since.
+	group: Option<(moq_lite::GroupProducer, usize, usize)>,
+}
+
+impl IndexProducer {
+	// The minimum number of keyframes appended before starting a new group, so short histories aren't resent every time.
+	const MIN_APPENDED: usize = 16;
+
+	pub fn new(track: moq_lite::TrackProducer) -> Self {
+		Self {
+			track,
+			keyframes: BTreeMap::new(),
+			group: None,
+		}
+	}
+
+	/// The index track for the given media track.
+	pub fn track(media: &moq_lite::Track) -> moq_lite::Track {
+		moq_lite::Track {
+			name: format!("{}.index", media.name),
+			priority: media.priority,
+			..Default::default()
+		}
+	}
+
+	/// Add a keyframe and publish it, dropping keyframes older than the history.
+	///
+	/// The history is measured using the timestamps, which advance in real time for live media.
+	pub fn keyframe(&mut self, timestamp: Timestamp, sequence: u64, history: Duration) {
+		self.keyframes.insert(timestamp, sequence);
+
+		if let Some(cutoff) = timestamp.checked_sub(history) {
+			self.keyframes = self.keyframes.split_off(&cutoff);
+		}
+
+		if let Some((group, snapshot, appended)) = self.group.as_mut() {
+			if *appended < (*snapshot).max(Self::MIN_APPENDED) {
+				// Also write the oldest keyframe still retained, so consumers drop the rest.
+				let oldest = self
+					.keyframes
+					.first_key_value()
+					.map_or(timestamp, |(oldest, _)| *oldest);
+
+				let mut buf = BytesMut::new();
+				(timestamp.as_micros() as u64).encode(&mut buf);
+				sequence.encode(&mut buf);
+				(oldest.as_micros() as u64).encode(&mut buf);
+				group.write_frame(buf.freeze());
+
+				*appended += 1;
+				return;
+			}
+		}
+
+		if let Some((group, _, _)) = self.group.take() {
+			group.finish();
+		}
+
+		// The first frame is the number of keyframes in the snapshot.
+		let mut group = self.track.append_group();
+		let mut count = BytesMut::new();
+		(self.keyframes.len() as u64).encode(&mut count);
+		group.write_frame(count.freeze());
+
+		for (timestamp, sequence) in &self.keyframes {
+			let mut buf = BytesMut::new();
+			(timestamp.as_micros() as u64).encode(&mut buf);
+			sequence.encode(&mut buf);
+			group.write_frame(buf.freeze());
+		}
+
+		self.group = Some((group, self.keyframes.len(), 0));
+	}
+
+	pub fn consume(&self) -> IndexConsumer {
+		IndexConsumer::new(self.track.consume())
+	}
+
+	pub fn finish(mut self) {
+		if let Some((group, _, _)) = self.group.take() {
+			group.finish();
+		}
+
+		self.track.finish();
+	}
+}
+
+/// Consumes an index track, see [IndexProducer].
+pub struct IndexConsumer {
+	pub track: moq_lite::TrackConsumer,
+}
+
+impl IndexConsumer {
+	pub fn new(track: moq_lite::TrackConsumer) -> Self {
+		Self { track }
+	}
+
+	/// Return the latest index, mapping the decode timestamp of each keyframe to the sequence number of its group.
+	///
+	/// This waits for the snapshot at the start of the latest group, along with any keyframes appended so far.
+	pub async fn read(&mut self) -> Result<Option<BTreeMap<Timestamp, u64>>> {
+		let mut group = match self.track.next_group().await? {
+			Some(group) => group,
+			None => return Ok(None),
+		};
+
+		let mut count = group.read_frame().await?.ok_or(Error::EmptyGroup)?;
+		let count = u64::decode(&mut count)?;
+
+		let mut keyframes = BTreeMap::new();
+
+		for _ in 0..count {
+			let mut frame = group.read_frame().await?.ok_or(Error::EmptyGroup)?;
+			let micros = u64::decode(&mut frame)?;
+			let sequence = u64::decode(&mut frame)?;
+			keyframes.insert(Timestamp::from_micros(micros), sequence);
+		}
+
+		// Don't wait for keyframes that haven't been appended yet.
+		while let Some(frame) = group.read_frame().now_or_never() {
+			let Some(mut frame) = frame? else { break };
+			let micros = u64::decode(&mut frame)?;
+			let sequence = u64::decode(&mut frame)?;
+			let oldest = u64::decode(&mut frame)?;
+
+			keyframes.insert(Timestamp::from_micros(micros), sequence);
+			keyframes = keyframes.split_off(&Timestamp::from_micros(oldest));
+		}
+
+		Ok(Some(keyframes))
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn history() {
+		let mut producer = IndexProducer::new(moq_lite::Track::new("video.index").produce());
+		let mut consumer = producer.consume();
+
+		for second in 0..5 {
+			producer.keyframe(Timestamp::from_secs(second), second + 10, Duration::from_secs(2));
+		}
+
+		// Keyframes older than the history are dropped, even within the same group.
+		let index = consumer.read().now_or_never().unwrap().unwrap().unwrap();
+		let expected = BTreeMap::from([
+			(Timestamp::from_secs(2), 12),
+			(Timestamp::from_secs(3), 13),
+			(Timestamp::from_secs(4), 14),
+		]);
+		assert_eq!(index, expected);
+
+		// A new group only snapshots the keyframes within the history.
+		let last = IndexProducer::MIN_APPENDED as u64 + 5;
+		for second in 5..=last {
+			producer.keyframe(Timestamp::from_secs(second), second + 10, Duration::from_secs(2));
+		}
+
+		let index = consumer.read().now_or_never().unwrap().unwrap().unwrap();
+		assert_eq!(index.len(), 3);
+		assert_eq!(index.get(&Timestamp::from_secs(last)), Some(&(last + 10)));
+	}
+
+	#[test]
+	fn deltas() {
+		let mut producer = IndexProducer::new(moq_lite::Track::new("video.index").produce());
+		let mut consumer = producer.consume();
+		let history = Duration::from_secs(60);
+
+		producer.keyframe(Timestamp::from_secs(0), 0, history);
+		let mut group = consumer.track.next_group().now_or_never().unwrap().unwrap().unwrap();
+
+		// Each keyframe is appended to the same group as a single frame.
+		for second in 1..=IndexProducer::MIN_APPENDED as u64 {
+			producer.keyframe(Timestamp::from_secs(second), second, history);
+		}
+
+		let mut frames = 0;
+		while let Some(Ok(Some(_))) = group.read_frame().now_or_never() {
+			frames += 1;
+		}
+		assert_eq!(frames, 2 + IndexProducer::MIN_APPENDED);
+
+		// The next keyframe starts a new group with a full snapshot.
+		let last = IndexProducer::MIN_APPENDED as u64 + 1;
+		producer.keyframe(Timestamp::from_secs(last), last, history);
+
+		let index = consumer.read().now_or_never().unwrap().unwrap().unwrap();
+		assert_eq!(index.len() as u64, last + 1);
+		assert_eq!(index.get(&Timestamp::from_secs(last)), Some(&last));
+
+		// Appended keyframes are included when reading the latest group.
+		producer.keyframe(Timestamp::from_secs(last + 1), last + 1, history);
+		let index = producer.consume().read().now_or_never().unwrap().unwrap().unwrap();
+		assert_eq!(index.len() as u64, last + 2);
+	}
+}
@@ -1,13 +1,23 @@
 mod broadcast;
+mod cue;
+mod event;
 mod frame;
 mod group;
+mod index;
 mod location;
+mod rendition;
 mod room;
+mod sync;
 mod track;
 
 pub use broadcast::*;
+pub use cue::*;
+pub use event::*;
 pub use frame::*;
 pub use group::*;
+pub use index::*;
 pub use location::*;
+pub use rendition::*;
 pub use room::*;
+pub use sync::*;
 pub use track::*;
@@ -0,0 +1,474 @@
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::catalog::{Container, Video};
+use crate::model::{Frame, Timestamp, TrackConsumer};
+use crate::{Error, Result};
+
+// How often the throughput and buffer are measured.
+const WINDOW: Duration = Duration::from_secs(1);
+
+// Only use this fraction of the measured throughput when switching down, leaving some headroom.
+const SAFETY: f64 = 0.8;
+
+// The number of windows in a row with a full buffer before switching up.
+const UPGRADE: u32 = 3;
+
+/// Consumes a rendition group (ex. an ABR ladder), switching between the tracks based on throughput and buffer.
+///
+/// A switch only happens at a keyframe of the new rendition, after the last frame returned.
+/// The result is a single stream of frames in decode order, as if there was only one track.
+/// The decoder must be reconfigured using [Self::rendition] whenever a switch happens, which is always a keyframe.
+pub struct RenditionConsumer {
+	broadcast: moq_lite::BroadcastConsumer,
+
+	// The renditions, in ascending order of bitrate, followed by any without a bitrate.
+	renditions: Vec<Video>,
+
+	// The rendition currently being returned.
+	current: usize,
+	track: TrackConsumer,
+
+	// Set when the current track has ended, so we only wait for the pending rendition.
+	ended: bool,
+
+	// The rendition we're switching to, until it produces a keyframe.
+	pending: Option<Pending>,
+
+	// The decode timestamp of the last frame returned.
+	position: Option<Timestamp>,
+
+	// Measures the current rendition, if switching automatically.
+	abr: Abr,
+	automatic: bool,
+
+	// The target buffer size, also used to skip groups in each track.
+	latency: Duration,
+
+	// The layout of each frame header, shared by all renditions.
+	container: Container,
+}
+
+struct Pending {
+	index: usize,
+	track: TrackConsumer,
+
+	// The first keyframe after the current position.
+	keyframe: Option<Frame>,
+}
+
+impl RenditionConsumer {
+	/// Start with the lowest bitrate rendition, switching up once the buffer is healthy.
+	///
+	/// Renditions without a bitrate are sorted last by resolution, and are only used when switching manually.
+	pub fn new(broadcast: moq_lite::BroadcastConsumer, mut renditions: Vec<Video>) -> Result<Self> {
+		renditions.sort_by_key(|video| video.config.quality());
+
+		let first = renditions.first().ok_or(Error::MissingTrack)?;
+		let latency = Duration::from_millis(500);
+
+		let mut track: TrackConsumer = broadcast.subscribe(&first.track).into();
+		track.set_latency(latency);
+
+		Ok(Self {
+			broadcast,
+			renditions,
+			current: 0,
+			track,
+			ended: false,
+			pending: None,
+			position: None,
+			abr: Abr::new(latency),
+			automatic: true,
+			latency,
+			container: Container::default(),
+		})
+	}
+
+	/// The renditions, in ascending order of bitrate, followed by any without a bitrate.
+	pub fn renditions(&self) -> &[Video] {
+		&self.renditions
+	}
+
+	/// The rendition of the last frame returned.
+	pub fn rendition(&self) -> &Video {
+		&self.renditions[self.current]
+	}
+
+	/// Switch to the rendition at the given index, starting at its next keyframe.
+	///
+	/// This doesn't disable automatic switching; use [Self::set_automatic] for that.
+	pub fn switch(&mut self, index: usize) {
+		if index == self.current || index >= self.renditions.len() {
+			self.pending = None;
+			return;
+		}
+
+		if self.pending.as_ref().is_some_and(|pending| pending.index == index) {
+			return;
+		}
+
+		let mut track: TrackConsumer = self.broadcast.subscribe(&self.renditions[index].track).into();
+		track.set_latency(self.latency);
+		track.set_container(self.container);
+
+		tracing::debug!(from = ?self.rendition().track.name, to = ?self.renditions[index].track.name, "switching rendition");
+
+		self.pending = Some(Pending {
+			index,
+			track,
+			keyframe: None,
+		});
+	}
+
+	/// Enable or disable switching based on the measured throughput and buffer.
+	pub fn set_automatic(&mut self, automatic: bool) {
+		self.automatic = automatic;
+	}
+
+	/// Set the target buffer size, which is also the maximum latency before skipping groups.
+	pub fn set_latency(&mut self, latency: Duration) {
+		self.latency = latency;
+		self.abr.buffer = latency;
+		self.track.set_latency(latency);
+
+		if let Some(pending) = self.pending.as_mut() {
+			pending.track.set_latency(latency);
+		}
+	}
+
+	/// Set the layout of each frame header, from [crate::catalog::Catalog::container].
+	pub fn set_container(&mut self, container: Container) {
+		self.container = container;
+		self.track.set_container(container);
+
+		if let Some(pending) = self.pending.as_mut() {
+			pending.track.set_container(container);
+		}
+	}
+
+	/// Return the next frame in decode order, switching renditions at keyframes.
+	pub async fn read(&mut self) -> Result<Option<Frame>> {
+		loop {
+			let position = self.position;
+			let deadline = self.abr.deadline().filter(|_| self.automatic);
+
+			tokio::select! {
+				biased;
+				// Look for the first keyframe after our position in the pending rendition.
+				Some(res) = async {
+					let pending = self.pending.as_mut().filter(|pending| pending.keyframe.is_none())?;
+					Some(pending.track.read().await)
+				} => {
+					let pending = self.pending.as_mut().expect("pending rendition");
+
+					match res? {
+						Some(frame) if frame.keyframe && position.is_none_or(|position| frame.dts() > position) => {
+							pending.keyframe = Some(frame);
+						}
+						Some(_) => continue,
+						None => {
+							tracing::warn!(track = ?self.renditions[pending.index].track.name, "rendition ended before switching");
+							self.pending = None;
+						}
+					}
+
+					if self.ended {
+						if let Some(frame) = self.splice() {
+							return Ok(Some(frame));
+						}
+
+						// Both tracks have ended.
+						if self.pending.is_none() {
+							return Ok(None);
+						}
+					}
+				}
+				res = self.track.read(), if !self.ended => {
+					let Some(frame) = res? else {
+						self.ended = true;
+
+						match self.pending.as_ref() {
+							Some(pending) if pending.keyframe.is_some() => return Ok(self.splice()),
+							Some(_) => continue,
+							None => return Ok(None),
+						}
+					};
+
+					// Switch as soon as the current track reaches the pending keyframe.
+					let keyframe = self.pending.as_ref().and_then(|pending| pending.keyframe.as_ref());
+					if keyframe.is_some_and(|keyframe| frame.dts() >= keyframe.dts()) {
+						return Ok(self.splice());
+					}
+
+					self.abr.record(Instant::now(), &frame);
+					self.position = Some(frame.dts());
+
+					return Ok(Some(frame));
+				}
+				_ = async { tokio::time::sleep_until(deadline?).await; Some(()) }, if deadline.is_some() => {
+					let bitrates: Vec<Option<u64>> = self.renditions.iter().map(|video| video.config.bitrate).collect();
+
+					if let Some(index) = self.abr.decide(Instant::now(), &bitrates, self.current, self.latency) {
+						self.switch(index);
+					}
+				}
+				else => return Ok(None),
+			}
+		}
+	}
+
+	// Replace the current track with the pending rendition, returning its keyframe.
+	fn splice(&mut self) -> Option<Frame> {
+		let pending = self.pending.take()?;
+		let keyframe = pending.keyframe?;
+
+		self.current = pending.index;
+		self.track = pending.track;
+		self.ended = false;
+		self.position = Some(keyframe.dts());
+
+		self.abr.reset();
+		self.abr.record(Instant::now(), &keyframe);
+
+		Some(keyframe)
+	}
+
+	pub async fn closed(&self) -> Result<()> {
+		self.track.closed().await
+	}
+}
+
+// Measures the throughput and a virtual playback buffer, deciding when to switch renditions.
+//
+// The buffer starts full at the target latency and drains whenever media arrives slower than real-time.
+// When it's less than half full, we switch down to a rendition that fits within the measured throughput.
+// After a few windows with a full buffer, we probe the next rendition up.
+struct Abr {
+	// The wall clock time and decode timestamp at the start of the window.
+	start: Option<(Instant, Timestamp)>,
+
+	// The latest decode timestamp and the number of bytes received during the window.
+	latest: Timestamp,
+	bytes: u64,
+
+	buffer: Duration,
+	healthy: u32,
+}
+
+impl Abr {
+	fn new(latency: Duration) -> Self {
+		Self {
+			start: None,
+			latest: Timestamp::ZERO,
+			bytes: 0,
+			buffer: latency,
+			healthy: 0,
+		}
+	}
+
+	// Start a new window, without changing the buffer.
+	fn reset(&mut self) {
+		self.start = None;
+		self.bytes = 0;
+		self.healthy = 0;
+	}
+
+	fn record(&mut self, now: Instant, frame: &Frame) {
+		self.start.get_or_insert((now, frame.dts()));
+		self.latest = self.latest.max(frame.dts());
+		self.bytes += frame.payload.len() as u64;
+	}
+
+	fn deadline(&self) -> Option<Instant> {
+		self.start.map(|(start, _)| start + WINDOW)
+	}
+
+	// End the window, returning the index of the rendition to switch to, if any.
+	// Renditions without a bitrate are never chosen, as we can't tell if they would fit.
+	fn decide(&mut self, now: Instant, bitrates: &[Option<u64>], current: usize, latency: Duration) -> Option<usize> {
+		let (start, base) = self.start.replace((now, self.latest))?;
+
+		let wall = now.saturating_duration_since(start);
+		let media = self.latest.saturating_sub(base);
+		let throughput = self.bytes as f64 * 8.0 / wall.as_secs_f64().max(0.001);
+		self.bytes = 0;
+
+		self.buffer = (self.buffer + media).saturating_sub(wall).min(latency);
+
+		if self.buffer < latency / 2 {
+			self.healthy = 0;
+
+			// Switch down at least one rendition, or further if the throughput is much lower.
+			let fits = bitrates[..current]
+				.iter()
+				.rposition(|bitrate| bitrate.is_some_and(|bitrate| bitrate as f64 <= throughput * SAFETY))
+				.unwrap_or(0);
+
+			return (current > 0).then_some(fits);
+		}
+
+		if self.buffer < latency {
+			self.healthy = 0;
+			return None;
+		}
+
+		self.healthy += 1;
+		if self.healthy >= UPGRADE && bitrates.get(current + 1).is_some_and(Option::is_some) {
+			self.healthy = 0;
+			return Some(current + 1);
+		}
+
+		None
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::catalog::{VideoConfig, H264};
+	use crate::model::{BroadcastProducer, TrackProducer};
+	use bytes::Bytes;
+	use futures::FutureExt;
+	use moq_lite::Track;
+
+	fn video(name: &str, bitrate: u64) -> Video {
+		Video {
+			track: Track {
+				name: name.to_string(),
+				priority: 1,
+				..Default::default()
+			},
+			config: VideoConfig {
+				codec: H264 {
+					profile: 0x64,
+					constraints: 0x00,
+					level: 0x1f,
+				}
+				.into(),
+				description: None,
+				coded_width: None,
+				coded_height: None,
+				display_ratio_width: None,
+				display_ratio_height: None,
+				bitrate: Some(bitrate),
+				framerate: None,
+				optimize_for_latency: None,
+				color_space: None,
+				rotation: None,
+				flip: None,
+			},
+			rendition_group: Some("main".to_string()),
+			index: None,
+		}
+	}
+
+	fn frame(millis: u64, keyframe: bool, payload: &'static [u8]) -> Frame {
+		Frame {
+			timestamp: Timestamp::from_millis(millis),
+			decode_timestamp: None,
+			duration: None,
+			keyframe,
+			payload: Bytes::from_static(payload),
+		}
+	}
+
+	fn read(consumer: &mut RenditionConsumer) -> Option<Frame> {
+		consumer.read().now_or_never().expect("blocked").unwrap()
+	}
+
+	#[tokio::test]
+	async fn splice() {
+		let mut broadcast = BroadcastProducer::new();
+		let mut low: TrackProducer = broadcast.create_video(video("low", 1_000_000));
+		let mut high: TrackProducer = broadcast.create_video(video("high", 4_000_000));
+
+		let renditions = vec![video("high", 4_000_000), video("low", 1_000_000)];
+		let mut consumer = RenditionConsumer::new(broadcast.consume().inner, renditions).unwrap();
+		consumer.set_automatic(false);
+		assert_eq!(consumer.rendition().track.name, "low");
+
+		low.write(frame(0, true, b"low"));
+		low.write(frame(33, false, b"low"));
+		assert_eq!(read(&mut consumer).unwrap().timestamp, Timestamp::from_millis(0));
+		assert_eq!(read(&mut consumer).unwrap().timestamp, Timestamp::from_millis(33));
+
+		consumer.switch(1);
+
+		// The first keyframe isn't after our position, so it's skipped.
+		high.write(frame(33, true, b"high"));
+		high.write(frame(66, true, b"high"));
+		low.write(frame(66, false, b"low"));
+
+		// The low frame at the same timestamp is replaced by the high keyframe.
+		let frame = read(&mut consumer).unwrap();
+		assert_eq!(frame.timestamp, Timestamp::from_millis(66));
+		assert!(frame.keyframe);
+		assert_eq!(frame.payload, "high");
+		assert_eq!(consumer.rendition().track.name, "high");
+
+		high.write(self::frame(99, false, b"high"));
+		assert_eq!(read(&mut consumer).unwrap().payload, "high");
+	}
+
+	#[test]
+	fn decide() {
+		let bitrates = [Some(1_000_000), Some(2_000_000), Some(4_000_000), None];
+		let latency = Duration::from_millis(500);
+		let now = Instant::now();
+
+		let window = |abr: &mut Abr, start: Instant, media: u64, bytes: u64| {
+			abr.start = Some((start, Timestamp::ZERO));
+			abr.latest = Timestamp::from_millis(media);
+			abr.bytes = bytes;
+			abr.decide(start + WINDOW, &bitrates, 2, latency)
+		};
+
+		// Falling 400ms behind at 1.2Mb/s switches down to the lowest rendition.
+		let mut abr = Abr::new(latency);
+		assert_eq!(window(&mut abr, now, 600, 150_000), Some(0));
+
+		// Falling 100ms behind keeps the same rendition.
+		let mut abr = Abr::new(latency);
+		assert_eq!(window(&mut abr, now, 900, 500_000), None);
+		assert_eq!(abr.buffer, Duration::from_millis(400));
+
+		// Keeping up for a few windows probes the next rendition.
+		let mut abr = Abr::new(latency);
+		abr.latest = Timestamp::from_secs(1);
+		for _ in 1..UPGRADE {
+			abr.start = Some((now, Timestamp::ZERO));
+			assert_eq!(abr.decide(now + WINDOW, &bitrates, 1, latency), None);
+		}
+		abr.start = Some((now, Timestamp::ZERO));
+		assert_eq!(abr.decide(now + WINDOW, &bitrates, 1, latency), Some(2));
+
+		// The rendition without a bitrate is never probed.
+		for _ in 0..UPGRADE {
+			abr.start = Some((now, Timestamp::ZERO));
+			assert_eq!(abr.decide(now + WINDOW, &bitrates, 2, latency), None);
+		}
+	}
+
+	#[tokio::test]
+	async fn order() {
+		let broadcast = BroadcastProducer::new();
+
+		let mut unknown = video("unknown", 0);
+		unknown.config.bitrate = None;
+		unknown.config.coded_width = Some(320);
+		unknown.config.coded_height = Some(240);
+
+		let renditions = vec![unknown, video("high", 4_000_000), video("low", 1_000_000)];
+		let consumer = RenditionConsumer::new(broadcast.consume().inner, renditions).unwrap();
+
+		// Renditions are sorted by bitrate, with any missing a bitrate last.
+		let names: Vec<_> = consumer
+			.renditions()
+			.iter()
+			.map(|video| video.track.name.as_str())
+			.collect();
+		assert_eq!(names, ["low", "high", "unknown"]);
+	}
+}
@@ -0,0 +1,472 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use futures::{stream::FuturesUnordered, StreamExt};
+use tokio::time::Instant;
+
+use crate::catalog::{Audio, Container, Video};
+use crate::model::{Frame, Timestamp, TrackConsumer};
+use crate::Result;
+
+// A jump in timestamps smaller than this is treated as a low frame rate rather than a gap.
+const MIN_GAP: Duration = Duration::from_millis(250);
+
+/// Returned by [SyncConsumer::read].
+#[derive(Debug)]
+pub enum SyncEvent {
+	/// The next frame in decode order, along with the index of its track.
+	Frame(usize, Frame),
+
+	/// Frames were skipped for the track, from the first timestamp up until the second.
+	///
+	/// This happens when a track falls too far behind, or when another track skipped and this one follows to stay in sync.
+	Gap(usize, Timestamp, Timestamp),
+
+	/// The newest track is this far ahead of the oldest track.
+	///
+	/// This is reported when it exceeds the latency target, and again once the tracks are back in sync.
+	Drift(Duration),
+}
+
+/// Reads multiple tracks from a broadcast, interleaving the frames in decode order.
+///
+/// All tracks share a single latency target, used to skip groups in each track.
+/// When one track skips ahead, the others skip to the same timestamp so playback stays in sync.
+/// A track that stops producing frames is ignored after waiting for the latency target, so it can't stall the others.
+pub struct SyncConsumer {
+	broadcast: moq_lite::BroadcastConsumer,
+	tracks: Vec<SyncTrack>,
+
+	latency: Duration,
+	container: Container,
+
+	// The decode timestamp of the last frame returned.
+	position: Option<Timestamp>,
+
+	// When we'll give up waiting for tracks without a frame.
+	deadline: Option<Instant>,
+
+	// Gaps and drift waiting to be returned.
+	events: VecDeque<SyncEvent>,
+	drifting: bool,
+}
+
+struct SyncTrack {
+	consumer: TrackConsumer,
+
+	// Video can only resume at a keyframe after skipping.
+	video: bool,
+
+	// The next frame to return.
+	head: Option<Frame>,
+
+	// The maximum decode timestamp received.
+	received: Option<Timestamp>,
+
+	// Drop frames until this timestamp, to catch up with the other tracks.
+	skip: Option<Timestamp>,
+
+	// The timestamp of the first frame dropped, reported once we resume.
+	gap: Option<Timestamp>,
+
+	// Set when we gave up waiting for the next frame, cleared when it arrives.
+	stalled: bool,
+	ended: bool,
+}
+
+impl SyncConsumer {
+	pub fn new(broadcast: moq_lite::BroadcastConsumer) -> Self {
+		Self {
+			broadcast,
+			tracks: Vec::new(),
+			latency: Duration::from_millis(500),
+			container: Container::default(),
+			position: None,
+			deadline: None,
+			events: VecDeque::new(),
+			drifting: false,
+		}
+	}
+
+	/// Subscribe to a video track, returning the index used by [SyncEvent].
+	pub fn subscribe_video(&mut self, video: &Video) -> usize {
+		self.subscribe(&video.track, true)
+	}
+
+	/// Subscribe to an audio track, returning the index used by [SyncEvent].
+	pub fn subscribe_audio(&mut self, audio: &Audio) -> usize {
+		self.subscribe(&audio.track, false)
+	}
+
+	fn subscribe(&mut self, track: &moq_lite::Track, video: bool) -> usize {
+		let mut consumer: TrackConsumer = self.broadcast.subscribe(track).into();
+		consumer.set_latency(self.latency);
+		consumer.set_container(self.container);
+
+		self.tracks.push(SyncTrack {
+			consumer,
+			video,
+			head: None,
+			received: None,
+			skip: None,
+			gap: None,
+			stalled: false,
+			ended: false,
+		});
+
+		self.tracks.len() - 1
+	}
+
+	/// Set the latency target shared by all tracks.
+	pub fn set_latency(&mut self, latency: Duration) {
+		self.latency = latency;
+
+		for track in &mut self.tracks {
+			track.consumer.set_latency(latency);
+		}
+	}
+
+	/// Set the layout of each frame header, from [crate::catalog::Catalog::container].
+	pub fn set_container(&mut self, container: Container) {
+		self.container = container;
+
+		for track in &mut self.tracks {
+			track.consumer.set_container(container);
+		}
+	}
+
+	/// How far the newest track is ahead of the oldest track, based on the frames received.
+	pub fn drift(&self) -> Duration {
+		let received = self
+			.tracks
+			.iter()
+			.filter(|track| !track.ended)
+			.filter_map(|track| track.received);
+
+		let (min, max) = received.fold((None, None), |(min, max), timestamp| {
+			(
+				Some(min.map_or(timestamp, |min: Timestamp| min.min(timestamp))),
+				Some(max.map_or(timestamp, |max: Timestamp| max.max(timestamp))),
+			)
+		});
+
+		match (min, max) {
+			(Some(min), Some(max)) => max - min,
+			_ => Duration::ZERO,
+		}
+	}
+
+	/// Return the next frame in decode order across all tracks, or a gap or drift report.
+	///
+	/// Returns None once every track has ended.
+	pub async fn read(&mut self) -> Result<Option<SyncEvent>> {
+		loop {
+			if let Some(event) = self.events.pop_front() {
+				return Ok(Some(event));
+			}
+
+			// A track without a frame can only hold up frames after the last one it received.
+			let earliest = self
+				.tracks
+				.iter()
+				.filter_map(|track| track.head.as_ref())
+				.map(Frame::dts)
+				.min();
+			let waiting = self.tracks.iter().any(|track| {
+				!track.ended
+					&& !track.stalled
+					&& track.head.is_none()
+					&& earliest.is_none_or(|earliest| track.received.is_none_or(|received| received < earliest))
+			});
+
+			if !waiting {
+				if let Some(event) = self.next() {
+					return Ok(Some(event));
+				}
+
+				if self.tracks.iter().all(|track| track.ended) {
+					return Ok(None);
+				}
+			} else if self.deadline.is_none() && self.tracks.iter().any(|track| track.head.is_some()) {
+				// Don't wait longer than the latency target for the other tracks.
+				self.deadline = Some(Instant::now() + self.latency);
+			}
+
+			let deadline = self.deadline;
+
+			let next = {
+				let mut reading: FuturesUnordered<_> = self
+					.tracks
+					.iter_mut()
+					.enumerate()
+					.filter(|(_, track)| !track.ended && track.head.is_none())
+					.map(|(index, track)| async move { (index, track.consumer.read().await) })
+					.collect();
+
+				tokio::select! {
+					Some(next) = reading.next() => Some(next),
+					_ = async { tokio::time::sleep_until(deadline?).await; Some(()) }, if deadline.is_some() => None,
+					else => None,
+				}
+			};
+
+			match next {
+				Some((index, res)) => self.receive(index, res?),
+				None => {
+					for track in &mut self.tracks {
+						if !track.ended && track.head.is_none() && !track.stalled {
+							tracing::debug!(track = ?track.consumer.inner.info.name, latency = ?self.latency, "track stalled");
+							track.stalled = true;
+						}
+					}
+
+					self.deadline = None;
+				}
+			}
+		}
+	}
+
+	// Return the buffered frame with the smallest decode timestamp.
+	fn next(&mut self) -> Option<SyncEvent> {
+		let (index, track) = self
+			.tracks
+			.iter_mut()
+			.enumerate()
+			.filter(|(_, track)| track.head.is_some())
+			.min_by_key(|(_, track)| track.head.as_ref().map(Frame::dts))?;
+
+		let frame = track.head.take()?;
+		self.position = Some(self.position.unwrap_or_default().max(frame.dts()));
+		self.deadline = None;
+
+		Some(SyncEvent::Frame(index, frame))
+	}
+
+	fn receive(&mut self, index: usize, frame: Option<Frame>) {
+		let latency = self.latency;
+		let track = &mut self.tracks[index];
+
+		let Some(frame) = frame else {
+			track.ended = true;
+			return;
+		};
+
+		let dts = frame.dts();
+		track.stalled = false;
+
+		// The track consumer skipped a group, so the other tracks follow.
+		if let Some(received) = track.received.filter(|received| dts > *received + latency.max(MIN_GAP)) {
+			self.events.push_back(SyncEvent::Gap(index, received, dts));
+
+			for (other, track) in self.tracks.iter_mut().enumerate() {
+				if other == index || track.ended {
+					continue;
+				}
+
+				track.skip = Some(track.skip.unwrap_or_default().max(dts));
+
+				if let Some(head) = track.head.take_if(|head| head.dts() < dts) {
+					track.gap.get_or_insert(head.dts());
+				}
+			}
+		}
+
+		let track = &mut self.tracks[index];
+		track.received = Some(track.received.unwrap_or_default().max(dts));
+
+		// Drop frames that arrived too late to be played in order.
+		if self.position.is_some_and(|position| dts + latency < position) {
+			track.skip = Some(track.skip.unwrap_or_default().max(self.position.unwrap_or_default()));
+		}
+
+		if let Some(skip) = track.skip {
+			if dts < skip || (track.video && !frame.keyframe) {
+				track.gap.get_or_insert(dts);
+				return;
+			}
+
+			track.skip = None;
+		}
+
+		if let Some(gap) = track.gap.take() {
+			self.events.push_back(SyncEvent::Gap(index, gap, dts));
+		}
+
+		track.head = Some(frame);
+
+		let drift = self.drift();
+		if drift > latency && !self.drifting {
+			tracing::debug!(?drift, ?latency, "tracks are out of sync");
+			self.drifting = true;
+			self.events.push_back(SyncEvent::Drift(drift));
+		} else if drift <= latency / 2 && self.drifting {
+			self.drifting = false;
+			self.events.push_back(SyncEvent::Drift(drift));
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::catalog::{AudioCodec, AudioConfig, VideoConfig, H264};
+	use crate::model::{BroadcastProducer, TrackProducer};
+	use bytes::Bytes;
+	use moq_lite::Track;
+
+	fn video() -> Video {
+		Video {
+			track: Track {
+				name: "video".to_string(),
+				priority: 1,
+				..Default::default()
+			},
+			config: VideoConfig {
+				codec: H264 {
+					profile: 0x64,
+					constraints: 0x00,
+					level: 0x1f,
+				}
+				.into(),
+				description: None,
+				coded_width: None,
+				coded_height: None,
+				display_ratio_width: None,
+				display_ratio_height: None,
+				bitrate: None,
+				framerate: None,
+				optimize_for_latency: None,
+				color_space: None,
+				rotation: None,
+				flip: None,
+			},
+			rendition_group: None,
+			index: None,
+		}
+	}
+
+	fn audio() -> Audio {
+		Audio {
+			track: Track {
+				name: "audio".to_string(),
+				priority: 2,
+				..Default::default()
+			},
+			config: AudioConfig {
+				codec: AudioCodec::Opus,
+				sample_rate: 48_000,
+				channel_count: 2,
+				bitrate: None,
+				description: None,
+			},
+			index: None,
+		}
+	}
+
+	fn frame(millis: u64, keyframe: bool) -> Frame {
+		Frame {
+			timestamp: Timestamp::from_millis(millis),
+			decode_timestamp: None,
+			duration: None,
+			keyframe,
+			payload: Bytes::from_static(b"frame"),
+		}
+	}
+
+	fn setup() -> (BroadcastProducer, TrackProducer, TrackProducer, SyncConsumer) {
+		let mut broadcast = BroadcastProducer::new();
+		let video_track = broadcast.create_video(video());
+		let audio_track = broadcast.create_audio(audio());
+
+		let mut consumer = SyncConsumer::new(broadcast.consume().inner);
+		assert_eq!(consumer.subscribe_video(&video()), 0);
+		assert_eq!(consumer.subscribe_audio(&audio()), 1);
+
+		(broadcast, video_track, audio_track, consumer)
+	}
+
+	async fn read(consumer: &mut SyncConsumer) -> SyncEvent {
+		let res = tokio::time::timeout(Duration::from_secs(1), consumer.read()).await;
+		res.expect("blocked").unwrap().expect("ended")
+	}
+
+	async fn frame_of(consumer: &mut SyncConsumer) -> (usize, u128) {
+		match read(consumer).await {
+			SyncEvent::Frame(index, frame) => (index, frame.timestamp.as_millis()),
+			event => panic!("unexpected event: {event:?}"),
+		}
+	}
+
+	#[tokio::test]
+	async fn interleave() {
+		let (_broadcast, mut video, mut audio, mut consumer) = setup();
+
+		video.write(frame(0, true));
+		audio.write(frame(0, true));
+		assert_eq!(frame_of(&mut consumer).await, (0, 0));
+		assert_eq!(frame_of(&mut consumer).await, (1, 0));
+
+		video.write(frame(33, false));
+		audio.write(frame(20, true));
+		assert_eq!(frame_of(&mut consumer).await, (1, 20));
+
+		audio.write(frame(40, true));
+		assert_eq!(frame_of(&mut consumer).await, (0, 33));
+
+		// The audio at 40ms waits for the next video frame.
+		let res = tokio::time::timeout(Duration::from_millis(50), consumer.read()).await;
+		assert!(res.is_err());
+
+		video.write(frame(66, false));
+		assert_eq!(frame_of(&mut consumer).await, (1, 40));
+
+		audio.write(frame(80, true));
+		assert_eq!(frame_of(&mut consumer).await, (0, 66));
+	}
+
+	#[tokio::test]
+	async fn skip() {
+		let (_broadcast, mut video, mut audio, mut consumer) = setup();
+		consumer.set_latency(Duration::from_millis(100));
+
+		video.write(frame(0, true));
+		audio.write(frame(0, true));
+		assert_eq!(frame_of(&mut consumer).await, (0, 0));
+		assert_eq!(frame_of(&mut consumer).await, (1, 0));
+
+		// The audio jumps ahead, so the video drops frames until a keyframe at the same point.
+		audio.write(frame(1000, true));
+		video.write(frame(33, false));
+		video.write(frame(1000, true));
+
+		let mut frames = Vec::new();
+		let mut gaps = Vec::new();
+		while frames.len() < 2 {
+			match read(&mut consumer).await {
+				SyncEvent::Frame(index, frame) => frames.push((index, frame.timestamp.as_millis())),
+				SyncEvent::Gap(index, from, to) => gaps.push((index, from.as_millis(), to.as_millis())),
+				SyncEvent::Drift(_) => {}
+			}
+		}
+
+		assert_eq!(frames, [(0, 1000), (1, 1000)]);
+		assert!(gaps.contains(&(1, 0, 1000)));
+		assert!(gaps.iter().any(|(index, _, to)| *index == 0 && *to == 1000));
+		assert!(!consumer.drifting);
+	}
+
+	#[tokio::test]
+	async fn stalled() {
+		let (_broadcast, mut video, _audio, mut consumer) = setup();
+		consumer.set_latency(Duration::from_millis(10));
+
+		// The audio never arrives, so the video is returned after the latency target.
+		video.write(frame(0, true));
+		assert_eq!(frame_of(&mut consumer).await, (0, 0));
+
+		// Once stalled, the audio doesn't hold up the video.
+		video.write(frame(33, false));
+		let res = tokio::time::timeout(Duration::from_millis(5), consumer.read()).await;
+		assert!(matches!(res, Ok(Ok(Some(SyncEvent::Frame(0, _))))));
+	}
+}
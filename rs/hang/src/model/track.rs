@@ -1,31 +1,55 @@
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, SystemTime};
 
-use crate::model::{Frame, GroupConsumer, Timestamp};
+use crate::catalog::{CatalogProducer, Clock, Container};
+use crate::model::{Frame, GroupConsumer, IndexConsumer, IndexProducer, Timestamp};
 use crate::Error;
 use futures::{stream::FuturesUnordered, StreamExt};
 
-use moq_lite::coding::*;
-
 #[derive(Clone)]
 pub struct TrackProducer {
 	pub inner: moq_lite::TrackProducer,
 	group: Option<moq_lite::GroupProducer>,
+
+	// Used to anchor keyframes to the wall clock, if enabled.
+	clock: Option<CatalogProducer>,
+
+	// Lists each keyframe so consumers can seek, if enabled.
+	index: Option<IndexProducer>,
 }
 
 impl TrackProducer {
 	pub fn new(inner: moq_lite::TrackProducer) -> Self {
-		Self { inner, group: None }
+		Self {
+			inner,
+			group: None,
+			clock: None,
+			index: None,
+		}
+	}
+
+	pub(crate) fn with_clock(mut self, catalog: CatalogProducer) -> Self {
+		self.clock = Some(catalog);
+		self
+	}
+
+	/// Write the timestamp and group of each keyframe to an index track, used by [TrackConsumer::seek].
+	pub fn with_index(mut self, index: IndexProducer) -> Self {
+		self.index = Some(index);
+		self
 	}
 
 	pub fn write(&mut self, frame: Frame) {
-		let timestamp = frame.timestamp.as_micros() as u64;
-		let mut header = BytesMut::with_capacity(timestamp.encode_size());
-		timestamp.encode(&mut header);
+		let header = frame.encode_header();
 
 		if frame.keyframe {
 			if let Some(group) = self.group.take() {
 				group.finish();
 			}
+
+			if let Some(catalog) = self.clock.as_mut() {
+				catalog.report_clock(frame.timestamp);
+			}
 		}
 
 		let mut group = match self.group.take() {
@@ -33,11 +57,20 @@ impl TrackProducer {
 			None => self.inner.append_group(),
 		};
 
+		if let Some(index) = self.index.as_mut().filter(|_| frame.keyframe) {
+			index.keyframe(frame.dts(), group.info.sequence, self.inner.history());
+		}
+
+		// The group may have expired (see [moq_lite::Track::max_age]), in which case frames are dropped until the next keyframe.
 		let size = header.len() + frame.payload.len();
-		let mut chunked = group.create_frame(size.into());
-		chunked.write(header.freeze());
-		chunked.write(frame.payload);
-		chunked.finish();
+		match group.create_frame(size.into()) {
+			Ok(mut chunked) => {
+				chunked.write(header);
+				chunked.write(frame.payload);
+				chunked.finish();
+			}
+			Err(err) => tracing::trace!(?err, group = group.info.sequence, "dropping frame"),
+		}
 
 		self.group.replace(group);
 	}
@@ -45,6 +78,24 @@ impl TrackProducer {
 	pub fn consume(&self) -> TrackConsumer {
 		TrackConsumer::new(self.inner.consume())
 	}
+
+	/// Retain groups for the given duration, so consumers can seek backwards (ex. DVR).
+	pub fn set_history(&mut self, retain: std::time::Duration) {
+		self.inner.set_history(retain);
+	}
+
+	/// Finish the current group and the track.
+	pub fn finish(mut self) {
+		if let Some(group) = self.group.take() {
+			group.finish();
+		}
+
+		if let Some(index) = self.index.take() {
+			index.finish();
+		}
+
+		self.inner.finish();
+	}
 }
 
 impl From<moq_lite::TrackProducer> for TrackProducer {
@@ -62,14 +113,62 @@ pub struct TrackConsumer {
 	// Future groups that we are monitoring, deciding based on [latency] whether to skip.
 	pending: VecDeque<GroupConsumer>,
 
-	// The maximum timestamp seen thus far, or zero because that's easier than None.
+	// The maximum decode timestamp seen thus far, or zero because that's easier than None.
+	// Presentation timestamps are not monotonic with B-frames, so they can't be used to measure the buffer.
 	max_timestamp: Timestamp,
 
 	// The maximum buffer size before skipping a group.
 	latency: std::time::Duration,
+
+	// Used to request older groups from the publisher when seeking.
+	broadcast: Option<moq_lite::BroadcastConsumer>,
+
+	// The index track listing each keyframe, provided by the catalog.
+	index: Option<moq_lite::Track>,
+
+	// The decode timestamp of each keyframe known so far, and the sequence number of its group.
+	keyframes: BTreeMap<Timestamp, u64>,
+
+	// Keyframes older than this are forgotten, as the publisher no longer retains their groups.
+	history: Duration,
+
+	// Set until the frames at the seek target are reached.
+	seeking: Option<Seeking>,
+
+	// Frames to return before reading any more, after a seek completes.
+	ready: VecDeque<Frame>,
+
+	// Groups are no longer skipped after seeking, as playback is intentionally behind the live edge.
+	seeked: bool,
+
+	// Maps timestamps to wall clock time, provided by the catalog.
+	clock: Option<Clock>,
+
+	// The presentation timestamp of the last frame returned.
+	latest: Option<Timestamp>,
+
+	// The layout of each frame header, provided by the catalog.
+	container: Container,
+}
+
+struct Seeking {
+	target: Timestamp,
+
+	// The group requested from the publisher.
+	sequence: u64,
+
+	// Set once the first group is received, to check the publisher still had the requested group.
+	started: bool,
+
+	// The frames from the last keyframe at or before the target.
+	// They're only returned once a later frame is read, in case a later keyframe also precedes the target.
+	buffered: VecDeque<Frame>,
 }
 
 impl TrackConsumer {
+	// How long keyframes are remembered by default.
+	const HISTORY: Duration = Duration::from_secs(30);
+
 	pub fn new(inner: moq_lite::TrackConsumer) -> Self {
 		Self {
 			inner,
@@ -77,18 +176,40 @@ impl TrackConsumer {
 			pending: VecDeque::new(),
 			max_timestamp: Timestamp::default(),
 			latency: std::time::Duration::ZERO,
+			broadcast: None,
+			index: None,
+			keyframes: BTreeMap::new(),
+			history: Self::HISTORY,
+			seeking: None,
+			ready: VecDeque::new(),
+			seeked: false,
+			clock: None,
+			latest: None,
+			container: Container::default(),
 		}
 	}
 
+	/// Use the broadcast to resubscribe when seeking, so older groups are requested from the publisher.
+	///
+	/// Otherwise [Self::seek] only returns groups that are available locally.
+	pub fn with_broadcast(mut self, broadcast: moq_lite::BroadcastConsumer) -> Self {
+		self.broadcast = Some(broadcast);
+		self
+	}
+
 	pub async fn read(&mut self) -> Result<Option<Frame>, Error> {
 		loop {
+			if let Some(frame) = self.ready.pop_front() {
+				return Ok(Some(self.deliver(frame)));
+			}
+
 			let cutoff = self.max_timestamp + self.latency;
 
 			// Keep track of all pending groups, buffering until we detect a timestamp far enough in the future.
 			// This is a race; only the first group will succeed.
 			// TODO is there a way to do this without FuturesUnordered?
 			let mut buffering = FuturesUnordered::new();
-			for (index, pending) in self.pending.iter_mut().enumerate() {
+			for (index, pending) in self.pending.iter_mut().enumerate().filter(|_| !self.seeked) {
 				buffering.push(async move { (index, pending.buffer_frames_until(cutoff).await) })
 			}
 
@@ -100,8 +221,17 @@ impl TrackConsumer {
 					match res? {
 						// Got the next frame.
 						Some(frame) => {
-							self.max_timestamp = frame.timestamp;
-							return Ok(Some(frame));
+							let sequence = self.current.as_ref().expect("current group").info.sequence;
+							if frame.keyframe {
+								self.keyframe(frame.dts(), sequence);
+							}
+
+							if self.seeking.is_some() {
+								self.seek_frame(frame, sequence)?;
+								continue;
+							}
+
+							return Ok(Some(self.deliver(frame)));
 						}
 						None => {
 							// Group ended cleanly, instantly move to the next group.
@@ -111,7 +241,7 @@ impl TrackConsumer {
 					};
 				},
 				Some(res) = async { self.inner.next_group().await.transpose() } => {
-					let group = GroupConsumer::new(res?);
+					let group = GroupConsumer::new(res?).with_container(self.container);
 					drop(buffering);
 
 					match self.current.as_ref() {
@@ -141,15 +271,236 @@ impl TrackConsumer {
 
 					self.current = self.pending.pop_front();
 				}
-				else => return Ok(None),
+				else => {
+					// The track ended before passing the seek target, so return what we have.
+					if let Some(seeking) = self.seeking.take() {
+						self.ready = seeking.buffered;
+						continue;
+					}
+
+					return Ok(None);
+				}
+			}
+		}
+	}
+
+	// Update the position before returning a frame.
+	fn deliver(&mut self, frame: Frame) -> Frame {
+		self.max_timestamp = frame.dts();
+		self.latest = Some(frame.timestamp);
+		frame
+	}
+
+	// Handle a frame read while seeking, buffering it until we know where playback should start.
+	fn seek_frame(&mut self, frame: Frame, sequence: u64) -> Result<(), Error> {
+		let seeking = self.seeking.as_mut().expect("not seeking");
+
+		// The publisher skips to the next group it has if the requested group is no longer retained.
+		if !seeking.started {
+			seeking.started = true;
+
+			if sequence > seeking.sequence {
+				tracing::debug!(
+					requested = seeking.sequence,
+					received = sequence,
+					"seek target not retained"
+				);
+				self.seeking = None;
+				return Err(Error::NoHistory);
+			}
+		}
+
+		if frame.dts() <= seeking.target {
+			// A later keyframe still precedes the target, so step forward and drop the earlier frames.
+			if frame.keyframe {
+				seeking.buffered.clear();
+			}
+
+			// Frames before the first keyframe can't be decoded anyway.
+			if frame.keyframe || !seeking.buffered.is_empty() {
+				seeking.buffered.push_back(frame);
 			}
+
+			return Ok(());
+		}
+
+		if seeking.buffered.is_empty() {
+			// The requested group starts after the target, so step back to an earlier group if we know of one.
+			let target = seeking.target;
+			let requested = seeking.sequence;
+
+			return match self.locate(target).filter(|sequence| *sequence < requested) {
+				Some(sequence) => {
+					self.restart(target, sequence);
+					Ok(())
+				}
+				None => {
+					self.seeking = None;
+					Err(Error::NoHistory)
+				}
+			};
 		}
+
+		// We passed the target, so playback starts at the last keyframe before it.
+		let seeking = self.seeking.take().expect("not seeking");
+		self.ready = seeking.buffered;
+		self.ready.push_back(frame);
+
+		Ok(())
 	}
 
 	pub fn set_latency(&mut self, max: std::time::Duration) {
 		self.latency = max;
 	}
 
+	/// Set the layout of each frame header, from [crate::catalog::Catalog::container].
+	pub fn set_container(&mut self, container: Container) {
+		self.container = container;
+	}
+
+	/// How far back the publisher retains groups, see [TrackProducer::set_history].
+	///
+	/// Keyframes older than this are forgotten, so they're not used by [Self::seek]. Defaults to 30 seconds.
+	pub fn set_history(&mut self, history: Duration) {
+		self.history = history;
+	}
+
+	// Remember a keyframe for seeking, forgetting any that are older than the history.
+	fn keyframe(&mut self, timestamp: Timestamp, sequence: u64) {
+		self.keyframes.insert(timestamp, sequence);
+
+		if let Some(cutoff) = timestamp.checked_sub(self.history) {
+			if self
+				.keyframes
+				.first_key_value()
+				.is_some_and(|(oldest, _)| *oldest < cutoff)
+			{
+				self.keyframes = self.keyframes.split_off(&cutoff);
+			}
+		}
+	}
+
+	/// Set the index track used to seek, from [crate::catalog::Video::index] or [crate::catalog::Audio::index].
+	pub fn set_index(&mut self, index: Option<moq_lite::Track>) {
+		self.index = index;
+	}
+
+	/// Set the mapping from timestamps to wall clock time, from [crate::catalog::Catalog::clock].
+	pub fn set_clock(&mut self, clock: Option<Clock>) {
+		self.clock = clock;
+	}
+
+	pub fn clock(&self) -> Option<&Clock> {
+		self.clock.as_ref()
+	}
+
+	/// When the last frame returned was captured, if the clock is known.
+	pub fn capture_time(&self) -> Option<SystemTime> {
+		Some(self.clock?.capture_time(self.latest?))
+	}
+
+	/// The estimated time since the last frame returned was captured, if the clock is known.
+	///
+	/// This is the end-to-end latency as of now; add any buffering before the frame is rendered.
+	pub fn latency(&self) -> Option<Duration> {
+		SystemTime::now().duration_since(self.capture_time()?).ok()
+	}
+
+	/// Convert a timestamp from this track to the timeline of another track, which could be from another broadcast.
+	///
+	/// Returns the timestamp in the other track that was captured at the same time, if both clocks are known.
+	pub fn align(&self, timestamp: Timestamp, other: &TrackConsumer) -> Option<Timestamp> {
+		Some(self.clock?.align(timestamp, other.clock.as_ref()?))
+	}
+
+	/// Seek to the last keyframe at or before the timestamp, reading every group in order from there.
+	///
+	/// The group is found using the index track (see [Self::set_index]), along with any keyframes read so far.
+	/// Returns [Error::NoHistory] if the publisher doesn't retain the group, see [TrackProducer::set_history].
+	/// Groups are no longer skipped based on the latency until [Self::live] is called.
+	pub async fn seek(&mut self, timestamp: Timestamp) -> Result<(), Error> {
+		if let (Some(broadcast), Some(index)) = (&self.broadcast, &self.index) {
+			let mut index = IndexConsumer::new(broadcast.subscribe(index));
+
+			// The index lists every keyframe the publisher retains, so forget about any older ones.
+			if let Some(keyframes) = index.read().await? {
+				if let Some(oldest) = keyframes.keys().next() {
+					self.keyframes = self.keyframes.split_off(oldest);
+				}
+
+				self.keyframes.extend(keyframes);
+			}
+		}
+
+		let sequence = self.locate(timestamp).ok_or(Error::NoHistory)?;
+		self.restart(timestamp, sequence);
+
+		Ok(())
+	}
+
+	/// Return to the live edge after [Self::seek], skipping groups based on the latency again.
+	pub fn live(&mut self) {
+		tracing::debug!(track = %self.inner.info.name, "returning to live");
+
+		match &self.broadcast {
+			Some(broadcast) => {
+				let track = moq_lite::Track {
+					resume: None,
+					..self.inner.info.clone()
+				};
+				self.inner = broadcast.subscribe(&track);
+			}
+			None => self.inner.live(),
+		}
+
+		self.reset();
+		self.seeked = false;
+	}
+
+	// Return the sequence number of the group with the last keyframe at or before the timestamp.
+	fn locate(&self, timestamp: Timestamp) -> Option<u64> {
+		self.keyframes
+			.range(..=timestamp)
+			.next_back()
+			.map(|(_, sequence)| *sequence)
+	}
+
+	fn restart(&mut self, timestamp: Timestamp, sequence: u64) {
+		tracing::debug!(track = %self.inner.info.name, ?timestamp, sequence, "seeking");
+
+		match &self.broadcast {
+			Some(broadcast) => {
+				let track = moq_lite::Track {
+					resume: Some(moq_lite::ResumePoint {
+						group: sequence,
+						frame: 0,
+					}),
+					..self.inner.info.clone()
+				};
+				self.inner = broadcast.subscribe(&track);
+			}
+			None => self.inner.seek(sequence),
+		}
+
+		self.reset();
+		self.seeking = Some(Seeking {
+			target: timestamp,
+			sequence,
+			started: false,
+			buffered: VecDeque::new(),
+		});
+		self.seeked = true;
+	}
+
+	// Forget about any groups and frames from before seeking.
+	fn reset(&mut self) {
+		self.current = None;
+		self.pending.clear();
+		self.ready.clear();
+		self.seeking = None;
+		self.max_timestamp = Timestamp::default();
+	}
+
 	pub async fn closed(&self) -> Result<(), Error> {
 		Ok(self.inner.closed().await?)
 	}
@@ -160,3 +511,185 @@ impl From<moq_lite::TrackConsumer> for TrackConsumer {
 		Self::new(inner)
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use bytes::Bytes;
+	use futures::FutureExt;
+
+	fn read(consumer: &mut TrackConsumer) -> Frame {
+		consumer
+			.read()
+			.now_or_never()
+			.expect("blocked")
+			.unwrap()
+			.expect("ended")
+	}
+
+	// A video track published with an index, along with a consumer that can seek.
+	fn publish(history: Duration) -> (moq_lite::BroadcastProducer, TrackProducer, TrackConsumer) {
+		let mut broadcast = moq_lite::BroadcastProducer::new();
+		let track = moq_lite::Track::new("video");
+
+		let index = IndexProducer::new(broadcast.create(IndexProducer::track(&track)));
+		let mut producer = TrackProducer::new(broadcast.create(track.clone())).with_index(index);
+		producer.set_history(history);
+
+		let mut consumer =
+			TrackConsumer::new(broadcast.consume().subscribe(&track)).with_broadcast(broadcast.consume());
+		consumer.set_index(Some(IndexProducer::track(&track)));
+
+		(broadcast, producer, consumer)
+	}
+
+	// Write a keyframe every second, followed by a delta frame.
+	fn write(producer: &mut TrackProducer, seconds: std::ops::Range<u64>) {
+		for second in seconds {
+			for (millis, keyframe) in [(0, true), (500, false)] {
+				producer.write(Frame {
+					timestamp: Timestamp::from_millis(second * 1000 + millis),
+					decode_timestamp: None,
+					duration: None,
+					keyframe,
+					payload: Bytes::from_static(b"frame"),
+				});
+			}
+		}
+	}
+
+	#[tokio::test]
+	async fn seek() {
+		let (_broadcast, mut producer, mut consumer) = publish(Duration::from_secs(60));
+		write(&mut producer, 0..4);
+
+		// A live consumer starts at the latest group.
+		assert_eq!(read(&mut consumer).timestamp, Timestamp::from_secs(3));
+
+		// The index maps the timestamp to the group with the preceding keyframe.
+		consumer
+			.seek(Timestamp::from_millis(1500))
+			.now_or_never()
+			.unwrap()
+			.unwrap();
+		let frame = read(&mut consumer);
+		assert_eq!(frame.timestamp, Timestamp::from_secs(1));
+		assert!(frame.keyframe);
+
+		// Groups are read in order without skipping.
+		for millis in [1500, 2000, 2500, 3000, 3500] {
+			assert_eq!(read(&mut consumer).timestamp, Timestamp::from_millis(millis));
+		}
+
+		// Return to the latest group.
+		write(&mut producer, 4..6);
+		consumer.live();
+		assert_eq!(read(&mut consumer).timestamp, Timestamp::from_secs(5));
+	}
+
+	#[tokio::test]
+	async fn seek_forward() {
+		let (_broadcast, mut producer, mut consumer) = publish(Duration::from_secs(60));
+		write(&mut producer, 0..2);
+		assert_eq!(read(&mut consumer).timestamp, Timestamp::from_secs(1));
+
+		// The target is after the last keyframe in the index, so the first group is too early.
+		consumer
+			.seek(Timestamp::from_millis(2500))
+			.now_or_never()
+			.unwrap()
+			.unwrap();
+		write(&mut producer, 2..4);
+
+		// The keyframe at 2s also precedes the target, so the first group is skipped.
+		for millis in [2000, 2500, 3000, 3500] {
+			assert_eq!(read(&mut consumer).timestamp, Timestamp::from_millis(millis));
+		}
+	}
+
+	#[tokio::test]
+	async fn seek_without_history() {
+		let (_broadcast, mut producer, mut consumer) = publish(Duration::ZERO);
+		write(&mut producer, 0..4);
+		assert_eq!(read(&mut consumer).timestamp, Timestamp::from_secs(3));
+
+		// Only the latest group is retained, so there's nothing to seek to.
+		let res = consumer.seek(Timestamp::from_secs(1)).now_or_never().unwrap();
+		assert!(matches!(res, Err(Error::NoHistory)));
+	}
+
+	#[test]
+	fn clock() {
+		let mut catalog = crate::catalog::Catalog::default().produce();
+		let mut producer: TrackProducer = moq_lite::Track::new("video").produce().into();
+		producer = producer.with_clock(catalog.clone());
+
+		let mut consumer = producer.consume();
+		assert_eq!(consumer.latency(), None);
+
+		let frame = |timestamp| Frame {
+			timestamp,
+			decode_timestamp: None,
+			duration: None,
+			keyframe: true,
+			payload: Bytes::from_static(b"frame"),
+		};
+
+		// Keyframes are anchored to the current time, but not more often than the interval.
+		producer.write(frame(Timestamp::from_secs(5)));
+		producer.write(frame(Timestamp::from_secs(6)));
+
+		let clock = catalog.update().clock.expect("no clock");
+		assert_eq!(clock.timestamp, 5_000_000);
+
+		assert_eq!(read(&mut consumer).timestamp, Timestamp::from_secs(6));
+		consumer.set_clock(Some(clock));
+
+		// The frame at 6s is a second ahead of the anchor, as if it was captured in the future.
+		assert_eq!(consumer.latency(), None);
+		let captured = consumer.capture_time().unwrap();
+		assert!(captured > SystemTime::now());
+
+		// The frame at 6s was captured two seconds ago, so it has at least that much latency.
+		let past = SystemTime::now() - Duration::from_secs(2);
+		consumer.set_clock(Some(Clock::new(Timestamp::from_secs(6), past)));
+		let latency = consumer.latency().expect("no latency");
+		assert!(latency >= Duration::from_secs(2));
+		assert!(latency < Duration::from_secs(3));
+		consumer.set_clock(Some(clock));
+
+		// Another broadcast that started at the same time, but with timestamps 10s ahead.
+		let mut other = TrackConsumer::new(moq_lite::Track::new("other").produce().consume());
+		other.set_clock(Some(Clock {
+			timestamp: clock.timestamp + 10_000_000,
+			utc: clock.utc,
+		}));
+		assert_eq!(
+			consumer.align(Timestamp::from_secs(6), &other),
+			Some(Timestamp::from_secs(16))
+		);
+	}
+
+	#[test]
+	fn keyframes() {
+		let mut producer: TrackProducer = moq_lite::Track::new("video").produce().into();
+		let mut consumer = producer.consume();
+		consumer.set_history(Duration::from_secs(2));
+
+		for second in 0..5 {
+			producer.write(Frame {
+				timestamp: Timestamp::from_secs(second),
+				decode_timestamp: None,
+				duration: None,
+				keyframe: true,
+				payload: Bytes::from_static(b"frame"),
+			});
+
+			read(&mut consumer);
+		}
+
+		// Only the keyframes within the history are remembered.
+		let keyframes: Vec<_> = consumer.keyframes.keys().copied().collect();
+		assert_eq!(keyframes, [2, 3, 4].map(Timestamp::from_secs));
+	}
+}
@@ -0,0 +1,22 @@
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("mp4 error: {0}")]
+	Mp4(#[from] mp4_atom::Error),
+
+	#[error("cmaf error: {0}")]
+	Cmaf(#[from] crate::cmaf::Error),
+
+	#[error("missing box: {0}")]
+	MissingBox(mp4_atom::FourCC),
+
+	#[error("fragmented mp4, use cmaf::Import instead")]
+	Fragmented,
+
+	#[error("invalid sample table")]
+	InvalidSampleTable,
+
+	#[error("io error: {0}")]
+	Io(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
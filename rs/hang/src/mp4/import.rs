@@ -0,0 +1,473 @@
+use super::{Error, Result, Sample};
+use crate::model::{BroadcastProducer, Frame, Timestamp, TrackProducer};
+use mp4_atom::{AsyncReadAtom, AsyncReadFrom, Atom, Moof, Moov};
+use std::{io::SeekFrom, time::Duration};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt};
+use tokio::time::Instant;
+
+/// Converts a progressive (non-fragmented) MP4 -> Karp
+///
+/// The moov may be at the start or the end of the file, so the input must be seekable.
+/// Samples are located using the sample tables and published in decode order, interleaved across tracks.
+pub struct Import<T> {
+	input: T,
+
+	// The broadcast being produced
+	broadcast: BroadcastProducer,
+
+	tracks: Vec<ImportTrack>,
+
+	// If true, samples are published at the rate they would be played.
+	realtime: bool,
+
+	// The wall clock time and decode timestamp of the first sample published, used for pacing.
+	reference: Option<(Instant, Timestamp)>,
+}
+
+struct ImportTrack {
+	producer: TrackProducer,
+	samples: Vec<Sample>,
+	timescale: u64,
+	video: bool,
+
+	// The index of the next sample to publish.
+	index: usize,
+
+	// The timestamp of the last keyframe, used to group audio samples.
+	last_keyframe: Option<Timestamp>,
+}
+
+impl ImportTrack {
+	fn timestamp(&self, units: u64) -> Timestamp {
+		Timestamp::from_micros(units * 1_000_000 / self.timescale)
+	}
+}
+
+impl<T: AsyncRead + AsyncSeek + Unpin> Import<T> {
+	/// Find and parse the moov atom, creating a track for each video and audio trak.
+	pub async fn open(broadcast: BroadcastProducer, mut input: T) -> Result<Self> {
+		input.seek(SeekFrom::Start(0)).await?;
+
+		// Scan the top-level atoms, skipping over the mdat without reading it.
+		let mut moov = None;
+		while let Some(header) = Option::<mp4_atom::Header>::read_from(&mut input).await? {
+			match header.kind {
+				Moov::KIND => {
+					moov = Some(Moov::read_atom(&header, &mut input).await?);
+					break;
+				}
+				Moof::KIND => return Err(Error::Fragmented),
+				_ => match header.size {
+					Some(size) => input.seek(SeekFrom::Current(size as i64)).await?,
+					// The atom extends to the end of the file.
+					None => break,
+				},
+			};
+		}
+
+		let moov = moov.ok_or(Error::MissingBox(Moov::KIND))?;
+		if moov.mvex.is_some() {
+			return Err(Error::Fragmented);
+		}
+
+		let mut this = Self {
+			input,
+			broadcast,
+			tracks: Vec::new(),
+			realtime: false,
+			reference: None,
+		};
+
+		this.init(&moov)?;
+
+		Ok(this)
+	}
+
+	fn init(&mut self, moov: &Moov) -> Result<()> {
+		for trak in &moov.trak {
+			let (producer, video) = match trak.mdia.hdlr.handler.as_ref() {
+				b"vide" => {
+					let track = crate::cmaf::Import::init_video(trak)?;
+					(self.broadcast.create_video(track), true)
+				}
+				b"soun" => {
+					let track = crate::cmaf::Import::init_audio(trak)?;
+					(self.broadcast.create_audio(track), false)
+				}
+				handler => {
+					// Files often contain timecode or hint tracks, which we can ignore.
+					tracing::warn!(handler = ?String::from_utf8_lossy(handler), "skipping unsupported track");
+					continue;
+				}
+			};
+
+			let timescale = trak.mdia.mdhd.timescale as u64;
+			if timescale == 0 {
+				return Err(Error::InvalidSampleTable);
+			}
+
+			self.tracks.push(ImportTrack {
+				producer,
+				samples: Sample::from_trak(trak)?,
+				timescale,
+				video,
+				index: 0,
+				last_keyframe: None,
+			});
+		}
+
+		Ok(())
+	}
+
+	/// Publish samples at the rate they would be played, instead of as fast as possible.
+	pub fn with_realtime(mut self, realtime: bool) -> Self {
+		self.realtime = realtime;
+		self
+	}
+
+	/// The duration of the longest track.
+	pub fn duration(&self) -> Timestamp {
+		self.tracks
+			.iter()
+			.filter_map(|track| Some(track.timestamp(track.samples.last()?.pts)))
+			.max()
+			.unwrap_or_default()
+	}
+
+	/// Seek to the video keyframe at or before the given timestamp.
+	///
+	/// Audio tracks resume at the same position, so every track starts with a keyframe.
+	pub fn seek(&mut self, timestamp: Timestamp) {
+		let mut start = timestamp;
+
+		for track in self.tracks.iter_mut().filter(|track| track.video) {
+			track.index = track
+				.samples
+				.iter()
+				.rposition(|sample| sample.keyframe && track.timestamp(sample.pts) <= timestamp)
+				.unwrap_or(0);
+
+			if let Some(sample) = track.samples.get(track.index) {
+				start = start.min(track.timestamp(sample.dts));
+			}
+		}
+
+		for track in self.tracks.iter_mut().filter(|track| !track.video) {
+			track.index = track
+				.samples
+				.partition_point(|sample| track.timestamp(sample.dts) < start);
+		}
+
+		for track in &mut self.tracks {
+			track.last_keyframe = None;
+		}
+
+		self.reference = None;
+	}
+
+	/// Publish the remaining samples, returning at the end of the file.
+	pub async fn run(&mut self) -> Result<()> {
+		while let Some(index) = self.next_track() {
+			self.write(index).await?;
+		}
+
+		Ok(())
+	}
+
+	// Returns the track with the earliest next sample, by decode timestamp.
+	fn next_track(&self) -> Option<usize> {
+		self.tracks
+			.iter()
+			.enumerate()
+			.filter_map(|(index, track)| {
+				let sample = track.samples.get(track.index)?;
+				Some((track.timestamp(sample.dts), index))
+			})
+			.min()
+			.map(|(_, index)| index)
+	}
+
+	async fn write(&mut self, index: usize) -> Result<()> {
+		let track = &mut self.tracks[index];
+		let sample = track.samples[track.index];
+		track.index += 1;
+
+		if self.realtime {
+			let dts = track.timestamp(sample.dts);
+			let (start, base) = *self.reference.get_or_insert((Instant::now(), dts));
+			tokio::time::sleep_until(start + dts.saturating_sub(base)).await;
+		}
+
+		let mut payload = vec![0; sample.size as usize];
+		self.input.seek(SeekFrom::Start(sample.offset)).await?;
+		self.input.read_exact(&mut payload).await?;
+
+		let track = &mut self.tracks[index];
+		let timestamp = track.timestamp(sample.pts);
+
+		let keyframe = if track.video {
+			sample.keyframe
+		} else {
+			match track.last_keyframe {
+				// Force an audio keyframe at least every 10 seconds, but ideally at video keyframes
+				Some(prev) => timestamp.saturating_sub(prev) > Duration::from_secs(10),
+				None => true,
+			}
+		};
+
+		if keyframe {
+			track.last_keyframe = Some(timestamp);
+		}
+
+		track.producer.write(Frame {
+			timestamp,
+			decode_timestamp: (sample.dts != sample.pts).then(|| track.timestamp(sample.dts)),
+			duration: None,
+			keyframe,
+			payload: payload.into(),
+		});
+
+		if keyframe && track.video {
+			for audio in self.tracks.iter_mut().filter(|track| !track.video) {
+				audio.last_keyframe = None;
+			}
+		}
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use futures::FutureExt;
+	use mp4_atom::Encode;
+
+	fn trak(
+		track_id: u32,
+		handler: &[u8; 4],
+		timescale: u32,
+		codec: mp4_atom::Codec,
+		stbl: mp4_atom::Stbl,
+	) -> mp4_atom::Trak {
+		mp4_atom::Trak {
+			tkhd: mp4_atom::Tkhd {
+				track_id,
+				..Default::default()
+			},
+			mdia: mp4_atom::Mdia {
+				mdhd: mp4_atom::Mdhd {
+					timescale,
+					..Default::default()
+				},
+				hdlr: mp4_atom::Hdlr {
+					handler: handler.into(),
+					name: String::new(),
+				},
+				minf: mp4_atom::Minf {
+					stbl: mp4_atom::Stbl {
+						stsd: mp4_atom::Stsd { codecs: vec![codec] },
+						..stbl
+					},
+					..Default::default()
+				},
+			},
+			..Default::default()
+		}
+	}
+
+	// A file with 4 video samples (keyframes every 2) and 5 audio samples, with the moov at the end.
+	fn file() -> Vec<u8> {
+		let ftyp = mp4_atom::Ftyp {
+			major_brand: b"isom".into(),
+			minor_version: 0,
+			compatible_brands: vec![b"isom".into()],
+		};
+
+		let mut data = Vec::new();
+		ftyp.encode(&mut data).unwrap();
+
+		// The mdat contains a chunk of 2 video samples, a chunk of 5 audio samples, then another video chunk.
+		let video: Vec<Vec<u8>> = (0..4u8).map(|i| vec![i; 10 + i as usize]).collect();
+		let audio: Vec<Vec<u8>> = (0..5u8).map(|i| vec![0x80 | i; 4]).collect();
+
+		let start = data.len() as u32 + 8;
+		let chunks = [start, start + 21, start + 21 + 20];
+
+		let mdat = mp4_atom::Mdat {
+			data: [&video[0..2], &audio[..], &video[2..4]].concat().concat(),
+		};
+		mdat.encode(&mut data).unwrap();
+
+		let avc1 = mp4_atom::Avc1 {
+			visual: mp4_atom::Visual {
+				width: 1280,
+				height: 720,
+				..Default::default()
+			},
+			avcc: mp4_atom::Avcc::new(&[0x67, 0x64, 0x00, 0x1f], &[0x68, 0xee, 0x3c, 0x80]).unwrap(),
+			..Default::default()
+		};
+
+		let video = trak(
+			1,
+			b"vide",
+			1000,
+			avc1.into(),
+			mp4_atom::Stbl {
+				stts: mp4_atom::Stts {
+					entries: vec![mp4_atom::SttsEntry {
+						sample_count: 4,
+						sample_delta: 40,
+					}],
+				},
+				stss: Some(mp4_atom::Stss { entries: vec![1, 3] }),
+				stsc: mp4_atom::Stsc {
+					entries: vec![mp4_atom::StscEntry {
+						first_chunk: 1,
+						samples_per_chunk: 2,
+						sample_description_index: 1,
+					}],
+				},
+				stsz: mp4_atom::Stsz {
+					samples: mp4_atom::StszSamples::Different {
+						sizes: vec![10, 11, 12, 13],
+					},
+				},
+				stco: Some(mp4_atom::Stco {
+					entries: vec![chunks[0], chunks[2]],
+				}),
+				..Default::default()
+			},
+		);
+
+		let opus = mp4_atom::Opus {
+			audio: mp4_atom::Audio {
+				data_reference_index: 1,
+				channel_count: 2,
+				sample_size: 16,
+				sample_rate: mp4_atom::FixedPoint::new(48000, 0),
+			},
+			dops: mp4_atom::Dops {
+				output_channel_count: 2,
+				pre_skip: 0,
+				input_sample_rate: 48000,
+				output_gain: 0,
+			},
+		};
+
+		let audio = trak(
+			2,
+			b"soun",
+			48000,
+			opus.into(),
+			mp4_atom::Stbl {
+				stts: mp4_atom::Stts {
+					entries: vec![mp4_atom::SttsEntry {
+						sample_count: 5,
+						sample_delta: 960,
+					}],
+				},
+				stsc: mp4_atom::Stsc {
+					entries: vec![mp4_atom::StscEntry {
+						first_chunk: 1,
+						samples_per_chunk: 5,
+						sample_description_index: 1,
+					}],
+				},
+				stsz: mp4_atom::Stsz {
+					samples: mp4_atom::StszSamples::Identical { count: 5, size: 4 },
+				},
+				stco: Some(mp4_atom::Stco {
+					entries: vec![chunks[1]],
+				}),
+				..Default::default()
+			},
+		);
+
+		let moov = mp4_atom::Moov {
+			trak: vec![video, audio],
+			..Default::default()
+		};
+		moov.encode(&mut data).unwrap();
+
+		data
+	}
+
+	#[tokio::test]
+	async fn import() {
+		let broadcast = BroadcastProducer::new();
+		let mut consumer = broadcast.consume();
+
+		let mut import = Import::open(broadcast, std::io::Cursor::new(file())).await.unwrap();
+		assert_eq!(import.duration(), Timestamp::from_millis(120));
+
+		let catalog = consumer.catalog.next().now_or_never().unwrap().unwrap().unwrap();
+		let video = &catalog.video[0];
+		assert_eq!(video.config.codec.to_string(), "avc1.64001f");
+		assert_eq!(video.config.coded_width, Some(1280));
+
+		let audio = &catalog.audio[0];
+		assert_eq!(audio.config.channel_count, 2);
+
+		let mut video = consumer.subscribe(&video.track);
+		let mut audio = consumer.subscribe(&audio.track);
+
+		import.run().await.unwrap();
+
+		// Only the latest video group is available.
+		for i in 2..4u8 {
+			let frame = video.read().now_or_never().unwrap().unwrap().unwrap();
+			assert_eq!(frame.keyframe, i == 2);
+			assert_eq!(frame.timestamp, Timestamp::from_millis(i as u64 * 40));
+			assert_eq!(frame.payload, vec![i; 10 + i as usize]);
+		}
+
+		// The audio group was restarted on the second video keyframe.
+		for i in 4..5u8 {
+			let frame = audio.read().now_or_never().unwrap().unwrap().unwrap();
+			assert!(frame.keyframe);
+			assert_eq!(frame.timestamp, Timestamp::from_millis(i as u64 * 20));
+			assert_eq!(frame.payload, vec![0x80 | i; 4]);
+		}
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn seek() {
+		let broadcast = BroadcastProducer::new();
+		let mut consumer = broadcast.consume();
+
+		let mut import = Import::open(broadcast, std::io::Cursor::new(file()))
+			.await
+			.unwrap()
+			.with_realtime(true);
+
+		let catalog = consumer.catalog.next().now_or_never().unwrap().unwrap().unwrap();
+		let mut audio = consumer.subscribe(&catalog.audio[0].track);
+
+		// Seeking to 100ms starts at the video keyframe at 80ms.
+		import.seek(Timestamp::from_millis(100));
+
+		let start = Instant::now();
+		import.run().await.unwrap();
+		assert_eq!(start.elapsed(), Duration::from_millis(40));
+
+		let frame = audio.read().now_or_never().unwrap().unwrap().unwrap();
+		assert!(frame.keyframe);
+		assert_eq!(frame.timestamp, Timestamp::from_millis(80));
+	}
+
+	#[tokio::test]
+	async fn fragmented() {
+		let moov = mp4_atom::Moov {
+			mvex: Some(Default::default()),
+			..Default::default()
+		};
+
+		let mut data = Vec::new();
+		moov.encode(&mut data).unwrap();
+
+		let res = Import::open(BroadcastProducer::new(), std::io::Cursor::new(data)).await;
+		assert!(matches!(res, Err(Error::Fragmented)));
+	}
+}
@@ -0,0 +1,7 @@
+mod error;
+mod import;
+mod sample;
+
+pub use error::*;
+pub use import::*;
+pub use sample::*;
@@ -0,0 +1,203 @@
+use super::{Error, Result};
+use mp4_atom::{Atom, Stco, Trak};
+
+/// A sample in a progressive MP4 file, flattened from the sample tables (stbl).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+	/// The offset of the sample from the start of the file.
+	pub offset: u64,
+	pub size: u32,
+
+	/// The decode timestamp, in the track timescale.
+	pub dts: u64,
+
+	/// The presentation timestamp, in the track timescale, after applying the edit list.
+	pub pts: u64,
+
+	/// True if this is a sync sample.
+	pub keyframe: bool,
+}
+
+impl Sample {
+	/// Flatten the stsz/stco/stsc/stts/ctts/stss tables into a list of samples in decode order.
+	pub fn from_trak(trak: &Trak) -> Result<Vec<Self>> {
+		let stbl = &trak.mdia.minf.stbl;
+
+		let sizes = match &stbl.stsz.samples {
+			mp4_atom::StszSamples::Identical { count, size } => vec![*size; *count as usize],
+			mp4_atom::StszSamples::Different { sizes } => sizes.clone(),
+		};
+
+		let chunks: Vec<u64> = match (&stbl.co64, &stbl.stco) {
+			(Some(co64), _) => co64.entries.clone(),
+			(None, Some(stco)) => stco.entries.iter().map(|&offset| offset as u64).collect(),
+			(None, None) => return Err(Error::MissingBox(Stco::KIND)),
+		};
+
+		// Each stsc entry applies to a run of chunks, until the next entry.
+		let mut offsets = Vec::with_capacity(sizes.len());
+		let stsc = &stbl.stsc.entries;
+
+		for (i, entry) in stsc.iter().enumerate() {
+			let first = entry.first_chunk.saturating_sub(1) as usize;
+			let last = match stsc.get(i + 1) {
+				Some(next) => next.first_chunk.saturating_sub(1) as usize,
+				None => chunks.len(),
+			};
+
+			for &chunk in chunks.get(first..last).ok_or(Error::InvalidSampleTable)? {
+				// Samples within a chunk are contiguous.
+				let mut offset = chunk;
+
+				for _ in 0..entry.samples_per_chunk {
+					let size = *sizes.get(offsets.len()).ok_or(Error::InvalidSampleTable)?;
+					offsets.push(offset);
+					offset += size as u64;
+				}
+			}
+		}
+
+		if offsets.len() != sizes.len() {
+			return Err(Error::InvalidSampleTable);
+		}
+
+		let mut durations = stbl
+			.stts
+			.entries
+			.iter()
+			.flat_map(|entry| std::iter::repeat_n(entry.sample_delta, entry.sample_count as usize));
+
+		let mut cts = stbl
+			.ctts
+			.iter()
+			.flat_map(|ctts| &ctts.entries)
+			.flat_map(|entry| std::iter::repeat_n(entry.sample_offset, entry.sample_count as usize));
+
+		// The edit list is commonly used to remove the initial composition offset.
+		// NOTE: Empty edits, which delay the start of the track, are ignored.
+		let media_time = trak
+			.edts
+			.iter()
+			.flat_map(|edts| &edts.elst)
+			.flat_map(|elst| &elst.entries)
+			// An empty edit has a media time of -1.
+			.find(|entry| entry.media_time as u32 != u32::MAX)
+			.map(|entry| entry.media_time as i64)
+			.unwrap_or_default();
+
+		let mut samples = Vec::with_capacity(sizes.len());
+		let mut dts = 0;
+
+		for (size, offset) in sizes.into_iter().zip(offsets) {
+			let pts = dts as i64 + cts.next().unwrap_or_default() as i64 - media_time;
+
+			samples.push(Sample {
+				offset,
+				size,
+				dts,
+				pts: pts.max(0) as u64,
+				// Every sample is a sync sample when there's no stss.
+				keyframe: stbl.stss.is_none(),
+			});
+
+			dts += durations.next().ok_or(Error::InvalidSampleTable)? as u64;
+		}
+
+		// The sync samples are numbered starting at 1.
+		for &number in stbl.stss.iter().flat_map(|stss| &stss.entries) {
+			let index = (number as usize).checked_sub(1).ok_or(Error::InvalidSampleTable)?;
+			samples.get_mut(index).ok_or(Error::InvalidSampleTable)?.keyframe = true;
+		}
+
+		Ok(samples)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn tables() {
+		let mut trak = Trak::default();
+		let stbl = &mut trak.mdia.minf.stbl;
+
+		stbl.stsz.samples = mp4_atom::StszSamples::Different {
+			sizes: vec![10, 20, 30, 40, 50],
+		};
+		stbl.stco = Some(Stco {
+			entries: vec![100, 1000, 2000],
+		});
+		// Two samples in the first two chunks, then one sample in the last.
+		stbl.stsc.entries = vec![
+			mp4_atom::StscEntry {
+				first_chunk: 1,
+				samples_per_chunk: 2,
+				sample_description_index: 1,
+			},
+			mp4_atom::StscEntry {
+				first_chunk: 3,
+				samples_per_chunk: 1,
+				sample_description_index: 1,
+			},
+		];
+		stbl.stts.entries = vec![mp4_atom::SttsEntry {
+			sample_count: 5,
+			sample_delta: 512,
+		}];
+		stbl.ctts = Some(mp4_atom::Ctts {
+			entries: vec![
+				mp4_atom::CttsEntry {
+					sample_count: 1,
+					sample_offset: 1024,
+				},
+				mp4_atom::CttsEntry {
+					sample_count: 1,
+					sample_offset: 2048,
+				},
+				mp4_atom::CttsEntry {
+					sample_count: 3,
+					sample_offset: 1024,
+				},
+			],
+		});
+		stbl.stss = Some(mp4_atom::Stss { entries: vec![1, 4] });
+		trak.edts = Some(mp4_atom::Edts {
+			elst: Some(mp4_atom::Elst {
+				entries: vec![mp4_atom::ElstEntry {
+					segment_duration: 0,
+					media_time: 1024,
+					media_rate: 1,
+					media_rate_fraction: 0,
+				}],
+			}),
+		});
+
+		let samples = Sample::from_trak(&trak).unwrap();
+		let expected = [
+			(100, 10, 0, 0, true),
+			(110, 20, 512, 1536, false),
+			(1000, 30, 1024, 1024, false),
+			(1030, 40, 1536, 1536, true),
+			(2000, 50, 2048, 2048, false),
+		];
+
+		for (sample, (offset, size, dts, pts, keyframe)) in samples.iter().zip(expected) {
+			assert_eq!(
+				*sample,
+				Sample {
+					offset,
+					size,
+					dts,
+					pts,
+					keyframe
+				}
+			);
+		}
+		assert_eq!(samples.len(), expected.len());
+
+		// The tables don't cover every sample.
+		trak.mdia.minf.stbl.stts.entries[0].sample_count = 4;
+		assert!(matches!(Sample::from_trak(&trak), Err(Error::InvalidSampleTable)));
+	}
+}
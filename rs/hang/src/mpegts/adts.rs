@@ -0,0 +1,94 @@
+use bytes::Bytes;
+
+use super::{Error, Result};
+use crate::catalog::{AudioConfig, AAC};
+
+const SAMPLE_RATES: [u32; 13] = [
+	96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000, 7350,
+];
+
+/// An ADTS header, which precedes each AAC frame in an MPEG-TS stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Adts {
+	pub object_type: u8,
+	pub frequency_index: u8,
+	pub channel_config: u8,
+
+	// The size of the header, which depends on the presence of a CRC.
+	pub header_size: usize,
+
+	// The size of the header and the frame.
+	pub frame_size: usize,
+}
+
+impl Adts {
+	pub fn parse(data: &[u8]) -> Result<Self> {
+		if data.len() < 7 || data[0] != 0xff || data[1] & 0xf0 != 0xf0 {
+			return Err(Error::InvalidAdts);
+		}
+
+		let protection_absent = data[1] & 0x01 == 1;
+		let object_type = (data[2] >> 6) + 1;
+		let frequency_index = (data[2] >> 2) & 0x0f;
+		let channel_config = ((data[2] & 0x01) << 2) | (data[3] >> 6);
+		let frame_size = ((data[3] as usize & 0x03) << 11) | ((data[4] as usize) << 3) | (data[5] as usize >> 5);
+		let header_size = if protection_absent { 7 } else { 9 };
+
+		if frequency_index as usize >= SAMPLE_RATES.len() || frame_size < header_size {
+			return Err(Error::InvalidAdts);
+		}
+
+		Ok(Self {
+			object_type,
+			frequency_index,
+			channel_config,
+			header_size,
+			frame_size,
+		})
+	}
+
+	pub fn sample_rate(&self) -> u32 {
+		SAMPLE_RATES[self.frequency_index as usize]
+	}
+
+	/// The AudioSpecificConfig, used as the description.
+	pub fn audio_specific_config(&self) -> Bytes {
+		let config = ((self.object_type as u16) << 11)
+			| ((self.frequency_index as u16) << 7)
+			| ((self.channel_config as u16) << 3);
+		Bytes::copy_from_slice(&config.to_be_bytes())
+	}
+
+	pub fn config(&self) -> AudioConfig {
+		AudioConfig {
+			codec: AAC {
+				profile: self.object_type,
+			}
+			.into(),
+			sample_rate: self.sample_rate(),
+			channel_count: self.channel_config as _,
+			bitrate: None,
+			description: Some(self.audio_specific_config()),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn parse() {
+		// AAC-LC, 48kHz, stereo, 371 bytes, no CRC.
+		let header = [0xff, 0xf1, 0x4c, 0x80, 0x2e, 0x7f, 0xfc];
+		let adts = Adts::parse(&header).unwrap();
+
+		assert_eq!(adts.object_type, 2);
+		assert_eq!(adts.sample_rate(), 48000);
+		assert_eq!(adts.channel_config, 2);
+		assert_eq!(adts.header_size, 7);
+		assert_eq!(adts.frame_size, 371);
+		assert_eq!(adts.audio_specific_config().as_ref(), &[0x11, 0x90]);
+		assert_eq!(adts.config().codec.to_string(), "mp4a.40.2");
+	}
+}
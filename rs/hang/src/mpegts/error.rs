@@ -0,0 +1,22 @@
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("annex-b error: {0}")]
+	AnnexB(#[from] crate::annexb::Error),
+
+	#[error("invalid packet")]
+	InvalidPacket,
+
+	#[error("invalid section")]
+	InvalidSection,
+
+	#[error("invalid PES")]
+	InvalidPes,
+
+	#[error("invalid ADTS")]
+	InvalidAdts,
+
+	#[error("io error: {0}")]
+	Io(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,731 @@
+use super::{adts::Adts, Error, Result};
+use crate::annexb::{Codec, VideoTrack};
+use crate::catalog::Audio;
+use crate::model::{BroadcastProducer, Frame, Timestamp, TrackProducer};
+use bytes::BytesMut;
+use moq_lite::Track;
+use std::{collections::HashMap, time::Duration};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+const PACKET_SIZE: usize = 188;
+const SYNC_BYTE: u8 = 0x47;
+
+const STREAM_TYPE_AAC: u8 = 0x0f;
+const STREAM_TYPE_H264: u8 = 0x1b;
+const STREAM_TYPE_H265: u8 = 0x24;
+
+// PTS values are 33 bits at 90kHz, wrapping every ~26.5 hours.
+const PTS_WRAP: u64 = 1 << 33;
+
+/// Converts MPEG-TS -> Karp
+///
+/// Supports H.264/H.265 (Annex-B) and AAC (ADTS) for the first program in the PAT.
+pub struct Import {
+	// Any partial packet in the input buffer
+	buffer: BytesMut,
+
+	// The broadcast being produced
+	broadcast: BroadcastProducer,
+
+	// The PID of the PMT, learned from the PAT.
+	pmt: Option<u16>,
+
+	// The version of the last PMT, so we only process changes.
+	pmt_version: Option<u8>,
+
+	// The elementary streams, keyed by PID.
+	streams: HashMap<u16, Stream>,
+
+	// The timestamp of the last keyframe for each audio stream
+	last_keyframe: HashMap<u16, Timestamp>,
+}
+
+struct Stream {
+	kind: StreamKind,
+
+	// The PES packet currently being received.
+	pes: Option<Pes>,
+
+	// The continuity counter of the last packet, used to detect packet loss.
+	continuity: Option<u8>,
+
+	// Used to unwrap the 33-bit PTS.
+	last_pts: Option<u64>,
+	pts_offset: u64,
+}
+
+enum StreamKind {
+	Video(VideoTrack),
+	// The track is created on the first ADTS header.
+	Audio(Option<TrackProducer>),
+}
+
+struct Pes {
+	pts: Option<u64>,
+
+	// Only present when it differs from the PTS, ex. B-frames.
+	dts: Option<u64>,
+
+	// The size of the payload, if signaled.
+	size: Option<usize>,
+	data: BytesMut,
+}
+
+impl Import {
+	pub fn new(broadcast: BroadcastProducer) -> Self {
+		Self {
+			buffer: BytesMut::new(),
+			broadcast,
+			pmt: None,
+			pmt_version: None,
+			streams: HashMap::default(),
+			last_keyframe: HashMap::default(),
+		}
+	}
+
+	pub fn parse(&mut self, data: &[u8]) -> Result<()> {
+		if !self.buffer.is_empty() {
+			let mut buffer = std::mem::replace(&mut self.buffer, BytesMut::new());
+			buffer.extend_from_slice(data);
+			let n = self.parse_inner(&buffer)?;
+			self.buffer = buffer.split_off(n);
+		} else {
+			let n = self.parse_inner(data)?;
+			self.buffer = BytesMut::from(&data[n..]);
+		}
+
+		Ok(())
+	}
+
+	fn parse_inner<T: AsRef<[u8]>>(&mut self, data: T) -> Result<usize> {
+		let mut remain = data.as_ref();
+
+		while remain.len() >= PACKET_SIZE {
+			if remain[0] != SYNC_BYTE {
+				// Skip until the next sync byte.
+				let skip = remain.iter().position(|&b| b == SYNC_BYTE).unwrap_or(remain.len());
+				tracing::warn!(skip, "lost sync");
+				remain = &remain[skip..];
+				continue;
+			}
+
+			self.packet(&remain[..PACKET_SIZE])?;
+			remain = &remain[PACKET_SIZE..];
+		}
+
+		// Return the number of bytes consumed
+		Ok(data.as_ref().len() - remain.len())
+	}
+
+	// Read the media from a stream until EOF, flushing any partial frames at the end.
+	pub async fn read_from<T: AsyncRead + Unpin>(&mut self, input: &mut T) -> Result<()> {
+		let mut buffer = BytesMut::new();
+
+		while input.read_buf(&mut buffer).await? > 0 {
+			let n = self.parse_inner(&buffer)?;
+			let _ = buffer.split_to(n);
+		}
+
+		self.flush()
+	}
+
+	/// Write any PES packets that are still being received.
+	///
+	/// Video PES packets are usually unbounded, so the last frame is only known to be complete at the end of the stream.
+	pub fn flush(&mut self) -> Result<()> {
+		let pending: Vec<_> = self
+			.streams
+			.iter_mut()
+			.filter_map(|(pid, stream)| Some((*pid, stream.pes.take()?)))
+			.collect();
+
+		for (pid, pes) in pending {
+			self.write(pid, pes)?;
+		}
+
+		Ok(())
+	}
+
+	fn packet(&mut self, packet: &[u8]) -> Result<()> {
+		if packet[1] & 0x80 != 0 {
+			tracing::warn!("skipping packet with transport error");
+			return Ok(());
+		}
+
+		let start = packet[1] & 0x40 != 0;
+		let pid = u16::from_be_bytes([packet[1] & 0x1f, packet[2]]);
+		let adaptation = (packet[3] >> 4) & 0x03;
+		let continuity = packet[3] & 0x0f;
+
+		let mut payload = &packet[4..];
+		let mut discontinuity = false;
+
+		if adaptation & 0x02 != 0 {
+			let size = *payload.first().ok_or(Error::InvalidPacket)? as usize;
+			discontinuity = size > 0 && payload.get(1).ok_or(Error::InvalidPacket)? & 0x80 != 0;
+			payload = payload.get(1 + size..).ok_or(Error::InvalidPacket)?;
+		}
+
+		if adaptation & 0x01 == 0 {
+			// No payload
+			return Ok(());
+		}
+
+		if pid == 0 {
+			self.pat(start, payload)
+		} else if Some(pid) == self.pmt {
+			self.pmt(start, payload)
+		} else if let Some(stream) = self.streams.get_mut(&pid) {
+			if discontinuity {
+				stream.continuity = None;
+			}
+
+			let prev = stream.continuity.replace(continuity);
+
+			// A packet can be sent twice, in which case the copy is ignored.
+			if prev == Some(continuity) {
+				return Ok(());
+			}
+
+			let expected = prev.map(|prev| (prev + 1) & 0x0f);
+			if expected.is_some_and(|expected| expected != continuity) && stream.pes.take().is_some() {
+				tracing::warn!(pid, ?expected, continuity, "dropping PES after packet loss");
+			}
+
+			self.pes(pid, start, payload)
+		} else {
+			Ok(())
+		}
+	}
+
+	// Returns the table ID, the version, and the body of a PSI section.
+	// NOTE: Sections that span multiple packets are not supported, but they're rare for the PAT/PMT.
+	fn section(start: bool, payload: &[u8]) -> Result<Option<(u8, u8, &[u8])>> {
+		if !start {
+			return Ok(None);
+		}
+
+		let pointer = *payload.first().ok_or(Error::InvalidSection)? as usize;
+		let section = payload.get(1 + pointer..).ok_or(Error::InvalidSection)?;
+		if section.len() < 3 {
+			return Err(Error::InvalidSection);
+		}
+
+		let size = 3 + (u16::from_be_bytes([section[1] & 0x0f, section[2]]) as usize);
+
+		// The header is 8 bytes and the CRC is 4 bytes.
+		if size < 12 {
+			return Err(Error::InvalidSection);
+		}
+
+		let Some(section) = section.get(..size) else {
+			tracing::warn!("skipping section that spans multiple packets");
+			return Ok(None);
+		};
+
+		let version = (section[5] >> 1) & 0x1f;
+		Ok(Some((section[0], version, &section[8..size - 4])))
+	}
+
+	fn pat(&mut self, start: bool, payload: &[u8]) -> Result<()> {
+		let Some((0x00, _, body)) = Self::section(start, payload)? else {
+			return Ok(());
+		};
+
+		for &[number_hi, number_lo, pid_hi, pid_lo] in body.as_chunks::<4>().0 {
+			let number = u16::from_be_bytes([number_hi, number_lo]);
+			let pid = u16::from_be_bytes([pid_hi & 0x1f, pid_lo]);
+
+			// Program 0 is the network PID
+			if number != 0 {
+				if self.pmt.is_none() {
+					tracing::debug!(number, pid, "found program");
+				}
+
+				self.pmt = Some(pid);
+				break;
+			}
+		}
+
+		Ok(())
+	}
+
+	fn pmt(&mut self, start: bool, payload: &[u8]) -> Result<()> {
+		let Some((0x02, version, body)) = Self::section(start, payload)? else {
+			return Ok(());
+		};
+
+		if self.pmt_version.replace(version) == Some(version) {
+			return Ok(());
+		}
+
+		if body.len() < 4 {
+			return Err(Error::InvalidSection);
+		}
+
+		let info_size = u16::from_be_bytes([body[2] & 0x0f, body[3]]) as usize;
+		let mut streams = body.get(4 + info_size..).ok_or(Error::InvalidSection)?;
+
+		while streams.len() >= 5 {
+			let stream_type = streams[0];
+			let pid = u16::from_be_bytes([streams[1] & 0x1f, streams[2]]);
+			let info_size = u16::from_be_bytes([streams[3] & 0x0f, streams[4]]) as usize;
+			streams = streams.get(5 + info_size..).ok_or(Error::InvalidSection)?;
+
+			if self.streams.contains_key(&pid) {
+				continue;
+			}
+
+			let kind = match stream_type {
+				STREAM_TYPE_H264 => StreamKind::Video(VideoTrack::new(Codec::H264, format!("video{pid}"))),
+				STREAM_TYPE_H265 => StreamKind::Video(VideoTrack::new(Codec::H265, format!("video{pid}"))),
+				STREAM_TYPE_AAC => StreamKind::Audio(None),
+				_ => {
+					tracing::warn!(pid, stream_type, "skipping unsupported stream");
+					continue;
+				}
+			};
+
+			self.streams.insert(
+				pid,
+				Stream {
+					kind,
+					pes: None,
+					continuity: None,
+					last_pts: None,
+					pts_offset: 0,
+				},
+			);
+		}
+
+		Ok(())
+	}
+
+	fn pes(&mut self, pid: u16, start: bool, payload: &[u8]) -> Result<()> {
+		let stream = self.streams.get_mut(&pid).expect("unknown stream");
+
+		let complete = if start {
+			let previous = match Self::pes_header(payload) {
+				Ok(pes) => stream.pes.replace(pes),
+				Err(err) => {
+					// Skip until the next PES packet.
+					tracing::warn!(pid, %err, "skipping corrupt PES");
+					stream.pes.take()
+				}
+			};
+
+			if let Some(previous) = previous {
+				self.write(pid, previous)?;
+			}
+
+			let stream = self.streams.get_mut(&pid).expect("unknown stream");
+			stream.pes.as_ref().is_some_and(Pes::complete)
+		} else {
+			let Some(pes) = stream.pes.as_mut() else {
+				// We joined in the middle of a PES packet.
+				return Ok(());
+			};
+
+			pes.data.extend_from_slice(payload);
+			pes.complete()
+		};
+
+		if complete {
+			let pes = self.streams.get_mut(&pid).and_then(|stream| stream.pes.take());
+			if let Some(pes) = pes {
+				self.write(pid, pes)?;
+			}
+		}
+
+		Ok(())
+	}
+
+	fn pes_header(payload: &[u8]) -> Result<Pes> {
+		if payload.len() < 9 || payload[..3] != [0, 0, 1] {
+			return Err(Error::InvalidPes);
+		}
+
+		let packet_size = u16::from_be_bytes([payload[4], payload[5]]) as usize;
+		let flags = payload[7];
+		let header_size = payload[8] as usize;
+
+		let data = payload.get(9 + header_size..).ok_or(Error::InvalidPes)?;
+
+		let pts = match flags & 0x80 != 0 {
+			true => Some(pes_timestamp(payload.get(9..14).ok_or(Error::InvalidPes)?)),
+			false => None,
+		};
+
+		// The DTS follows the PTS when both are present.
+		let dts = match flags & 0xc0 == 0xc0 {
+			true => Some(pes_timestamp(payload.get(14..19).ok_or(Error::InvalidPes)?)),
+			false => None,
+		};
+
+		// A size of 0 means unbounded, which is common for video.
+		let size = match packet_size {
+			0 => None,
+			size => Some(size.checked_sub(3 + header_size).ok_or(Error::InvalidPes)?),
+		};
+
+		Ok(Pes {
+			pts,
+			dts,
+			size,
+			data: BytesMut::from(data),
+		})
+	}
+
+	// Write a complete PES packet to the track.
+	fn write(&mut self, pid: u16, pes: Pes) -> Result<()> {
+		let stream = self.streams.get_mut(&pid).expect("unknown stream");
+
+		let Some(pts) = pes.pts else {
+			tracing::warn!(pid, "skipping PES without a PTS");
+			return Ok(());
+		};
+
+		let timestamp = stream.timestamp(pts);
+		let decode_timestamp = pes.dts.map(|dts| stream.timestamp(dts));
+		let data = pes.data.freeze();
+
+		match &mut stream.kind {
+			StreamKind::Video(video) => {
+				if video.write(&mut self.broadcast, timestamp, decode_timestamp, data)? {
+					// Force an audio keyframe on video keyframes
+					self.last_keyframe.clear();
+				}
+			}
+			StreamKind::Audio(track) => {
+				let mut data = data;
+				let mut timestamp = timestamp;
+
+				// There can be multiple ADTS frames in a PES packet.
+				while !data.is_empty() {
+					let adts = match Adts::parse(&data) {
+						Ok(adts) if adts.frame_size <= data.len() => adts,
+						Ok(_) | Err(_) => {
+							// Skip the rest of the PES packet, since we can't find the next frame.
+							tracing::warn!(pid, remaining = data.len(), "skipping corrupt ADTS frame");
+							break;
+						}
+					};
+
+					let frame = data.split_to(adts.frame_size).slice(adts.header_size..);
+
+					let track = track.get_or_insert_with(|| {
+						let track = Track {
+							name: format!("audio{pid}"),
+							priority: 2,
+							..Default::default()
+						};
+
+						self.broadcast.create_audio(Audio {
+							track,
+							config: adts.config(),
+							index: None,
+						})
+					});
+
+					let keyframe = match self.last_keyframe.get(&pid) {
+						// Force an audio keyframe at least every 10 seconds, but ideally at video keyframes
+						Some(prev) => timestamp.saturating_sub(*prev) > Duration::from_secs(10),
+						None => true,
+					};
+
+					if keyframe {
+						self.last_keyframe.insert(pid, timestamp);
+					}
+
+					track.write(Frame {
+						timestamp,
+						decode_timestamp: None,
+						duration: None,
+						keyframe,
+						payload: frame,
+					});
+
+					// Each AAC frame contains 1024 samples.
+					timestamp += Duration::from_micros(1024 * 1_000_000 / adts.sample_rate() as u64);
+				}
+			}
+		}
+
+		Ok(())
+	}
+}
+
+// Decode a 33-bit PTS or DTS, which is split by marker bits.
+fn pes_timestamp(data: &[u8]) -> u64 {
+	((data[0] as u64 >> 1) & 0x07) << 30
+		| (data[1] as u64) << 22
+		| (data[2] as u64 >> 1) << 15
+		| (data[3] as u64) << 7
+		| (data[4] as u64 >> 1)
+}
+
+impl Stream {
+	// Convert a PTS into a timestamp, accounting for the 33-bit wraparound.
+	fn timestamp(&mut self, pts: u64) -> Timestamp {
+		if let Some(last) = self.last_pts {
+			// B-frames can go backwards a little bit, so only a large jump is a wrap.
+			if pts + PTS_WRAP / 2 < last {
+				self.pts_offset += PTS_WRAP;
+			}
+		}
+
+		self.last_pts = Some(pts);
+		Timestamp::from_micros((pts + self.pts_offset) * 1_000_000 / 90_000)
+	}
+}
+
+impl Pes {
+	fn complete(&self) -> bool {
+		self.size.is_some_and(|size| self.data.len() >= size)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::annexb::h264::test::{PPS, SPS_720P};
+	use bytes::Bytes;
+	use futures::FutureExt;
+
+	fn packetize(pid: u16, payload: &[u8], output: &mut Vec<u8>) {
+		// The continuity counter is incremented for each packet with the same PID.
+		let mut continuity = output
+			.chunks(PACKET_SIZE)
+			.filter(|packet| u16::from_be_bytes([packet[1] & 0x1f, packet[2]]) == pid)
+			.count() as u8;
+
+		for (i, chunk) in payload.chunks(184).enumerate() {
+			let start = if i == 0 { 0x40 } else { 0x00 };
+			let mut packet = vec![SYNC_BYTE, start | (pid >> 8) as u8, pid as u8];
+			let cc = continuity & 0x0f;
+			continuity = continuity.wrapping_add(1);
+
+			if chunk.len() == 184 {
+				packet.push(0x10 | cc);
+			} else {
+				// Pad the packet with an adaptation field.
+				let stuffing = 184 - chunk.len();
+				packet.push(0x30 | cc);
+				packet.push(stuffing as u8 - 1);
+				if stuffing > 1 {
+					packet.push(0x00);
+					packet.resize(packet.len() + stuffing - 2, 0xff);
+				}
+			}
+
+			packet.extend_from_slice(chunk);
+			assert_eq!(packet.len(), PACKET_SIZE);
+			output.extend(packet);
+		}
+	}
+
+	fn pes(stream_id: u8, pts: u64, bounded: bool, data: &[u8]) -> Vec<u8> {
+		let size = if bounded { 8 + data.len() } else { 0 };
+		let mut pes = vec![0, 0, 1, stream_id, (size >> 8) as u8, size as u8, 0x80, 0x80, 5];
+		pes.extend_from_slice(&[
+			0x21 | ((pts >> 29) & 0x0e) as u8,
+			(pts >> 22) as u8,
+			((pts >> 14) & 0xfe) as u8 | 1,
+			(pts >> 7) as u8,
+			((pts << 1) & 0xfe) as u8 | 1,
+		]);
+		pes.extend_from_slice(data);
+		pes
+	}
+
+	fn annexb(nals: &[&[u8]]) -> Vec<u8> {
+		nals.iter().flat_map(|nal| [&[0, 0, 0, 1], *nal].concat()).collect()
+	}
+
+	fn adts(size: usize) -> Vec<u8> {
+		// AAC-LC, 48kHz, stereo, no CRC.
+		let frame_size = 7 + size;
+		let mut frame = vec![
+			0xff,
+			0xf1,
+			0x4c,
+			0x80 | (frame_size >> 11) as u8,
+			(frame_size >> 3) as u8,
+			((frame_size & 0x07) << 5) as u8 | 0x1f,
+			0xfc,
+		];
+		frame.resize(frame_size, 0xaa);
+		frame
+	}
+
+	fn idr() -> Vec<u8> {
+		[0x65, 0x88, 0x84].repeat(100)
+	}
+
+	fn stream() -> Vec<u8> {
+		let mut output = Vec::new();
+
+		// PAT: program 1 with the PMT on PID 0x1000.
+		let pat = [
+			0x00, 0x00, 0xb0, 0x0d, 0x00, 0x01, 0xc1, 0x00, 0x00, 0x00, 0x01, 0xf0, 0x00, 0, 0, 0, 0,
+		];
+		packetize(0, &pat, &mut output);
+
+		// PMT: H.264 on PID 0x100, AAC on PID 0x101, and an unsupported stream on PID 0x102.
+		let pmt = [
+			0x00, 0x02, 0xb0, 0x1c, 0x00, 0x01, 0xc1, 0x00, 0x00, 0xe1, 0x00, 0xf0, 0x00, 0x1b, 0xe1, 0x00, 0xf0, 0x00,
+			0x0f, 0xe1, 0x01, 0xf0, 0x00, 0x86, 0xe1, 0x02, 0xf0, 0x00, 0, 0, 0, 0,
+		];
+		packetize(0x1000, &pmt, &mut output);
+
+		// A delta frame before the first keyframe, which is dropped.
+		let delta = annexb(&[&[0x09, 0xf0], &[0x41, 0x9a, 0x02]]);
+		packetize(0x100, &pes(0xe0, 0, false, &delta), &mut output);
+
+		let keyframe = annexb(&[&[0x09, 0xf0], SPS_720P, PPS, &idr()]);
+		packetize(0x100, &pes(0xe0, 9000, false, &keyframe), &mut output);
+
+		let audio = [adts(200), adts(10)].concat();
+		packetize(0x101, &pes(0xc0, 9000, true, &audio), &mut output);
+
+		packetize(0x100, &pes(0xe0, 12000, false, &delta), &mut output);
+
+		output
+	}
+
+	#[tokio::test]
+	async fn import() {
+		let broadcast = BroadcastProducer::new();
+		let mut consumer = broadcast.consume();
+
+		let mut import = Import::new(broadcast);
+
+		// Parse in odd sized chunks to exercise the buffering.
+		for chunk in stream().chunks(100) {
+			import.parse(chunk).unwrap();
+		}
+		import.flush().unwrap();
+
+		let catalog = consumer.catalog.next().now_or_never().unwrap().unwrap().unwrap();
+
+		let video = &catalog.video[0];
+		assert_eq!(video.track.name, "video256");
+		assert_eq!(video.config.codec.to_string(), "avc1.42c01f");
+		assert_eq!(video.config.coded_width, Some(1280));
+		assert_eq!(video.config.coded_height, Some(720));
+
+		let audio = &catalog.audio[0];
+		assert_eq!(audio.track.name, "audio257");
+		assert_eq!(audio.config.codec.to_string(), "mp4a.40.2");
+		assert_eq!(audio.config.sample_rate, 48000);
+		assert_eq!(audio.config.channel_count, 2);
+
+		let mut track = consumer.subscribe(&video.track);
+
+		let frame = track.read().now_or_never().unwrap().unwrap().unwrap();
+		assert!(frame.keyframe);
+		assert_eq!(frame.timestamp, Timestamp::from_millis(100));
+
+		// The AUD is removed and the NALs are length prefixed.
+		let nals: Vec<Bytes> = vec![
+			Bytes::from_static(SPS_720P),
+			Bytes::from_static(PPS),
+			Bytes::from(idr()),
+		];
+		assert_eq!(frame.payload, crate::annexb::length_prefixed(&nals));
+
+		let frame = track.read().now_or_never().unwrap().unwrap().unwrap();
+		assert!(!frame.keyframe);
+		assert_eq!(frame.timestamp, Timestamp::from_micros(133_333));
+
+		let mut track = consumer.subscribe(&audio.track);
+
+		let frame = track.read().now_or_never().unwrap().unwrap().unwrap();
+		assert!(frame.keyframe);
+		assert_eq!(frame.timestamp, Timestamp::from_millis(100));
+		assert_eq!(frame.payload.len(), 200);
+
+		let frame = track.read().now_or_never().unwrap().unwrap().unwrap();
+		assert!(!frame.keyframe);
+		assert_eq!(frame.timestamp, Timestamp::from_micros(100_000 + 21_333));
+		assert_eq!(frame.payload.len(), 10);
+	}
+
+	#[tokio::test]
+	async fn corrupt() {
+		let mut input = stream();
+
+		// A corrupt PES header skips the PES packet.
+		packetize(0x101, &[0xde, 0xad, 0xbe, 0xef], &mut input);
+
+		// A corrupt ADTS frame skips the rest of the PES packet.
+		let audio = [adts(20), vec![0xde, 0xad]].concat();
+		packetize(0x101, &pes(0xc0, 27000, true, &audio), &mut input);
+
+		// A video frame that spans three packets, but the middle one is lost.
+		let offset = input.len();
+		let large = annexb(&[&[0x41; 500]]);
+		packetize(0x100, &pes(0xe0, 13500, false, &large), &mut input);
+
+		let delta = annexb(&[&[0x41, 0x9a, 0x02]]);
+		packetize(0x100, &pes(0xe0, 15000, false, &delta), &mut input);
+		input.drain(offset + PACKET_SIZE..offset + 2 * PACKET_SIZE);
+
+		let broadcast = BroadcastProducer::new();
+		let mut consumer = broadcast.consume();
+
+		let mut import = Import::new(broadcast);
+		import.parse(&input).unwrap();
+		import.flush().unwrap();
+
+		let catalog = consumer.catalog.next().now_or_never().unwrap().unwrap().unwrap();
+
+		let mut track = consumer.subscribe(&catalog.video[0].track);
+		let mut timestamps = Vec::new();
+		while let Some(Some(frame)) = track.read().now_or_never().map(|frame| frame.unwrap()) {
+			timestamps.push(frame.timestamp);
+		}
+
+		assert_eq!(
+			timestamps,
+			[
+				Timestamp::from_millis(100),
+				Timestamp::from_micros(133_333),
+				Timestamp::from_micros(166_666)
+			]
+		);
+
+		let mut track = consumer.subscribe(&catalog.audio[0].track);
+		let mut frames = Vec::new();
+		while let Some(Some(frame)) = track.read().now_or_never().map(|frame| frame.unwrap()) {
+			frames.push((frame.timestamp, frame.payload.len()));
+		}
+
+		// The audio restarted at the video keyframe, and only the frame before the corrupt one was written.
+		assert_eq!(frames, [(Timestamp::from_millis(300), 20)]);
+	}
+
+	#[test]
+	fn pts_wrap() {
+		let mut stream = Stream {
+			kind: StreamKind::Audio(None),
+			pes: None,
+			continuity: None,
+			last_pts: None,
+			pts_offset: 0,
+		};
+
+		assert_eq!(
+			stream.timestamp(PTS_WRAP - 90_000),
+			Timestamp::from_micros((PTS_WRAP - 90_000) * 100 / 9)
+		);
+		assert_eq!(
+			stream.timestamp(PTS_WRAP - 93_000),
+			Timestamp::from_micros((PTS_WRAP - 93_000) * 100 / 9)
+		);
+		assert_eq!(
+			stream.timestamp(90_000),
+			Timestamp::from_micros((PTS_WRAP + 90_000) * 100 / 9)
+		);
+	}
+}
@@ -0,0 +1,6 @@
+mod adts;
+mod error;
+mod import;
+
+pub use error::*;
+pub use import::*;
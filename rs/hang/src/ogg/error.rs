@@ -0,0 +1,16 @@
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("invalid page")]
+	InvalidPage,
+
+	#[error("invalid OpusHead")]
+	InvalidOpusHead,
+
+	#[error("invalid Opus packet")]
+	InvalidPacket,
+
+	#[error("io error: {0}")]
+	Io(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,372 @@
+use super::{packet_samples, Error, OpusHead, Result};
+use crate::catalog::Audio;
+use crate::model::{BroadcastProducer, Frame, Timestamp, TrackProducer};
+use bytes::{Bytes, BytesMut};
+use moq_lite::Track;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+const HEADER_SIZE: usize = 27;
+
+const FLAG_BOS: u8 = 0x02;
+const FLAG_EOS: u8 = 0x04;
+
+// A granule position of -1 means no packet finishes on the page.
+const GRANULE_NONE: u64 = u64::MAX;
+
+/// Converts Ogg/Opus -> Karp
+///
+/// Only the first Opus stream is imported; any other logical streams are ignored.
+pub struct Import {
+	// Any partial page in the input buffer
+	buffer: BytesMut,
+
+	// The broadcast being produced
+	broadcast: BroadcastProducer,
+
+	// The serial number of the Opus stream, once found.
+	serial: Option<u32>,
+
+	// A packet that continues onto the next page.
+	packet: BytesMut,
+
+	// The number of header packets received (OpusHead and OpusTags).
+	headers: usize,
+
+	// Created after parsing the OpusHead.
+	track: Option<TrackProducer>,
+
+	// The number of samples to discard at the start of the stream, from the OpusHead.
+	pre_skip: u64,
+
+	// The granule position (in samples) at the end of the last packet, used to compute the timestamp.
+	granule: u64,
+
+	// The timestamp of the last keyframe
+	last_keyframe: Option<Timestamp>,
+}
+
+impl Import {
+	pub fn new(broadcast: BroadcastProducer) -> Self {
+		Self {
+			buffer: BytesMut::new(),
+			broadcast,
+			serial: None,
+			packet: BytesMut::new(),
+			headers: 0,
+			track: None,
+			pre_skip: 0,
+			granule: 0,
+			last_keyframe: None,
+		}
+	}
+
+	pub fn parse(&mut self, data: &[u8]) -> Result<()> {
+		if !self.buffer.is_empty() {
+			let mut buffer = std::mem::replace(&mut self.buffer, BytesMut::new());
+			buffer.extend_from_slice(data);
+			let n = self.parse_inner(&buffer)?;
+			self.buffer = buffer.split_off(n);
+		} else {
+			let n = self.parse_inner(data)?;
+			self.buffer = BytesMut::from(&data[n..]);
+		}
+
+		Ok(())
+	}
+
+	fn parse_inner<T: AsRef<[u8]>>(&mut self, data: T) -> Result<usize> {
+		let mut remain = data.as_ref();
+
+		while remain.len() >= HEADER_SIZE {
+			if &remain[..4] != b"OggS" || remain[4] != 0 {
+				return Err(Error::InvalidPage);
+			}
+
+			let segments = remain[26] as usize;
+			let Some(table) = remain.get(HEADER_SIZE..HEADER_SIZE + segments) else {
+				break;
+			};
+
+			let size = HEADER_SIZE + segments + table.iter().map(|&s| s as usize).sum::<usize>();
+			if remain.len() < size {
+				break;
+			}
+
+			// NOTE: The CRC is not validated.
+			self.page(&remain[..size])?;
+			remain = &remain[size..];
+		}
+
+		// Return the number of bytes consumed
+		Ok(data.as_ref().len() - remain.len())
+	}
+
+	// Read the media from a stream until EOF.
+	pub async fn read_from<T: AsyncRead + Unpin>(&mut self, input: &mut T) -> Result<()> {
+		let mut buffer = BytesMut::new();
+
+		while input.read_buf(&mut buffer).await? > 0 {
+			let n = self.parse_inner(&buffer)?;
+			let _ = buffer.split_to(n);
+		}
+
+		if !buffer.is_empty() {
+			return Err(Error::InvalidPage);
+		}
+
+		Ok(())
+	}
+
+	fn page(&mut self, page: &[u8]) -> Result<()> {
+		let flags = page[5];
+		let granule = u64::from_le_bytes(page[6..14].try_into().unwrap());
+		let serial = u32::from_le_bytes([page[14], page[15], page[16], page[17]]);
+
+		let segments = page[26] as usize;
+		let table = &page[HEADER_SIZE..HEADER_SIZE + segments];
+		let mut data = &page[HEADER_SIZE + segments..];
+
+		if self.serial.is_none() {
+			// The OpusHead is always the only packet on the first page of the stream.
+			if flags & FLAG_BOS == 0 || !data.starts_with(b"OpusHead") {
+				tracing::debug!(serial, "skipping non-Opus stream");
+				return Ok(());
+			}
+
+			self.serial = Some(serial);
+		}
+
+		if self.serial != Some(serial) {
+			return Ok(());
+		}
+
+		let mut packets = Vec::new();
+
+		for &size in table {
+			let (segment, rest) = data.split_at(size as usize);
+			self.packet.extend_from_slice(segment);
+			data = rest;
+
+			// A segment of 255 bytes means the packet continues, possibly on the next page.
+			if size < 255 {
+				packets.push(self.packet.split().freeze());
+			}
+		}
+
+		let mut packets = packets.into_iter();
+
+		// The header packets don't contain any audio.
+		while self.headers < 2 {
+			match packets.next() {
+				Some(packet) => self.header(packet)?,
+				None => return Ok(()),
+			}
+		}
+
+		let packets = packets
+			.map(|packet| Ok((packet_samples(&packet)? as u64, packet)))
+			.collect::<Result<Vec<_>>>()?;
+
+		if packets.is_empty() {
+			return Ok(());
+		}
+
+		// The granule position is the sample at the end of the last packet on the page.
+		// We work backwards because the first page may start at a non-zero position.
+		// The last page is the exception; its granule position can end before the last packet to trim padding.
+		let samples: u64 = packets.iter().map(|(samples, _)| samples).sum();
+		self.granule = match granule {
+			GRANULE_NONE => self.granule,
+			_ if flags & FLAG_EOS != 0 => self.granule,
+			granule => granule.saturating_sub(samples),
+		};
+
+		for (samples, packet) in packets {
+			self.frame(packet, samples);
+		}
+
+		Ok(())
+	}
+
+	fn header(&mut self, packet: Bytes) -> Result<()> {
+		match self.headers {
+			0 => {
+				let head = OpusHead::parse(packet)?;
+				self.pre_skip = head.pre_skip as u64;
+
+				let track = Track {
+					name: "audio".to_string(),
+					priority: 2,
+					..Default::default()
+				};
+
+				self.track = Some(self.broadcast.create_audio(Audio {
+					track,
+					config: head.config(),
+					index: None,
+				}));
+				self.headers += 1;
+			}
+			_ => {
+				// Skip the OpusTags
+				self.headers += 1;
+			}
+		}
+
+		Ok(())
+	}
+
+	fn frame(&mut self, packet: Bytes, samples: u64) {
+		let track = self.track.as_mut().expect("missing track");
+
+		// The pre-skip samples are decoded but not played, so the timestamp starts after them.
+		let position = self.granule.saturating_sub(self.pre_skip);
+		let timestamp = Timestamp::from_micros(position * 1_000_000 / 48_000);
+		let duration = Timestamp::from_micros(samples * 1_000_000 / 48_000);
+		self.granule += samples;
+
+		let keyframe = match self.last_keyframe {
+			// Force a keyframe at least every 10 seconds
+			Some(prev) => timestamp.saturating_sub(prev) > Duration::from_secs(10),
+			None => true,
+		};
+
+		if keyframe {
+			self.last_keyframe = Some(timestamp);
+		}
+
+		track.write(Frame {
+			timestamp,
+			decode_timestamp: None,
+			duration: Some(duration),
+			keyframe,
+			payload: packet,
+		});
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use futures::FutureExt;
+
+	fn page(serial: u32, flags: u8, granule: u64, packets: &[&[u8]], partial: Option<&[u8]>) -> Vec<u8> {
+		let mut table = Vec::new();
+		let mut data = Vec::new();
+
+		for packet in packets {
+			table.extend(std::iter::repeat_n(255, packet.len() / 255));
+			table.push((packet.len() % 255) as u8);
+			data.extend_from_slice(packet);
+		}
+
+		// Continue this packet on the next page.
+		if let Some(partial) = partial {
+			assert_eq!(partial.len() % 255, 0);
+			table.extend(std::iter::repeat_n(255, partial.len() / 255));
+			data.extend_from_slice(partial);
+		}
+
+		let mut page = b"OggS".to_vec();
+		page.extend_from_slice(&[0, flags]);
+		page.extend_from_slice(&granule.to_le_bytes());
+		page.extend_from_slice(&serial.to_le_bytes());
+		page.extend_from_slice(&[0; 8]);
+		page.push(table.len() as u8);
+		page.extend(table);
+		page.extend(data);
+		page
+	}
+
+	fn head() -> Vec<u8> {
+		// Mono with 312 samples of pre-skip.
+		let mut head = b"OpusHead".to_vec();
+		head.extend_from_slice(&[1, 1, 0x38, 0x01, 0x80, 0xbb, 0, 0, 0, 0, 0]);
+		head
+	}
+
+	#[tokio::test]
+	async fn import() {
+		// A 20ms packet that spans two pages.
+		let large = [vec![0xfc], vec![0xaa; 600]].concat();
+
+		// The granule positions include the 312 samples of pre-skip.
+		let mut data = Vec::new();
+		data.extend(page(1, FLAG_BOS, 0, &[&head()], None));
+		data.extend(page(2, FLAG_BOS, 0, &[b"\x80theora"], None));
+		data.extend(page(1, 0, 0, &[b"OpusTags"], None));
+		data.extend(page(
+			1,
+			0,
+			312 + 1920,
+			&[&[0xfc, 0x01], &[0xfc, 0x02]],
+			Some(&large[..510]),
+		));
+		data.extend(page(2, 0, 0, &[b"ignored"], None));
+		data.extend(page(
+			1,
+			0x01,
+			312 + 1920 + 960 + 5760,
+			&[&large[510..], &[0x19, 0x03]],
+			None,
+		));
+
+		let broadcast = BroadcastProducer::new();
+		let mut consumer = broadcast.consume();
+
+		let mut import = Import::new(broadcast);
+		for chunk in data.chunks(50) {
+			import.parse(chunk).unwrap();
+		}
+
+		let catalog = consumer.catalog.next().now_or_never().unwrap().unwrap().unwrap();
+		let audio = &catalog.audio[0];
+		assert_eq!(audio.config.codec.to_string(), "opus");
+		assert_eq!(audio.config.sample_rate, 48000);
+		assert_eq!(audio.config.channel_count, 1);
+
+		let mut track = consumer.subscribe(&audio.track);
+
+		let expected: [(&[u8], u64); 4] = [
+			(&[0xfc, 0x01], 0),
+			(&[0xfc, 0x02], 20),
+			(&large, 40),
+			(&[0x19, 0x03], 60),
+		];
+		for (i, (payload, millis)) in expected.into_iter().enumerate() {
+			let frame = track.read().now_or_never().unwrap().unwrap().unwrap();
+			assert_eq!(frame.keyframe, i == 0);
+			assert_eq!(frame.timestamp, Timestamp::from_millis(millis));
+			assert_eq!(&frame.payload[..], payload);
+		}
+	}
+
+	#[tokio::test]
+	async fn granule() {
+		let mut data = Vec::new();
+		data.extend(page(1, FLAG_BOS, 0, &[&head()], None));
+		data.extend(page(1, 0, 0, &[b"OpusTags"], None));
+
+		// The stream starts 10 seconds in, ex. a live capture.
+		let start = 480_000 + 312;
+		data.extend(page(1, 0, start + 1920, &[&[0xfc, 0x01], &[0xfc, 0x02]], None));
+
+		// The last page trims the padding from the end of the last packet, which doesn't change its timestamp.
+		data.extend(page(1, FLAG_EOS, start + 1920 + 500, &[&[0xfc, 0x03]], None));
+
+		let broadcast = BroadcastProducer::new();
+		let mut consumer = broadcast.consume();
+
+		let mut import = Import::new(broadcast);
+		import.parse(&data).unwrap();
+
+		let catalog = consumer.catalog.next().now_or_never().unwrap().unwrap().unwrap();
+		let mut track = consumer.subscribe(&catalog.audio[0].track);
+
+		for millis in [10_000, 10_020, 10_040] {
+			let frame = track.read().now_or_never().unwrap().unwrap().unwrap();
+			assert_eq!(frame.timestamp, Timestamp::from_millis(millis));
+		}
+	}
+}
@@ -0,0 +1,134 @@
+use bytes::Bytes;
+
+use super::{Error, Result};
+use crate::catalog::{AudioCodec, AudioConfig};
+
+/// The Opus identification header, which is the first packet in an Ogg/Opus stream.
+///
+/// https://datatracker.ietf.org/doc/html/rfc7845#section-5.1
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpusHead {
+	pub channel_count: u8,
+	pub pre_skip: u16,
+	pub input_sample_rate: u32,
+	pub output_gain: i16,
+	pub mapping_family: u8,
+
+	// The entire packet, used as the description.
+	pub raw: Bytes,
+}
+
+impl OpusHead {
+	pub fn parse(packet: Bytes) -> Result<Self> {
+		if packet.len() < 19 || !packet.starts_with(b"OpusHead") {
+			return Err(Error::InvalidOpusHead);
+		}
+
+		// Only the major version (upper 4 bits) is incompatible.
+		if packet[8] >> 4 != 0 {
+			return Err(Error::InvalidOpusHead);
+		}
+
+		Ok(Self {
+			channel_count: packet[9],
+			pre_skip: u16::from_le_bytes([packet[10], packet[11]]),
+			input_sample_rate: u32::from_le_bytes([packet[12], packet[13], packet[14], packet[15]]),
+			output_gain: i16::from_le_bytes([packet[16], packet[17]]),
+			mapping_family: packet[18],
+			raw: packet,
+		})
+	}
+
+	/// Build a header with the default channel mapping (mono/stereo), such as from an MP4 dOps box.
+	pub fn new(channel_count: u8, pre_skip: u16, input_sample_rate: u32, output_gain: i16) -> Self {
+		let mut raw = b"OpusHead".to_vec();
+		raw.push(1);
+		raw.push(channel_count);
+		raw.extend_from_slice(&pre_skip.to_le_bytes());
+		raw.extend_from_slice(&input_sample_rate.to_le_bytes());
+		raw.extend_from_slice(&output_gain.to_le_bytes());
+		raw.push(0);
+
+		Self {
+			channel_count,
+			pre_skip,
+			input_sample_rate,
+			output_gain,
+			mapping_family: 0,
+			raw: raw.into(),
+		}
+	}
+
+	pub fn config(&self) -> AudioConfig {
+		AudioConfig {
+			codec: AudioCodec::Opus,
+			// Opus is always decoded at 48kHz, regardless of the input sample rate.
+			sample_rate: 48_000,
+			channel_count: self.channel_count as _,
+			bitrate: None,
+			// The header is the description, carrying the pre-skip and any channel mapping.
+			description: Some(self.raw.clone()),
+		}
+	}
+}
+
+/// Returns the number of samples (at 48kHz) in an Opus packet, based on the TOC byte.
+///
+/// https://datatracker.ietf.org/doc/html/rfc6716#section-3.1
+pub fn packet_samples(packet: &[u8]) -> Result<u32> {
+	let toc = *packet.first().ok_or(Error::InvalidPacket)?;
+	let config = toc >> 3;
+
+	// The frame size in units of 2.5ms (120 samples).
+	let frame_size = match config {
+		// SILK: 10, 20, 40, 60ms
+		0..=11 => [4, 8, 16, 24][config as usize % 4],
+		// Hybrid: 10, 20ms
+		12..=15 => [4, 8][config as usize % 2],
+		// CELT: 2.5, 5, 10, 20ms
+		_ => [1, 2, 4, 8][config as usize % 4],
+	};
+
+	let frames = match toc & 0x03 {
+		0 => 1,
+		1 | 2 => 2,
+		_ => (*packet.get(1).ok_or(Error::InvalidPacket)? & 0x3f) as u32,
+	};
+
+	Ok(frames * frame_size * 120)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn head() {
+		let mut packet = b"OpusHead".to_vec();
+		packet.extend_from_slice(&[1, 2, 0x38, 0x01, 0x80, 0xbb, 0, 0, 0, 0, 0]);
+
+		let head = OpusHead::parse(packet.into()).unwrap();
+		assert_eq!(head.channel_count, 2);
+		assert_eq!(head.pre_skip, 312);
+		assert_eq!(head.input_sample_rate, 48000);
+
+		let config = head.config();
+		assert_eq!(config.codec, AudioCodec::Opus);
+		assert_eq!(config.channel_count, 2);
+		assert_eq!(config.description.as_ref(), Some(&head.raw));
+
+		// A header built from the fields is parsed the same way.
+		assert_eq!(OpusHead::new(2, 312, 48000, 0), head);
+	}
+
+	#[test]
+	fn samples() {
+		// CELT 20ms, one frame
+		assert_eq!(packet_samples(&[0xfc]).unwrap(), 960);
+		// SILK 60ms, two frames
+		assert_eq!(packet_samples(&[0x19]).unwrap(), 5760);
+		// Hybrid 10ms, code 3 with 3 frames
+		assert_eq!(packet_samples(&[0x63, 0x03]).unwrap(), 1440);
+		assert!(packet_samples(&[]).is_err());
+	}
+}
@@ -0,0 +1,197 @@
+//! A minimal EBML reader, enough to parse the Matroska elements we care about.
+use super::{Error, Result};
+
+pub const EBML: u32 = 0x1A45DFA3;
+pub const DOC_TYPE: u32 = 0x4282;
+
+pub const SEGMENT: u32 = 0x18538067;
+pub const INFO: u32 = 0x1549A966;
+pub const TIMESTAMP_SCALE: u32 = 0x2AD7B1;
+
+pub const TRACKS: u32 = 0x1654AE6B;
+pub const TRACK_ENTRY: u32 = 0xAE;
+pub const TRACK_NUMBER: u32 = 0xD7;
+pub const TRACK_TYPE: u32 = 0x83;
+pub const CODEC_ID: u32 = 0x86;
+pub const CODEC_PRIVATE: u32 = 0x63A2;
+pub const VIDEO: u32 = 0xE0;
+pub const PIXEL_WIDTH: u32 = 0xB0;
+pub const PIXEL_HEIGHT: u32 = 0xBA;
+pub const AUDIO: u32 = 0xE1;
+pub const SAMPLING_FREQUENCY: u32 = 0xB5;
+pub const CHANNELS: u32 = 0x9F;
+
+pub const CLUSTER: u32 = 0x1F43B675;
+pub const TIMESTAMP: u32 = 0xE7;
+pub const SIMPLE_BLOCK: u32 = 0xA3;
+pub const BLOCK_GROUP: u32 = 0xA0;
+pub const BLOCK: u32 = 0xA1;
+pub const REFERENCE_BLOCK: u32 = 0xFB;
+
+/// Read a variable length integer, returning the value and the number of bytes, or None if more data is needed.
+///
+/// Element IDs keep the length marker, while sizes and track numbers do not.
+pub fn vint(data: &[u8], marker: bool) -> Result<Option<(u64, usize)>> {
+	let Some(&first) = data.first() else {
+		return Ok(None);
+	};
+
+	let size = first.leading_zeros() as usize + 1;
+	if size > 8 {
+		return Err(Error::InvalidVint);
+	}
+
+	let Some(bytes) = data.get(..size) else {
+		return Ok(None);
+	};
+
+	let mut value = match marker {
+		true => first as u64,
+		false => first as u64 & ((1 << (8 - size)) - 1),
+	};
+
+	for &byte in &bytes[1..] {
+		value = (value << 8) | byte as u64;
+	}
+
+	Ok(Some((value, size)))
+}
+
+/// An element header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+	pub id: u32,
+
+	// The size of the body, or None if unknown (ex. live streams).
+	pub size: Option<u64>,
+
+	// The size of the ID and size fields.
+	pub header_size: usize,
+}
+
+impl Header {
+	/// Decode an element header, or None if more data is needed.
+	pub fn decode(data: &[u8]) -> Result<Option<Self>> {
+		let Some((id, id_size)) = vint(data, true)? else {
+			return Ok(None);
+		};
+
+		if id_size > 4 {
+			return Err(Error::InvalidVint);
+		}
+
+		let Some((size, size_size)) = vint(&data[id_size..], false)? else {
+			return Ok(None);
+		};
+
+		// All ones means the size is unknown.
+		let unknown = (1u64 << (7 * size_size)) - 1;
+
+		Ok(Some(Self {
+			id: id as u32,
+			size: (size != unknown).then_some(size),
+			header_size: id_size + size_size,
+		}))
+	}
+
+	/// Returns the size, or an error if it's unknown.
+	pub fn size(&self) -> Result<usize> {
+		let size = self.size.ok_or(Error::UnknownSize(self.id))?;
+		size.try_into().map_err(|_| Error::InvalidElement(self.id))
+	}
+}
+
+/// Iterate over the child elements of a complete master element.
+pub struct Children<'a> {
+	data: &'a [u8],
+}
+
+impl<'a> Children<'a> {
+	pub fn new(data: &'a [u8]) -> Self {
+		Self { data }
+	}
+}
+
+impl<'a> Iterator for Children<'a> {
+	type Item = Result<(u32, &'a [u8])>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		if self.data.is_empty() {
+			return None;
+		}
+
+		let res = (|| {
+			let header = Header::decode(self.data)?.ok_or(Error::TrailingData)?;
+			let end = header.header_size + header.size()?;
+			let body = self
+				.data
+				.get(header.header_size..end)
+				.ok_or(Error::InvalidElement(header.id))?;
+			self.data = &self.data[end..];
+			Ok((header.id, body))
+		})();
+
+		// Stop after the first error.
+		if res.is_err() {
+			self.data = &[];
+		}
+
+		Some(res)
+	}
+}
+
+pub fn uint(data: &[u8]) -> Result<u64> {
+	if data.len() > 8 {
+		return Err(Error::InvalidElement(0));
+	}
+
+	Ok(data.iter().fold(0, |value, &byte| (value << 8) | byte as u64))
+}
+
+pub fn float(data: &[u8]) -> Result<f64> {
+	Ok(match data.len() {
+		0 => 0.0,
+		4 => f32::from_be_bytes(data.try_into().unwrap()) as f64,
+		8 => f64::from_be_bytes(data.try_into().unwrap()),
+		_ => return Err(Error::InvalidElement(0)),
+	})
+}
+
+pub fn string(data: &[u8]) -> String {
+	// Strings may be zero padded.
+	let data = data.split(|&b| b == 0).next().unwrap_or_default();
+	String::from_utf8_lossy(data).to_string()
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn header() {
+		let header = Header::decode(&[0x1A, 0x45, 0xDF, 0xA3, 0x9F]).unwrap().unwrap();
+		assert_eq!(header.id, EBML);
+		assert_eq!(header.size, Some(31));
+		assert_eq!(header.header_size, 5);
+
+		// An unknown size, as used by live streams.
+		let header = Header::decode(&[0x1F, 0x43, 0xB6, 0x75, 0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF])
+			.unwrap()
+			.unwrap();
+		assert_eq!(header.id, CLUSTER);
+		assert_eq!(header.size, None);
+
+		// More data is needed.
+		assert_eq!(Header::decode(&[0x1A, 0x45]).unwrap(), None);
+		assert_eq!(Header::decode(&[0x1A, 0x45, 0xDF, 0xA3, 0x40]).unwrap(), None);
+	}
+
+	#[test]
+	fn children() {
+		let data = [0xD7, 0x81, 0x02, 0x86, 0x85, b'V', b'_', b'V', b'P', b'8'];
+		let children: Vec<_> = Children::new(&data).collect::<Result<_>>().unwrap();
+		assert_eq!(children, [(TRACK_NUMBER, &[0x02][..]), (CODEC_ID, b"V_VP8")]);
+		assert_eq!(uint(children[0].1).unwrap(), 2);
+		assert_eq!(string(children[1].1), "V_VP8");
+	}
+}
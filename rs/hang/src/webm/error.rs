@@ -0,0 +1,34 @@
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("mp4 error: {0}")]
+	Mp4(#[from] mp4_atom::Error),
+
+	#[error("ogg error: {0}")]
+	Ogg(#[from] crate::ogg::Error),
+
+	#[error("invalid vint")]
+	InvalidVint,
+
+	#[error("invalid element: {0:#x}")]
+	InvalidElement(u32),
+
+	#[error("unknown size: {0:#x}")]
+	UnknownSize(u32),
+
+	#[error("unsupported doc type: {0}")]
+	UnsupportedDocType(String),
+
+	#[error("unsupported lacing")]
+	UnsupportedLacing,
+
+	#[error("missing codec private")]
+	MissingCodecPrivate,
+
+	#[error("trailing data")]
+	TrailingData,
+
+	#[error("io error: {0}")]
+	Io(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
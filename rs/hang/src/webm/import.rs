@@ -0,0 +1,614 @@
+use super::ebml::{self, Children, Header};
+use super::{Error, Result};
+use crate::catalog::{Audio, AudioCodec, AudioConfig, Video, VideoCodec, VideoConfig, AAC, AV1, H264, VP9};
+use crate::model::{BroadcastProducer, Frame, Timestamp, TrackProducer};
+use bytes::{Bytes, BytesMut};
+use moq_lite::Track;
+use mp4_atom::Atom;
+use std::{collections::HashMap, time::Duration};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+const TRACK_TYPE_VIDEO: u64 = 1;
+const TRACK_TYPE_AUDIO: u64 = 2;
+
+/// Converts WebM (Matroska) -> Karp
+///
+/// Clusters are imported as they arrive, so Cues and other seeking metadata are skipped.
+/// Video groups start on each keyframe, as signaled by the SimpleBlock flags or a BlockGroup without a ReferenceBlock.
+pub struct Import {
+	// Any partial element in the input buffer
+	buffer: BytesMut,
+
+	// The broadcast being produced
+	broadcast: BroadcastProducer,
+
+	// The number of nanoseconds per timestamp unit.
+	timestamp_scale: u64,
+
+	// The timestamp of the current cluster, in timestamp units.
+	cluster: Option<u64>,
+
+	// The number of bytes remaining in an element we're skipping.
+	skip: usize,
+
+	// A lookup from the track number to the track producer.
+	video: HashMap<u64, TrackProducer>,
+	audio: HashMap<u64, TrackProducer>,
+
+	// The timestamp of the last keyframe for each audio track
+	last_keyframe: HashMap<u64, Timestamp>,
+}
+
+impl Import {
+	pub fn new(broadcast: BroadcastProducer) -> Self {
+		Self {
+			buffer: BytesMut::new(),
+			broadcast,
+			timestamp_scale: 1_000_000,
+			cluster: None,
+			skip: 0,
+			video: HashMap::new(),
+			audio: HashMap::new(),
+			last_keyframe: HashMap::new(),
+		}
+	}
+
+	pub fn parse(&mut self, data: &[u8]) -> Result<()> {
+		if !self.buffer.is_empty() {
+			let mut buffer = std::mem::replace(&mut self.buffer, BytesMut::new());
+			buffer.extend_from_slice(data);
+			let n = self.parse_inner(&buffer)?;
+			self.buffer = buffer.split_off(n);
+		} else {
+			let n = self.parse_inner(data)?;
+			self.buffer = BytesMut::from(&data[n..]);
+		}
+
+		Ok(())
+	}
+
+	fn parse_inner<T: AsRef<[u8]>>(&mut self, data: T) -> Result<usize> {
+		let mut remain = data.as_ref();
+
+		loop {
+			if self.skip > 0 {
+				let n = self.skip.min(remain.len());
+				remain = &remain[n..];
+				self.skip -= n;
+
+				if self.skip > 0 {
+					break;
+				}
+			}
+
+			let Some(header) = Header::decode(remain)? else {
+				break;
+			};
+
+			match header.id {
+				// Descend into these elements, which are often written with an unknown size.
+				ebml::SEGMENT | ebml::CLUSTER => {
+					if header.id == ebml::CLUSTER {
+						self.cluster = None;
+					}
+
+					remain = &remain[header.header_size..];
+				}
+				// Wait until these elements are fully buffered.
+				ebml::EBML | ebml::INFO | ebml::TRACKS | ebml::TIMESTAMP | ebml::SIMPLE_BLOCK | ebml::BLOCK_GROUP => {
+					let size = header.header_size + header.size()?;
+					if remain.len() < size {
+						break;
+					}
+
+					self.element(header.id, &remain[header.header_size..size])?;
+					remain = &remain[size..];
+				}
+				// Skip everything else, including Cues, without buffering it.
+				_ => {
+					tracing::trace!(id = header.id, "skipping element");
+					self.skip = header.size()?;
+					remain = &remain[header.header_size..];
+				}
+			}
+		}
+
+		// Return the number of bytes consumed
+		Ok(data.as_ref().len() - remain.len())
+	}
+
+	// Read the media from a stream until EOF.
+	pub async fn read_from<T: AsyncRead + Unpin>(&mut self, input: &mut T) -> Result<()> {
+		let mut buffer = BytesMut::new();
+
+		while input.read_buf(&mut buffer).await? > 0 {
+			let n = self.parse_inner(&buffer)?;
+			let _ = buffer.split_to(n);
+		}
+
+		if !buffer.is_empty() || self.skip > 0 {
+			return Err(Error::TrailingData);
+		}
+
+		Ok(())
+	}
+
+	fn element(&mut self, id: u32, body: &[u8]) -> Result<()> {
+		match id {
+			ebml::EBML => {
+				for child in Children::new(body) {
+					let (id, body) = child?;
+					if id == ebml::DOC_TYPE {
+						let doc_type = ebml::string(body);
+						if doc_type != "webm" && doc_type != "matroska" {
+							return Err(Error::UnsupportedDocType(doc_type));
+						}
+					}
+				}
+			}
+			ebml::INFO => {
+				for child in Children::new(body) {
+					let (id, body) = child?;
+					if id == ebml::TIMESTAMP_SCALE {
+						self.timestamp_scale = ebml::uint(body)?;
+					}
+				}
+			}
+			ebml::TRACKS => {
+				for child in Children::new(body) {
+					let (id, body) = child?;
+					if id == ebml::TRACK_ENTRY {
+						self.init_track(body)?;
+					}
+				}
+			}
+			ebml::TIMESTAMP => self.cluster = Some(ebml::uint(body)?),
+			ebml::SIMPLE_BLOCK => {
+				let keyframe = body.get(3).ok_or(Error::InvalidElement(id))? & 0x80 != 0;
+				self.block(body, keyframe)?;
+			}
+			ebml::BLOCK_GROUP => {
+				let mut block = None;
+				let mut keyframe = true;
+
+				for child in Children::new(body) {
+					let (id, body) = child?;
+					match id {
+						ebml::BLOCK => block = Some(body),
+						// Any reference means this isn't a keyframe.
+						ebml::REFERENCE_BLOCK => keyframe = false,
+						_ => {}
+					}
+				}
+
+				let block = block.ok_or(Error::InvalidElement(id))?;
+				self.block(block, keyframe)?;
+			}
+			_ => unreachable!("unexpected element"),
+		}
+
+		Ok(())
+	}
+
+	fn init_track(&mut self, entry: &[u8]) -> Result<()> {
+		let mut number = None;
+		let mut kind = None;
+		let mut codec_id = String::new();
+		let mut private = None;
+		let mut width = None;
+		let mut height = None;
+		let mut sample_rate = 8000.0;
+		let mut channel_count = 1;
+
+		for child in Children::new(entry) {
+			let (id, body) = child?;
+			match id {
+				ebml::TRACK_NUMBER => number = Some(ebml::uint(body)?),
+				ebml::TRACK_TYPE => kind = Some(ebml::uint(body)?),
+				ebml::CODEC_ID => codec_id = ebml::string(body),
+				ebml::CODEC_PRIVATE => private = Some(Bytes::copy_from_slice(body)),
+				ebml::VIDEO => {
+					for child in Children::new(body) {
+						let (id, body) = child?;
+						match id {
+							ebml::PIXEL_WIDTH => width = Some(ebml::uint(body)? as u32),
+							ebml::PIXEL_HEIGHT => height = Some(ebml::uint(body)? as u32),
+							_ => {}
+						}
+					}
+				}
+				ebml::AUDIO => {
+					for child in Children::new(body) {
+						let (id, body) = child?;
+						match id {
+							ebml::SAMPLING_FREQUENCY => sample_rate = ebml::float(body)?,
+							ebml::CHANNELS => channel_count = ebml::uint(body)? as u32,
+							_ => {}
+						}
+					}
+				}
+				_ => {}
+			}
+		}
+
+		let number = number.ok_or(Error::InvalidElement(ebml::TRACK_NUMBER))?;
+
+		match kind {
+			Some(TRACK_TYPE_VIDEO) => {
+				let (codec, description) = match codec_id.as_str() {
+					"V_VP8" => (VideoCodec::VP8, None),
+					"V_VP9" => (Self::init_vp9(private.as_deref())?.into(), None),
+					"V_AV1" => {
+						let mut private = private.as_deref().ok_or(Error::MissingCodecPrivate)?;
+						let av1c = mp4_atom::Av1c::decode_body(&mut private)?;
+
+						let codec = AV1 {
+							profile: av1c.seq_profile,
+							level: av1c.seq_level_idx_0,
+							tier: if av1c.seq_tier_0 { 'H' } else { 'M' },
+							bitdepth: match (av1c.high_bitdepth, av1c.twelve_bit) {
+								(true, true) => 12,
+								(true, false) => 10,
+								(false, _) => 8,
+							},
+							mono_chrome: av1c.monochrome,
+							chroma_subsampling_x: av1c.chroma_subsampling_x,
+							chroma_subsampling_y: av1c.chroma_subsampling_y,
+							chroma_sample_position: av1c.chroma_sample_position,
+							..Default::default()
+						};
+
+						(codec.into(), None)
+					}
+					"V_MPEG4/ISO/AVC" => {
+						let private = private.ok_or(Error::MissingCodecPrivate)?;
+						let avcc = mp4_atom::Avcc::decode_body(&mut private.as_ref())?;
+
+						let codec = H264 {
+							profile: avcc.avc_profile_indication,
+							constraints: avcc.profile_compatibility,
+							level: avcc.avc_level_indication,
+						};
+
+						// The CodecPrivate is the avcC box body.
+						(codec.into(), Some(private))
+					}
+					_ => {
+						tracing::warn!(number, codec = codec_id, "skipping unsupported video codec");
+						return Ok(());
+					}
+				};
+
+				let track = Track {
+					name: format!("video{number}"),
+					priority: 2,
+					..Default::default()
+				};
+
+				let video = Video {
+					track,
+					config: VideoConfig {
+						codec,
+						description,
+						coded_width: width,
+						coded_height: height,
+						display_ratio_width: None,
+						display_ratio_height: None,
+						bitrate: None,
+						framerate: None,
+						rotation: None,
+						flip: None,
+						optimize_for_latency: None,
+						color_space: None,
+					},
+					rendition_group: None,
+					index: None,
+				};
+
+				let producer = self.broadcast.create_video(video);
+				self.video.insert(number, producer);
+			}
+			Some(TRACK_TYPE_AUDIO) => {
+				let config = match codec_id.as_str() {
+					// The CodecPrivate is the OpusHead, which contains the channel mapping.
+					"A_OPUS" => match private {
+						Some(private) => crate::ogg::OpusHead::parse(private)?.config(),
+						None => AudioConfig {
+							codec: AudioCodec::Opus,
+							sample_rate: 48_000,
+							channel_count,
+							bitrate: None,
+							description: None,
+						},
+					},
+					// The CodecPrivate is the AudioSpecificConfig.
+					"A_AAC" => {
+						let private = private.ok_or(Error::MissingCodecPrivate)?;
+						let profile = private.first().ok_or(Error::MissingCodecPrivate)? >> 3;
+
+						AudioConfig {
+							codec: AAC { profile }.into(),
+							sample_rate: sample_rate as _,
+							channel_count,
+							bitrate: None,
+							description: Some(private),
+						}
+					}
+					_ => {
+						tracing::warn!(number, codec = codec_id, "skipping unsupported audio codec");
+						return Ok(());
+					}
+				};
+
+				let track = Track {
+					name: format!("audio{number}"),
+					priority: 2,
+					..Default::default()
+				};
+
+				let producer = self.broadcast.create_audio(Audio {
+					track,
+					config,
+					index: None,
+				});
+				self.audio.insert(number, producer);
+			}
+			_ => tracing::warn!(number, ?kind, "skipping unsupported track type"),
+		}
+
+		Ok(())
+	}
+
+	// The optional CodecPrivate contains the VP9 codec features.
+	// https://www.webmproject.org/docs/container/#vp9-codec-feature-metadata-codecprivate
+	fn init_vp9(private: Option<&[u8]>) -> Result<VP9> {
+		// Defaults for when the CodecPrivate is missing, which is common.
+		let mut vp9 = VP9 {
+			level: 10,
+			bit_depth: 8,
+			..Default::default()
+		};
+
+		let mut remain = private.unwrap_or_default();
+		while let [id, size, rest @ ..] = remain {
+			let (value, rest) = rest
+				.split_at_checked(*size as usize)
+				.ok_or(Error::MissingCodecPrivate)?;
+			let value = *value.first().ok_or(Error::MissingCodecPrivate)?;
+
+			match id {
+				1 => vp9.profile = value,
+				2 => vp9.level = value,
+				3 => vp9.bit_depth = value,
+				4 => vp9.chroma_subsampling = value,
+				_ => {}
+			}
+
+			remain = rest;
+		}
+
+		Ok(vp9)
+	}
+
+	fn block(&mut self, block: &[u8], keyframe: bool) -> Result<()> {
+		let (number, size) = ebml::vint(block, false)?.ok_or(Error::InvalidElement(ebml::BLOCK))?;
+		let header = block.get(size..size + 3).ok_or(Error::InvalidElement(ebml::BLOCK))?;
+
+		let relative = i16::from_be_bytes([header[0], header[1]]);
+		let flags = header[2];
+
+		// Lacing packs multiple frames into a block, which is rare for video and not supported.
+		if flags & 0x06 != 0 {
+			return Err(Error::UnsupportedLacing);
+		}
+
+		let cluster = self.cluster.ok_or(Error::InvalidElement(ebml::TIMESTAMP))?;
+		let units = (cluster as i64 + relative as i64).max(0) as u64;
+		let timestamp = Timestamp::from_nanos(units * self.timestamp_scale);
+
+		let (track, keyframe) = if let Some(track) = self.video.get_mut(&number) {
+			if keyframe {
+				// Start a new audio group alongside each video group.
+				self.last_keyframe.clear();
+			}
+
+			(track, keyframe)
+		} else if let Some(track) = self.audio.get_mut(&number) {
+			let keyframe = match self.last_keyframe.get(&number) {
+				// Force an audio keyframe at least every 10 seconds, but ideally at video keyframes
+				Some(prev) => timestamp.saturating_sub(*prev) > Duration::from_secs(10),
+				None => true,
+			};
+
+			if keyframe {
+				self.last_keyframe.insert(number, timestamp);
+			}
+
+			(track, keyframe)
+		} else {
+			// Ignore blocks for unsupported tracks.
+			return Ok(());
+		};
+
+		track.write(Frame {
+			timestamp,
+			decode_timestamp: None,
+			duration: None,
+			keyframe,
+			payload: Bytes::copy_from_slice(&block[size + 3..]),
+		});
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use futures::FutureExt;
+
+	fn element(id: u32, body: &[u8]) -> Vec<u8> {
+		let id = id.to_be_bytes();
+		let start = id.iter().position(|&b| b != 0).unwrap();
+
+		// Always use an 8 byte size for simplicity.
+		let mut data = id[start..].to_vec();
+		data.push(0x01);
+		data.extend_from_slice(&(body.len() as u64).to_be_bytes()[1..]);
+		data.extend_from_slice(body);
+		data
+	}
+
+	fn block(id: u32, track: u8, relative: i16, flags: u8, payload: &[u8]) -> Vec<u8> {
+		let mut body = vec![0x80 | track];
+		body.extend_from_slice(&relative.to_be_bytes());
+		body.push(flags);
+		body.extend_from_slice(payload);
+		element(id, &body)
+	}
+
+	#[tokio::test]
+	async fn import() {
+		let mut head = b"OpusHead".to_vec();
+		head.extend_from_slice(&[1, 2, 0x38, 0x01, 0x80, 0xbb, 0, 0, 0, 0, 0]);
+
+		let video = [
+			element(ebml::TRACK_NUMBER, &[1]),
+			element(ebml::TRACK_TYPE, &[1]),
+			element(ebml::CODEC_ID, b"V_VP8"),
+			element(
+				ebml::VIDEO,
+				&[
+					element(ebml::PIXEL_WIDTH, &[0x02, 0x80]),
+					element(ebml::PIXEL_HEIGHT, &[0x01, 0xe0]),
+				]
+				.concat(),
+			),
+		]
+		.concat();
+
+		let audio = [
+			element(ebml::TRACK_NUMBER, &[2]),
+			element(ebml::TRACK_TYPE, &[2]),
+			element(ebml::CODEC_ID, b"A_OPUS"),
+			element(ebml::CODEC_PRIVATE, &head),
+		]
+		.concat();
+
+		// A subtitle track that should be ignored.
+		let text = [
+			element(ebml::TRACK_NUMBER, &[3]),
+			element(ebml::TRACK_TYPE, &[0x11]),
+			element(ebml::CODEC_ID, b"S_TEXT/WEBVTT"),
+		]
+		.concat();
+
+		let mut data = element(ebml::EBML, &element(ebml::DOC_TYPE, b"webm"));
+
+		// The segment and first cluster have an unknown size, like a live stream.
+		data.extend_from_slice(&[0x18, 0x53, 0x80, 0x67, 0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+		data.extend(element(
+			ebml::INFO,
+			&element(ebml::TIMESTAMP_SCALE, &[0x0f, 0x42, 0x40]),
+		));
+		data.extend(element(
+			ebml::TRACKS,
+			&[
+				element(ebml::TRACK_ENTRY, &video),
+				element(ebml::TRACK_ENTRY, &audio),
+				element(ebml::TRACK_ENTRY, &text),
+			]
+			.concat(),
+		));
+		data.extend(element(0xEC, &[0; 100]));
+		data.extend_from_slice(&[0x1f, 0x43, 0xb6, 0x75, 0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+		data.extend(element(ebml::TIMESTAMP, &[0x03, 0xe8]));
+		data.extend(block(ebml::SIMPLE_BLOCK, 1, 0, 0x80, b"key"));
+		data.extend(block(ebml::SIMPLE_BLOCK, 2, 0, 0x80, b"opus1"));
+		data.extend(block(ebml::SIMPLE_BLOCK, 3, 0, 0x80, b"text"));
+		data.extend(block(ebml::SIMPLE_BLOCK, 2, 20, 0x80, b"opus2"));
+
+		// A cluster with a known size, using a BlockGroup with a reference.
+		let group = [
+			block(ebml::BLOCK, 1, -7, 0, b"delta"),
+			element(ebml::REFERENCE_BLOCK, &[0xff]),
+		]
+		.concat();
+		data.extend(element(
+			ebml::CLUSTER,
+			&[
+				element(ebml::TIMESTAMP, &[0x04, 0x0b]),
+				element(ebml::BLOCK_GROUP, &group),
+			]
+			.concat(),
+		));
+		data.extend(element(0x1C53BB6B, &[0; 20]));
+
+		let broadcast = BroadcastProducer::new();
+		let mut consumer = broadcast.consume();
+
+		let mut import = Import::new(broadcast);
+		for chunk in data.chunks(7) {
+			import.parse(chunk).unwrap();
+		}
+
+		let catalog = consumer.catalog.next().now_or_never().unwrap().unwrap().unwrap();
+		assert_eq!(catalog.video.len(), 1);
+		assert_eq!(catalog.audio.len(), 1);
+
+		let video = &catalog.video[0];
+		assert_eq!(video.track.name, "video1");
+		assert_eq!(video.config.codec, VideoCodec::VP8);
+		assert_eq!(video.config.coded_width, Some(640));
+		assert_eq!(video.config.coded_height, Some(480));
+
+		let audio = &catalog.audio[0];
+		assert_eq!(audio.track.name, "audio2");
+		assert_eq!(audio.config.codec, AudioCodec::Opus);
+		assert_eq!(audio.config.channel_count, 2);
+
+		let mut track = consumer.subscribe(&video.track);
+		let expected = [(true, 1000, &b"key"[..]), (false, 1028, b"delta")];
+		for (keyframe, millis, payload) in expected {
+			let frame = track.read().now_or_never().unwrap().unwrap().unwrap();
+			assert_eq!(frame.keyframe, keyframe);
+			assert_eq!(frame.timestamp, Timestamp::from_millis(millis));
+			assert_eq!(&frame.payload[..], payload);
+		}
+
+		let mut track = consumer.subscribe(&audio.track);
+		let expected = [(true, 1000, &b"opus1"[..]), (false, 1020, b"opus2")];
+		for (keyframe, millis, payload) in expected {
+			let frame = track.read().now_or_never().unwrap().unwrap().unwrap();
+			assert_eq!(frame.keyframe, keyframe);
+			assert_eq!(frame.timestamp, Timestamp::from_millis(millis));
+			assert_eq!(&frame.payload[..], payload);
+		}
+	}
+
+	#[tokio::test]
+	async fn lacing() {
+		let video = [
+			element(ebml::TRACK_NUMBER, &[1]),
+			element(ebml::TRACK_TYPE, &[1]),
+			element(ebml::CODEC_ID, b"V_VP8"),
+		]
+		.concat();
+
+		let mut data = element(ebml::TRACKS, &element(ebml::TRACK_ENTRY, &video));
+		data.extend(element(ebml::TIMESTAMP, &[0]));
+		data.extend(block(ebml::SIMPLE_BLOCK, 1, 0, 0x82, b"laced"));
+
+		let mut import = Import::new(BroadcastProducer::new());
+		assert!(matches!(import.parse(&data), Err(Error::UnsupportedLacing)));
+	}
+
+	#[test]
+	fn vp9() {
+		let vp9 = Import::init_vp9(None).unwrap();
+		assert_eq!(vp9.to_string(), "vp09.00.10.08");
+
+		let vp9 = Import::init_vp9(Some(&[1, 1, 2, 2, 1, 31, 3, 1, 10])).unwrap();
+		assert_eq!(vp9.to_string(), "vp09.02.31.10");
+	}
+}
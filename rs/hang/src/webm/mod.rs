@@ -0,0 +1,6 @@
+mod ebml;
+mod error;
+mod import;
+
+pub use error::*;
+pub use import::*;
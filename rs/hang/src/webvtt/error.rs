@@ -0,0 +1,19 @@
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("missing WEBVTT header")]
+	MissingHeader,
+
+	#[error("invalid timestamp: {0}")]
+	InvalidTimestamp(String),
+
+	#[error("invalid text")]
+	InvalidText,
+
+	#[error("invalid sample")]
+	InvalidSample,
+
+	#[error("io error: {0}")]
+	Io(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
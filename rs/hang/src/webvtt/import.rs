@@ -0,0 +1,304 @@
+use super::{encode_sample, Error, Result, VttCue};
+use crate::catalog::{Text, TextCodec, TextConfig, TextKind};
+use crate::model::{BroadcastProducer, Cue, Timestamp, TrackProducer};
+use bytes::{Bytes, BytesMut};
+use moq_lite::Track;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Converts a WebVTT file -> Karp
+///
+/// Each cue is converted into an ISO/IEC 14496-30 sample, so the track matches one imported from fMP4.
+pub struct Import {
+	// Any partial line in the input buffer
+	buffer: BytesMut,
+
+	// The broadcast being produced
+	broadcast: BroadcastProducer,
+
+	// The lines of the current block, until a blank line.
+	block: Vec<String>,
+
+	// Everything before the first cue, starting with the WEBVTT line.
+	header: Option<String>,
+
+	// Created when the first cue is found, so the header is complete.
+	track: Option<TrackProducer>,
+
+	language: Option<String>,
+	kind: TextKind,
+	label: Option<String>,
+}
+
+impl Import {
+	pub fn new(broadcast: BroadcastProducer) -> Self {
+		Self {
+			buffer: BytesMut::new(),
+			broadcast,
+			block: Vec::new(),
+			header: None,
+			track: None,
+			language: None,
+			kind: TextKind::default(),
+			label: None,
+		}
+	}
+
+	/// Set the language of the text as a BCP 47 tag, since WebVTT files don't include it.
+	pub fn with_language(mut self, language: impl Into<String>) -> Self {
+		self.language = Some(language.into());
+		self
+	}
+
+	pub fn with_kind(mut self, kind: TextKind) -> Self {
+		self.kind = kind;
+		self
+	}
+
+	pub fn with_label(mut self, label: impl Into<String>) -> Self {
+		self.label = Some(label.into());
+		self
+	}
+
+	pub fn parse(&mut self, data: &[u8]) -> Result<()> {
+		if !self.buffer.is_empty() {
+			let mut buffer = std::mem::replace(&mut self.buffer, BytesMut::new());
+			buffer.extend_from_slice(data);
+			let n = self.parse_inner(&buffer)?;
+			self.buffer = buffer.split_off(n);
+		} else {
+			let n = self.parse_inner(data)?;
+			self.buffer = BytesMut::from(&data[n..]);
+		}
+
+		Ok(())
+	}
+
+	fn parse_inner<T: AsRef<[u8]>>(&mut self, data: T) -> Result<usize> {
+		let mut remain = data.as_ref();
+
+		while let Some(end) = remain.iter().position(|&b| b == b'\n') {
+			self.line(&remain[..end])?;
+			remain = &remain[end + 1..];
+		}
+
+		// Return the number of bytes consumed
+		Ok(data.as_ref().len() - remain.len())
+	}
+
+	// Read the file from a stream until EOF.
+	pub async fn read_from<T: AsyncRead + Unpin>(&mut self, input: &mut T) -> Result<()> {
+		let mut buffer = BytesMut::new();
+
+		while input.read_buf(&mut buffer).await? > 0 {
+			let n = self.parse_inner(&buffer)?;
+			let _ = buffer.split_to(n);
+		}
+
+		self.parse(&buffer)?;
+		self.finish()
+	}
+
+	/// Flush the final line and block at the end of the file, which may not be followed by a blank line.
+	pub fn finish(&mut self) -> Result<()> {
+		let remain = self.buffer.split();
+		if !remain.is_empty() {
+			self.line(&remain)?;
+		}
+
+		self.line(b"")
+	}
+
+	fn line(&mut self, line: &[u8]) -> Result<()> {
+		let line = std::str::from_utf8(line).map_err(|_| Error::InvalidText)?;
+		let line = line.strip_suffix('\r').unwrap_or(line);
+
+		if !line.is_empty() {
+			// Skip the byte order mark at the start of the file.
+			let line = match self.header.is_none() && self.block.is_empty() {
+				true => line.trim_start_matches('\u{feff}'),
+				false => line,
+			};
+
+			self.block.push(line.to_string());
+			return Ok(());
+		}
+
+		if self.block.is_empty() {
+			return Ok(());
+		}
+
+		let block = std::mem::take(&mut self.block);
+		self.block(block)
+	}
+
+	fn block(&mut self, block: Vec<String>) -> Result<()> {
+		let Some(header) = self.header.as_mut() else {
+			let first = &block[0];
+			if first != "WEBVTT" && !first.starts_with("WEBVTT ") && !first.starts_with("WEBVTT\t") {
+				return Err(Error::MissingHeader);
+			}
+
+			self.header = Some(block.join("\n"));
+			return Ok(());
+		};
+
+		let first = &block[0];
+		if first == "NOTE" || first.starts_with("NOTE ") || first.starts_with("NOTE\t") {
+			return Ok(());
+		}
+
+		// The timing line is either first, or after the cue identifier.
+		let (id, timing, payload) = match block.iter().position(|line| line.contains("-->")) {
+			Some(0) => (None, &block[0], &block[1..]),
+			Some(1) => (Some(block[0].clone()), &block[1], &block[2..]),
+			_ => {
+				if self.track.is_none() {
+					// STYLE and REGION blocks are part of the header.
+					header.push_str("\n\n");
+					header.push_str(&block.join("\n"));
+				} else {
+					tracing::warn!(?block, "skipping unknown block");
+				}
+
+				return Ok(());
+			}
+		};
+
+		let (start, rest) = timing.split_once("-->").expect("missing arrow");
+		let start = parse_timestamp(start.trim())?;
+
+		let rest = rest.trim_start();
+		let (end, settings) = match rest.split_once(char::is_whitespace) {
+			Some((end, settings)) => (end, Some(settings.trim().to_string()).filter(|s| !s.is_empty())),
+			None => (rest, None),
+		};
+		let end = parse_timestamp(end)?;
+
+		let cue = VttCue {
+			id,
+			settings,
+			payload: payload.join("\n"),
+		};
+
+		let track = self.track.get_or_insert_with(|| {
+			self.broadcast.create_text(Text {
+				track: Track {
+					name: "text".to_string(),
+					priority: 2,
+					..Default::default()
+				},
+				config: TextConfig {
+					codec: TextCodec::WebVtt,
+					language: self.language.clone(),
+					kind: self.kind,
+					label: self.label.clone(),
+					description: self.header.clone().map(Bytes::from),
+				},
+			})
+		});
+
+		track.write(
+			Cue {
+				start,
+				end,
+				payload: encode_sample(&[cue]),
+			}
+			.encode(),
+		);
+
+		Ok(())
+	}
+}
+
+// Parse a timestamp in the form (hh:)mm:ss.ttt
+fn parse_timestamp(s: &str) -> Result<Timestamp> {
+	let invalid = || Error::InvalidTimestamp(s.to_string());
+
+	let (rest, millis) = s.split_once('.').ok_or_else(invalid)?;
+	let mut parts = rest.rsplit(':');
+
+	let seconds = parts.next().ok_or_else(invalid)?;
+	let minutes = parts.next().ok_or_else(invalid)?;
+	let hours = parts.next().unwrap_or("0");
+
+	if parts.next().is_some() || millis.len() != 3 || seconds.len() != 2 || minutes.len() != 2 {
+		return Err(invalid());
+	}
+
+	let parse = |s: &str| s.parse::<u64>().map_err(|_| invalid());
+	let (hours, minutes, seconds, millis) = (parse(hours)?, parse(minutes)?, parse(seconds)?, parse(millis)?);
+
+	if minutes >= 60 || seconds >= 60 {
+		return Err(invalid());
+	}
+
+	Ok(Timestamp::from_millis(
+		((hours * 60 + minutes) * 60 + seconds) * 1000 + millis,
+	))
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::webvtt::decode_sample;
+	use futures::FutureExt;
+
+	const FILE: &str = "\u{feff}WEBVTT - Some title\r\n\r\nSTYLE\r\n::cue { color: yellow }\r\n\r\nNOTE This is a comment\r\n\r\n00:00.500 --> 00:02.000\r\n<v Bob>Hi\r\n\r\nbye\r\n01:00:03.000 --> 01:00:04.250 line:0 align:start\r\nHello\r\nworld";
+
+	#[tokio::test]
+	async fn import() {
+		let broadcast = BroadcastProducer::new();
+		let mut consumer = broadcast.consume();
+
+		let mut import = Import::new(broadcast).with_language("en").with_kind(TextKind::Captions);
+		for chunk in FILE.as_bytes().chunks(7) {
+			import.parse(chunk).unwrap();
+		}
+
+		// The final cue isn't complete until the end of the file.
+		import.finish().unwrap();
+
+		let catalog = consumer.catalog.next().now_or_never().unwrap().unwrap().unwrap();
+		let text = &catalog.text[0];
+		assert_eq!(text.config.codec, TextCodec::WebVtt);
+		assert_eq!(text.config.language.as_deref(), Some("en"));
+		assert_eq!(text.config.kind, TextKind::Captions);
+		assert_eq!(
+			text.config.description.as_deref(),
+			Some(&b"WEBVTT - Some title\n\nSTYLE\n::cue { color: yellow }"[..])
+		);
+
+		let mut track = consumer.subscribe(&text.track);
+
+		// Only the latest cue is available, since each cue is a new group.
+		let cue = Cue::decode(track.read().now_or_never().unwrap().unwrap().unwrap()).unwrap();
+		assert_eq!(cue.start, Timestamp::from_millis(3_603_000));
+		assert_eq!(cue.end, Timestamp::from_millis(3_604_250));
+		assert_eq!(
+			decode_sample(&cue.payload).unwrap(),
+			[VttCue {
+				id: Some("bye".to_string()),
+				settings: Some("line:0 align:start".to_string()),
+				payload: "Hello\nworld".to_string(),
+			}]
+		);
+	}
+
+	#[test]
+	fn timestamps() {
+		assert_eq!(parse_timestamp("00:02.000").unwrap(), Timestamp::from_secs(2));
+		assert_eq!(
+			parse_timestamp("100:00:00.001").unwrap(),
+			Timestamp::from_millis(360_000_001)
+		);
+		assert!(parse_timestamp("00:02").is_err());
+		assert!(parse_timestamp("0:02.000").is_err());
+		assert!(parse_timestamp("00:60.000").is_err());
+	}
+
+	#[tokio::test]
+	async fn header() {
+		let mut import = Import::new(BroadcastProducer::new());
+		assert!(matches!(import.parse(b"WEBVTTX\n\n"), Err(Error::MissingHeader)));
+	}
+}
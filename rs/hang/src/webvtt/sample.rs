@@ -0,0 +1,132 @@
+use super::{Error, Result};
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// An empty sample (vtte), used to fill the gaps between cues.
+pub const EMPTY_SAMPLE: [u8; 8] = [0, 0, 0, 8, b'v', b't', b't', b'e'];
+
+/// A WebVTT cue, as carried in an ISO/IEC 14496-30 sample.
+///
+/// The timing is not included; it comes from the sample instead.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct VttCue {
+	/// The optional cue identifier.
+	pub id: Option<String>,
+
+	/// The cue settings after the timing, ex. "line:0 align:start".
+	pub settings: Option<String>,
+
+	/// The cue text, which may span multiple lines.
+	pub payload: String,
+}
+
+/// Encode the cues that are active at the same time into a sample, or an empty sample if there are none.
+pub fn encode_sample(cues: &[VttCue]) -> Bytes {
+	if cues.is_empty() {
+		return Bytes::from_static(&EMPTY_SAMPLE);
+	}
+
+	let mut sample = BytesMut::new();
+
+	for cue in cues {
+		let mut vttc = BytesMut::new();
+
+		if let Some(id) = &cue.id {
+			encode_box(&mut vttc, b"iden", id.as_bytes());
+		}
+
+		if let Some(settings) = &cue.settings {
+			encode_box(&mut vttc, b"sttg", settings.as_bytes());
+		}
+
+		encode_box(&mut vttc, b"payl", cue.payload.as_bytes());
+		encode_box(&mut sample, b"vttc", &vttc);
+	}
+
+	sample.freeze()
+}
+
+/// Decode the cues in a sample, returning an empty list for an empty sample.
+pub fn decode_sample(mut sample: &[u8]) -> Result<Vec<VttCue>> {
+	let mut cues = Vec::new();
+
+	while !sample.is_empty() {
+		let (kind, body) = decode_box(&mut sample)?;
+		if &kind != b"vttc" {
+			// Skip vtte and any additional text (vtta).
+			continue;
+		}
+
+		let mut cue = VttCue::default();
+		let mut body = body;
+
+		while !body.is_empty() {
+			let (kind, text) = decode_box(&mut body)?;
+			let text = std::str::from_utf8(text).map_err(|_| Error::InvalidText)?.to_string();
+
+			match &kind {
+				b"iden" => cue.id = Some(text),
+				b"sttg" => cue.settings = Some(text),
+				b"payl" => cue.payload = text,
+				_ => {}
+			}
+		}
+
+		cues.push(cue);
+	}
+
+	Ok(cues)
+}
+
+fn encode_box(buffer: &mut BytesMut, kind: &[u8; 4], body: &[u8]) {
+	buffer.put_u32(8 + body.len() as u32);
+	buffer.put_slice(kind);
+	buffer.put_slice(body);
+}
+
+fn decode_box<'a>(buffer: &mut &'a [u8]) -> Result<([u8; 4], &'a [u8])> {
+	if buffer.len() < 8 {
+		return Err(Error::InvalidSample);
+	}
+
+	let size = u32::from_be_bytes(buffer[0..4].try_into().unwrap()) as usize;
+	let kind = buffer[4..8].try_into().unwrap();
+
+	if size < 8 || size > buffer.len() {
+		return Err(Error::InvalidSample);
+	}
+
+	let body = &buffer[8..size];
+	*buffer = &buffer[size..];
+
+	Ok((kind, body))
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn roundtrip() {
+		let cues = vec![
+			VttCue {
+				id: Some("1".to_string()),
+				settings: Some("line:0".to_string()),
+				payload: "Hello\nworld".to_string(),
+			},
+			VttCue {
+				payload: "<v Bob>Hi".to_string(),
+				..Default::default()
+			},
+		];
+
+		let sample = encode_sample(&cues);
+		assert_eq!(&sample[..8], &[0, 0, 0, 50, b'v', b't', b't', b'c']);
+		assert_eq!(decode_sample(&sample).unwrap(), cues);
+
+		let empty = encode_sample(&[]);
+		assert_eq!(empty, EMPTY_SAMPLE[..]);
+		assert!(decode_sample(&empty).unwrap().is_empty());
+
+		assert!(matches!(decode_sample(&sample[..20]), Err(Error::InvalidSample)));
+	}
+}
@@ -23,6 +23,10 @@ pub struct Config {
 	#[arg(long, default_value = "seconds")]
 	pub track: String,
 
+	/// Record the session to a capture file, decoded with `moq-dump`.
+	#[arg(long)]
+	pub capture: Option<std::path::PathBuf>,
+
 	/// The log configuration.
 	#[command(flatten)]
 	pub log: moq_native::Log,
@@ -48,11 +52,17 @@ async fn main() -> anyhow::Result<()> {
 	tracing::info!(url = ?config.url, "connecting to server");
 
 	let session = client.connect(config.url).await?;
-	let mut session = moq_lite::Session::connect(session).await?;
+	let mut session_config = SessionConfig::default();
+	if let Some(path) = config.capture {
+		session_config.capture = Some(moq_lite::Capture::create(path, &session_config.limits)?);
+	}
+
+	let mut session = moq_lite::Session::connect_with(session, session_config).await?;
 
 	let track = Track {
 		name: config.track,
 		priority: 0,
+		..Default::default()
 	};
 
 	match config.role {
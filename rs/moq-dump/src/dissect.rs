@@ -0,0 +1,492 @@
+use std::{collections::HashMap, fmt, io, time::Duration};
+
+use bytes::{Buf, BytesMut};
+use moq_lite::{
+	coding::*,
+	message::{self, Version, Versioned},
+	CaptureDirection, CaptureHeader, CaptureRecord,
+};
+
+/// A decoded message, or an error, on a captured stream.
+pub struct Event {
+	pub timestamp: Duration,
+	pub stream: u64,
+	pub direction: CaptureDirection,
+	pub message: String,
+}
+
+impl fmt::Display for Event {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let direction = match self.direction {
+			CaptureDirection::Send => "send",
+			CaptureDirection::Recv => "recv",
+		};
+
+		write!(
+			f,
+			"{:>12.3}ms  stream={:<5} {}  {}",
+			self.timestamp.as_secs_f64() * 1000.0,
+			self.stream,
+			direction,
+			self.message
+		)
+	}
+}
+
+/// The next message expected on one direction of a stream.
+#[derive(Clone, Copy, Debug)]
+enum Stage {
+	/// Waiting for the other side to send the stream type.
+	Pending,
+
+	ControlType,
+	DataType,
+
+	ClientSetup,
+	ServerSetup,
+	SessionMessage,
+
+	AnnounceRequest,
+	Announce,
+
+	Subscribe,
+	SubscribeOk,
+
+	Group,
+	Frame,
+	Payload(u64),
+
+	/// Nothing else is expected on this stream.
+	Unexpected,
+
+	/// A decode error occurred, so the rest of the stream is ignored.
+	Error,
+}
+
+struct Half {
+	buffer: BytesMut,
+	stage: Stage,
+}
+
+impl Half {
+	fn new(stage: Stage) -> Self {
+		Self {
+			buffer: BytesMut::new(),
+			stage,
+		}
+	}
+
+	/// Decode the next message, returning the stage of the reply direction if this was the stream type.
+	///
+	/// The version is updated when the server setup is decoded.
+	fn next(
+		&mut self,
+		limits: &DecodeLimits,
+		version: &mut Version,
+	) -> Result<Option<(String, Option<Stage>)>, DecodeError> {
+		// Skip over any frame payloads.
+		while let Stage::Payload(remain) = self.stage {
+			let size = remain.min(self.buffer.len() as u64);
+			self.buffer.advance(size as usize);
+
+			match remain - size {
+				0 => self.stage = Stage::Frame,
+				remain => {
+					self.stage = Stage::Payload(remain);
+					return Ok(None);
+				}
+			}
+		}
+
+		if self.buffer.is_empty() {
+			return Ok(None);
+		}
+
+		match self.stage {
+			Stage::Pending | Stage::Error => return Ok(None),
+			Stage::Unexpected => {
+				let size = self.buffer.len();
+				self.buffer.clear();
+				return Ok(Some((format!("unexpected {size} bytes"), None)));
+			}
+			_ => {}
+		}
+
+		let mut cursor = io::Cursor::new(&self.buffer[..]);
+
+		let (text, next, reply) = match Self::decode(self.stage, &mut cursor, limits, version) {
+			Err(DecodeError::Short) => return Ok(None),
+			res => res?,
+		};
+
+		let size = cursor.position() as usize;
+		self.buffer.advance(size);
+		self.stage = next;
+
+		Ok(Some((text, reply)))
+	}
+
+	/// Decode a message for the given stage, returning the next stage for both directions.
+	fn decode<B: Buf>(
+		stage: Stage,
+		buf: &mut B,
+		limits: &DecodeLimits,
+		version: &mut Version,
+	) -> Result<(String, Stage, Option<Stage>), DecodeError> {
+		Ok(match stage {
+			Stage::ControlType => {
+				let typ = message::ControlType::decode_limited(buf, limits)?;
+				let (next, reply) = match typ {
+					message::ControlType::Session => (Stage::ClientSetup, Stage::ServerSetup),
+					message::ControlType::Announce => (Stage::AnnounceRequest, Stage::Announce),
+					message::ControlType::Subscribe => (Stage::Subscribe, Stage::SubscribeOk),
+				};
+				(format!("{typ:?}"), next, Some(reply))
+			}
+			Stage::DataType => {
+				let typ = message::DataType::decode_limited(buf, limits)?;
+				let next = match typ {
+					message::DataType::Group => Stage::Group,
+				};
+				(format!("{typ:?}"), next, None)
+			}
+			Stage::ClientSetup => {
+				let msg = message::ClientSetup::decode_limited(buf, limits)?;
+				(format!("{msg:?}"), Stage::SessionMessage, None)
+			}
+			Stage::ServerSetup => {
+				let msg = message::ServerSetup::decode_limited(buf, limits)?;
+				*version = msg.version;
+				(format!("{msg:?}"), Stage::SessionMessage, None)
+			}
+			Stage::SessionMessage => {
+				let msg = message::SessionMessage::decode_limited(buf, limits)?;
+				(format!("{msg:?}"), Stage::SessionMessage, None)
+			}
+			Stage::AnnounceRequest => {
+				let msg = message::AnnounceRequest::decode_limited(buf, limits)?;
+				(format!("{msg:?}"), Stage::Unexpected, None)
+			}
+			Stage::Announce => {
+				let msg = message::Announce::decode_version(buf, limits, *version)?;
+				(format!("{msg:?}"), Stage::Announce, None)
+			}
+			Stage::Subscribe => {
+				let msg = message::Subscribe::decode_version(buf, limits, *version)?;
+				(format!("{msg:?}"), Stage::Unexpected, None)
+			}
+			Stage::SubscribeOk => {
+				let msg = message::SubscribeOk::decode_limited(buf, limits)?;
+				(format!("{msg:?}"), Stage::Unexpected, None)
+			}
+			Stage::Group => {
+				let msg = message::Group::decode_version(buf, limits, *version)?;
+				(format!("{msg:?}"), Stage::Frame, None)
+			}
+			Stage::Frame => {
+				let msg = message::Frame::decode_limited(buf, limits)?;
+				(format!("{msg:?}"), Stage::Payload(msg.size), None)
+			}
+			Stage::Pending | Stage::Payload(_) | Stage::Unexpected | Stage::Error => unreachable!(),
+		})
+	}
+}
+
+struct Stream {
+	send: Half,
+	recv: Half,
+	last: Duration,
+}
+
+impl Stream {
+	fn new(record: &CaptureRecord) -> Self {
+		// Whoever sends first opened the stream and sends the stream type.
+		let opener = match record.is_uni() {
+			true => Stage::DataType,
+			false => Stage::ControlType,
+		};
+
+		let (send, recv) = match record.direction {
+			CaptureDirection::Send => (opener, Stage::Pending),
+			CaptureDirection::Recv => (Stage::Pending, opener),
+		};
+
+		Self {
+			send: Half::new(send),
+			recv: Half::new(recv),
+			last: record.timestamp,
+		}
+	}
+
+	fn half(&mut self, direction: CaptureDirection) -> (&mut Half, &mut Half) {
+		match direction {
+			CaptureDirection::Send => (&mut self.send, &mut self.recv),
+			CaptureDirection::Recv => (&mut self.recv, &mut self.send),
+		}
+	}
+}
+
+/// Reassembles captured chunks into moq-lite messages.
+pub struct Dissector {
+	streams: HashMap<u64, Stream>,
+	limits: DecodeLimits,
+
+	// The negotiated version, assumed to be the current version until the server setup is decoded.
+	version: Version,
+}
+
+impl Default for Dissector {
+	fn default() -> Self {
+		Self::new(CaptureHeader::default().limits())
+	}
+}
+
+impl Dissector {
+	/// Decode messages using the limits recorded in the [CaptureHeader].
+	pub fn new(limits: DecodeLimits) -> Self {
+		Self {
+			streams: HashMap::new(),
+			limits,
+			version: Version::CURRENT,
+		}
+	}
+
+	/// Process the next record, returning any messages that are now complete.
+	pub fn record(&mut self, record: &CaptureRecord) -> Vec<Event> {
+		let stream = self.streams.entry(record.stream).or_insert_with(|| Stream::new(record));
+		stream.last = record.timestamp;
+
+		let (half, other) = stream.half(record.direction);
+		half.buffer.extend_from_slice(&record.data);
+
+		let mut events = Vec::new();
+		let mut replied = false;
+
+		loop {
+			let message = match half.next(&self.limits, &mut self.version) {
+				Ok(Some((message, reply))) => {
+					if let Some(reply) = reply {
+						other.stage = reply;
+						replied = true;
+					}
+
+					message
+				}
+				Ok(None) => break,
+				Err(err) => {
+					half.stage = Stage::Error;
+					format!("decode error: {err}")
+				}
+			};
+
+			events.push(Event {
+				timestamp: record.timestamp,
+				stream: record.stream,
+				direction: record.direction,
+				message,
+			});
+		}
+
+		// The reply may have arrived before we knew the stream type.
+		if replied && !other.buffer.is_empty() {
+			let direction = match record.direction {
+				CaptureDirection::Send => CaptureDirection::Recv,
+				CaptureDirection::Recv => CaptureDirection::Send,
+			};
+
+			events.extend(self.record(&CaptureRecord {
+				stream: record.stream,
+				direction,
+				timestamp: record.timestamp,
+				data: Default::default(),
+			}));
+		}
+
+		events
+	}
+
+	/// Report any streams that ended in the middle of a message.
+	pub fn finish(self) -> Vec<Event> {
+		let mut events = Vec::new();
+
+		let mut streams: Vec<_> = self.streams.into_iter().collect();
+		streams.sort_by_key(|(id, _)| *id);
+
+		for (id, stream) in streams {
+			for (direction, half) in [
+				(CaptureDirection::Send, stream.send),
+				(CaptureDirection::Recv, stream.recv),
+			] {
+				let message = match half.stage {
+					Stage::Payload(remain) => format!("incomplete frame: {remain} bytes missing"),
+					Stage::Error => continue,
+					_ if !half.buffer.is_empty() => format!("incomplete message: {} bytes buffered", half.buffer.len()),
+					_ => continue,
+				};
+
+				events.push(Event {
+					timestamp: stream.last,
+					stream: id,
+					direction,
+					message,
+				});
+			}
+		}
+
+		events
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn encode<T: Encode>(msg: T) -> Vec<u8> {
+		let mut data = Vec::new();
+		msg.encode(&mut data);
+		data
+	}
+
+	fn record(stream: u64, direction: CaptureDirection, data: Vec<u8>) -> CaptureRecord {
+		CaptureRecord {
+			stream,
+			direction,
+			timestamp: Duration::ZERO,
+			data: data.into(),
+		}
+	}
+
+	fn messages(dissector: &mut Dissector, record: CaptureRecord) -> Vec<String> {
+		dissector
+			.record(&record)
+			.into_iter()
+			.map(|event| event.message)
+			.collect()
+	}
+
+	#[test]
+	fn subscribe() {
+		let mut dissector = Dissector::default();
+
+		let msgs = messages(
+			&mut dissector,
+			record(0, CaptureDirection::Recv, encode(message::ControlType::Subscribe)),
+		);
+		assert_eq!(msgs, ["Subscribe"]);
+
+		let subscribe = message::Subscribe {
+			id: 1,
+			broadcast: "demo".to_string(),
+			track: "video".to_string(),
+			priority: 0,
+			first_frame: false,
+			nth_group: 0,
+			min_interval: Duration::ZERO,
+			max_age: Duration::ZERO,
+			resume_group: None,
+			resume_frame: 0,
+		};
+
+		let msgs = messages(&mut dissector, record(0, CaptureDirection::Recv, encode(subscribe)));
+		assert_eq!(msgs.len(), 1);
+		assert!(msgs[0].starts_with("Subscribe {"));
+
+		let msgs = messages(
+			&mut dissector,
+			record(0, CaptureDirection::Send, encode(message::SubscribeOk { priority: 0 })),
+		);
+		assert_eq!(msgs.len(), 1);
+		assert!(msgs[0].starts_with("SubscribeOk {"));
+	}
+
+	#[test]
+	fn group() {
+		let mut dissector = Dissector::default();
+
+		let mut data = Vec::new();
+		message::DataType::Group.encode(&mut data);
+		message::Group {
+			subscribe: 1,
+			sequence: 2,
+			priority: 0,
+			frame_offset: 0,
+		}
+		.encode(&mut data);
+		message::Frame { size: 5 }.encode(&mut data);
+		data.extend_from_slice(b"hel");
+
+		let msgs = messages(&mut dissector, record(1, CaptureDirection::Send, data));
+		assert_eq!(msgs.len(), 3);
+		assert!(msgs[2].contains("size: 5"));
+
+		// The rest of the payload is skipped.
+		let mut data = b"lo".to_vec();
+		message::Frame { size: 0 }.encode(&mut data);
+
+		let msgs = messages(&mut dissector, record(1, CaptureDirection::Send, data));
+		assert_eq!(msgs.len(), 1);
+		assert!(msgs[0].contains("size: 0"));
+
+		assert!(dissector.finish().is_empty());
+	}
+
+	#[test]
+	fn incomplete() {
+		let mut dissector = Dissector::default();
+
+		let mut data = Vec::new();
+		message::DataType::Group.encode(&mut data);
+		message::Group {
+			subscribe: 1,
+			sequence: 2,
+			priority: 0,
+			frame_offset: 0,
+		}
+		.encode(&mut data);
+		message::Frame { size: 5 }.encode(&mut data);
+
+		messages(&mut dissector, record(1, CaptureDirection::Recv, data));
+
+		let events = dissector.finish();
+		assert_eq!(events.len(), 1);
+		assert_eq!(events[0].message, "incomplete frame: 5 bytes missing");
+	}
+
+	#[test]
+	fn version() {
+		let mut dissector = Dissector::default();
+
+		let mut data = Vec::new();
+		message::ControlType::Session.encode(&mut data);
+		let msgs = messages(&mut dissector, record(0, CaptureDirection::Send, data));
+		assert_eq!(msgs, ["Session"]);
+
+		let setup = message::ServerSetup {
+			version: Version::LITE_00,
+			extensions: Default::default(),
+		};
+		let msgs = messages(&mut dissector, record(0, CaptureDirection::Recv, encode(setup)));
+		assert_eq!(msgs.len(), 1);
+		assert!(msgs[0].starts_with("ServerSetup {"));
+
+		// Groups are decoded without the fields added in later versions.
+		let group = message::Group {
+			subscribe: 1,
+			sequence: 2,
+			priority: 0,
+			frame_offset: 0,
+		};
+
+		let mut data = Vec::new();
+		message::DataType::Group.encode(&mut data);
+		group.encode_version(&mut data, Version::LITE_00);
+		message::Frame { size: 0 }.encode(&mut data);
+
+		let msgs = messages(&mut dissector, record(1, CaptureDirection::Recv, data));
+		assert_eq!(msgs.len(), 3);
+		assert!(msgs[1].contains("sequence: 2"));
+		assert!(msgs[2].contains("size: 0"));
+
+		assert!(dissector.finish().is_empty());
+	}
+}
@@ -0,0 +1,66 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::Context;
+use bytes::Buf;
+use clap::Parser;
+use moq_lite::{coding::*, CaptureHeader, CaptureRecord, CAPTURE_MAGIC};
+
+mod dissect;
+use dissect::*;
+
+/// Decode a moq-lite session capture into a readable timeline.
+#[derive(Parser, Clone)]
+pub struct Config {
+	/// The capture file produced by [moq_lite::Capture].
+	#[arg()]
+	pub input: PathBuf,
+
+	/// Only show the given stream IDs.
+	#[arg(long)]
+	pub stream: Vec<u64>,
+}
+
+fn main() -> anyhow::Result<()> {
+	let config = Config::parse();
+
+	let data = fs::read(&config.input).with_context(|| format!("failed to read {}", config.input.display()))?;
+	let mut buf = bytes::Bytes::from(data);
+
+	anyhow::ensure!(
+		buf.len() >= CAPTURE_MAGIC.len() && buf[..CAPTURE_MAGIC.len()] == CAPTURE_MAGIC[..],
+		"not a capture file"
+	);
+	buf.advance(CAPTURE_MAGIC.len());
+
+	// Decode messages with the same limits as the session, but without protecting against large chunks.
+	let header = CaptureHeader::decode(&mut buf).context("failed to decode capture header")?;
+	let limits = header.limits();
+
+	let mut dissector = Dissector::new(limits);
+
+	while buf.has_remaining() {
+		let record = match CaptureRecord::decode_limited(&mut buf, &limits) {
+			Ok(record) => record,
+			Err(DecodeError::Short) => {
+				// The session was probably still running when the capture was copied.
+				eprintln!("capture truncated");
+				break;
+			}
+			Err(err) => return Err(err).context("failed to decode capture"),
+		};
+
+		if !config.stream.is_empty() && !config.stream.contains(&record.stream) {
+			continue;
+		}
+
+		for event in dissector.record(&record) {
+			println!("{event}");
+		}
+	}
+
+	for event in dissector.finish() {
+		println!("{event}");
+	}
+
+	Ok(())
+}
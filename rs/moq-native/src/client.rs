@@ -167,28 +167,28 @@ impl Client {
 			url.set_scheme("https").expect("failed to set scheme");
 		}
 
-		let alpn = match url.scheme() {
-			"https" => web_transport::quinn::ALPN,
-			"moql" => moq_lite::ALPN,
+		let alpns: &[&str] = match url.scheme() {
+			"https" => &[web_transport::quinn::ALPN],
+			"moql" => &moq_lite::ALPNS,
 			_ => anyhow::bail!("url scheme must be 'http', 'https', or 'moql'"),
 		};
 
-		// TODO support connecting to both ALPNs at the same time
-		config.alpn_protocols = vec![alpn.as_bytes().to_vec()];
+		// TODO support connecting to both WebTransport and QUIC at the same time
+		config.alpn_protocols = alpns.iter().map(|alpn| alpn.as_bytes().to_vec()).collect();
 		config.key_log = Arc::new(rustls::KeyLogFile::new());
 
 		let config: quinn::crypto::rustls::QuicClientConfig = config.try_into()?;
 		let mut config = quinn::ClientConfig::new(Arc::new(config));
 		config.transport_config(self.transport.clone());
 
-		tracing::debug!(%url, %ip, %alpn, "connecting");
+		tracing::debug!(%url, %ip, ?alpns, "connecting");
 
 		let connection = self.quic.connect_with(config, ip, &host)?.await?;
 		tracing::Span::current().record("id", connection.stable_id());
 
 		let session = match url.scheme() {
 			"https" => web_transport::quinn::Session::connect(connection, url).await?,
-			moq_lite::ALPN => web_transport::quinn::Session::raw(connection, url),
+			"moql" => web_transport::quinn::Session::raw(connection, url),
 			_ => unreachable!(),
 		};
 
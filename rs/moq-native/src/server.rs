@@ -105,10 +105,9 @@ impl Server {
 			.with_no_client_auth()
 			.with_cert_resolver(Arc::new(serve));
 
-		tls.alpn_protocols = vec![
-			web_transport::quinn::ALPN.as_bytes().to_vec(),
-			moq_lite::ALPN.as_bytes().to_vec(),
-		];
+		tls.alpn_protocols = vec![web_transport::quinn::ALPN.as_bytes().to_vec()];
+		tls.alpn_protocols
+			.extend(moq_lite::ALPNS.iter().map(|alpn| alpn.as_bytes().to_vec()));
 		tls.key_log = Arc::new(rustls::KeyLogFile::new());
 
 		let tls: quinn::crypto::rustls::QuicServerConfig = tls.try_into()?;
@@ -195,7 +194,7 @@ impl Server {
 					.context("failed to respond to WebTransport request")?
 			}
 			// A bit of a hack to pretend like we're a WebTransport session
-			moq_lite::ALPN => {
+			alpn if moq_lite::ALPNS.contains(&alpn) => {
 				// Fake a URL to so we can treat it like a WebTransport session.
 				let url = Url::parse(format!("moql://{}", host).as_str()).unwrap();
 				web_transport::quinn::Session::raw(conn, url)
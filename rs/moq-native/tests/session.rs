@@ -0,0 +1,370 @@
+//! Session tests over a loopback QUIC connection.
+
+use std::{
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc,
+	},
+	time::Duration,
+};
+
+use moq_lite::{
+	message::Version, BroadcastProducer, Error, Filter, Group, GroupConsumer, ResumePoint, Session, SessionConfig,
+	Track, TrackConsumer, TrackProducer,
+};
+use moq_native::{ClientConfig, ClientTls, ServerConfig, ServerTlsConfig};
+use url::Url;
+
+// A connected client and server, keeping the QUIC endpoints alive.
+struct Loopback {
+	client: Session,
+	server: Session,
+
+	_endpoints: (moq_native::Client, moq_native::Server),
+}
+
+impl Loopback {
+	async fn new() -> Self {
+		Self::with(SessionConfig::default(), SessionConfig::default()).await
+	}
+
+	async fn with(client_config: SessionConfig, server_config: SessionConfig) -> Self {
+		let mut server = ServerConfig {
+			listen: Some("127.0.0.1:0".parse().unwrap()),
+			tls: ServerTlsConfig {
+				generate: vec!["localhost".to_string()],
+				..Default::default()
+			},
+		}
+		.init()
+		.unwrap();
+
+		let client = ClientConfig {
+			bind: "127.0.0.1:0".parse().unwrap(),
+			tls: ClientTls {
+				disable_verify: Some(true),
+				..Default::default()
+			},
+		}
+		.init()
+		.unwrap();
+
+		let url = Url::parse(&format!("moql://127.0.0.1:{}", server.local_addr().unwrap().port())).unwrap();
+		let (client_session, server_session) = tokio::join!(client.connect(url), server.accept());
+
+		let (client_session, server_session) = tokio::join!(
+			Session::connect_with(client_session.unwrap(), client_config),
+			Session::accept_with(server_session.unwrap(), server_config),
+		);
+
+		Self {
+			client: client_session.unwrap(),
+			server: server_session.unwrap(),
+			_endpoints: (client, server),
+		}
+	}
+}
+
+async fn next_group(track: &mut TrackConsumer) -> GroupConsumer {
+	tokio::time::timeout(Duration::from_secs(5), track.next_group())
+		.await
+		.expect("timeout")
+		.expect("track error")
+		.expect("track closed")
+}
+
+#[tokio::test]
+async fn rtt() {
+	let client = SessionConfig {
+		ping_interval: Some(Duration::from_millis(10)),
+		..Default::default()
+	};
+	let server = SessionConfig {
+		ping_interval: None,
+		..Default::default()
+	};
+
+	let mut session = Loopback::with(client, server).await;
+
+	let rtt = tokio::time::timeout(Duration::from_secs(5), session.client.rtt_changed())
+		.await
+		.expect("no pong")
+		.expect("session closed");
+	assert!(rtt < Duration::from_secs(1));
+	assert_eq!(session.client.rtt(), Some(rtt));
+
+	// Only one ping is outstanding at a time, so the next one is sent after the pong.
+	tokio::time::timeout(Duration::from_secs(5), session.client.rtt_changed())
+		.await
+		.expect("no second pong")
+		.expect("session closed");
+
+	// The server only responds to pings.
+	assert_eq!(session.server.rtt(), None);
+}
+
+#[tokio::test]
+async fn priority() {
+	let origin = Loopback::new().await;
+	let relay = Loopback::new().await;
+
+	let mut broadcast = BroadcastProducer::new();
+	let mut track = broadcast.create(Track::new("video"));
+	origin.server.clone().publish("demo", broadcast.consume());
+
+	// The relay serves the broadcast it receives from the origin.
+	relay.server.clone().publish("demo", origin.client.consume("demo"));
+
+	let broadcast = relay.client.consume("demo");
+	let mut consumer = broadcast.subscribe(&Track::new("video"));
+
+	let mut group = track.append_group_priority(5);
+	group.write_frame(b"hello".as_slice());
+
+	// The group priority is forwarded by each hop.
+	let mut group = next_group(&mut consumer).await;
+	assert_eq!(group.info.priority, 5);
+	assert_eq!(group.read_frame().await.unwrap().unwrap(), "hello");
+}
+
+#[tokio::test]
+async fn legacy() {
+	// The client only supports the original version, so the newer fields aren't sent.
+	let client = SessionConfig {
+		versions: [Version::LITE_00].into(),
+		ping_interval: Some(Duration::from_millis(10)),
+		..Default::default()
+	};
+
+	let mut session = Loopback::with(client, SessionConfig::default()).await;
+
+	let mut broadcast = BroadcastProducer::new();
+	let mut track = broadcast.create(Track::new("video"));
+	session.server.publish("demo", broadcast.consume());
+
+	let broadcast = session.client.consume("demo");
+	let mut consumer = broadcast.subscribe(&Track::new("video"));
+
+	let mut group = track.append_group_priority(5);
+	group.write_frame(b"hello".as_slice());
+	group.finish();
+
+	let mut group = next_group(&mut consumer).await;
+	assert_eq!(group.info.priority, 0);
+	assert_eq!(group.read_frame().await.unwrap().unwrap(), "hello");
+
+	// The original version doesn't support pings, even though both sides are configured to send them.
+	tokio::time::sleep(Duration::from_millis(50)).await;
+	assert_eq!(session.client.rtt(), None);
+	assert_eq!(session.server.rtt(), None);
+}
+
+#[tokio::test]
+async fn legacy_filters() {
+	let client = SessionConfig {
+		versions: [Version::LITE_00].into(),
+		..Default::default()
+	};
+
+	let mut session = Loopback::with(client, SessionConfig::default()).await;
+
+	let mut broadcast = BroadcastProducer::new();
+	let mut track = broadcast.create(Track::new("video"));
+	session.server.publish("demo", broadcast.consume());
+
+	// The original version can't send the filter, so it's only applied by the subscriber.
+	let broadcast = session.client.consume("demo");
+	let mut consumer = broadcast.subscribe(&Track {
+		filter: Filter {
+			first_frame: true,
+			nth_group: Some(2),
+			..Default::default()
+		},
+		..Track::new("video")
+	});
+
+	let write = |track: &mut TrackProducer| {
+		let mut group = track.append_group();
+		group.write_frame(b"key".as_slice());
+		group.write_frame(b"delta".as_slice());
+		group.finish();
+	};
+
+	write(&mut track);
+
+	let mut group = next_group(&mut consumer).await;
+	assert_eq!(group.info.sequence, 0);
+	assert_eq!(group.read_frame().await.unwrap().unwrap(), "key");
+	assert_eq!(group.read_frame().await.unwrap(), None);
+
+	write(&mut track);
+	write(&mut track);
+
+	let group = next_group(&mut consumer).await;
+	assert_eq!(group.info.sequence, 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn fairness() {
+	let session = Loopback::new().await;
+
+	let mut a = BroadcastProducer::new();
+	let mut b = BroadcastProducer::new();
+	session.client.clone().publish("a", a.consume());
+	session.client.clone().publish("b", b.consume());
+
+	let track = Track::new("video");
+	let mut track_a = a.create(track.clone());
+	let mut track_b = b.create(track.clone());
+
+	// Count the groups that were fully received, keeping the broadcasts alive.
+	let broadcast_a = session.server.consume("a");
+	let broadcast_b = session.server.consume("b");
+	let received_a = count(broadcast_a.subscribe(&track));
+	let received_b = count(broadcast_b.subscribe(&track));
+	tokio::time::sleep(Duration::from_millis(100)).await;
+
+	// Produce more than the loopback can deliver, so groups are dropped.
+	// Track A uses much larger sequence numbers, which must not affect the order.
+	let payload = vec![0u8; 128 * 1024];
+	for sequence in 0..300u64 {
+		let mut group = track_a.create_group(Group::from(1000 + sequence)).unwrap();
+		group.write_frame(payload.clone());
+		group.finish();
+
+		let mut group = track_b.append_group();
+		group.write_frame(payload.clone());
+		group.finish();
+
+		tokio::time::sleep(Duration::from_millis(5)).await;
+	}
+
+	tokio::time::sleep(Duration::from_millis(500)).await;
+
+	let a = received_a.load(Ordering::Relaxed);
+	let b = received_b.load(Ordering::Relaxed);
+	let total = a + b;
+
+	// Both tracks get roughly half of the delivered groups.
+	assert!(a * 5 >= total * 2, "unfair: a={a} b={b}");
+	assert!(b * 5 >= total * 2, "unfair: a={a} b={b}");
+}
+
+// Count the groups that have been fully received on a track.
+fn count(mut track: TrackConsumer) -> Arc<AtomicUsize> {
+	let received = Arc::new(AtomicUsize::new(0));
+
+	let counter = received.clone();
+	tokio::spawn(async move {
+		while let Ok(Some(mut group)) = track.next_group().await {
+			let counter = counter.clone();
+			tokio::spawn(async move {
+				if let Ok(Some(_)) = group.read_frame().await {
+					counter.fetch_add(1, Ordering::Relaxed);
+				}
+			});
+		}
+	});
+
+	received
+}
+
+#[tokio::test]
+async fn failover() {
+	let mut session = Loopback::new().await;
+
+	let mut primary = BroadcastProducer::new();
+	let mut track = primary.create(Track::new("video"));
+	session.server.publish("demo", primary.consume());
+
+	let broadcast = session.client.consume("demo");
+	let mut consumer = broadcast.subscribe(&Track::new("video"));
+
+	for payload in ["a0", "a1"] {
+		publish(&mut track, None, payload);
+		let mut group = next_group(&mut consumer).await;
+		assert_eq!(group.read_frame().await.unwrap().unwrap(), payload);
+	}
+
+	// The backup uses its own sequence numbers.
+	let mut backup = BroadcastProducer::new();
+	let mut backup_track = backup.create(Track::new("video"));
+	publish(&mut backup_track, Some(50), "b0");
+	session.server.publish("demo", backup.consume());
+
+	// The subscription switches to the backup at the next group, continuing the wire sequence.
+	let mut group = next_group(&mut consumer).await;
+	assert_eq!(group.info.sequence, 2);
+	assert_eq!(group.read_frame().await.unwrap().unwrap(), "b0");
+
+	publish(&mut backup_track, None, "b1");
+	let mut group = next_group(&mut consumer).await;
+	assert_eq!(group.info.sequence, 3);
+	assert_eq!(group.read_frame().await.unwrap().unwrap(), "b1");
+
+	// The primary no longer reaches the subscriber.
+	publish(&mut track, None, "a2");
+	publish(&mut backup_track, None, "b2");
+	let mut group = next_group(&mut consumer).await;
+	assert_eq!(group.info.sequence, 4);
+	assert_eq!(group.read_frame().await.unwrap().unwrap(), "b2");
+}
+
+#[tokio::test]
+async fn resume() {
+	let mut broadcast = BroadcastProducer::new();
+	let mut track = broadcast.create(Track::new("video"));
+
+	// Retain recent groups so a subscriber can resume after reconnecting.
+	track.set_history(Duration::from_secs(10));
+
+	let mut first = Loopback::new().await;
+	first.server.publish("demo", broadcast.consume());
+
+	let remote = first.client.consume("demo");
+	let mut consumer = remote.subscribe(&Track::new("video"));
+
+	let mut group = track.append_group();
+	group.write_frame(b"a".as_slice());
+
+	let mut received = next_group(&mut consumer).await;
+	assert_eq!(received.read_frame().await.unwrap().unwrap(), "a");
+
+	let resume = received.resume_point();
+	assert_eq!(resume, ResumePoint { group: 0, frame: 1 });
+
+	// The session dies mid-group, and the publisher moves on to the next group before we reconnect.
+	first.client.close(Error::Cancel);
+	group.write_frame(b"b".as_slice());
+	group.finish();
+	publish(&mut track, None, "c");
+
+	// The partial group is no longer the latest, but it's still retained by the publisher.
+	let mut second = Loopback::new().await;
+	second.server.publish("demo", broadcast.consume());
+
+	let remote = second.client.consume("demo");
+	let mut consumer = remote.subscribe(&Track {
+		resume: Some(resume),
+		..Track::new("video")
+	});
+
+	let mut group = next_group(&mut consumer).await;
+	assert_eq!(group.resume_point(), resume);
+	assert_eq!(group.read_frame().await.unwrap().unwrap(), "b");
+	assert_eq!(group.read_frame().await.unwrap(), None);
+
+	let mut group = next_group(&mut consumer).await;
+	assert_eq!(group.info.sequence, 1);
+	assert_eq!(group.read_frame().await.unwrap().unwrap(), "c");
+}
+
+// Write a group containing a single frame, with the next sequence number unless provided.
+fn publish(track: &mut TrackProducer, sequence: Option<u64>, payload: &'static str) {
+	let mut group = match sequence {
+		Some(sequence) => track.create_group(Group::from(sequence)).unwrap(),
+		None => track.append_group(),
+	};
+	group.write_frame(payload.as_bytes());
+	group.finish();
+}
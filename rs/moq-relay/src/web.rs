@@ -106,6 +106,7 @@ async fn serve_fetch(Path(path): Path<String>, cluster: Cluster) -> axum::respon
 	let track = moq_lite::Track {
 		name: track,
 		priority: 0,
+		..Default::default()
 	};
 
 	tracing::info!(?broadcast, ?track, "subscribing to track");
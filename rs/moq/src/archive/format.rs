@@ -0,0 +1,219 @@
+use std::time::Duration;
+
+use bytes::{Buf, Bytes, BytesMut};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use super::ArchiveError;
+use crate::coding::*;
+
+/// The magic bytes at the start of every archive.
+pub const ARCHIVE_MAGIC: &[u8; 8] = b"moqarc00";
+
+/// Something that happened to a broadcast, and when it happened.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArchiveRecord {
+	/// The time since the recording started.
+	pub timestamp: Duration,
+
+	pub event: ArchiveEvent,
+}
+
+/// An event in the archive; tracks are referred to by an ID assigned by the recorder.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ArchiveEvent {
+	/// A track was subscribed.
+	Track { id: u64, name: String, priority: u8 },
+
+	/// A group was received.
+	Group { track: u64, sequence: u64, priority: u8 },
+
+	/// A frame was received for a group.
+	Frame { track: u64, group: u64, payload: Bytes },
+
+	/// A group was finished, or aborted before all of its frames were received.
+	GroupEnd { track: u64, group: u64, aborted: bool },
+
+	/// A track was finished, or aborted.
+	TrackEnd { track: u64 },
+}
+
+impl Encode for ArchiveRecord {
+	fn encode<W: bytes::BufMut>(&self, w: &mut W) {
+		self.timestamp.encode(w);
+
+		match &self.event {
+			ArchiveEvent::Track { id, name, priority } => {
+				0u64.encode(w);
+				id.encode(w);
+				name.encode(w);
+				priority.encode(w);
+			}
+			ArchiveEvent::Group {
+				track,
+				sequence,
+				priority,
+			} => {
+				1u64.encode(w);
+				track.encode(w);
+				sequence.encode(w);
+				priority.encode(w);
+			}
+			ArchiveEvent::Frame { track, group, payload } => {
+				2u64.encode(w);
+				track.encode(w);
+				group.encode(w);
+				payload.len().encode(w);
+				w.put_slice(payload);
+			}
+			ArchiveEvent::GroupEnd { track, group, aborted } => {
+				3u64.encode(w);
+				track.encode(w);
+				group.encode(w);
+				(*aborted as u8).encode(w);
+			}
+			ArchiveEvent::TrackEnd { track } => {
+				4u64.encode(w);
+				track.encode(w);
+			}
+		}
+	}
+}
+
+impl Decode for ArchiveRecord {
+	fn decode_limited<R: bytes::Buf>(r: &mut R, limits: &DecodeLimits) -> Result<Self, DecodeError> {
+		let timestamp = Duration::decode_limited(r, limits)?;
+
+		let event = match u64::decode_limited(r, limits)? {
+			0 => ArchiveEvent::Track {
+				id: u64::decode_limited(r, limits)?,
+				name: String::decode_limited(r, limits)?,
+				priority: u8::decode_limited(r, limits)?,
+			},
+			1 => ArchiveEvent::Group {
+				track: u64::decode_limited(r, limits)?,
+				sequence: u64::decode_limited(r, limits)?,
+				priority: u8::decode_limited(r, limits)?,
+			},
+			2 => {
+				let track = u64::decode_limited(r, limits)?;
+				let group = u64::decode_limited(r, limits)?;
+
+				let size = u64::decode_limited(r, limits)?;
+				if size > limits.max_frame_size {
+					return Err(DecodeError::FrameTooLarge(size));
+				}
+
+				if (r.remaining() as u64) < size {
+					return Err(DecodeError::Short);
+				}
+
+				let payload = r.copy_to_bytes(size as usize);
+				ArchiveEvent::Frame { track, group, payload }
+			}
+			3 => ArchiveEvent::GroupEnd {
+				track: u64::decode_limited(r, limits)?,
+				group: u64::decode_limited(r, limits)?,
+				aborted: match u8::decode_limited(r, limits)? {
+					0 => false,
+					1 => true,
+					_ => return Err(DecodeError::InvalidValue),
+				},
+			},
+			4 => ArchiveEvent::TrackEnd {
+				track: u64::decode_limited(r, limits)?,
+			},
+			kind => return Err(DecodeError::InvalidMessage(kind)),
+		};
+
+		Ok(Self { timestamp, event })
+	}
+}
+
+/// Writes [ArchiveRecord]s to an output, starting with [ARCHIVE_MAGIC].
+pub struct ArchiveWriter<W: AsyncWrite + Unpin> {
+	output: W,
+	buffer: Vec<u8>,
+}
+
+impl<W: AsyncWrite + Unpin> ArchiveWriter<W> {
+	pub async fn new(mut output: W) -> Result<Self, ArchiveError> {
+		output.write_all(ARCHIVE_MAGIC).await?;
+
+		Ok(Self {
+			output,
+			buffer: Vec::new(),
+		})
+	}
+
+	pub async fn write(&mut self, record: &ArchiveRecord) -> Result<(), ArchiveError> {
+		self.buffer.clear();
+		record.encode(&mut self.buffer);
+		self.output.write_all(&self.buffer).await?;
+		Ok(())
+	}
+
+	pub async fn flush(&mut self) -> Result<(), ArchiveError> {
+		self.output.flush().await?;
+		Ok(())
+	}
+}
+
+/// Reads [ArchiveRecord]s from an input, validating [ARCHIVE_MAGIC].
+pub struct ArchiveReader<R: AsyncRead + Unpin> {
+	input: R,
+	buffer: BytesMut,
+	limits: DecodeLimits,
+	magic: bool,
+}
+
+impl<R: AsyncRead + Unpin> ArchiveReader<R> {
+	pub fn new(input: R) -> Self {
+		Self {
+			input,
+			buffer: BytesMut::new(),
+			// Only the frame size is enforced; the archive can be as long as it wants.
+			limits: DecodeLimits {
+				max_message: usize::MAX,
+				..Default::default()
+			},
+			magic: false,
+		}
+	}
+
+	/// Returns the next record, or None at the end of the archive.
+	pub async fn next(&mut self) -> Result<Option<ArchiveRecord>, ArchiveError> {
+		while !self.magic {
+			if self.buffer.len() >= ARCHIVE_MAGIC.len() {
+				if self.buffer[..ARCHIVE_MAGIC.len()] != ARCHIVE_MAGIC[..] {
+					return Err(ArchiveError::InvalidMagic);
+				}
+
+				self.buffer.advance(ARCHIVE_MAGIC.len());
+				self.magic = true;
+			} else if self.input.read_buf(&mut self.buffer).await? == 0 {
+				return Err(ArchiveError::InvalidMagic);
+			}
+		}
+
+		loop {
+			let mut cursor = std::io::Cursor::new(&self.buffer[..]);
+
+			match ArchiveRecord::decode_limited(&mut cursor, &self.limits) {
+				Ok(record) => {
+					let size = cursor.position() as usize;
+					self.buffer.advance(size);
+					return Ok(Some(record));
+				}
+				Err(DecodeError::Short) => {}
+				Err(err) => return Err(err.into()),
+			}
+
+			if self.input.read_buf(&mut self.buffer).await? == 0 {
+				return match self.buffer.is_empty() {
+					true => Ok(None),
+					false => Err(ArchiveError::Truncated),
+				};
+			}
+		}
+	}
+}
@@ -0,0 +1,201 @@
+//! A codec-agnostic archive of what a [crate::BroadcastConsumer] delivered, used for reproducible bug reports and load tests.
+//!
+//! A [Recorder] writes the tracks, groups and frames of a broadcast along with their arrival times.
+//! [replay] reads the archive back into a [crate::BroadcastProducer], either with the original pacing or as fast as possible.
+
+mod format;
+mod record;
+mod replay;
+
+pub use format::*;
+pub use record::*;
+pub use replay::*;
+
+use crate::{coding, Error};
+
+/// An error while reading or writing an archive.
+#[derive(thiserror::Error, Debug)]
+pub enum ArchiveError {
+	#[error("io error: {0}")]
+	Io(#[from] std::io::Error),
+
+	#[error("decode error: {0}")]
+	Decode(#[from] coding::DecodeError),
+
+	#[error("moq error: {0}")]
+	Moq(#[from] Error),
+
+	#[error("not an archive")]
+	InvalidMagic,
+
+	#[error("archive truncated")]
+	Truncated,
+}
+
+#[cfg(test)]
+mod test {
+	use std::time::Duration;
+
+	use super::*;
+	use crate::{BroadcastProducer, Track};
+
+	#[tokio::test(start_paused = true)]
+	async fn roundtrip() {
+		let mut broadcast = BroadcastProducer::new();
+		let mut track = broadcast.create(Track::new("video"));
+
+		let (output, mut input) = tokio::io::duplex(1 << 16);
+		let mut recorder = Recorder::new(broadcast.consume(), output);
+		assert!(recorder.track(&Track::new("video")));
+		assert!(!recorder.track(&Track::new("video")));
+
+		tokio::time::sleep(Duration::from_millis(10)).await;
+		let mut group = track.append_group();
+		group.write_frame(bytes::Bytes::from_static(b"hello"));
+
+		tokio::time::sleep(Duration::from_millis(10)).await;
+		group.write_frame(bytes::Bytes::from_static(b"world"));
+		group.finish();
+		track.finish();
+
+		let mut archive = Vec::new();
+		let (res, _) = tokio::join!(recorder.finish(), async {
+			tokio::io::AsyncReadExt::read_to_end(&mut input, &mut archive).await
+		});
+		res.unwrap();
+
+		let mut reader = ArchiveReader::new(archive.as_slice());
+		let mut events = Vec::new();
+		while let Some(record) = reader.next().await.unwrap() {
+			events.push((record.timestamp.as_millis(), record.event));
+		}
+
+		assert_eq!(
+			events,
+			[
+				(
+					0,
+					ArchiveEvent::Track {
+						id: 0,
+						name: "video".to_string(),
+						priority: 0
+					}
+				),
+				(
+					10,
+					ArchiveEvent::Group {
+						track: 0,
+						sequence: 0,
+						priority: 0
+					}
+				),
+				(
+					10,
+					ArchiveEvent::Frame {
+						track: 0,
+						group: 0,
+						payload: "hello".into()
+					}
+				),
+				(
+					20,
+					ArchiveEvent::Frame {
+						track: 0,
+						group: 0,
+						payload: "world".into()
+					}
+				),
+				(
+					20,
+					ArchiveEvent::GroupEnd {
+						track: 0,
+						group: 0,
+						aborted: false
+					}
+				),
+				(20, ArchiveEvent::TrackEnd { track: 0 }),
+			]
+		);
+
+		// Replay with the original pacing.
+		let mut replayed = BroadcastProducer::new();
+		let consumer = replayed.consume();
+		let start = tokio::time::Instant::now();
+
+		let replay = replay(archive.as_slice(), &mut replayed, Pacing::Original);
+		tokio::pin!(replay);
+
+		// Run the replay until the track is created.
+		tokio::select! {
+			biased;
+			_ = &mut replay => panic!("replay finished early"),
+			_ = tokio::task::yield_now() => {},
+		}
+
+		let mut track = consumer.subscribe(&Track::new("video"));
+		let (res, _) = tokio::join!(&mut replay, async {
+			let mut group = track.next_group().await.unwrap().unwrap();
+			assert_eq!(start.elapsed().as_millis(), 10);
+
+			assert_eq!(group.read_frame().await.unwrap().unwrap(), "hello");
+			assert_eq!(group.read_frame().await.unwrap().unwrap(), "world");
+			assert_eq!(start.elapsed().as_millis(), 20);
+			assert!(group.read_frame().await.unwrap().is_none());
+		});
+		res.unwrap();
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn aborted() {
+		let mut broadcast = BroadcastProducer::new();
+		let mut track = broadcast.create(Track::new("video"));
+
+		let (output, mut input) = tokio::io::duplex(1 << 16);
+		let mut recorder = Recorder::new(broadcast.consume(), output);
+		recorder.track(&Track::new("video"));
+
+		tokio::time::sleep(Duration::from_millis(10)).await;
+		let mut group = track.append_group();
+		group.write_frame(bytes::Bytes::from_static(b"hello"));
+
+		tokio::time::sleep(Duration::from_millis(10)).await;
+		group.abort(Error::Timeout);
+		track.finish();
+
+		let mut archive = Vec::new();
+		let (res, _) = tokio::join!(recorder.finish(), async {
+			tokio::io::AsyncReadExt::read_to_end(&mut input, &mut archive).await
+		});
+		res.unwrap();
+
+		let mut reader = ArchiveReader::new(archive.as_slice());
+		let mut ends = Vec::new();
+		while let Some(record) = reader.next().await.unwrap() {
+			if let ArchiveEvent::GroupEnd { aborted, .. } = record.event {
+				ends.push(aborted);
+			}
+		}
+		assert_eq!(ends, [true]);
+
+		// The replayed group is aborted after the frames that were received.
+		let mut replayed = BroadcastProducer::new();
+		let consumer = replayed.consume();
+
+		let replay = replay(archive.as_slice(), &mut replayed, Pacing::Original);
+		tokio::pin!(replay);
+
+		tokio::select! {
+			biased;
+			_ = &mut replay => panic!("replay finished early"),
+			_ = tokio::task::yield_now() => {},
+		}
+
+		let mut track = consumer.subscribe(&Track::new("video"));
+		let (res, _) = tokio::join!(&mut replay, async {
+			let mut group = track.next_group().await.unwrap().unwrap();
+			assert_eq!(group.read_frame().await.unwrap().unwrap(), "hello");
+			assert!(group.read_frame().await.is_err());
+		});
+		res.unwrap();
+	}
+}
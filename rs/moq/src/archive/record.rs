@@ -0,0 +1,169 @@
+use std::collections::HashSet;
+
+use futures::{stream::FuturesUnordered, StreamExt};
+use tokio::{
+	io::AsyncWrite,
+	sync::{mpsc, oneshot},
+	time::Instant,
+};
+use web_async::spawn;
+
+use super::{ArchiveError, ArchiveEvent, ArchiveRecord, ArchiveWriter};
+use crate::{BroadcastConsumer, GroupConsumer, Track, TrackConsumer};
+
+/// Records the tracks of a broadcast to an archive, see [super::replay].
+///
+/// Each track is recorded in the background until it's closed.
+/// Call [Self::finish] to wait until every track has ended and the archive is flushed.
+pub struct Recorder {
+	broadcast: BroadcastConsumer,
+	start: Instant,
+	tracks: HashSet<String>,
+	records: mpsc::UnboundedSender<ArchiveRecord>,
+	done: oneshot::Receiver<Result<(), ArchiveError>>,
+}
+
+impl Recorder {
+	/// Start recording to the given output; no tracks are recorded until [Self::track] is called.
+	pub fn new<W: AsyncWrite + Unpin + Send + 'static>(broadcast: BroadcastConsumer, output: W) -> Self {
+		let (records, rx) = mpsc::unbounded_channel();
+		let (tx, done) = oneshot::channel();
+
+		spawn(async move {
+			tx.send(Self::run(output, rx).await).ok();
+		});
+
+		Self {
+			broadcast,
+			start: Instant::now(),
+			tracks: HashSet::new(),
+			records,
+			done,
+		}
+	}
+
+	/// Subscribe to a track and record it, returning false if it's already being recorded.
+	pub fn track(&mut self, track: &Track) -> bool {
+		if !self.tracks.insert(track.name.clone()) {
+			return false;
+		}
+
+		let id = self.tracks.len() as u64 - 1;
+		let recording = TrackRecording {
+			track: self.broadcast.subscribe(track),
+			id,
+			start: self.start,
+			records: self.records.clone(),
+		};
+
+		recording.event(ArchiveEvent::Track {
+			id,
+			name: track.name.clone(),
+			priority: track.priority,
+		});
+
+		spawn(recording.run());
+
+		true
+	}
+
+	/// Wait until every recorded track has ended and the archive has been flushed.
+	pub async fn finish(self) -> Result<(), ArchiveError> {
+		drop(self.records);
+		self.done.await.unwrap_or(Ok(()))
+	}
+
+	async fn run<W: AsyncWrite + Unpin>(
+		output: W,
+		mut records: mpsc::UnboundedReceiver<ArchiveRecord>,
+	) -> Result<(), ArchiveError> {
+		let mut writer = ArchiveWriter::new(output).await?;
+
+		while let Some(record) = records.recv().await {
+			writer.write(&record).await?;
+
+			// Flush when we're caught up, so the archive is usable even if we're killed.
+			if records.is_empty() {
+				writer.flush().await?;
+			}
+		}
+
+		writer.flush().await
+	}
+}
+
+#[derive(Clone)]
+struct TrackRecording {
+	track: TrackConsumer,
+	id: u64,
+	start: Instant,
+	records: mpsc::UnboundedSender<ArchiveRecord>,
+}
+
+impl TrackRecording {
+	fn event(&self, event: ArchiveEvent) {
+		let record = ArchiveRecord {
+			timestamp: self.start.elapsed(),
+			event,
+		};
+
+		self.records.send(record).ok();
+	}
+
+	async fn run(mut self) {
+		let mut groups = FuturesUnordered::new();
+
+		loop {
+			tokio::select! {
+				res = self.track.next_group() => match res {
+					Ok(Some(group)) => {
+						self.event(ArchiveEvent::Group {
+							track: self.id,
+							sequence: group.info.sequence,
+							priority: group.info.priority,
+						});
+
+						groups.push(self.clone().run_group(group));
+					}
+					Ok(None) => break,
+					Err(err) => {
+						tracing::debug!(?err, track = %self.track.info.name, "recorded track error");
+						break;
+					}
+				},
+				Some(_) = groups.next() => {},
+				// Stop recording if the archive can no longer be written.
+				_ = self.records.closed() => return,
+			}
+		}
+
+		while groups.next().await.is_some() {}
+
+		self.event(ArchiveEvent::TrackEnd { track: self.id });
+	}
+
+	async fn run_group(self, mut group: GroupConsumer) {
+		let sequence = group.info.sequence;
+
+		let aborted = loop {
+			match group.read_frame().await {
+				Ok(Some(payload)) => self.event(ArchiveEvent::Frame {
+					track: self.id,
+					group: sequence,
+					payload,
+				}),
+				Ok(None) => break false,
+				Err(err) => {
+					tracing::debug!(?err, track = %self.track.info.name, group = sequence, "recorded group error");
+					break true;
+				}
+			}
+		};
+
+		self.event(ArchiveEvent::GroupEnd {
+			track: self.id,
+			group: sequence,
+			aborted,
+		});
+	}
+}
@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+
+use tokio::{io::AsyncRead, time::Instant};
+
+use super::{ArchiveError, ArchiveEvent, ArchiveReader};
+use crate::{BroadcastProducer, Error, Group, GroupProducer, Track, TrackProducer};
+
+/// How quickly an archive is replayed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Pacing {
+	/// Replay each event at the same time relative to the start of the recording.
+	#[default]
+	Original,
+
+	/// Replay every event as fast as possible.
+	Fast,
+}
+
+/// Replay an archive created by a [super::Recorder] into the broadcast, returning when the archive ends.
+///
+/// Any tracks or groups that were still open at the end of the recording are finished.
+pub async fn replay<R: AsyncRead + Unpin>(
+	input: R,
+	broadcast: &mut BroadcastProducer,
+	pacing: Pacing,
+) -> Result<(), ArchiveError> {
+	let mut archive = ArchiveReader::new(input);
+	let start = Instant::now();
+
+	let mut tracks: HashMap<u64, TrackProducer> = HashMap::new();
+	let mut groups: HashMap<(u64, u64), GroupProducer> = HashMap::new();
+
+	while let Some(record) = archive.next().await? {
+		if pacing == Pacing::Original {
+			tokio::time::sleep_until(start + record.timestamp).await;
+		}
+
+		// Events for unknown tracks or groups are ignored; a group may be too old to create.
+		match record.event {
+			ArchiveEvent::Track { id, name, priority } => {
+				let track = Track {
+					name,
+					priority,
+					..Default::default()
+				};
+				tracks.insert(id, broadcast.create(track));
+			}
+			ArchiveEvent::Group {
+				track,
+				sequence,
+				priority,
+			} => {
+				let group = Group {
+					sequence,
+					priority,
+					..Default::default()
+				};
+				if let Some(group) = tracks.get_mut(&track).and_then(|t| t.create_group(group)) {
+					groups.insert((track, sequence), group);
+				}
+			}
+			ArchiveEvent::Frame { track, group, payload } => {
+				if let Some(group) = groups.get_mut(&(track, group)) {
+					group.write_frame(payload);
+				}
+			}
+			ArchiveEvent::GroupEnd { track, group, aborted } => {
+				if let Some(group) = groups.remove(&(track, group)) {
+					match aborted {
+						// The original error isn't recorded, only that the group was incomplete.
+						true => group.abort(Error::Cancel),
+						false => group.finish(),
+					}
+				}
+			}
+			ArchiveEvent::TrackEnd { track } => {
+				if let Some(track) = tracks.remove(&track) {
+					track.finish();
+				}
+			}
+		}
+	}
+
+	for (_, group) in groups {
+		group.finish();
+	}
+
+	for (_, track) in tracks {
+		track.finish();
+	}
+
+	Ok(())
+}
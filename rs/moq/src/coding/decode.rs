@@ -2,7 +2,50 @@ use std::string::FromUtf8Error;
 use thiserror::Error;
 
 pub trait Decode: Sized {
-	fn decode<B: bytes::Buf>(buf: &mut B) -> Result<Self, DecodeError>;
+	/// Decode using the [DecodeLimits::default] limits.
+	fn decode<B: bytes::Buf>(buf: &mut B) -> Result<Self, DecodeError> {
+		Self::decode_limited(buf, &DecodeLimits::default())
+	}
+
+	/// Decode, returning an error instead of buffering or allocating beyond the provided limits.
+	fn decode_limited<B: bytes::Buf>(buf: &mut B, limits: &DecodeLimits) -> Result<Self, DecodeError>;
+}
+
+/// Limits applied while decoding untrusted input from the network.
+///
+/// Length prefixes and counts are supplied by the remote, so they're checked before anything is allocated.
+#[derive(Clone, Copy, Debug)]
+pub struct DecodeLimits {
+	/// The maximum number of bytes buffered while waiting for a complete message.
+	pub max_message: usize,
+
+	/// The maximum length of a string or byte array.
+	pub max_string: usize,
+
+	/// The maximum number of elements in a list.
+	pub max_list: usize,
+
+	/// The maximum number of extensions in a setup message.
+	pub max_extensions: usize,
+
+	/// The maximum size of a single frame.
+	pub max_frame_size: u64,
+
+	/// The maximum number of frames within a single group.
+	pub max_group_frames: usize,
+}
+
+impl Default for DecodeLimits {
+	fn default() -> Self {
+		Self {
+			max_message: 64 * 1024,
+			max_string: 4096,
+			max_list: 64,
+			max_extensions: 64,
+			max_frame_size: 32 * 1024 * 1024,
+			max_group_frames: 65536,
+		}
+	}
 }
 
 /// A decode error.
@@ -44,10 +87,28 @@ pub enum DecodeError {
 
 	#[error("invalid parameter")]
 	InvalidParameter,
+
+	#[error("message too large")]
+	MessageTooLarge,
+
+	#[error("string too long: {0}")]
+	StringTooLong(usize),
+
+	#[error("too many elements: {0}")]
+	TooManyElements(usize),
+
+	#[error("too many extensions: {0}")]
+	TooManyExtensions(usize),
+
+	#[error("frame too large: {0}")]
+	FrameTooLarge(u64),
+
+	#[error("too many frames")]
+	TooManyFrames,
 }
 
 impl Decode for u8 {
-	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+	fn decode_limited<R: bytes::Buf>(r: &mut R, _limits: &DecodeLimits) -> Result<Self, DecodeError> {
 		match r.has_remaining() {
 			true => Ok(r.get_u8()),
 			false => Err(DecodeError::Short),
@@ -57,8 +118,18 @@ impl Decode for u8 {
 
 impl Decode for String {
 	/// Decode a string with a varint length prefix.
-	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
-		let v = Vec::<u8>::decode(r)?;
+	fn decode_limited<R: bytes::Buf>(r: &mut R, limits: &DecodeLimits) -> Result<Self, DecodeError> {
+		let size = usize::decode_limited(r, limits)?;
+		if size > limits.max_string {
+			return Err(DecodeError::StringTooLong(size));
+		}
+
+		if r.remaining() < size {
+			return Err(DecodeError::Short);
+		}
+
+		let mut v = vec![0; size];
+		r.copy_to_slice(&mut v);
 		let str = String::from_utf8(v)?;
 
 		Ok(str)
@@ -66,14 +137,17 @@ impl Decode for String {
 }
 
 impl<T: Decode> Decode for Vec<T> {
-	fn decode<B: bytes::Buf>(buf: &mut B) -> Result<Self, DecodeError> {
-		let size = usize::decode(buf)?;
+	fn decode_limited<B: bytes::Buf>(buf: &mut B, limits: &DecodeLimits) -> Result<Self, DecodeError> {
+		let size = usize::decode_limited(buf, limits)?;
 
-		// Don't allocate more than 1024 elements upfront
-		let mut v = Vec::with_capacity(size.min(1024));
+		if size > limits.max_list {
+			return Err(DecodeError::TooManyElements(size));
+		}
+
+		let mut v = Vec::with_capacity(size);
 
 		for _ in 0..size {
-			v.push(T::decode(buf)?);
+			v.push(T::decode_limited(buf, limits)?);
 		}
 
 		Ok(v)
@@ -81,14 +155,14 @@ impl<T: Decode> Decode for Vec<T> {
 }
 
 impl Decode for std::time::Duration {
-	fn decode<B: bytes::Buf>(buf: &mut B) -> Result<Self, DecodeError> {
-		let ms = u64::decode(buf)?;
+	fn decode_limited<B: bytes::Buf>(buf: &mut B, limits: &DecodeLimits) -> Result<Self, DecodeError> {
+		let ms = u64::decode_limited(buf, limits)?;
 		Ok(std::time::Duration::from_micros(ms))
 	}
 }
 
 impl Decode for i8 {
-	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+	fn decode_limited<R: bytes::Buf>(r: &mut R, _limits: &DecodeLimits) -> Result<Self, DecodeError> {
 		if !r.has_remaining() {
 			return Err(DecodeError::Short);
 		}
@@ -101,8 +175,12 @@ impl Decode for i8 {
 }
 
 impl Decode for bytes::Bytes {
-	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
-		let len = usize::decode(r)?;
+	fn decode_limited<R: bytes::Buf>(r: &mut R, limits: &DecodeLimits) -> Result<Self, DecodeError> {
+		let len = usize::decode_limited(r, limits)?;
+		if len > limits.max_string {
+			return Err(DecodeError::StringTooLong(len));
+		}
+
 		if r.remaining() < len {
 			return Err(DecodeError::Short);
 		}
@@ -110,3 +188,60 @@ impl Decode for bytes::Bytes {
 		Ok(bytes)
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::{coding::Encode, message};
+
+	#[test]
+	fn string_limit() {
+		let limits = DecodeLimits {
+			max_string: 4,
+			..Default::default()
+		};
+
+		let mut buf = Vec::new();
+		"hello".to_string().encode(&mut buf);
+
+		let err = String::decode_limited(&mut buf.as_slice(), &limits).unwrap_err();
+		assert!(matches!(err, DecodeError::StringTooLong(5)));
+
+		// The length is checked before waiting for the rest of the string.
+		let err = String::decode_limited(&mut &buf[..1], &limits).unwrap_err();
+		assert!(matches!(err, DecodeError::StringTooLong(5)));
+	}
+
+	#[test]
+	fn list_limit() {
+		let mut buf = Vec::new();
+		u64::MAX.wrapping_shr(2).encode(&mut buf);
+
+		let err = Vec::<u64>::decode(&mut buf.as_slice()).unwrap_err();
+		assert!(matches!(err, DecodeError::TooManyElements(_)));
+
+		let err = message::Versions::decode(&mut buf.as_slice()).unwrap_err();
+		assert!(matches!(err, DecodeError::TooManyElements(_)));
+
+		let err = message::Extensions::decode(&mut buf.as_slice()).unwrap_err();
+		assert!(matches!(err, DecodeError::TooManyExtensions(_)));
+	}
+
+	#[test]
+	fn frame_limit() {
+		let limits = DecodeLimits {
+			max_frame_size: 1024,
+			..Default::default()
+		};
+
+		let mut buf = Vec::new();
+		message::Frame { size: 1024 }.encode(&mut buf);
+		let frame = message::Frame::decode_limited(&mut buf.as_slice(), &limits).unwrap();
+		assert_eq!(frame.size, 1024);
+
+		buf.clear();
+		message::Frame { size: 1025 }.encode(&mut buf);
+		let err = message::Frame::decode_limited(&mut buf.as_slice(), &limits).unwrap_err();
+		assert!(matches!(err, DecodeError::FrameTooLarge(1025)));
+	}
+}
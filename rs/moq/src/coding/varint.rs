@@ -7,7 +7,7 @@ use std::fmt;
 
 use thiserror::Error;
 
-use super::{Decode, DecodeError, Encode};
+use super::{Decode, DecodeError, DecodeLimits, Encode};
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Error)]
 #[error("value out of range")]
@@ -159,7 +159,7 @@ impl fmt::Display for VarInt {
 
 impl Decode for VarInt {
 	/// Decode a varint from the given reader.
-	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+	fn decode_limited<R: bytes::Buf>(r: &mut R, _limits: &DecodeLimits) -> Result<Self, DecodeError> {
 		if !r.has_remaining() {
 			return Err(DecodeError::Short);
 		}
@@ -235,8 +235,8 @@ impl Encode for u64 {
 }
 
 impl Decode for u64 {
-	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
-		VarInt::decode(r).map(|v| v.into_inner())
+	fn decode_limited<R: bytes::Buf>(r: &mut R, limits: &DecodeLimits) -> Result<Self, DecodeError> {
+		VarInt::decode_limited(r, limits).map(|v| v.into_inner())
 	}
 }
 
@@ -250,8 +250,8 @@ impl Encode for usize {
 }
 
 impl Decode for usize {
-	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
-		VarInt::decode(r).map(|v| v.into_inner() as usize)
+	fn decode_limited<R: bytes::Buf>(r: &mut R, limits: &DecodeLimits) -> Result<Self, DecodeError> {
+		VarInt::decode_limited(r, limits).map(|v| v.into_inner() as usize)
 	}
 }
 
@@ -262,8 +262,8 @@ impl Encode for u32 {
 }
 
 impl Decode for u32 {
-	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
-		let v = VarInt::decode(r)?;
+	fn decode_limited<R: bytes::Buf>(r: &mut R, limits: &DecodeLimits) -> Result<Self, DecodeError> {
+		let v = VarInt::decode_limited(r, limits)?;
 		let v = v.try_into().map_err(|_| DecodeError::BoundsExceeded)?;
 		Ok(v)
 	}
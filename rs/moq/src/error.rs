@@ -54,6 +54,10 @@ pub enum Error {
 
 	#[error("protocol violation")]
 	ProtocolViolation,
+
+	/// A value is too large to be encoded within the decode limits of the remote.
+	#[error("too large")]
+	TooLarge,
 }
 
 impl Error {
@@ -73,6 +77,7 @@ impl Error {
 			Self::NotFound => 13,
 			Self::WrongSize => 14,
 			Self::ProtocolViolation => 15,
+			Self::TooLarge => 16,
 			Self::App(app) => *app + 64,
 		}
 	}
@@ -36,6 +36,7 @@ mod error;
 mod model;
 mod session;
 
+pub mod archive;
 pub mod coding;
 pub mod message;
 pub use error::*;
@@ -45,5 +46,8 @@ pub use session::*;
 /// The ALPN used when connecting via QUIC directly.
 pub const ALPN: &str = message::Alpn::CURRENT.0;
 
+/// Every ALPN supported when connecting via QUIC directly, in preferred order.
+pub const ALPNS: [&str; 2] = [message::Alpn::LITE_01.0, message::Alpn::LITE_00.0];
+
 /// Export the web_transport crate.
 pub use web_transport;
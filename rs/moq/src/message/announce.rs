@@ -1,5 +1,6 @@
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 
+use super::{Version, Versioned};
 use crate::coding::*;
 
 /// Sent by the publisher to announce the availability of a track.
@@ -7,38 +8,50 @@ use crate::coding::*;
 #[derive(Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Announce {
-	Active { suffix: String },
-	Ended { suffix: String },
+	Active {
+		suffix: String,
+		/// An optional blob provided by the publisher, empty if not set.
+		metadata: bytes::Bytes,
+	},
+	Ended {
+		suffix: String,
+	},
 }
 
 impl Announce {
 	pub fn suffix(&self) -> &str {
 		match self {
-			Announce::Active { suffix } => suffix,
+			Announce::Active { suffix, .. } => suffix,
 			Announce::Ended { suffix } => suffix,
 		}
 	}
 }
 
-impl Decode for Announce {
-	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
-		Ok(match AnnounceStatus::decode(r)? {
+impl Versioned for Announce {
+	fn decode_version<R: bytes::Buf>(r: &mut R, limits: &DecodeLimits, version: Version) -> Result<Self, DecodeError> {
+		Ok(match AnnounceStatus::decode_limited(r, limits)? {
 			AnnounceStatus::Active => Self::Active {
-				suffix: String::decode(r)?,
+				suffix: String::decode_limited(r, limits)?,
+				metadata: match version {
+					Version::LITE_00 => bytes::Bytes::new(),
+					_ => bytes::Bytes::decode_limited(r, limits)?,
+				},
 			},
 			AnnounceStatus::Ended => Self::Ended {
-				suffix: String::decode(r)?,
+				suffix: String::decode_limited(r, limits)?,
 			},
 		})
 	}
-}
 
-impl Encode for Announce {
-	fn encode<W: bytes::BufMut>(&self, w: &mut W) {
+	fn encode_version<W: bytes::BufMut>(&self, w: &mut W, version: Version) {
 		match self {
-			Self::Active { suffix } => {
+			Self::Active { suffix, metadata } => {
 				AnnounceStatus::Active.encode(w);
 				suffix.encode(w);
+
+				if version != Version::LITE_00 {
+					metadata.encode(w);
+				}
 			}
 			Self::Ended { suffix } => {
 				AnnounceStatus::Ended.encode(w);
@@ -48,6 +61,18 @@ impl Encode for Announce {
 	}
 }
 
+impl Decode for Announce {
+	fn decode_limited<R: bytes::Buf>(r: &mut R, limits: &DecodeLimits) -> Result<Self, DecodeError> {
+		Self::decode_version(r, limits, Version::CURRENT)
+	}
+}
+
+impl Encode for Announce {
+	fn encode<W: bytes::BufMut>(&self, w: &mut W) {
+		self.encode_version(w, Version::CURRENT)
+	}
+}
+
 /// Sent by the subscriber to request ANNOUNCE messages.
 #[derive(Clone, Debug)]
 pub struct AnnounceRequest {
@@ -56,8 +81,8 @@ pub struct AnnounceRequest {
 }
 
 impl Decode for AnnounceRequest {
-	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
-		let prefix = String::decode(r)?;
+	fn decode_limited<R: bytes::Buf>(r: &mut R, limits: &DecodeLimits) -> Result<Self, DecodeError> {
+		let prefix = String::decode_limited(r, limits)?;
 		Ok(Self { prefix })
 	}
 }
@@ -77,8 +102,8 @@ enum AnnounceStatus {
 }
 
 impl Decode for AnnounceStatus {
-	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
-		let status = u8::decode(r)?;
+	fn decode_limited<R: bytes::Buf>(r: &mut R, limits: &DecodeLimits) -> Result<Self, DecodeError> {
+		let status = u8::decode_limited(r, limits)?;
 		match status {
 			0 => Ok(Self::Ended),
 			1 => Ok(Self::Active),
@@ -92,3 +117,41 @@ impl Encode for AnnounceStatus {
 		(*self as u8).encode(w)
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn roundtrip(msg: &Announce, version: Version) -> Announce {
+		let mut buf = Vec::new();
+		msg.encode_version(&mut buf, version);
+
+		let mut buf = buf.as_slice();
+		let decoded = Announce::decode_version(&mut buf, &DecodeLimits::default(), version).unwrap();
+		assert!(buf.is_empty());
+
+		decoded
+	}
+
+	#[test]
+	fn metadata() {
+		let msg = Announce::Active {
+			suffix: "demo".to_string(),
+			metadata: bytes::Bytes::from_static(b"title"),
+		};
+
+		assert_eq!(roundtrip(&msg, Version::LITE_01), msg);
+
+		// The original version has no metadata, so it's dropped.
+		let expected = Announce::Active {
+			suffix: "demo".to_string(),
+			metadata: bytes::Bytes::new(),
+		};
+		assert_eq!(roundtrip(&msg, Version::LITE_00), expected);
+
+		let msg = Announce::Ended {
+			suffix: "demo".to_string(),
+		};
+		assert_eq!(roundtrip(&msg, Version::LITE_00), msg);
+	}
+}
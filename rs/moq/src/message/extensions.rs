@@ -11,19 +11,23 @@ pub trait Extension: Encode + Decode {
 pub struct Extensions(HashMap<u64, Vec<u8>>);
 
 impl Decode for Extensions {
-	fn decode<R: bytes::Buf>(mut r: &mut R) -> Result<Self, DecodeError> {
+	fn decode_limited<R: bytes::Buf>(r: &mut R, limits: &DecodeLimits) -> Result<Self, DecodeError> {
 		let mut map = HashMap::new();
 
 		// I hate this encoding so much; let me encode my role and get on with my life.
-		let count = u64::decode(r)?;
+		let count = usize::decode_limited(r, limits)?;
+		if count > limits.max_extensions {
+			return Err(DecodeError::TooManyExtensions(count));
+		}
+
 		for _ in 0..count {
-			let kind = u64::decode(r)?;
+			let kind = u64::decode_limited(r, limits)?;
 			if map.contains_key(&kind) {
 				return Err(DecodeError::DupliateParameter);
 			}
 
-			let data = Vec::<u8>::decode(&mut r)?;
-			map.insert(kind, data);
+			let data = bytes::Bytes::decode_limited(r, limits)?;
+			map.insert(kind, data.to_vec());
 		}
 
 		Ok(Extensions(map))
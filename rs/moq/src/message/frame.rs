@@ -6,8 +6,13 @@ pub struct Frame {
 }
 
 impl Decode for Frame {
-	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
-		Ok(Self { size: u64::decode(r)? })
+	fn decode_limited<R: bytes::Buf>(r: &mut R, limits: &DecodeLimits) -> Result<Self, DecodeError> {
+		let size = u64::decode_limited(r, limits)?;
+		if size > limits.max_frame_size {
+			return Err(DecodeError::FrameTooLarge(size));
+		}
+
+		Ok(Self { size })
 	}
 }
 
@@ -1,3 +1,4 @@
+use super::{Version, Versioned};
 use crate::coding::*;
 
 #[derive(Clone, Debug)]
@@ -7,20 +8,99 @@ pub struct Group {
 
 	// The group sequence number
 	pub sequence: u64,
+
+	// The publisher's priority boost for this group.
+	pub priority: u8,
+
+	// The index of the first frame, non-zero when resuming a group.
+	pub frame_offset: u64,
 }
 
-impl Decode for Group {
-	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+impl Versioned for Group {
+	fn decode_version<R: bytes::Buf>(r: &mut R, limits: &DecodeLimits, version: Version) -> Result<Self, DecodeError> {
+		let subscribe = u64::decode_limited(r, limits)?;
+		let sequence = u64::decode_limited(r, limits)?;
+
+		let (priority, frame_offset) = match version {
+			Version::LITE_00 => (0, 0),
+			_ => (u8::decode_limited(r, limits)?, u64::decode_limited(r, limits)?),
+		};
+
 		Ok(Self {
-			subscribe: u64::decode(r)?,
-			sequence: u64::decode(r)?,
+			subscribe,
+			sequence,
+			priority,
+			frame_offset,
 		})
 	}
+
+	fn encode_version<W: bytes::BufMut>(&self, w: &mut W, version: Version) {
+		self.subscribe.encode(w);
+		self.sequence.encode(w);
+
+		if version != Version::LITE_00 {
+			self.priority.encode(w);
+			self.frame_offset.encode(w);
+		}
+	}
+}
+
+impl Decode for Group {
+	fn decode_limited<R: bytes::Buf>(r: &mut R, limits: &DecodeLimits) -> Result<Self, DecodeError> {
+		Self::decode_version(r, limits, Version::CURRENT)
+	}
 }
 
 impl Encode for Group {
 	fn encode<W: bytes::BufMut>(&self, w: &mut W) {
-		self.subscribe.encode(w);
-		self.sequence.encode(w);
+		self.encode_version(w, Version::CURRENT)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn roundtrip(msg: &Group, version: Version) -> Group {
+		let mut buf = Vec::new();
+		msg.encode_version(&mut buf, version);
+
+		let mut buf = buf.as_slice();
+		let decoded = Group::decode_version(&mut buf, &DecodeLimits::default(), version).unwrap();
+		assert!(buf.is_empty());
+
+		decoded
+	}
+
+	#[test]
+	fn priority() {
+		let msg = Group {
+			subscribe: 1,
+			sequence: 2,
+			priority: 3,
+			frame_offset: 0,
+		};
+
+		let decoded = roundtrip(&msg, Version::LITE_01);
+		assert_eq!((decoded.subscribe, decoded.sequence, decoded.priority), (1, 2, 3));
+
+		// The original version has no group priority, so there's no boost.
+		let decoded = roundtrip(&msg, Version::LITE_00);
+		assert_eq!((decoded.subscribe, decoded.sequence, decoded.priority), (1, 2, 0));
+	}
+
+	#[test]
+	fn frame_offset() {
+		let msg = Group {
+			subscribe: 1,
+			sequence: 2,
+			priority: 0,
+			frame_offset: 4,
+		};
+
+		assert_eq!(roundtrip(&msg, Version::LITE_01).frame_offset, 4);
+
+		// The original version can't resume, so groups always start at the first frame.
+		assert_eq!(roundtrip(&msg, Version::LITE_00).frame_offset, 0);
 	}
 }
@@ -6,8 +6,8 @@ pub struct SessionInfo {
 }
 
 impl Decode for SessionInfo {
-	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
-		let bitrate = match u64::decode(r)? {
+	fn decode_limited<R: bytes::Buf>(r: &mut R, limits: &DecodeLimits) -> Result<Self, DecodeError> {
+		let bitrate = match u64::decode_limited(r, limits)? {
 			0 => None,
 			bitrate => Some(bitrate),
 		};
@@ -21,3 +21,53 @@ impl Encode for SessionInfo {
 		self.bitrate.unwrap_or(0).encode(w);
 	}
 }
+
+/// Sent by either side on the session stream after the setup.
+#[derive(Clone, Debug)]
+pub enum SessionMessage {
+	Info(SessionInfo),
+
+	/// Request a [Self::Pong] with the same sequence number, used to measure the RTT.
+	Ping {
+		sequence: u64,
+	},
+
+	/// Sent in response to a [Self::Ping] as soon as possible.
+	Pong {
+		sequence: u64,
+	},
+}
+
+impl Decode for SessionMessage {
+	fn decode_limited<R: bytes::Buf>(r: &mut R, limits: &DecodeLimits) -> Result<Self, DecodeError> {
+		match u64::decode_limited(r, limits)? {
+			0 => Ok(Self::Info(SessionInfo::decode_limited(r, limits)?)),
+			1 => Ok(Self::Ping {
+				sequence: u64::decode_limited(r, limits)?,
+			}),
+			2 => Ok(Self::Pong {
+				sequence: u64::decode_limited(r, limits)?,
+			}),
+			t => Err(DecodeError::InvalidMessage(t)),
+		}
+	}
+}
+
+impl Encode for SessionMessage {
+	fn encode<W: bytes::BufMut>(&self, w: &mut W) {
+		match self {
+			Self::Info(info) => {
+				0u64.encode(w);
+				info.encode(w);
+			}
+			Self::Ping { sequence } => {
+				1u64.encode(w);
+				sequence.encode(w);
+			}
+			Self::Pong { sequence } => {
+				2u64.encode(w);
+				sequence.encode(w);
+			}
+		}
+	}
+}
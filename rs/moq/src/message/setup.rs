@@ -13,9 +13,9 @@ pub struct ClientSetup {
 
 impl Decode for ClientSetup {
 	/// Decode a client setup message.
-	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
-		let versions = Versions::decode(r)?;
-		let extensions = Extensions::decode(r)?;
+	fn decode_limited<R: bytes::Buf>(r: &mut R, limits: &DecodeLimits) -> Result<Self, DecodeError> {
+		let versions = Versions::decode_limited(r, limits)?;
+		let extensions = Extensions::decode_limited(r, limits)?;
 
 		Ok(Self { versions, extensions })
 	}
@@ -41,9 +41,9 @@ pub struct ServerSetup {
 
 impl Decode for ServerSetup {
 	/// Decode the server setup.
-	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
-		let version = Version::decode(r)?;
-		let extensions = Extensions::decode(r)?;
+	fn decode_limited<R: bytes::Buf>(r: &mut R, limits: &DecodeLimits) -> Result<Self, DecodeError> {
+		let version = Version::decode_limited(r, limits)?;
+		let extensions = Extensions::decode_limited(r, limits)?;
 
 		Ok(Self { version, extensions })
 	}
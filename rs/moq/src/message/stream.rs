@@ -8,8 +8,8 @@ pub enum ControlType {
 }
 
 impl Decode for ControlType {
-	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
-		let t = u64::decode(r)?;
+	fn decode_limited<R: bytes::Buf>(r: &mut R, limits: &DecodeLimits) -> Result<Self, DecodeError> {
+		let t = u64::decode_limited(r, limits)?;
 		match t {
 			0 => Ok(Self::Session),
 			1 => Ok(Self::Announce),
@@ -36,8 +36,8 @@ pub enum DataType {
 }
 
 impl Decode for DataType {
-	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
-		let t = u64::decode(r)?;
+	fn decode_limited<R: bytes::Buf>(r: &mut R, limits: &DecodeLimits) -> Result<Self, DecodeError> {
+		let t = u64::decode_limited(r, limits)?;
 		match t {
 			0 => Ok(Self::Group),
 			_ => Err(DecodeError::InvalidValue),
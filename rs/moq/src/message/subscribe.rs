@@ -1,4 +1,7 @@
-use crate::coding::{Decode, DecodeError, Encode};
+use std::time::Duration;
+
+use super::{Version, Versioned};
+use crate::coding::{Decode, DecodeError, DecodeLimits, Encode};
 
 /// Sent by the subscriber to request all future objects for the given track.
 ///
@@ -9,30 +12,91 @@ pub struct Subscribe {
 	pub broadcast: String,
 	pub track: String,
 	pub priority: u8,
+
+	/// Only deliver the first frame of each group.
+	pub first_frame: bool,
+
+	/// Only deliver groups with a sequence number divisible by this value, or 0 for all groups.
+	pub nth_group: u64,
+
+	/// Skip groups within this duration of the previously delivered group, or zero for all groups.
+	pub min_interval: Duration,
+
+	/// Abort groups older than this duration, or zero for no limit.
+	pub max_age: Duration,
+
+	/// Skip groups before this sequence number and frames before [Self::resume_frame] within it.
+	pub resume_group: Option<u64>,
+
+	/// The index of the first frame to deliver for [Self::resume_group].
+	pub resume_frame: u64,
 }
 
-impl Decode for Subscribe {
-	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
-		let id = u64::decode(r)?;
-		let broadcast = String::decode(r)?;
-		let track = String::decode(r)?;
-		let priority = u8::decode(r)?;
+impl Versioned for Subscribe {
+	fn decode_version<R: bytes::Buf>(r: &mut R, limits: &DecodeLimits, version: Version) -> Result<Self, DecodeError> {
+		let mut subscribe = Self {
+			id: u64::decode_limited(r, limits)?,
+			broadcast: String::decode_limited(r, limits)?,
+			track: String::decode_limited(r, limits)?,
+			priority: u8::decode_limited(r, limits)?,
+			first_frame: false,
+			nth_group: 0,
+			min_interval: Duration::ZERO,
+			max_age: Duration::ZERO,
+			resume_group: None,
+			resume_frame: 0,
+		};
+
+		// Older versions don't support filters, max age, or resumption.
+		if version == Version::LITE_00 {
+			return Ok(subscribe);
+		}
 
-		Ok(Self {
-			id,
-			broadcast,
-			track,
-			priority,
-		})
+		subscribe.first_frame = match u8::decode_limited(r, limits)? {
+			0 => false,
+			1 => true,
+			_ => return Err(DecodeError::InvalidValue),
+		};
+		subscribe.nth_group = u64::decode_limited(r, limits)?;
+		subscribe.min_interval = Duration::decode_limited(r, limits)?;
+		subscribe.max_age = Duration::decode_limited(r, limits)?;
+		subscribe.resume_group = match u64::decode_limited(r, limits)? {
+			0 => None,
+			group => Some(group - 1),
+		};
+		subscribe.resume_frame = u64::decode_limited(r, limits)?;
+
+		Ok(subscribe)
 	}
-}
 
-impl Encode for Subscribe {
-	fn encode<W: bytes::BufMut>(&self, w: &mut W) {
+	fn encode_version<W: bytes::BufMut>(&self, w: &mut W, version: Version) {
 		self.id.encode(w);
 		self.broadcast.encode(w);
 		self.track.encode(w);
 		self.priority.encode(w);
+
+		if version == Version::LITE_00 {
+			return;
+		}
+
+		(self.first_frame as u8).encode(w);
+		self.nth_group.encode(w);
+		self.min_interval.encode(w);
+		self.max_age.encode(w);
+		self.resume_group.map(|group| group + 1).unwrap_or(0).encode(w);
+		self.resume_frame.encode(w);
+	}
+}
+
+impl Decode for Subscribe {
+	fn decode_limited<R: bytes::Buf>(r: &mut R, limits: &DecodeLimits) -> Result<Self, DecodeError> {
+		Self::decode_version(r, limits, Version::CURRENT)
+	}
+}
+
+impl Encode for Subscribe {
+	fn encode<W: bytes::BufMut>(&self, w: &mut W) {
+		self.encode_version(w, Version::CURRENT)
 	}
 }
 
@@ -48,8 +112,94 @@ impl Encode for SubscribeOk {
 }
 
 impl Decode for SubscribeOk {
-	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
-		let priority = u8::decode(r)?;
+	fn decode_limited<R: bytes::Buf>(r: &mut R, limits: &DecodeLimits) -> Result<Self, DecodeError> {
+		let priority = u8::decode_limited(r, limits)?;
 		Ok(Self { priority })
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn subscribe() -> Subscribe {
+		Subscribe {
+			id: 1,
+			broadcast: "demo".to_string(),
+			track: "video".to_string(),
+			priority: 2,
+			first_frame: false,
+			nth_group: 0,
+			min_interval: Duration::ZERO,
+			max_age: Duration::ZERO,
+			resume_group: None,
+			resume_frame: 0,
+		}
+	}
+
+	fn roundtrip(msg: &Subscribe, version: Version) -> Subscribe {
+		let mut buf = Vec::new();
+		msg.encode_version(&mut buf, version);
+
+		let mut buf = buf.as_slice();
+		let decoded = Subscribe::decode_version(&mut buf, &DecodeLimits::default(), version).unwrap();
+		assert!(buf.is_empty());
+
+		decoded
+	}
+
+	#[test]
+	fn filters() {
+		let msg = Subscribe {
+			first_frame: true,
+			nth_group: 3,
+			min_interval: Duration::from_millis(500),
+			..subscribe()
+		};
+
+		let decoded = roundtrip(&msg, Version::LITE_01);
+		assert_eq!((decoded.id, decoded.priority), (1, 2));
+		assert!(decoded.first_frame);
+		assert_eq!(decoded.nth_group, 3);
+		assert_eq!(decoded.min_interval, Duration::from_millis(500));
+
+		// The original version has no filters, so they're not sent.
+		let decoded = roundtrip(&msg, Version::LITE_00);
+		assert_eq!((decoded.id, decoded.priority), (1, 2));
+		assert!(!decoded.first_frame);
+		assert_eq!(decoded.nth_group, 0);
+		assert_eq!(decoded.min_interval, Duration::ZERO);
+	}
+
+	#[test]
+	fn max_age() {
+		let msg = Subscribe {
+			max_age: Duration::from_secs(2),
+			..subscribe()
+		};
+
+		assert_eq!(roundtrip(&msg, Version::LITE_01).max_age, Duration::from_secs(2));
+
+		// The original version has no max age, so the subscriber expires groups itself.
+		assert_eq!(roundtrip(&msg, Version::LITE_00).max_age, Duration::ZERO);
+	}
+
+	#[test]
+	fn resume() {
+		let msg = Subscribe {
+			resume_group: Some(0),
+			resume_frame: 3,
+			..subscribe()
+		};
+
+		let decoded = roundtrip(&msg, Version::LITE_01);
+		assert_eq!((decoded.resume_group, decoded.resume_frame), (Some(0), 3));
+
+		let decoded = roundtrip(&subscribe(), Version::LITE_01);
+		assert_eq!((decoded.resume_group, decoded.resume_frame), (None, 0));
+
+		// The original version can't resume, so the subscription starts at the latest group.
+		let decoded = roundtrip(&msg, Version::LITE_00);
+		assert_eq!((decoded.resume_group, decoded.resume_frame), (None, 0));
+	}
+}
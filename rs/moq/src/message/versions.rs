@@ -39,7 +39,13 @@ impl Version {
 
 	pub const LITE_00: Version = Version(0xff0dad00);
 
-	pub const CURRENT: Version = Version::LITE_00;
+	/// Adds subscription filters, resumption, group priorities, session messages and announce metadata.
+	pub const LITE_01: Version = Version(0xff0dad01);
+
+	pub const CURRENT: Version = Version::LITE_01;
+
+	/// Every supported version, in preferred order.
+	pub const SUPPORTED: [Version; 2] = [Version::LITE_01, Version::LITE_00];
 }
 
 /// A version number negotiated during the setup.
@@ -48,7 +54,19 @@ pub struct Alpn(pub &'static str);
 
 impl Alpn {
 	pub const LITE_00: Alpn = Alpn("moql-00");
-	pub const CURRENT: Alpn = Alpn::LITE_00;
+	pub const LITE_01: Alpn = Alpn("moql-01");
+	pub const CURRENT: Alpn = Alpn::LITE_01;
+
+	/// Every supported ALPN, in preferred order.
+	pub const SUPPORTED: [Alpn; 2] = [Alpn::LITE_01, Alpn::LITE_00];
+}
+
+/// A message with fields that depend on the negotiated version.
+///
+/// The [Encode] and [Decode] implementations use [Version::CURRENT].
+pub trait Versioned: Sized {
+	fn decode_version<R: bytes::Buf>(r: &mut R, limits: &DecodeLimits, version: Version) -> Result<Self, DecodeError>;
+	fn encode_version<W: bytes::BufMut>(&self, w: &mut W, version: Version);
 }
 
 impl From<u64> for Version {
@@ -65,8 +83,8 @@ impl From<Version> for u64 {
 
 impl Decode for Version {
 	/// Decode the version number.
-	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
-		let v = u64::decode(r)?;
+	fn decode_limited<R: bytes::Buf>(r: &mut R, limits: &DecodeLimits) -> Result<Self, DecodeError> {
+		let v = u64::decode_limited(r, limits)?;
 		Ok(Self(v))
 	}
 }
@@ -89,12 +107,16 @@ pub struct Versions(Vec<Version>);
 
 impl Decode for Versions {
 	/// Decode the version list.
-	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
-		let count = u64::decode(r)?;
-		let mut vs = Vec::new();
+	fn decode_limited<R: bytes::Buf>(r: &mut R, limits: &DecodeLimits) -> Result<Self, DecodeError> {
+		let count = usize::decode_limited(r, limits)?;
+		if count > limits.max_list {
+			return Err(DecodeError::TooManyElements(count));
+		}
+
+		let mut vs = Vec::with_capacity(count);
 
 		for _ in 0..count {
-			let v = Version::decode(r)?;
+			let v = Version::decode_limited(r, limits)?;
 			vs.push(v);
 		}
 
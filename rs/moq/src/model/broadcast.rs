@@ -13,7 +13,15 @@ use web_async::Lock;
 
 use super::Track;
 
-type State = HashMap<String, TrackConsumer>;
+type State = HashMap<String, Published>;
+
+#[derive(Clone)]
+struct Published {
+	track: TrackConsumer,
+
+	// False if this is a deduplicated request, in which case older groups may only be available upstream.
+	explicit: bool,
+}
 
 /// Receive broadcast/track requests and return if we can fulfill them.
 pub struct BroadcastProducer {
@@ -56,11 +64,12 @@ impl BroadcastProducer {
 
 	/// Insert a track into the lookup, returning true if it was unique.
 	pub fn insert(&mut self, track: TrackConsumer) -> bool {
-		let unique = self
-			.published
-			.lock()
-			.insert(track.info.name.clone(), track.clone())
-			.is_none();
+		let entry = Published {
+			track: track.clone(),
+			explicit: true,
+		};
+
+		let unique = self.published.lock().insert(track.info.name.clone(), entry).is_none();
 
 		web_async::spawn(Self::cleanup(track, self.published.clone()));
 
@@ -76,7 +85,7 @@ impl BroadcastProducer {
 		let mut published = published.lock();
 		match published.remove(&track.info.name) {
 			// Make sure we are removing the correct track.
-			Some(other) if other.is_clone(&track) => true,
+			Some(other) if other.track.is_clone(&track) => true,
 			// Put it back if it's not the same track.
 			Some(other) => published.insert(track.info.name.clone(), other.clone()).is_some(),
 			None => false,
@@ -89,6 +98,7 @@ impl BroadcastProducer {
 			published: self.published.clone(),
 			closed: self.closed.subscribe(),
 			requested: self.requested.0.clone(),
+			metadata: None,
 		}
 	}
 
@@ -173,9 +183,24 @@ pub struct BroadcastConsumer {
 	published: Lock<State>,
 	closed: watch::Receiver<bool>,
 	requested: async_channel::Sender<TrackProducer>,
+	metadata: Option<bytes::Bytes>,
 }
 
 impl BroadcastConsumer {
+	/// Attach a small metadata blob, such as a title, sent along with the announcement.
+	///
+	/// This only applies to this handle (and its clones); see [crate::OriginProducer::publish_with].
+	pub fn with_metadata(mut self, metadata: bytes::Bytes) -> Self {
+		self.metadata = Some(metadata);
+		self
+	}
+
+	/// The metadata provided by the publisher when announced, if any.
+	pub fn metadata(&self) -> Option<&bytes::Bytes> {
+		self.metadata.as_ref()
+	}
+
+	/// Subscribe to a track, optionally skipping groups or frames via [Track::filter].
 	pub fn subscribe(&self, track: &Track) -> TrackConsumer {
 		/*
 		let closed = match self.closed.wait_for(|closed| *closed).now_or_never() {
@@ -194,15 +219,40 @@ impl BroadcastConsumer {
 
 		let mut published = self.published.lock();
 
-		// Return any explictly published track.
-		if let Some(consumer) = published.get(&track.name).cloned() {
-			return consumer;
+		// Return any existing track, applying the filter and resume point locally.
+		// A resumed request for a deduplicated track is sent upstream instead, as the older groups may not be cached.
+		if let Some(existing) = published.get(&track.name).cloned() {
+			if existing.explicit || track.resume.is_none() {
+				let mut consumer = match track.filter.is_empty() {
+					true => existing.track,
+					false => existing.track.filter(track.filter.clone()),
+				};
+
+				if let Some(resume) = track.resume {
+					consumer.seek(resume.group);
+				}
+
+				return consumer;
+			}
 		}
 
 		// Otherwise we have never seen this track before and need to create a new producer.
 		let producer = track.clone().produce();
-		let consumer = producer.consume();
-		published.insert(track.name.clone(), consumer.clone());
+		let mut consumer = producer.consume();
+
+		// Filtered or resumed tracks are not deduplicated, as they're applied by the publisher.
+		if track.filter.is_empty() && track.resume.is_none() {
+			let entry = Published {
+				track: consumer.clone(),
+				explicit: false,
+			};
+			published.insert(track.name.clone(), entry);
+		}
+
+		// The publisher starts at the resume point, so return every group in order.
+		if let Some(resume) = track.resume {
+			consumer.seek(resume.group);
+		}
 
 		// Insert the producer into the lookup so we will deduplicate requests.
 		// This is not a subscriber so it doesn't count towards "used" subscribers.
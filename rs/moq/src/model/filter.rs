@@ -0,0 +1,44 @@
+use std::time::Duration;
+
+/// Skip groups or frames that the subscriber doesn't need, such as for thumbnails and previews.
+///
+/// The filter is sent to the publisher, so any skipped data is never transmitted over the network.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Filter {
+	/// Only deliver the first frame of each group (ex. keyframes).
+	pub first_frame: bool,
+
+	/// Only deliver groups with a sequence number divisible by N.
+	pub nth_group: Option<u64>,
+
+	/// Skip groups that arrive within this duration of the previously delivered group.
+	pub min_interval: Option<Duration>,
+}
+
+impl Filter {
+	/// Returns true if the filter doesn't skip anything.
+	pub fn is_empty(&self) -> bool {
+		!self.first_frame && self.nth_group.unwrap_or(1) <= 1 && self.min_interval.unwrap_or_default().is_zero()
+	}
+
+	/// Returns true if a group with the given sequence number should be delivered.
+	pub(crate) fn group(&self, sequence: u64, elapsed: Option<Duration>) -> bool {
+		if let Some(nth) = self.nth_group.filter(|nth| *nth > 1) {
+			if !sequence.is_multiple_of(nth) {
+				return false;
+			}
+		}
+
+		match (self.min_interval, elapsed) {
+			(Some(min), Some(elapsed)) => elapsed >= min,
+			_ => true,
+		}
+	}
+
+	/// The maximum number of frames to deliver per group.
+	pub(crate) fn frames(&self) -> Option<usize> {
+		self.first_frame.then_some(1)
+	}
+}
@@ -7,19 +7,42 @@
 //! The reader can be cloned, in which case each reader receives a copy of each frame. (fanout)
 //!
 //! The stream is closed with [ServeError::MoqError] when all writers or readers are dropped.
-use std::future::Future;
+use std::{future::Future, time::Duration};
 
 use bytes::Bytes;
-use tokio::sync::watch;
+use tokio::{sync::watch, time::Instant};
 
 use crate::{Error, Result};
 
 use super::{Frame, FrameConsumer, FrameProducer};
 
-#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Clone, Debug, Default, Hash, Eq, PartialEq, Ord, PartialOrd)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Group {
 	pub sequence: u64,
+
+	/// Boosts the priority of this group relative to other groups, such as for keyframes or base layers.
+	///
+	/// This is added to the subscriber's track priority when scheduling streams.
+	#[cfg_attr(feature = "serde", serde(default))]
+	pub priority: u8,
+
+	/// The index of the first frame, non-zero when resuming a partially received group.
+	#[cfg_attr(feature = "serde", serde(default))]
+	pub frame_offset: u64,
+}
+
+/// A position within a track, used to resume a partially received group after reconnecting.
+///
+/// Use [GroupConsumer::resume_point] to get the position and [crate::Track::resume] to subscribe from it.
+#[derive(Clone, Copy, Debug, Default, Hash, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ResumePoint {
+	/// The sequence number of the group.
+	pub group: u64,
+
+	/// The index of the next frame within the group.
+	pub frame: u64,
 }
 
 impl Group {
@@ -32,13 +55,17 @@ impl From<usize> for Group {
 	fn from(sequence: usize) -> Self {
 		Self {
 			sequence: sequence as u64,
+			..Default::default()
 		}
 	}
 }
 
 impl From<u64> for Group {
 	fn from(sequence: u64) -> Self {
-		Self { sequence }
+		Self {
+			sequence,
+			..Default::default()
+		}
 	}
 }
 
@@ -46,6 +73,7 @@ impl From<u32> for Group {
 	fn from(sequence: u32) -> Self {
 		Self {
 			sequence: sequence as u64,
+			..Default::default()
 		}
 	}
 }
@@ -54,6 +82,7 @@ impl From<u16> for Group {
 	fn from(sequence: u16) -> Self {
 		Self {
 			sequence: sequence as u64,
+			..Default::default()
 		}
 	}
 }
@@ -67,6 +96,14 @@ struct GroupState {
 	closed: Option<Result<()>>,
 }
 
+impl GroupState {
+	// Abort the group even if it was finished, freeing any cached frames.
+	fn expire(&mut self) {
+		self.frames.clear();
+		self.closed = Some(Err(Error::Old));
+	}
+}
+
 /// Create a group, frame-by-frame.
 #[derive(Clone)]
 pub struct GroupProducer {
@@ -75,6 +112,9 @@ pub struct GroupProducer {
 
 	// Immutable stream state.
 	pub info: Group,
+
+	// When the group was created, used to expire old groups.
+	created: Instant,
 }
 
 impl GroupProducer {
@@ -82,6 +122,7 @@ impl GroupProducer {
 		Self {
 			info,
 			state: Default::default(),
+			created: Instant::now(),
 		}
 	}
 
@@ -89,47 +130,87 @@ impl GroupProducer {
 	///
 	/// If you want to write multiple chunks, use [Self::create_frame] or [Self::append_frame].
 	/// But an upfront size is required.
+	///
+	/// The frame is dropped if the group is already closed, ex. after it expired.
 	pub fn write_frame<B: Into<Bytes>>(&mut self, frame: B) {
 		let data = frame.into();
 		let frame = Frame {
 			size: data.len() as u64,
 		};
-		let mut frame = self.create_frame(frame);
-		frame.write(data);
-		frame.finish();
+
+		if let Ok(mut frame) = self.create_frame(frame) {
+			frame.write(data);
+			frame.finish();
+		}
 	}
 
-	/// Create a frame with an upfront size
-	pub fn create_frame(&mut self, info: Frame) -> FrameProducer {
+	/// Create a frame with an upfront size.
+	///
+	/// Returns an error if the group is already closed, see [Self::append_frame].
+	pub fn create_frame(&mut self, info: Frame) -> Result<FrameProducer> {
 		let producer = FrameProducer::new(info);
-		self.append_frame(producer.consume());
-		producer
+		self.append_frame(producer.consume())?;
+		Ok(producer)
 	}
 
 	/// Append a frame to the group.
-	pub fn append_frame(&mut self, consumer: FrameConsumer) {
-		self.state.send_modify(|state| {
-			assert!(state.closed.is_none());
-			state.frames.push(consumer)
+	///
+	/// Returns an error if the group is already closed, such as [Error::Old] once it expired (see [crate::Track::max_age]).
+	/// The group may be expired by the track while it's still being written, so the writer should stop.
+	pub fn append_frame(&mut self, consumer: FrameConsumer) -> Result<()> {
+		let mut res = Ok(());
+
+		self.state.send_if_modified(|state| match &state.closed {
+			Some(Ok(())) => {
+				res = Err(Error::Cancel);
+				false
+			}
+			Some(Err(err)) => {
+				res = Err(err.clone());
+				false
+			}
+			None => {
+				state.frames.push(consumer);
+				true
+			}
 		});
+
+		res
 	}
 
-	// Clean termination of the group.
+	// Clean termination of the group, unless it was already closed (ex. expired).
 	pub fn finish(self) {
-		self.state.send_modify(|state| state.closed = Some(Ok(())));
+		self.state.send_if_modified(|state| {
+			let open = state.closed.is_none();
+			state.closed.get_or_insert(Ok(()));
+			open
+		});
 	}
 
 	pub fn abort(self, err: Error) {
-		self.state.send_modify(|state| state.closed = Some(Err(err)));
+		self.state.send_if_modified(|state| {
+			let open = state.closed.is_none();
+			state.closed.get_or_insert(Err(err));
+			open
+		});
+	}
+
+	/// Abort the group with [Error::Old] because it's too old, even if it was finished.
+	///
+	/// Any cached frames are dropped, although existing [FrameConsumer]s can still be read.
+	pub fn expire(self) {
+		self.state.send_modify(GroupState::expire);
 	}
 
 	/// Create a new consumer for the group.
 	pub fn consume(&self) -> GroupConsumer {
 		GroupConsumer {
 			info: self.info.clone(),
+			created: self.created,
 			state: self.state.subscribe(),
 			index: 0,
 			active: None,
+			limit: None,
 		}
 	}
 
@@ -156,15 +237,47 @@ pub struct GroupConsumer {
 	// Immutable stream state.
 	pub info: Group,
 
+	// When the group was created.
+	created: Instant,
+
 	// The number of frames we've read.
 	// NOTE: Cloned readers inherit this offset, but then run in parallel.
 	index: usize,
 
 	// Used to make read_frame cancel safe.
 	active: Option<FrameConsumer>,
+
+	// The maximum number of frames to return, if filtered.
+	limit: Option<usize>,
 }
 
 impl GroupConsumer {
+	/// Return how long ago the group was created locally.
+	pub fn age(&self) -> Duration {
+		self.created.elapsed()
+	}
+
+	/// Return the position of the next frame, used to resume the group on a new subscription.
+	pub fn resume_point(&self) -> ResumePoint {
+		// A partially read frame will need to be received again.
+		let index = self.index - self.active.is_some() as usize;
+
+		ResumePoint {
+			group: self.info.sequence,
+			frame: self.info.frame_offset + index as u64,
+		}
+	}
+
+	// Skip over frames that were already received by a previous subscription.
+	pub(crate) fn skip(&mut self, frames: usize) {
+		self.index += frames;
+	}
+
+	// Stop returning frames after reaching the limit.
+	pub(super) fn limit(&mut self, limit: Option<usize>) {
+		self.limit = limit;
+	}
+
 	/// Read the next frame.
 	pub async fn read_frame(&mut self) -> Result<Option<Bytes>> {
 		// In order to be cancel safe, we need to save the active frame.
@@ -191,6 +304,10 @@ impl GroupConsumer {
 			return Ok(Some(frame));
 		}
 
+		if self.limit.is_some_and(|limit| self.index >= limit) {
+			return Ok(None);
+		}
+
 		loop {
 			{
 				let state = self.state.borrow_and_update();
@@ -1,10 +1,12 @@
 mod broadcast;
+mod filter;
 mod frame;
 mod group;
 mod origin;
 mod track;
 
 pub use broadcast::*;
+pub use filter::*;
 pub use frame::*;
 pub use group::*;
 pub use origin::*;
@@ -3,6 +3,7 @@ use tokio::sync::mpsc;
 use web_async::{Lock, LockWeak};
 
 use super::BroadcastConsumer;
+use crate::{coding::DecodeLimits, Error, Result};
 
 #[derive(Default)]
 struct ProducerState {
@@ -65,6 +66,16 @@ impl ConsumerState {
 	}
 }
 
+/// Optional settings used when announcing a broadcast via [OriginProducer::publish_with].
+#[derive(Clone, Debug, Default)]
+pub struct PublishOptions {
+	/// A small blob delivered with the announcement, such as a title or participant name.
+	///
+	/// Consumers receive it via [BroadcastConsumer::metadata] without subscribing to any tracks.
+	/// It's limited to [crate::coding::DecodeLimits::max_string] bytes over the network.
+	pub metadata: Option<bytes::Bytes>,
+}
+
 /// Announces broadcasts to consumers over the network.
 #[derive(Default, Clone)]
 pub struct OriginProducer {
@@ -77,17 +88,55 @@ impl OriginProducer {
 	}
 
 	/// Announce a broadcast, returning true if it was unique.
+	///
+	/// Any existing broadcast with the same path is replaced; see [Self::replace].
 	pub fn publish<S: ToString>(&mut self, path: S, broadcast: BroadcastConsumer) -> bool {
+		self.replace(path, broadcast).is_none()
+	}
+
+	/// Announce a broadcast with the provided options, returning true if it was unique.
+	///
+	/// Returns [Error::TooLarge] if the metadata exceeds the default [DecodeLimits::max_string].
+	pub fn publish_with<S: ToString>(
+		&mut self,
+		path: S,
+		broadcast: BroadcastConsumer,
+		options: PublishOptions,
+	) -> Result<bool> {
+		let broadcast = match options.metadata {
+			Some(metadata) if metadata.len() > DecodeLimits::default().max_string => return Err(Error::TooLarge),
+			Some(metadata) => broadcast.with_metadata(metadata),
+			None => broadcast,
+		};
+
+		Ok(self.publish(path, broadcast))
+	}
+
+	/// Announce a broadcast, replacing and returning any existing broadcast with the same path.
+	///
+	/// This is used for failover, such as a publisher restarting or switching to a backup encoder.
+	/// Consumers are notified via [OriginConsumer::next], which returns the same path again with the new broadcast.
+	/// Any network subscriptions will switch to the new broadcast's tracks at the next group boundary.
+	pub fn replace<S: ToString>(&mut self, path: S, broadcast: BroadcastConsumer) -> Option<BroadcastConsumer> {
 		let path = path.to_string();
-		let unique = self.state.lock().publish(path.clone(), broadcast.clone()).is_none();
+		let previous = self.state.lock().publish(path.clone(), broadcast.clone());
 
 		let state = self.state.clone();
 		web_async::spawn(async move {
 			broadcast.closed().await;
-			state.lock().active.remove(&path);
+
+			// Make sure we don't remove a broadcast that replaced us.
+			let mut state = state.lock();
+			if state
+				.active
+				.get(&path)
+				.is_some_and(|active| active.is_clone(&broadcast))
+			{
+				state.active.remove(&path);
+			}
 		});
 
-		unique
+		previous
 	}
 
 	/// Publish all broadcasts from the given origin.
@@ -179,6 +228,8 @@ impl OriginConsumer {
 	}
 
 	/// Returns the next announced broadcast.
+	///
+	/// The same path is returned again if the broadcast was replaced via [OriginProducer::replace].
 	pub async fn next(&mut self) -> Option<(String, BroadcastConsumer)> {
 		loop {
 			{
@@ -215,3 +266,83 @@ impl Clone for OriginConsumer {
 		}
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use crate::BroadcastProducer;
+
+	use super::*;
+	use futures::FutureExt;
+
+	#[tokio::test]
+	async fn replace() {
+		let mut origin = OriginProducer::new();
+		let mut consumer = origin.consume_all();
+
+		let broadcast1 = BroadcastProducer::new();
+		let broadcast2 = BroadcastProducer::new();
+
+		assert!(origin.publish("test", broadcast1.consume()));
+
+		let (path, active) = consumer.next().now_or_never().unwrap().unwrap();
+		assert_eq!(path, "test");
+		assert!(active.is_clone(&broadcast1.consume()));
+
+		// Replace the broadcast, returning the previous one.
+		let previous = origin.replace("test", broadcast2.consume()).expect("should replace");
+		assert!(previous.is_clone(&broadcast1.consume()));
+		assert!(origin.consume("test").unwrap().is_clone(&broadcast2.consume()));
+
+		// The consumer is notified with the same path.
+		let (path, active) = consumer.next().now_or_never().unwrap().unwrap();
+		assert_eq!(path, "test");
+		assert!(active.is_clone(&broadcast2.consume()));
+
+		// Closing the old broadcast should not remove the replacement.
+		drop(broadcast1);
+		drop(previous);
+		drop(active);
+		tokio::task::yield_now().await;
+		assert!(origin.consume("test").unwrap().is_clone(&broadcast2.consume()));
+
+		// Closing the new broadcast removes it.
+		drop(broadcast2);
+		tokio::task::yield_now().await;
+		assert!(origin.consume("test").is_none());
+	}
+
+	#[tokio::test]
+	async fn metadata() {
+		let mut origin = OriginProducer::new();
+		let mut consumer = origin.consume_all();
+
+		let broadcast = BroadcastProducer::new();
+		let options = PublishOptions {
+			metadata: Some(bytes::Bytes::from_static(b"title")),
+		};
+		assert!(origin.publish_with("test", broadcast.consume(), options).unwrap());
+
+		let (path, active) = consumer.next().now_or_never().unwrap().unwrap();
+		assert_eq!(path, "test");
+		assert_eq!(active.metadata().unwrap().as_ref(), b"title");
+
+		// The metadata is also available to later consumers.
+		assert_eq!(origin.consume("test").unwrap().metadata().unwrap().as_ref(), b"title");
+		assert!(broadcast.consume().metadata().is_none());
+	}
+
+	#[test]
+	fn metadata_too_large() {
+		let mut origin = OriginProducer::new();
+
+		let broadcast = BroadcastProducer::new();
+		let options = PublishOptions {
+			metadata: Some(vec![0; DecodeLimits::default().max_string + 1].into()),
+		};
+
+		// The remote would fail to decode the announcement, so it's rejected up front.
+		let res = origin.publish_with("test", broadcast.consume(), options);
+		assert!(matches!(res, Err(Error::TooLarge)));
+		assert!(origin.consume("test").is_none());
+	}
+}
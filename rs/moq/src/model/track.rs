@@ -16,22 +16,46 @@ use tokio::sync::watch;
 
 use crate::{Error, Result};
 
-use super::{Group, GroupConsumer, GroupProducer};
+use super::{Filter, Group, GroupConsumer, GroupProducer, ResumePoint};
 
-use std::{cmp::Ordering, future::Future};
+use std::{
+	collections::VecDeque,
+	future::Future,
+	sync::{Arc, OnceLock},
+	time::Duration,
+};
+use tokio::{sync::mpsc, time::Instant};
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Track {
 	pub name: String,
 	pub priority: u8,
+
+	/// Optionally skip groups or frames, applied by the publisher.
+	#[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Filter::is_empty"))]
+	pub filter: Filter,
+
+	/// Abort groups with [Error::Old] once they are older than this duration.
+	///
+	/// This is measured from when the group was created locally, so it's reset by each relay hop.
+	#[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+	pub max_age: Option<Duration>,
+
+	/// Start from a partially received group, skipping earlier groups and frames, applied by the publisher.
+	///
+	/// The resumed group uses [Group::frame_offset] to indicate the index of its first frame.
+	/// Groups retained by the publisher (see [TrackProducer::set_history]) are returned in order from this point,
+	/// so the resumed group doesn't need to be the latest.
+	#[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+	pub resume: Option<ResumePoint>,
 }
 
 impl Track {
 	pub fn new<T: Into<String>>(name: T) -> Self {
 		Self {
 			name: name.into(),
-			priority: 0,
+			..Default::default()
 		}
 	}
 
@@ -43,14 +67,58 @@ impl Track {
 #[derive(Default)]
 struct TrackState {
 	latest: Option<GroupConsumer>,
+
+	// Recent groups in sequence order, including the latest, for consumers that seek.
+	history: VecDeque<GroupConsumer>,
+
+	// How long to retain groups in the history, or zero to only keep the latest.
+	retain: Duration,
+
 	closed: Option<Result<()>>,
 }
 
+impl TrackState {
+	// Remove any groups older than the history, but always keep the latest.
+	fn prune(&mut self) -> bool {
+		let mut pruned = false;
+
+		while self.history.len() > 1 && self.history.front().is_some_and(|group| group.age() >= self.retain) {
+			self.history.pop_front();
+			pruned = true;
+		}
+
+		pruned
+	}
+
+	// When the oldest group in the history should be pruned.
+	fn prune_at(&self) -> Option<Instant> {
+		if self.history.len() <= 1 {
+			return None;
+		}
+
+		let age = self.history.front()?.age();
+		Some(Instant::now() + self.retain.saturating_sub(age))
+	}
+
+	// Return the next group after the given sequence number.
+	fn next(&self, prev: Option<u64>, ordered: bool) -> Option<&GroupConsumer> {
+		let after = |group: &&GroupConsumer| Some(group.info.sequence) > prev;
+
+		match ordered {
+			true => self.history.iter().find(after).or(self.latest.as_ref().filter(after)),
+			false => self.latest.as_ref().filter(after),
+		}
+	}
+}
+
 /// A producer for a track, used to create new groups.
 #[derive(Clone)]
 pub struct TrackProducer {
 	pub info: Track,
 	state: watch::Sender<TrackState>,
+
+	// Groups are sent to a single task per track that expires them, started on the first group.
+	expire: Arc<OnceLock<mpsc::UnboundedSender<GroupProducer>>>,
 }
 
 impl TrackProducer {
@@ -58,37 +126,147 @@ impl TrackProducer {
 		Self {
 			info,
 			state: Default::default(),
+			expire: Default::default(),
 		}
 	}
 
-	/// Insert a group into the track, returning true if this is the latest group.
+	/// Insert a group into the track, returning true if it was inserted.
+	///
+	/// Older groups are only inserted when retaining a history (see [Self::set_history]) and they're missing from it.
 	pub fn insert_group(&mut self, group: GroupConsumer) -> bool {
 		self.state.send_if_modified(|state| {
 			assert!(state.closed.is_none());
 
-			if let Some(latest) = &state.latest {
-				match group.info.cmp(&latest.info) {
-					Ordering::Less => return false,
-					Ordering::Equal => return false,
-					Ordering::Greater => (),
+			let sequence = group.info.sequence;
+			let retain = state.retain;
+
+			if !retain.is_zero() {
+				state.prune();
+
+				match state
+					.history
+					.binary_search_by_key(&sequence, |group| group.info.sequence)
+				{
+					Ok(_) => return false,
+					Err(index) => state.history.insert(index, group.clone()),
 				}
 			}
 
-			state.latest = Some(group.clone());
+			if state
+				.latest
+				.as_ref()
+				.is_some_and(|latest| sequence <= latest.info.sequence)
+			{
+				// Only an older group that was missing from the history.
+				return !retain.is_zero();
+			}
+
+			state.latest = Some(group);
 			true
 		})
 	}
 
+	/// Retain groups for the given duration, so consumers can [TrackConsumer::seek] to them.
+	///
+	/// By default only the latest group is kept, as live consumers skip older groups anyway.
+	/// A publisher should opt in if subscribers may resume a group after reconnecting (see [Track::resume]) or seek backwards.
+	/// Groups older than the history are dropped by a timer, even if the track is idle.
+	pub fn set_history(&mut self, retain: Duration) {
+		self.state.send_modify(|state| {
+			state.retain = retain;
+
+			match retain.is_zero() {
+				true => state.history.clear(),
+				false => {
+					state.prune();
+				}
+			}
+		});
+	}
+
+	/// How long groups are retained, see [Self::set_history].
+	pub fn history(&self) -> Duration {
+		self.state.borrow().retain
+	}
+
 	/// Create a new group with the given sequence number.
 	///
 	/// If the sequence number is not the latest, this method will return None.
+	/// The exception is an older group missing from the history, see [Self::set_history].
 	pub fn create_group(&mut self, info: Group) -> Option<GroupProducer> {
 		let group = GroupProducer::new(info);
-		self.insert_group(group.consume()).then_some(group)
+		if !self.insert_group(group.consume()) {
+			return None;
+		}
+
+		let max_age = self.info.max_age;
+		if max_age.is_some() || !self.history().is_zero() {
+			let expire = self.expire.get_or_init(|| {
+				let (tx, rx) = mpsc::unbounded_channel();
+				web_async::spawn(Self::expire(rx, self.state.clone(), max_age));
+				tx
+			});
+
+			expire.send(group.clone()).ok();
+		}
+
+		Some(group)
+	}
+
+	// Abort each group once it's too old and prune the history, using a single timer for the track.
+	// Groups are created in order, so they also expire in order.
+	async fn expire(
+		mut groups: mpsc::UnboundedReceiver<GroupProducer>,
+		state: watch::Sender<TrackState>,
+		max_age: Option<Duration>,
+	) {
+		let mut pending = VecDeque::new();
+
+		// Stop pruning the history once every producer is dropped, as no more groups can be inserted.
+		let mut state = Some(state);
+
+		loop {
+			let expire = max_age.and_then(|max_age| pending.front().map(|(created, _)| *created + max_age));
+			let prune = state.as_ref().and_then(|state| state.borrow().prune_at());
+			let next = expire.into_iter().chain(prune).min();
+
+			tokio::select! {
+				group = groups.recv(), if state.is_some() => match group {
+					// Wake up to reschedule the timer, even if the group itself never expires.
+					Some(group) if max_age.is_some() => pending.push_back((Instant::now(), group)),
+					Some(_) => {},
+					None => state = None,
+				},
+				_ = async { tokio::time::sleep_until(next.unwrap()).await }, if next.is_some() => {
+					let now = Instant::now();
+
+					while let Some((created, _)) = pending.front() {
+						if max_age.is_some_and(|max_age| *created + max_age > now) {
+							break;
+						}
+
+						let (_, group) = pending.pop_front().unwrap();
+						group.expire();
+					}
+
+					if let Some(state) = &state {
+						state.send_if_modified(TrackState::prune);
+					}
+				},
+				else => return,
+			}
+		}
 	}
 
 	/// Create a new group with the next sequence number.
 	pub fn append_group(&mut self) -> GroupProducer {
+		self.append_group_priority(0)
+	}
+
+	/// Create a new group with the next sequence number and a priority boost.
+	///
+	/// See [Group::priority] for more information.
+	pub fn append_group_priority(&mut self, priority: u8) -> GroupProducer {
 		// TODO remove this extra lock
 		let sequence = self
 			.state
@@ -97,7 +275,11 @@ impl TrackProducer {
 			.as_ref()
 			.map_or(0, |group| group.info.sequence + 1);
 
-		let group = Group { sequence };
+		let group = Group {
+			sequence,
+			priority,
+			..Default::default()
+		};
 		self.create_group(group).unwrap()
 	}
 
@@ -115,6 +297,9 @@ impl TrackProducer {
 			info: self.info.clone(),
 			state: self.state.subscribe(),
 			prev: None,
+			ordered: false,
+			filter: Filter::default(),
+			delivered: None,
 		}
 	}
 
@@ -144,36 +329,80 @@ pub struct TrackConsumer {
 	pub info: Track,
 	state: watch::Receiver<TrackState>,
 	prev: Option<u64>, // The previous sequence number
+
+	// Return every group in order after seeking, rather than skipping to the latest.
+	ordered: bool,
+
+	// Skip groups and frames locally.
+	filter: Filter,
+
+	// When the previous group was delivered, used for the filter.
+	delivered: Option<Instant>,
 }
 
 impl TrackConsumer {
 	/// Return the next group in order.
 	///
 	/// NOTE: This can have gaps if the reader is too slow or there were network slowdowns.
+	/// After [Self::seek], every group retained by the producer is returned in order instead.
 	pub async fn next_group(&mut self) -> Result<Option<GroupConsumer>> {
-		// Wait until there's a new latest group or the track is closed.
-		let state = match self
-			.state
-			.wait_for(|state| {
-				state.latest.as_ref().map(|group| group.info.sequence) > self.prev || state.closed.is_some()
-			})
-			.await
-		{
-			Ok(state) => state,
-			Err(_) => return Err(Error::Cancel),
-		};
+		loop {
+			// Wait until there's a new group or the track is closed.
+			let state = match self
+				.state
+				.wait_for(|state| state.next(self.prev, self.ordered).is_some() || state.closed.is_some())
+				.await
+			{
+				Ok(state) => state,
+				Err(_) => return Err(Error::Cancel),
+			};
+
+			let next = state.next(self.prev, self.ordered).cloned();
+
+			// A seeking consumer reads any remaining groups before the track is closed.
+			let mut group = match (&state.closed, next) {
+				(Some(Err(err)), _) => return Err(err.clone()),
+				(Some(Ok(_)), Some(group)) if self.ordered => group,
+				(Some(Ok(_)), _) => return Ok(None),
+				(None, Some(group)) => group,
+				(None, None) => unreachable!("woke without a group"),
+			};
+
+			self.prev = Some(group.info.sequence);
+
+			let now = Instant::now();
+			let elapsed = self.delivered.map(|delivered| now - delivered);
+			if !self.filter.group(group.info.sequence, elapsed) {
+				continue;
+			}
 
-		match &state.closed {
-			Some(Ok(_)) => return Ok(None),
-			Some(Err(err)) => return Err(err.clone()),
-			_ => {}
+			self.delivered = Some(now);
+			group.limit(self.filter.frames());
+
+			return Ok(Some(group));
 		}
+	}
 
-		// If there's a new latest group, return it.
-		let group = state.latest.clone().unwrap();
-		self.prev = Some(group.info.sequence);
+	/// Return groups in order starting at the given sequence number, rather than skipping to the latest.
+	///
+	/// Older groups are only available if retained by the producer, see [TrackProducer::set_history].
+	/// Otherwise this resumes from the oldest group available.
+	pub fn seek(&mut self, sequence: u64) {
+		self.prev = sequence.checked_sub(1);
+		self.ordered = true;
+	}
 
-		Ok(Some(group))
+	/// Skip to the latest group again after [Self::seek].
+	pub fn live(&mut self) {
+		self.ordered = false;
+	}
+
+	/// Skip groups and frames based on the provided filter, replacing any existing filter.
+	///
+	/// This is performed locally; use [Track::filter] to have the publisher skip them instead.
+	pub fn filter(mut self, filter: Filter) -> Self {
+		self.filter = filter;
+		self
 	}
 
 	/// Block until the track is closed.
@@ -233,3 +462,222 @@ impl TrackConsumer {
 		assert!(!self.is_clone(other), "should not be clone");
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use std::time::Duration;
+
+	use super::*;
+
+	#[tokio::test]
+	async fn filter_nth_group() {
+		let mut producer = Track::new("test").produce();
+		let mut consumer = producer.consume().filter(Filter {
+			nth_group: Some(2),
+			..Default::default()
+		});
+
+		producer.append_group();
+		assert_eq!(consumer.assert_group().info.sequence, 0);
+
+		producer.append_group();
+		consumer.assert_no_group();
+
+		producer.append_group();
+		assert_eq!(consumer.assert_group().info.sequence, 2);
+	}
+
+	#[tokio::test]
+	async fn filter_first_frame() {
+		let mut producer = Track::new("test").produce();
+		let mut consumer = producer.consume().filter(Filter {
+			first_frame: true,
+			..Default::default()
+		});
+
+		let mut group = producer.append_group();
+		group.write_frame(bytes::Bytes::from_static(b"keyframe"));
+		group.write_frame(bytes::Bytes::from_static(b"delta"));
+
+		let mut group = consumer.assert_group();
+		let frame = group.read_frame().now_or_never().unwrap().unwrap();
+		assert_eq!(frame.as_deref(), Some(&b"keyframe"[..]));
+
+		// The group isn't finished, but we don't wait for more frames.
+		let frame = group.read_frame().now_or_never().unwrap().unwrap();
+		assert_eq!(frame, None);
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn filter_min_interval() {
+		let mut producer = Track::new("test").produce();
+		let mut consumer = producer.consume().filter(Filter {
+			min_interval: Some(Duration::from_secs(1)),
+			..Default::default()
+		});
+
+		producer.append_group();
+		assert_eq!(consumer.assert_group().info.sequence, 0);
+
+		tokio::time::advance(Duration::from_millis(500)).await;
+		producer.append_group();
+		consumer.assert_no_group();
+
+		tokio::time::advance(Duration::from_millis(500)).await;
+		producer.append_group();
+		assert_eq!(consumer.assert_group().info.sequence, 2);
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn max_age() {
+		let mut producer = Track {
+			name: "test".to_string(),
+			max_age: Some(Duration::from_secs(1)),
+			..Default::default()
+		}
+		.produce();
+		let mut consumer = producer.consume();
+
+		let mut group = producer.append_group();
+		group.write_frame(bytes::Bytes::from_static(b"frame"));
+		group.finish();
+
+		let mut group = consumer.assert_group();
+
+		tokio::time::sleep(Duration::from_millis(500)).await;
+		let mut second = producer.append_group();
+		second.write_frame(bytes::Bytes::from_static(b"frame"));
+		let mut second = consumer.assert_group();
+
+		// Wait until the first group has expired, at which point the cached frames are gone.
+		tokio::time::sleep(Duration::from_millis(600)).await;
+
+		let res = group.read_frame().now_or_never().expect("should not block");
+		assert!(matches!(res, Err(Error::Old)));
+
+		// The second group expires half a second later.
+		let frame = second.read_frame().now_or_never().expect("should not block").unwrap();
+		assert_eq!(frame.as_deref(), Some(&b"frame"[..]));
+
+		tokio::time::sleep(Duration::from_millis(500)).await;
+		let res = second.read_frame().now_or_never().expect("should not block");
+		assert!(matches!(res, Err(Error::Old)));
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn write_after_expiry() {
+		let mut producer = Track {
+			name: "test".to_string(),
+			max_age: Some(Duration::from_secs(1)),
+			..Default::default()
+		}
+		.produce();
+		let mut consumer = producer.consume();
+
+		// The group is still being written when it expires, ex. a long GOP or a slow upstream.
+		let mut group = producer.append_group();
+		group.write_frame(bytes::Bytes::from_static(b"frame"));
+		let mut reader = consumer.assert_group();
+
+		tokio::time::sleep(Duration::from_millis(1100)).await;
+
+		// Writing is an error (or dropped) instead of a panic.
+		assert!(matches!(group.create_frame(5u64.into()), Err(Error::Old)));
+		group.write_frame(bytes::Bytes::from_static(b"late"));
+
+		// Finishing doesn't hide the expiry from consumers.
+		group.finish();
+		let res = reader.read_frame().now_or_never().expect("should not block");
+		assert!(matches!(res, Err(Error::Old)));
+	}
+
+	#[test]
+	fn resume_point() {
+		let mut producer = Track::new("test").produce();
+		let mut consumer = producer.consume();
+
+		let mut group = producer
+			.create_group(Group {
+				sequence: 3,
+				frame_offset: 2,
+				..Default::default()
+			})
+			.unwrap();
+		group.write_frame(bytes::Bytes::from_static(b"c"));
+		group.write_frame(bytes::Bytes::from_static(b"d"));
+		group.write_frame(bytes::Bytes::from_static(b"e"));
+
+		let mut group = consumer.assert_group();
+		assert_eq!(group.resume_point(), ResumePoint { group: 3, frame: 2 });
+
+		group.skip(1);
+		assert_eq!(group.resume_point(), ResumePoint { group: 3, frame: 3 });
+
+		let frame = group.read_frame().now_or_never().unwrap().unwrap().unwrap();
+		assert_eq!(frame, "d");
+		assert_eq!(group.resume_point(), ResumePoint { group: 3, frame: 4 });
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn history() {
+		let mut producer = Track::new("test").produce();
+		assert_eq!(producer.history(), Duration::ZERO);
+		producer.set_history(Duration::from_secs(10));
+
+		for _ in 0..4 {
+			producer.append_group();
+			tokio::time::advance(Duration::from_secs(1)).await;
+		}
+
+		// A live consumer skips to the latest group.
+		let mut live = producer.consume();
+		assert_eq!(live.assert_group().info.sequence, 3);
+
+		// It can go back to the latest group after seeking.
+		live.seek(0);
+		assert_eq!(live.assert_group().info.sequence, 0);
+		live.live();
+		assert_eq!(live.assert_group().info.sequence, 3);
+
+		let mut consumer = producer.consume();
+		consumer.seek(1);
+		assert_eq!(consumer.assert_group().info.sequence, 1);
+		assert_eq!(consumer.assert_group().info.sequence, 2);
+		assert_eq!(consumer.assert_group().info.sequence, 3);
+		consumer.assert_no_group();
+
+		// Older groups are inserted if they're missing from the history.
+		producer.create_group(Group::from(5usize)).unwrap();
+		producer.create_group(Group::from(4usize)).unwrap();
+		assert!(producer.create_group(Group::from(4usize)).is_none());
+		assert_eq!(consumer.assert_group().info.sequence, 4);
+		assert_eq!(consumer.assert_group().info.sequence, 5);
+
+		// Expired groups are removed by a timer, even if no new groups are created.
+		tokio::time::advance(Duration::from_secs(8)).await;
+		tokio::task::yield_now().await;
+
+		let mut consumer = producer.consume();
+		consumer.seek(0);
+		assert_eq!(consumer.assert_group().info.sequence, 3);
+
+		// The remaining groups are still returned after the track is finished.
+		producer.finish();
+		assert_eq!(consumer.assert_group().info.sequence, 4);
+	}
+
+	#[test]
+	fn seek_without_history() {
+		let mut producer = Track::new("test").produce();
+		producer.set_history(Duration::ZERO);
+		producer.append_group();
+		producer.append_group();
+
+		// Only the latest group is retained.
+		let mut consumer = producer.consume();
+		consumer.seek(0);
+		assert_eq!(consumer.assert_group().info.sequence, 1);
+		assert!(producer.create_group(Group::from(0usize)).is_none());
+		assert_eq!(producer.history(), Duration::ZERO);
+	}
+}
@@ -0,0 +1,324 @@
+use std::{
+	fs, io,
+	path::Path,
+	sync::{atomic, mpsc, Arc},
+	thread,
+	time::{Duration, Instant},
+};
+
+use crate::coding::*;
+
+/// The magic bytes at the start of every capture file.
+pub const CAPTURE_MAGIC: &[u8; 8] = b"moqcap01";
+
+/// How often the capture is flushed while records are being written.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Records the raw bytes of every stream in a [crate::Session] to a capture file.
+///
+/// The file starts with [CAPTURE_MAGIC] and a [CaptureHeader], followed by a sequence of [CaptureRecord]s.
+/// Use `moq-dump` to decode a capture into a readable timeline.
+///
+/// Records are written by a background thread so the session never blocks on the output.
+/// The output is flushed periodically, so the capture of a running session can be inspected.
+#[derive(Clone)]
+pub struct Capture {
+	records: mpsc::Sender<Vec<u8>>,
+	start: Instant,
+	next: Arc<atomic::AtomicU64>,
+}
+
+impl Capture {
+	/// Write the capture to the provided output, recording the limits used to decode the session.
+	pub fn new<W: io::Write + Send + 'static>(mut output: W, limits: &DecodeLimits) -> io::Result<Self> {
+		let mut header = CAPTURE_MAGIC.to_vec();
+		CaptureHeader::from(limits).encode(&mut header);
+		output.write_all(&header)?;
+
+		let (records, rx) = mpsc::channel();
+		thread::Builder::new().name("moq-capture".to_string()).spawn(move || {
+			if let Err(err) = Self::run(output, rx) {
+				// Stop capturing instead of failing the session.
+				tracing::warn!(?err, "failed to write capture");
+			}
+		})?;
+
+		Ok(Self {
+			records,
+			start: Instant::now(),
+			next: Default::default(),
+		})
+	}
+
+	/// Create (or truncate) a capture file at the given path.
+	pub fn create<P: AsRef<Path>>(path: P, limits: &DecodeLimits) -> io::Result<Self> {
+		let file = fs::File::create(path)?;
+		Self::new(io::BufWriter::new(file), limits)
+	}
+
+	// Write records until every handle is dropped, flushing shortly after each write.
+	fn run<W: io::Write>(mut output: W, records: mpsc::Receiver<Vec<u8>>) -> io::Result<()> {
+		let mut deadline: Option<Instant> = None;
+
+		loop {
+			let record = match deadline {
+				Some(deadline) => records.recv_timeout(deadline.saturating_duration_since(Instant::now())),
+				None => records.recv().map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+			};
+
+			match record {
+				Ok(record) => {
+					deadline.get_or_insert_with(|| Instant::now() + FLUSH_INTERVAL);
+					output.write_all(&record)?;
+				}
+				Err(mpsc::RecvTimeoutError::Timeout) => {
+					deadline = None;
+					output.flush()?;
+				}
+				Err(mpsc::RecvTimeoutError::Disconnected) => return output.flush(),
+			}
+		}
+	}
+
+	/// Allocate a new stream ID; the lowest bit is set for unidirectional streams.
+	pub(super) fn stream(&self, uni: bool) -> CaptureStream {
+		let id = self.next.fetch_add(1, atomic::Ordering::Relaxed);
+
+		CaptureStream {
+			capture: self.clone(),
+			id: (id << 1) | uni as u64,
+		}
+	}
+
+	fn record(&self, stream: u64, direction: CaptureDirection, data: &[u8]) {
+		let record = CaptureRecord {
+			stream,
+			direction,
+			timestamp: self.start.elapsed(),
+			data: bytes::Bytes::copy_from_slice(data),
+		};
+
+		let mut buf = Vec::with_capacity(data.len() + 16);
+		record.encode(&mut buf);
+
+		// The writer has stopped if this fails, which it already logged.
+		self.records.send(buf).ok();
+	}
+}
+
+/// A handle used by a single stream to record its bytes.
+#[derive(Clone)]
+pub(super) struct CaptureStream {
+	capture: Capture,
+	id: u64,
+}
+
+impl CaptureStream {
+	pub fn send(&self, data: &[u8]) {
+		self.capture.record(self.id, CaptureDirection::Send, data);
+	}
+
+	pub fn recv(&self, data: &[u8]) {
+		self.capture.record(self.id, CaptureDirection::Recv, data);
+	}
+}
+
+/// The limits used by the session, written after [CAPTURE_MAGIC] so messages are decoded the same way.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CaptureHeader {
+	/// See [DecodeLimits::max_string].
+	pub max_string: usize,
+
+	/// See [DecodeLimits::max_list].
+	pub max_list: usize,
+}
+
+impl CaptureHeader {
+	/// The limits used to decode the captured messages, without any limit on their size.
+	pub fn limits(&self) -> DecodeLimits {
+		DecodeLimits {
+			max_message: usize::MAX,
+			max_string: self.max_string,
+			max_list: self.max_list,
+			max_frame_size: u64::MAX,
+			..Default::default()
+		}
+	}
+}
+
+impl Default for CaptureHeader {
+	fn default() -> Self {
+		Self::from(&DecodeLimits::default())
+	}
+}
+
+impl From<&DecodeLimits> for CaptureHeader {
+	fn from(limits: &DecodeLimits) -> Self {
+		Self {
+			max_string: limits.max_string,
+			max_list: limits.max_list,
+		}
+	}
+}
+
+impl Encode for CaptureHeader {
+	fn encode<W: bytes::BufMut>(&self, w: &mut W) {
+		self.max_string.encode(w);
+		self.max_list.encode(w);
+	}
+}
+
+impl Decode for CaptureHeader {
+	fn decode_limited<R: bytes::Buf>(r: &mut R, limits: &DecodeLimits) -> Result<Self, DecodeError> {
+		Ok(Self {
+			max_string: usize::decode_limited(r, limits)?,
+			max_list: usize::decode_limited(r, limits)?,
+		})
+	}
+}
+
+/// Whether the captured bytes were sent or received by the local endpoint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptureDirection {
+	Send,
+	Recv,
+}
+
+/// A chunk of bytes sent or received on a stream.
+#[derive(Clone, Debug)]
+pub struct CaptureRecord {
+	/// A locally assigned stream ID; the lowest bit is set for unidirectional streams.
+	pub stream: u64,
+
+	/// Whether the bytes were sent or received.
+	pub direction: CaptureDirection,
+
+	/// The time since the capture started.
+	pub timestamp: Duration,
+
+	/// The raw bytes.
+	pub data: bytes::Bytes,
+}
+
+impl CaptureRecord {
+	/// Returns true if the record belongs to a unidirectional (data) stream.
+	pub fn is_uni(&self) -> bool {
+		self.stream & 1 == 1
+	}
+}
+
+impl Encode for CaptureRecord {
+	fn encode<W: bytes::BufMut>(&self, w: &mut W) {
+		self.stream.encode(w);
+		match self.direction {
+			CaptureDirection::Send => 0u8.encode(w),
+			CaptureDirection::Recv => 1u8.encode(w),
+		}
+		self.timestamp.encode(w);
+		self.data.len().encode(w);
+		w.put_slice(&self.data);
+	}
+}
+
+impl Decode for CaptureRecord {
+	fn decode_limited<R: bytes::Buf>(r: &mut R, limits: &DecodeLimits) -> Result<Self, DecodeError> {
+		let stream = u64::decode_limited(r, limits)?;
+		let direction = match u8::decode_limited(r, limits)? {
+			0 => CaptureDirection::Send,
+			1 => CaptureDirection::Recv,
+			_ => return Err(DecodeError::InvalidValue),
+		};
+		let timestamp = Duration::decode_limited(r, limits)?;
+
+		// Chunks can be much larger than a string, so only the message limit applies.
+		let size = usize::decode_limited(r, limits)?;
+		if size > limits.max_message {
+			return Err(DecodeError::MessageTooLarge);
+		}
+
+		if r.remaining() < size {
+			return Err(DecodeError::Short);
+		}
+
+		Ok(Self {
+			stream,
+			direction,
+			timestamp,
+			data: r.copy_to_bytes(size),
+		})
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[derive(Clone)]
+	struct Shared {
+		buf: Arc<std::sync::Mutex<Vec<u8>>>,
+		flushed: mpsc::Sender<()>,
+	}
+
+	impl io::Write for Shared {
+		fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+			self.buf.lock().unwrap().extend_from_slice(buf);
+			Ok(buf.len())
+		}
+
+		fn flush(&mut self) -> io::Result<()> {
+			self.flushed.send(()).ok();
+			Ok(())
+		}
+	}
+
+	#[test]
+	fn roundtrip() {
+		let (flushed, on_flush) = mpsc::channel();
+		let output = Shared {
+			buf: Default::default(),
+			flushed,
+		};
+
+		let limits = DecodeLimits {
+			max_string: 1024,
+			max_list: 8,
+			..Default::default()
+		};
+		let capture = Capture::new(output.clone(), &limits).unwrap();
+
+		let control = capture.stream(false);
+		let data = capture.stream(true);
+
+		// The output is flushed periodically while the session is running.
+		control.send(b"hello");
+		on_flush.recv_timeout(Duration::from_secs(5)).unwrap();
+
+		// And once every handle is dropped.
+		data.recv(b"world");
+		drop((capture, control, data));
+		on_flush.recv_timeout(Duration::from_secs(5)).unwrap();
+
+		let buf = output.buf.lock().unwrap().clone();
+		assert_eq!(&buf[..8], CAPTURE_MAGIC);
+
+		let mut buf = &buf[8..];
+		let header = CaptureHeader::decode(&mut buf).unwrap();
+		assert_eq!((header.max_string, header.max_list), (1024, 8));
+
+		let limits = header.limits();
+
+		let record = CaptureRecord::decode_limited(&mut buf, &limits).unwrap();
+		assert_eq!(record.stream, 0);
+		assert!(!record.is_uni());
+		assert_eq!(record.direction, CaptureDirection::Send);
+		assert_eq!(record.data.as_ref(), b"hello");
+
+		let record = CaptureRecord::decode_limited(&mut buf, &limits).unwrap();
+		assert_eq!(record.stream, 3);
+		assert!(record.is_uni());
+		assert_eq!(record.direction, CaptureDirection::Recv);
+		assert_eq!(record.data.as_ref(), b"world");
+
+		assert!(buf.is_empty());
+	}
+}
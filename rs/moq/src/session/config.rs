@@ -0,0 +1,32 @@
+use std::time::Duration;
+
+use crate::{coding::DecodeLimits, message};
+
+use super::Capture;
+
+/// Options used when establishing a [super::Session].
+#[derive(Clone)]
+pub struct SessionConfig {
+	/// Limits enforced on every stream received from the remote.
+	pub limits: DecodeLimits,
+
+	/// Record every stream to a capture file for offline debugging.
+	pub capture: Option<Capture>,
+
+	/// How often to ping the remote to measure the RTT, or None to only respond to pings.
+	pub ping_interval: Option<Duration>,
+
+	/// The versions offered by a client or accepted by a server, in preferred order.
+	pub versions: message::Versions,
+}
+
+impl Default for SessionConfig {
+	fn default() -> Self {
+		Self {
+			limits: DecodeLimits::default(),
+			capture: None,
+			ping_interval: Some(Duration::from_secs(1)),
+			versions: message::Version::SUPPORTED.into(),
+		}
+	}
+}
@@ -1,15 +1,24 @@
-use crate::{message, BroadcastConsumer, Error, OriginConsumer};
+use crate::{message, BroadcastConsumer, Error, OriginConsumer, PublishOptions};
 
+use std::time::Duration;
+
+use tokio::{sync::watch, time::Instant};
 use web_async::spawn;
 
+mod capture;
+mod config;
 mod publisher;
 mod reader;
+mod scheduler;
 mod stream;
 mod subscriber;
 mod writer;
 
+pub use capture::*;
+pub use config::*;
 use publisher::*;
 use reader::*;
+use scheduler::*;
 use stream::*;
 use subscriber::*;
 use writer::*;
@@ -23,26 +32,41 @@ pub struct Session {
 	webtransport: web_transport::Session,
 	publisher: Publisher,
 	subscriber: Subscriber,
+	rtt: watch::Receiver<Option<Duration>>,
 }
 
 impl Session {
-	fn new(mut session: web_transport::Session, stream: Stream) -> Self {
-		tracing::info!("session started");
+	fn new(
+		mut session: web_transport::Session,
+		stream: Stream,
+		config: SessionConfig,
+		version: message::Version,
+	) -> Self {
+		tracing::info!(?version, "session started");
+
+		let publisher = Publisher::new(session.clone(), config.clone(), version);
+		let subscriber = Subscriber::new(session.clone(), config.clone(), version);
+
+		// Older versions don't support session messages, so we can't ping.
+		let ping_interval = match version {
+			message::Version::LITE_00 => None,
+			_ => config.ping_interval,
+		};
 
-		let publisher = Publisher::new(session.clone());
-		let subscriber = Subscriber::new(session.clone());
+		let (rtt, rtt_rx) = watch::channel(None);
 
 		let this = Self {
 			webtransport: session.clone(),
 			publisher: publisher.clone(),
 			subscriber: subscriber.clone(),
+			rtt: rtt_rx,
 		};
 
 		spawn(async move {
 			let res = tokio::select! {
-				res = Self::run_session(stream) => res,
-				res = Self::run_bi(session.clone(), publisher) => res,
-				res = Self::run_uni(session.clone(), subscriber) => res,
+				res = Self::run_session(stream, ping_interval, rtt) => res,
+				res = Self::run_bi(session.clone(), publisher, &config) => res,
+				res = Self::run_uni(session.clone(), subscriber, &config) => res,
 			};
 
 			match res {
@@ -66,67 +90,144 @@ impl Session {
 
 	/// Perform the MoQ handshake as a client.
 	pub async fn connect<T: Into<web_transport::Session>>(session: T) -> Result<Self, Error> {
+		Self::connect_with(session, SessionConfig::default()).await
+	}
+
+	/// Perform the MoQ handshake as a client, using the provided limits and capture.
+	pub async fn connect_with<T: Into<web_transport::Session>>(
+		session: T,
+		config: SessionConfig,
+	) -> Result<Self, Error> {
 		let mut session = session.into();
-		let mut stream = Stream::open(&mut session, message::ControlType::Session).await?;
-		Self::connect_setup(&mut stream).await?;
-		Ok(Self::new(session, stream))
+		let mut stream = Stream::open(&mut session, message::ControlType::Session, &config).await?;
+		let version = Self::connect_setup(&mut stream, &config).await?;
+		Ok(Self::new(session, stream, config, version))
 	}
 
-	async fn connect_setup(setup: &mut Stream) -> Result<(), Error> {
+	async fn connect_setup(setup: &mut Stream, config: &SessionConfig) -> Result<message::Version, Error> {
 		let client = message::ClientSetup {
-			versions: [message::Version::CURRENT].into(),
+			versions: config.versions.clone(),
 			extensions: Default::default(),
 		};
 
 		setup.writer.encode(&client).await?;
 		let server: message::ServerSetup = setup.reader.decode().await?;
 
+		if !client.versions.contains(&server.version) {
+			return Err(Error::Version(client.versions, [server.version].into()));
+		}
+
 		tracing::debug!(version = ?server.version, "connected");
 
-		Ok(())
+		Ok(server.version)
 	}
 
 	/// Perform the MoQ handshake as a server
 	pub async fn accept<T: Into<web_transport::Session>>(session: T) -> Result<Self, Error> {
+		Self::accept_with(session, SessionConfig::default()).await
+	}
+
+	/// Perform the MoQ handshake as a server, using the provided limits and capture.
+	pub async fn accept_with<T: Into<web_transport::Session>>(
+		session: T,
+		config: SessionConfig,
+	) -> Result<Self, Error> {
 		let mut session = session.into();
-		let mut stream = Stream::accept(&mut session).await?;
+		let mut stream = Stream::accept(&mut session, &config).await?;
 		let kind = stream.reader.decode().await?;
 
 		if kind != message::ControlType::Session {
 			return Err(Error::UnexpectedStream(kind));
 		}
 
-		Self::accept_setup(&mut stream).await?;
-		Ok(Self::new(session, stream))
+		let version = Self::accept_setup(&mut stream, &config).await?;
+		Ok(Self::new(session, stream, config, version))
 	}
 
-	async fn accept_setup(control: &mut Stream) -> Result<(), Error> {
+	async fn accept_setup(control: &mut Stream, config: &SessionConfig) -> Result<message::Version, Error> {
 		let client: message::ClientSetup = control.reader.decode().await?;
 
-		if !client.versions.contains(&message::Version::CURRENT) {
-			return Err(Error::Version(client.versions, [message::Version::CURRENT].into()));
-		}
+		// Use the client's most preferred version that we support.
+		let version = client
+			.versions
+			.iter()
+			.find(|version| config.versions.contains(version))
+			.copied()
+			.ok_or_else(|| Error::Version(client.versions.clone(), config.versions.clone()))?;
 
 		let server = message::ServerSetup {
-			version: message::Version::CURRENT,
+			version,
 			extensions: Default::default(),
 		};
 
 		control.writer.encode(&server).await?;
 
-		tracing::debug!(version = ?server.version, "connected");
+		tracing::debug!(?version, "connected");
 
-		Ok(())
+		Ok(version)
 	}
 
-	async fn run_session(mut stream: Stream) -> Result<(), Error> {
-		while let Some(_info) = stream.reader.decode_maybe::<message::SubscribeOk>().await? {}
-		Err(Error::Cancel)
+	async fn run_session(
+		mut stream: Stream,
+		interval: Option<Duration>,
+		rtt: watch::Sender<Option<Duration>>,
+	) -> Result<(), Error> {
+		let mut interval = interval.map(tokio::time::interval);
+		let mut sequence = 0;
+
+		// Only one ping is outstanding at a time, so nothing accumulates if the remote never responds.
+		let mut pending: Option<(u64, Instant)> = None;
+
+		loop {
+			tokio::select! {
+				_ = async { interval.as_mut().unwrap().tick().await }, if interval.is_some() => {
+					if pending.is_some() {
+						tracing::trace!(sequence, "skipping ping, still waiting for pong");
+						continue;
+					}
+
+					stream.writer.encode(&message::SessionMessage::Ping { sequence }).await?;
+					pending = Some((sequence, Instant::now()));
+					sequence += 1;
+				}
+				msg = stream.reader.decode_maybe::<message::SessionMessage>() => match msg? {
+					Some(message::SessionMessage::Ping { sequence }) => {
+						stream.writer.encode(&message::SessionMessage::Pong { sequence }).await?;
+					}
+					Some(message::SessionMessage::Pong { sequence }) => {
+						let sent = match pending {
+							Some((ping, sent)) if ping == sequence => sent,
+							_ => {
+								tracing::debug!(sequence, "ignoring unexpected pong");
+								continue;
+							}
+						};
+						pending = None;
+
+						let sample = sent.elapsed();
+
+						// Smooth the RTT like TCP (RFC 6298) so a single slow response doesn't cause a spike.
+						rtt.send_modify(|rtt| {
+							*rtt = Some(match *rtt {
+								Some(rtt) => (rtt * 7 + sample) / 8,
+								None => sample,
+							})
+						});
+					}
+					Some(message::SessionMessage::Info(_)) => {}
+					None => return Err(Error::Cancel),
+				},
+			}
+		}
 	}
 
-	async fn run_uni(mut session: web_transport::Session, subscriber: Subscriber) -> Result<(), Error> {
+	async fn run_uni(
+		mut session: web_transport::Session,
+		subscriber: Subscriber,
+		config: &SessionConfig,
+	) -> Result<(), Error> {
 		loop {
-			let stream = Reader::accept(&mut session).await?;
+			let stream = Reader::accept(&mut session, config).await?;
 			let subscriber = subscriber.clone();
 
 			spawn(async move {
@@ -149,9 +250,13 @@ impl Session {
 		Ok(())
 	}
 
-	async fn run_bi(mut session: web_transport::Session, publisher: Publisher) -> Result<(), Error> {
+	async fn run_bi(
+		mut session: web_transport::Session,
+		publisher: Publisher,
+		config: &SessionConfig,
+	) -> Result<(), Error> {
 		loop {
-			let stream = Stream::accept(&mut session).await?;
+			let stream = Stream::accept(&mut session, config).await?;
 			let publisher = publisher.clone();
 
 			spawn(async move {
@@ -181,6 +286,18 @@ impl Session {
 		self.publisher.publish(path, broadcast);
 	}
 
+	/// Publish a broadcast with the provided options, such as metadata sent with the announcement.
+	///
+	/// Returns [Error::TooLarge] if the metadata is too large to announce.
+	pub fn publish_with<T: ToString>(
+		&mut self,
+		path: T,
+		broadcast: BroadcastConsumer,
+		options: PublishOptions,
+	) -> Result<(), Error> {
+		self.publisher.publish_with(path, broadcast, options)
+	}
+
 	/// Publish all broadcasts from the given origin with a prefix.
 	pub fn publish_prefix(&mut self, prefix: &str, broadcasts: OriginConsumer) {
 		self.publisher.publish_prefix(prefix, broadcasts);
@@ -191,6 +308,13 @@ impl Session {
 		self.publisher.publish_all(broadcasts);
 	}
 
+	/// Set the relative weight of a published broadcast, between 1 (default) and 16.
+	///
+	/// Tracks with the same priority are interleaved fairly, sending groups proportional to their broadcast's weight.
+	pub fn set_weight(&mut self, broadcast: &str, weight: u8) {
+		self.publisher.set_weight(broadcast, weight);
+	}
+
 	/// Consume a broadcast, returning a handle that can request tracks.
 	///
 	/// No tracks flow over the network until [BroadcastConsumer::subscribe] is called.
@@ -212,6 +336,20 @@ impl Session {
 		self.subscriber.consume_prefix(prefix)
 	}
 
+	/// The smoothed round-trip time of PING/PONG messages on the session stream, or None until one is measured.
+	///
+	/// Unlike the QUIC RTT, this includes any processing delay in the remote's MoQ implementation.
+	/// It only measures this hop: a relay answers pings itself, so the RTT to the origin through a relay chain is not included.
+	pub fn rtt(&self) -> Option<Duration> {
+		*self.rtt.borrow()
+	}
+
+	/// Block until the RTT is updated, returning the new value or None when the session is closed.
+	pub async fn rtt_changed(&mut self) -> Option<Duration> {
+		self.rtt.changed().await.ok()?;
+		self.rtt()
+	}
+
 	/// Close the underlying WebTransport session.
 	pub fn close(mut self, err: Error) {
 		self.webtransport.close(err.to_code(), &err.to_string());
@@ -1,33 +1,58 @@
-use std::collections::HashSet;
+use std::{collections::HashMap, time::Duration};
 
 use futures::{stream::FuturesUnordered, StreamExt};
 use web_async::FuturesExt;
 
 use crate::{
-	message, model::GroupConsumer, BroadcastConsumer, Error, OriginConsumer, OriginProducer, Track, TrackConsumer,
+	message, model::GroupConsumer, BroadcastConsumer, Error, Filter, OriginConsumer, OriginProducer, PublishOptions,
+	ResumePoint, Track, TrackConsumer,
 };
 
-use super::{Stream, Writer};
+use super::{Scheduler, SessionConfig, Stream, Writer};
 
 #[derive(Clone)]
 pub(super) struct Publisher {
 	session: web_transport::Session,
+	config: SessionConfig,
+
+	// The negotiated version, used to encode and decode messages.
+	version: message::Version,
 	broadcasts: OriginProducer,
+	scheduler: Scheduler,
 }
 
 impl Publisher {
-	pub fn new(session: web_transport::Session) -> Self {
+	pub fn new(session: web_transport::Session, config: SessionConfig, version: message::Version) -> Self {
 		Self {
 			session,
+			config,
+			version,
 			broadcasts: Default::default(),
+			scheduler: Default::default(),
 		}
 	}
 
+	/// Set the relative weight of a broadcast when sharing bandwidth with tracks of the same priority.
+	pub fn set_weight(&mut self, broadcast: &str, weight: u8) {
+		self.scheduler.set_weight(broadcast, weight);
+	}
+
 	/// Publish a broadcast.
 	pub fn publish<T: ToString>(&mut self, path: T, broadcast: BroadcastConsumer) {
 		self.broadcasts.publish(path, broadcast);
 	}
 
+	/// Publish a broadcast with the provided options.
+	pub fn publish_with<T: ToString>(
+		&mut self,
+		path: T,
+		broadcast: BroadcastConsumer,
+		options: PublishOptions,
+	) -> Result<(), Error> {
+		self.broadcasts.publish_with(path, broadcast, options)?;
+		Ok(())
+	}
+
 	/// Publish all broadcasts from the given origin with a prefix.
 	pub fn publish_prefix(&mut self, prefix: &str, broadcast: OriginConsumer) {
 		self.broadcasts.publish_prefix(prefix, broadcast);
@@ -63,7 +88,7 @@ impl Publisher {
 	async fn run_announce(&mut self, stream: &mut Stream, prefix: &str) -> Result<(), Error> {
 		let mut announced = self.broadcasts.consume_prefix(prefix);
 
-		let mut active = HashSet::new();
+		let mut active: HashMap<String, BroadcastConsumer> = HashMap::new();
 		let mut tasks = FuturesUnordered::new();
 
 		// Flush any synchronously announced paths
@@ -74,44 +99,59 @@ impl Publisher {
 				announced = announced.next() => {
 					match announced {
 						Some((suffix, broadcast)) => {
+							if active.get(&suffix).is_some_and(|active| active.is_clone(&broadcast)) {
+								continue;
+							}
+
+							// NOTE: A duplicate announcement means the broadcast was replaced.
 							tracing::debug!(?suffix, "announce");
 
-							let msg = message::Announce::Active { suffix: suffix.clone() };
-							stream.writer.encode(&msg).await?;
-							active.insert(suffix.clone());
+							let msg = message::Announce::Active {
+								suffix: suffix.clone(),
+								metadata: broadcast.metadata().cloned().unwrap_or_default(),
+							};
+							stream.writer.encode_version(&msg, self.version).await?;
+							active.insert(suffix.clone(), broadcast.clone());
 
 							// Wait until the broadcast is closed before unannouncing.
 							tasks.push(async move {
 								broadcast.closed().await;
-								suffix
+								(suffix, broadcast)
 							});
 						},
 						None => break,
 					}
 				}
-				Some(suffix) = tasks.next() => {
+				Some((suffix, broadcast)) = tasks.next() => {
+					// Skip broadcasts that were replaced.
+					if !active.get(&suffix).is_some_and(|active| active.is_clone(&broadcast)) {
+						continue;
+					}
+
 					active.remove(&suffix);
 					let msg = message::Announce::Ended { suffix };
-					stream.writer.encode(&msg).await?;
+					stream.writer.encode_version(&msg, self.version).await?;
 				}
 			}
 		}
 
 		// Clean up any remaining active broadcasts.
-		for suffix in active.drain() {
+		for (suffix, _) in active.drain() {
 			let msg = message::Announce::Ended { suffix };
-			stream.writer.encode(&msg).await?;
+			stream.writer.encode_version(&msg, self.version).await?;
 		}
 
 		stream.writer.finish().await
 	}
 
 	pub async fn recv_subscribe(&mut self, stream: &mut Stream) -> Result<(), Error> {
-		let mut subscribe = stream.reader.decode::<message::Subscribe>().await?;
+		let mut subscribe = stream.reader.decode_version::<message::Subscribe>(self.version).await?;
 
 		tracing::debug!(id = %subscribe.id, broadcast = %subscribe.broadcast, track = %subscribe.track, "subscribed started");
 
+		self.scheduler.insert(subscribe.id);
 		let res = self.run_subscribe(stream, &mut subscribe).await;
+		self.scheduler.remove(subscribe.id);
 
 		match res {
 			Err(Error::Cancel) | Err(Error::WebTransport(_)) => {
@@ -133,15 +173,28 @@ impl Publisher {
 		let track = Track {
 			name: subscribe.track.clone(),
 			priority: subscribe.priority,
+			filter: Filter {
+				first_frame: subscribe.first_frame,
+				nth_group: Some(subscribe.nth_group).filter(|nth| *nth > 0),
+				min_interval: Some(subscribe.min_interval).filter(|min| !min.is_zero()),
+			},
+			max_age: Some(subscribe.max_age).filter(|max| !max.is_zero()),
+			resume: subscribe.resume_group.map(|group| ResumePoint {
+				group,
+				frame: subscribe.resume_frame,
+			}),
 		};
 
+		// Watch for the broadcast being replaced so we can switch to the new one.
+		let announced = self.broadcasts.consume_prefix(&broadcast);
+
 		let broadcast = self.broadcasts.consume(&broadcast).ok_or(Error::NotFound)?;
-		let track = broadcast.subscribe(&track);
+		let track = ServedTrack::new(broadcast, &track, announced);
 
 		// TODO wait until track.info() to get the *real* priority
 
 		let info = message::SubscribeOk {
-			priority: track.info.priority,
+			priority: track.info().priority,
 		};
 
 		stream.writer.encode(&info).await?;
@@ -154,7 +207,7 @@ impl Publisher {
 		stream.writer.finish().await
 	}
 
-	async fn run_track(&mut self, mut track: TrackConsumer, subscribe: &mut message::Subscribe) -> Result<(), Error> {
+	async fn run_track(&mut self, mut track: ServedTrack, subscribe: &mut message::Subscribe) -> Result<(), Error> {
 		// Kind of hacky, but we only serve up to two groups concurrently.
 		// This avoids a race where we try to cancel the previous group at the same time as we FIN it.
 		// We don't want to allow N concurrent groups otherwise slow consumers will eat our RAM.
@@ -165,24 +218,48 @@ impl Publisher {
 		loop {
 			tokio::select! {
 				Some(group) = track.next_group().transpose() => {
-					let mut group = group?;
+					let (sequence, mut group) = group?;
 
 					let mut session = self.session.clone();
-					let priority = Self::stream_priority(subscribe.priority, group.info.sequence);
+					let config = self.config.clone();
+					let version = self.version;
+
+					// Interleave groups fairly with other tracks of the same priority.
+					let scheduled = self.scheduler.schedule(subscribe.id, &subscribe.broadcast);
+					let priority = Self::stream_priority(subscribe.priority, group.info.priority, scheduled.order);
 
+					// Skip any frames the subscriber already received before resuming.
+					let skip = match track.resume() {
+						Some(resume) if resume.group == sequence => resume.frame.saturating_sub(group.info.frame_offset),
+						_ => 0,
+					};
+					group.skip(skip as usize);
+
+					// Forward the group priority so relays can use it too.
 					let msg = message::Group {
 						subscribe: subscribe.id,
-						sequence: group.info.sequence,
+						sequence,
+						priority: group.info.priority,
+						frame_offset: group.info.frame_offset + skip,
 					};
 
-					let track = track.info.clone();
+					let track = track.info().clone();
+
+					// Use the smaller of the subscriber's and publisher's max age.
+					let max_age = Some(subscribe.max_age).filter(|max| !max.is_zero()).into_iter().chain(track.max_age).min();
+					let expires = max_age.map(|max_age| max_age.saturating_sub(group.age()));
 
 					let future = Some(Box::pin(async move {
+						if expires.is_some_and(|expires| expires.is_zero()) {
+							tracing::trace!(track = %track.name, group = %group.info.sequence, "skipping old group");
+							return Ok(());
+						}
+
 						// TODO open streams in priority order to help with MAX_STREAMS flow control issues.
 
 						let mut stream = tokio::select! {
 							biased;
-							res = Writer::open(&mut session, message::DataType::Group) => res?,
+							res = Writer::open(&mut session, message::DataType::Group, &config) => res?,
 							// Add a timeout to detect when we're blocked by flow control.
 							_ = tokio::time::sleep(tokio::time::Duration::from_secs(1)) => {
 								return Err(Error::Timeout);
@@ -193,7 +270,11 @@ impl Publisher {
 
 						tracing::trace!(track = %track.name, group = %group.info.sequence, "serving group");
 
-						let res = Self::serve_group(&mut stream, msg, &mut group).await;
+						let res = tokio::select! {
+							res = Self::serve_group(&mut stream, msg, version, &mut group) => res,
+							// Stop serving the group once it's too old.
+							_ = Self::expired(expires) => Err(Error::Old),
+						};
 
 						match res {
 							Err(Error::Cancel) | Err(Error::WebTransport(_)) => {
@@ -206,6 +287,7 @@ impl Publisher {
 							}
 							Ok(size) => {
 								tracing::trace!(track = %track.name, group = %group.info.sequence, size, "serving group complete");
+								scheduled.complete();
 							}
 						}
 
@@ -234,12 +316,21 @@ impl Publisher {
 		Ok(())
 	}
 
+	// Resolves once the group is too old to serve, or never if it doesn't expire.
+	async fn expired(expires: Option<Duration>) {
+		match expires {
+			Some(expires) => tokio::time::sleep(expires).await,
+			None => std::future::pending().await,
+		}
+	}
+
 	pub async fn serve_group(
 		stream: &mut Writer,
 		msg: message::Group,
+		version: message::Version,
 		group: &mut GroupConsumer,
 	) -> Result<usize, Error> {
-		stream.encode(&msg).await?;
+		stream.encode_version(&msg, version).await?;
 
 		let mut size = 0;
 
@@ -279,14 +370,108 @@ impl Publisher {
 		Ok(size)
 	}
 
-	// Quinn takes a i32 priority.
-	// We do our best to distill 70 bits of information into 32 bits, but overflows will happen.
-	// Specifically, group sequence 2^24 will overflow and be incorrectly prioritized.
-	// But even with a group per frame, it will take ~6 days to reach that point.
-	// TODO The behavior when two tracks share the same priority is undefined. Should we round-robin?
-	fn stream_priority(track_priority: u8, group_sequence: u64) -> i32 {
-		let sequence = (0xFFFFFF - group_sequence as u32) & 0xFFFFFF;
-		((track_priority as i32) << 24) | sequence as i32
+	// Quinn takes a i32 priority, but we avoid the sign bit so a higher priority is always a larger value.
+	// We do our best to distill 80 bits of information into 31 bits, but overflows will happen.
+	// Specifically, a group order of 2^23 will overflow and be incorrectly prioritized.
+	// The order is from the scheduler and advances by up to 16 per group, so this takes ~5 hours at 30 groups/s.
+	// However, the scheduler rebases the order whenever the session is idle and periodically when constantly congested.
+	// The group priority is a boost added to the track priority, saturating at the maximum.
+	// Tracks that share the same priority are interleaved fairly by the scheduler.
+	fn stream_priority(track_priority: u8, group_priority: u8, group_order: u64) -> i32 {
+		let priority = track_priority.saturating_add(group_priority);
+		let order = (0x7FFFFF - group_order as u32) & 0x7FFFFF;
+		((priority as i32) << 23) | order as i32
+	}
+}
+
+/// A track being served to a subscriber, switching tracks if the broadcast is replaced.
+struct ServedTrack {
+	track: TrackConsumer,
+	broadcast: BroadcastConsumer,
+
+	// The requested track, including any filter.
+	requested: Track,
+
+	// Used to detect when the broadcast at the same path is replaced.
+	announced: OriginConsumer,
+
+	// Added to each group sequence so they keep increasing after switching broadcasts.
+	offset: u64,
+
+	// The sequence number following the latest group served.
+	next: u64,
+
+	// Set when we switched tracks and need to compute a new offset.
+	switched: bool,
+}
+
+impl ServedTrack {
+	fn new(broadcast: BroadcastConsumer, track: &Track, announced: OriginConsumer) -> Self {
+		Self {
+			track: broadcast.subscribe(track),
+			broadcast,
+			requested: track.clone(),
+			announced,
+			offset: 0,
+			next: 0,
+			switched: false,
+		}
+	}
+
+	fn info(&self) -> &Track {
+		&self.track.info
+	}
+
+	fn resume(&self) -> Option<ResumePoint> {
+		self.requested.resume
+	}
+
+	/// Return the next group and the sequence number to use on the wire.
+	async fn next_group(&mut self) -> Result<Option<(u64, GroupConsumer)>, Error> {
+		loop {
+			tokio::select! {
+				biased;
+				Some((suffix, broadcast)) = self.announced.next() => {
+					// Ignore any broadcasts that merely share the prefix.
+					if !suffix.is_empty() || broadcast.is_clone(&self.broadcast) {
+						continue;
+					}
+
+					tracing::debug!(track = %self.track.info.name, "broadcast replaced, switching tracks");
+
+					// Keep the existing groups, but the next group will come from the new broadcast.
+					// The resume point refers to the old broadcast's groups, so it's not used.
+					let track = Track {
+						resume: None,
+						..self.requested.clone()
+					};
+					self.track = broadcast.subscribe(&track);
+					self.broadcast = broadcast;
+					self.switched = true;
+				}
+				group = self.track.next_group() => {
+					let group = match group? {
+						Some(group) => group,
+						None => return Ok(None),
+					};
+
+					if self.switched {
+						self.offset = self.next.wrapping_sub(group.info.sequence);
+						self.switched = false;
+					}
+
+					let sequence = group.info.sequence.wrapping_add(self.offset);
+					self.next = sequence + 1;
+
+					// The subscriber already received every group before the resume point.
+					if self.resume().is_some_and(|resume| sequence < resume.group) {
+						continue;
+					}
+
+					return Ok(Some((sequence, group)));
+				}
+			}
+		}
 	}
 }
 
@@ -296,17 +481,29 @@ mod test {
 
 	#[test]
 	fn stream_priority() {
-		let assert = |track_priority, group_sequence, expected| {
-			assert_eq!(Publisher::stream_priority(track_priority, group_sequence), expected);
+		let assert = |track_priority, group_priority, group_order, expected| {
+			assert_eq!(
+				Publisher::stream_priority(track_priority, group_priority, group_order),
+				expected
+			);
 		};
 
-		const U24: i32 = (1 << 24) - 1;
+		const U23: i32 = (1 << 23) - 1;
 
 		// NOTE: The lower the value, the higher the priority for Quinn.
 		// MoQ does the opposite, so we invert the values.
-		assert(0, 50, U24 - 50);
-		assert(0, 0, U24);
-		assert(1, 50, 2 * U24 - 49);
-		assert(1, 0, 2 * U24 + 1);
+		assert(0, 0, 50, U23 - 50);
+		assert(0, 0, 0, U23);
+		assert(1, 0, 50, 2 * U23 - 49);
+		assert(1, 0, 0, 2 * U23 + 1);
+
+		// The group priority is added to the track priority.
+		assert(0, 1, 50, 2 * U23 - 49);
+		assert(1, 1, 0, (2 << 23) | U23);
+		assert(255, 1, 0, (255 << 23) | U23);
+
+		// The sign bit is never set, so the highest priorities still sort above the lowest.
+		assert!(Publisher::stream_priority(128, 0, 0) > Publisher::stream_priority(127, 0, 0));
+		assert!(Publisher::stream_priority(255, 255, 0) > Publisher::stream_priority(0, 0, 0));
 	}
 }
@@ -2,32 +2,58 @@ use std::{cmp, fmt, io};
 
 use bytes::{Buf, Bytes, BytesMut};
 
-use crate::{coding::*, Error};
+use super::{CaptureStream, SessionConfig};
+use crate::{coding::*, message, Error};
 
 pub struct Reader {
 	stream: web_transport::RecvStream,
 	buffer: BytesMut,
+	limits: DecodeLimits,
+	capture: Option<CaptureStream>,
 }
 
 impl Reader {
-	pub fn new(stream: web_transport::RecvStream) -> Self {
+	pub fn new(stream: web_transport::RecvStream, limits: DecodeLimits, capture: Option<CaptureStream>) -> Self {
 		Self {
 			stream,
 			buffer: Default::default(),
+			limits,
+			capture,
 		}
 	}
 
-	pub async fn accept(session: &mut web_transport::Session) -> Result<Self, Error> {
+	pub async fn accept(session: &mut web_transport::Session, config: &SessionConfig) -> Result<Self, Error> {
 		let stream = session.accept_uni().await?;
-		Ok(Self::new(stream))
+		let capture = config.capture.as_ref().map(|capture| capture.stream(true));
+		Ok(Self::new(stream, config.limits, capture))
+	}
+
+	pub fn limits(&self) -> &DecodeLimits {
+		&self.limits
 	}
 
 	pub async fn decode<T: Decode + fmt::Debug>(&mut self) -> Result<T, Error> {
+		self.decode_with(|r, limits| T::decode_limited(r, limits)).await
+	}
+
+	/// Decode a message using the layout of the negotiated version.
+	pub async fn decode_version<T: message::Versioned + fmt::Debug>(
+		&mut self,
+		version: message::Version,
+	) -> Result<T, Error> {
+		self.decode_with(|r, limits| T::decode_version(r, limits, version))
+			.await
+	}
+
+	async fn decode_with<T, F>(&mut self, decode: F) -> Result<T, Error>
+	where
+		F: Fn(&mut io::Cursor<&BytesMut>, &DecodeLimits) -> Result<T, DecodeError>,
+	{
 		loop {
 			let mut cursor = io::Cursor::new(&self.buffer);
 
 			// Try to decode with the current buffer.
-			match T::decode(&mut cursor) {
+			match decode(&mut cursor, &self.limits) {
 				Ok(msg) => {
 					self.buffer.advance(cursor.position() as usize);
 					return Ok(msg);
@@ -36,11 +62,16 @@ impl Reader {
 				Err(err) => return Err(err.into()),
 			};
 
+			// Don't let the remote make us buffer an unbounded partial message.
+			if self.buffer.len() >= self.limits.max_message {
+				return Err(DecodeError::MessageTooLarge.into());
+			}
+
 			if !self.buffer.is_empty() {
 				tracing::trace!(buffer = ?self.buffer, "more data needed");
 			}
 
-			if self.stream.read_buf(&mut self.buffer).await?.is_none() {
+			if self.read_buf().await?.is_none() {
 				return Err(DecodeError::Short.into());
 			}
 		}
@@ -55,6 +86,18 @@ impl Reader {
 		}
 	}
 
+	// Decode optional messages at the end of a stream, using the layout of the negotiated version.
+	pub async fn decode_maybe_version<T: message::Versioned + fmt::Debug>(
+		&mut self,
+		version: message::Version,
+	) -> Result<Option<T>, Error> {
+		match self.finished().await {
+			Ok(()) => Ok(None),
+			Err(Error::Decode(DecodeError::ExpectedEnd)) => Ok(Some(self.decode_version(version).await?)),
+			Err(e) => Err(e),
+		}
+	}
+
 	// Returns a non-zero chunk of data, or None if the stream is closed
 	pub async fn read(&mut self, max: usize) -> Result<Option<Bytes>, Error> {
 		if !self.buffer.is_empty() {
@@ -63,12 +106,29 @@ impl Reader {
 			return Ok(Some(data));
 		}
 
-		Ok(self.stream.read(max).await?)
+		let chunk = self.stream.read(max).await?;
+		if let (Some(capture), Some(chunk)) = (&self.capture, &chunk) {
+			capture.recv(chunk);
+		}
+
+		Ok(chunk)
+	}
+
+	// Append more data to the buffer, recording it if capturing.
+	async fn read_buf(&mut self) -> Result<Option<usize>, Error> {
+		let offset = self.buffer.len();
+		let size = self.stream.read_buf(&mut self.buffer).await?;
+
+		if let Some(capture) = &self.capture {
+			capture.recv(&self.buffer[offset..]);
+		}
+
+		Ok(size)
 	}
 
 	/// Wait until the stream is closed, erroring if there are any additional bytes.
 	pub async fn finished(&mut self) -> Result<(), Error> {
-		if self.buffer.is_empty() && self.stream.read_buf(&mut self.buffer).await?.is_none() {
+		if self.buffer.is_empty() && self.read_buf().await?.is_none() {
 			return Ok(());
 		}
 
@@ -0,0 +1,281 @@
+use std::collections::{BTreeMap, HashMap};
+
+use web_async::Lock;
+
+/// Orders groups across tracks so those with the same priority are interleaved fairly.
+///
+/// Each group is assigned a virtual finish time (start-time fair queuing), which is used instead of the group sequence.
+/// Otherwise a track with a lower sequence number (ex. started later) would always be sent first and starve the others.
+#[derive(Clone, Default)]
+pub(super) struct Scheduler {
+	state: Lock<SchedulerState>,
+}
+
+#[derive(Default)]
+struct SchedulerState {
+	// The finish time of the latest group delivered for each subscription.
+	// Groups that are dropped (ex. too slow) don't count against the subscription.
+	tracks: HashMap<u64, u64>,
+
+	// The start time of each group currently being sent, used to compute the virtual time.
+	active: BTreeMap<u64, usize>,
+
+	// The latest finish time, used as the virtual time when idle.
+	latest: u64,
+
+	// Subtracted from each order so it stays small enough for the stream priority.
+	base: u64,
+
+	// An optional weight for each broadcast, defaulting to 1.
+	weights: HashMap<String, u8>,
+}
+
+impl SchedulerState {
+	// The virtual time is the start of the oldest group being sent.
+	// This way new or idle tracks don't accumulate credit.
+	fn now(&self) -> u64 {
+		self.active.keys().next().copied().unwrap_or(self.latest)
+	}
+}
+
+impl Scheduler {
+	// The virtual duration of a group with a weight of 1.
+	// Higher values allow more precise weights but overflow sooner.
+	const SCALE: u64 = 16;
+
+	// How far the virtual time can advance before the order is rebased.
+	// This must stay well below 2^23, the number of orders that fit in the stream priority.
+	const REBASE: u64 = 1 << 20;
+
+	/// Set the relative weight of a broadcast, between 1 and 16.
+	///
+	/// Tracks with the same priority will be allocated groups proportional to their weight.
+	pub fn set_weight(&self, broadcast: &str, weight: u8) {
+		self.state.lock().weights.insert(broadcast.to_string(), weight);
+	}
+
+	/// Schedule the next group for the given subscription, which is active until dropped.
+	pub fn schedule(&self, id: u64, broadcast: &str) -> Scheduled {
+		let mut state = self.state.lock();
+
+		let weight = state.weights.get(broadcast).copied().unwrap_or(1).max(1) as u64;
+
+		let now = state.now();
+		let start = state.tracks.get(&id).copied().unwrap_or_default().max(now);
+		let finish = start + (Self::SCALE / weight).max(1);
+
+		// Rebase the order when idle, or periodically when constantly congested.
+		// Groups already being sent keep their stream priority, so they're briefly behind newer groups.
+		if state.active.is_empty() || now.saturating_sub(state.base) >= Self::REBASE {
+			state.base = now;
+		}
+
+		*state.active.entry(start).or_default() += 1;
+
+		Scheduled {
+			state: self.state.clone(),
+			id,
+			start,
+			finish,
+			order: finish - state.base,
+		}
+	}
+
+	/// Start scheduling groups for the given subscription.
+	pub fn insert(&self, id: u64) {
+		let mut state = self.state.lock();
+		let now = state.now();
+		state.tracks.entry(id).or_insert(now);
+	}
+
+	/// Stop scheduling groups for the given subscription.
+	pub fn remove(&self, id: u64) {
+		self.state.lock().tracks.remove(&id);
+	}
+}
+
+/// A group that is being sent, removed from the scheduler when dropped.
+pub(super) struct Scheduled {
+	state: Lock<SchedulerState>,
+	id: u64,
+	start: u64,
+	finish: u64,
+
+	/// The order of the group relative to other groups; lower is sent first.
+	pub order: u64,
+}
+
+impl Scheduled {
+	/// Mark the group as delivered, counting it against the subscription.
+	pub fn complete(self) {
+		let mut state = self.state.lock();
+
+		// Charge the subscription for the group, even if it was scheduled before a previous group was delivered.
+		let cost = self.finish - self.start;
+		let finish = match state.tracks.get_mut(&self.id) {
+			Some(finish) => {
+				*finish = (*finish).max(self.start) + cost;
+				*finish
+			}
+			// The subscription is no longer active.
+			None => self.finish,
+		};
+
+		state.latest = state.latest.max(finish);
+	}
+}
+
+impl Drop for Scheduled {
+	fn drop(&mut self) {
+		let mut state = self.state.lock();
+		if let Some(count) = state.active.get_mut(&self.start) {
+			*count -= 1;
+			if *count == 0 {
+				state.active.remove(&self.start);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::collections::VecDeque;
+
+	use super::*;
+
+	// Simulate a congested session, returning the number of groups sent for each subscription.
+	//
+	// Every tick, each subscription queues `rate` groups and the session can only send `capacity` of them.
+	// Like the publisher, each subscription only keeps the two newest groups; older ones are dropped.
+	// QUIC round-robins streams with the same priority, so we break ties the same way.
+	// The result contains the running count after each tick so we can check the unfairness at any point.
+	fn simulate(
+		scheduler: &Scheduler,
+		tracks: &[(u64, &str, usize)],
+		capacity: usize,
+		ticks: usize,
+	) -> Vec<Vec<usize>> {
+		for (id, _, _) in tracks {
+			scheduler.insert(*id);
+		}
+
+		let mut queued: Vec<VecDeque<Scheduled>> = tracks.iter().map(|_| VecDeque::new()).collect();
+		let mut sent = vec![0; tracks.len()];
+		let mut history = Vec::new();
+		let mut round_robin = 0;
+
+		for _ in 0..ticks {
+			for (index, (id, broadcast, rate)) in tracks.iter().enumerate() {
+				for _ in 0..*rate {
+					let scheduled = scheduler.schedule(*id, broadcast);
+					assert!(scheduled.order < 1 << 23, "order overflow: {}", scheduled.order);
+					queued[index].push_back(scheduled);
+					if queued[index].len() > 2 {
+						queued[index].pop_front();
+					}
+				}
+			}
+
+			for _ in 0..capacity {
+				// Send the group with the lowest order, breaking ties via round-robin.
+				let next = queued
+					.iter()
+					.enumerate()
+					.filter_map(|(index, queue)| {
+						let order = queue.iter().map(|group| group.order).min()?;
+						let turn = (index + tracks.len() - round_robin) % tracks.len();
+						Some((order, turn, index))
+					})
+					.min();
+
+				if let Some((order, _, index)) = next {
+					let position = queued[index].iter().position(|group| group.order == order).unwrap();
+					queued[index].remove(position).unwrap().complete();
+
+					sent[index] += 1;
+					round_robin = (index + 1) % tracks.len();
+				}
+			}
+
+			history.push(sent.clone());
+		}
+
+		history
+	}
+
+	// Returns the largest difference in weighted groups sent between the first two tracks at any point.
+	fn unfairness(history: &[Vec<usize>], weight: usize) -> usize {
+		history
+			.iter()
+			.map(|sent| sent[0].abs_diff(sent[1] * weight))
+			.max()
+			.unwrap_or_default()
+	}
+
+	#[test]
+	fn equal() {
+		let scheduler = Scheduler::default();
+
+		let history = simulate(&scheduler, &[(1, "a", 1), (2, "b", 1)], 1, 1000);
+		assert!(unfairness(&history, 1) <= 1);
+
+		// Make sure both tracks actually got to send groups.
+		assert_eq!(history.last().unwrap(), &vec![500, 500]);
+	}
+
+	#[test]
+	fn many() {
+		let scheduler = Scheduler::default();
+
+		let tracks: Vec<_> = (0..10).map(|id| (id, "room", 1)).collect();
+		let history = simulate(&scheduler, &tracks, 3, 1000);
+
+		for sent in history {
+			let min = sent.iter().min().unwrap();
+			let max = sent.iter().max().unwrap();
+			assert!(max - min <= 1, "unfair: {sent:?}");
+		}
+	}
+
+	#[test]
+	fn weighted() {
+		let scheduler = Scheduler::default();
+		scheduler.set_weight("a", 2);
+
+		let history = simulate(&scheduler, &[(1, "a", 1), (2, "b", 1)], 1, 900);
+		assert!(unfairness(&history, 2) <= 2);
+	}
+
+	#[test]
+	fn greedy() {
+		let scheduler = Scheduler::default();
+
+		// The first track produces twice as many groups but shouldn't get more bandwidth.
+		let history = simulate(&scheduler, &[(1, "a", 2), (2, "b", 1)], 1, 1000);
+		assert!(unfairness(&history, 1) <= 1);
+	}
+
+	#[test]
+	fn late() {
+		let scheduler = Scheduler::default();
+
+		// The first track has been running for a while.
+		simulate(&scheduler, &[(1, "a", 1)], 1, 1000);
+
+		// The second track joins late but shouldn't get a huge amount of credit.
+		let history = simulate(&scheduler, &[(1, "a", 1), (2, "b", 1)], 1, 1000);
+		assert!(unfairness(&history, 1) <= 1);
+	}
+
+	#[test]
+	fn rebase() {
+		let scheduler = Scheduler::default();
+
+		// Constantly congested for long enough that the virtual time passes the rebase point several times.
+		let ticks = 4 * Scheduler::REBASE as usize / Scheduler::SCALE as usize;
+		let history = simulate(&scheduler, &[(1, "a", 1), (2, "b", 1)], 1, ticks);
+		assert!(unfairness(&history, 1) <= 1);
+
+		assert!(scheduler.state.lock().base > 0);
+	}
+}
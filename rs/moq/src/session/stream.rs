@@ -1,4 +1,4 @@
-use super::{Reader, Writer};
+use super::{Reader, SessionConfig, Writer};
 use crate::{message, Error};
 
 pub(super) struct Stream {
@@ -7,21 +7,27 @@ pub(super) struct Stream {
 }
 
 impl Stream {
-	pub async fn open(session: &mut web_transport::Session, typ: message::ControlType) -> Result<Self, Error> {
+	pub async fn open(
+		session: &mut web_transport::Session,
+		typ: message::ControlType,
+		config: &SessionConfig,
+	) -> Result<Self, Error> {
 		let (send, recv) = session.open_bi().await?;
 
-		let mut writer = Writer::new(send);
-		let reader = Reader::new(recv);
+		let capture = config.capture.as_ref().map(|capture| capture.stream(false));
+		let mut writer = Writer::new(send, capture.clone());
+		let reader = Reader::new(recv, config.limits, capture);
 		writer.encode(&typ).await?;
 
 		Ok(Stream { writer, reader })
 	}
 
-	pub async fn accept(session: &mut web_transport::Session) -> Result<Self, Error> {
+	pub async fn accept(session: &mut web_transport::Session, config: &SessionConfig) -> Result<Self, Error> {
 		let (send, recv) = session.accept_bi().await?;
 
-		let writer = Writer::new(send);
-		let reader = Reader::new(recv);
+		let capture = config.capture.as_ref().map(|capture| capture.stream(false));
+		let writer = Writer::new(send, capture.clone());
+		let reader = Reader::new(recv, config.limits, capture);
 
 		Ok(Stream { writer, reader })
 	}
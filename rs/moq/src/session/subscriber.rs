@@ -1,9 +1,11 @@
 use std::{
 	collections::HashMap,
 	sync::{atomic, Arc},
+	time::Duration,
 };
 
 use crate::{
+	coding::DecodeError,
 	message,
 	model::{BroadcastConsumer, BroadcastProducer},
 	Error, Frame, FrameProducer, Group, GroupProducer, OriginProducer, TrackProducer,
@@ -11,11 +13,18 @@ use crate::{
 
 use web_async::{spawn, Lock};
 
-use super::{OriginConsumer, Reader, Stream};
+use super::{OriginConsumer, Reader, SessionConfig, Stream};
+
+// How long to retain groups for a resumed subscription.
+const RESUME_HISTORY: Duration = Duration::from_secs(30);
 
 #[derive(Clone)]
 pub(super) struct Subscriber {
 	session: web_transport::Session,
+	config: SessionConfig,
+
+	// The negotiated version, used to encode and decode messages.
+	version: message::Version,
 
 	broadcasts: Lock<HashMap<String, BroadcastProducer>>,
 	subscribes: Lock<HashMap<u64, TrackProducer>>,
@@ -23,9 +32,11 @@ pub(super) struct Subscriber {
 }
 
 impl Subscriber {
-	pub fn new(session: web_transport::Session) -> Self {
+	pub fn new(session: web_transport::Session, config: SessionConfig, version: message::Version) -> Self {
 		Self {
 			session,
+			config,
+			version,
 
 			broadcasts: Default::default(),
 			subscribes: Default::default(),
@@ -65,7 +76,7 @@ impl Subscriber {
 	}
 
 	async fn run_broadcasts(&mut self, prefix: &str, mut announced: OriginProducer) -> Result<(), Error> {
-		let mut stream = Stream::open(&mut self.session, message::ControlType::Announce).await?;
+		let mut stream = Stream::open(&mut self.session, message::ControlType::Announce, &self.config).await?;
 
 		let msg = message::AnnounceRequest {
 			prefix: prefix.to_string(),
@@ -74,20 +85,30 @@ impl Subscriber {
 
 		let mut producers = HashMap::new();
 
-		while let Some(announce) = stream.reader.decode_maybe::<message::Announce>().await? {
+		while let Some(announce) = stream
+			.reader
+			.decode_maybe_version::<message::Announce>(self.version)
+			.await?
+		{
 			match announce {
-				message::Announce::Active { suffix } => {
-					tracing::debug!(%suffix, "received announce");
+				message::Announce::Active { suffix, metadata } => {
+					tracing::debug!(%suffix, metadata = metadata.len(), "received announce");
 
 					let producer = BroadcastProducer::new();
-					let consumer = producer.consume();
-
-					// Run the broadcast in the background until all consumers are dropped.
-					if !announced.publish(suffix.clone(), consumer) {
-						return Err(Error::Duplicate);
+					let consumer = match metadata.is_empty() {
+						true => producer.consume(),
+						false => producer.consume().with_metadata(metadata),
+					};
+
+					// A duplicate announcement means the broadcast was replaced by the publisher.
+					// Any existing subscriptions are switched over by the publisher, so we just close the old broadcast.
+					if let Some(mut previous) = producers.insert(suffix.clone(), producer.clone()) {
+						tracing::debug!(%suffix, "received replacement");
+						previous.finish();
 					}
 
-					producers.insert(suffix.clone(), producer.clone());
+					// Run the broadcast in the background until all consumers are dropped.
+					announced.replace(suffix.clone(), consumer);
 
 					spawn(self.clone().run_broadcast(suffix, producer));
 				}
@@ -151,7 +172,12 @@ impl Subscriber {
 		self.broadcasts.lock().remove(&path);
 	}
 
-	async fn run_subscribe(&mut self, id: u64, broadcast: String, track: TrackProducer) {
+	async fn run_subscribe(&mut self, id: u64, broadcast: String, mut track: TrackProducer) {
+		// A resumed subscription returns every group in order, so keep them until the consumer catches up.
+		if track.info.resume.is_some() {
+			track.set_history(RESUME_HISTORY);
+		}
+
 		self.subscribes.lock().insert(id, track.clone());
 
 		let msg = message::Subscribe {
@@ -159,8 +185,20 @@ impl Subscriber {
 			broadcast: broadcast.clone(),
 			track: track.info.name.clone(),
 			priority: track.info.priority,
+			first_frame: track.info.filter.first_frame,
+			nth_group: track.info.filter.nth_group.unwrap_or(0),
+			min_interval: track.info.filter.min_interval.unwrap_or_default(),
+			max_age: track.info.max_age.unwrap_or_default(),
+			resume_group: track.info.resume.map(|resume| resume.group),
+			resume_frame: track.info.resume.map(|resume| resume.frame).unwrap_or(0),
 		};
 
+		// The original version can't send filters, so we apply them to received groups instead; see recv_group.
+		// Only the publisher can skip to the resume point or space out groups.
+		if self.version == message::Version::LITE_00 && (msg.resume_group.is_some() || !msg.min_interval.is_zero()) {
+			tracing::warn!(%broadcast, track = %track.info.name, version = ?self.version, "resume and min interval not supported by the remote");
+		}
+
 		tracing::debug!(%broadcast, track = %track.info.name, id, "subscribe started");
 
 		let res = tokio::select! {
@@ -185,7 +223,7 @@ impl Subscriber {
 	}
 
 	async fn run_track(&mut self, msg: message::Subscribe) -> Result<(), Error> {
-		let mut stream = Stream::open(&mut self.session, message::ControlType::Subscribe).await?;
+		let mut stream = Stream::open(&mut self.session, message::ControlType::Subscribe, &self.config).await?;
 
 		if let Err(err) = self.run_track_stream(&mut stream, msg).await {
 			stream.writer.abort(&err);
@@ -196,7 +234,7 @@ impl Subscriber {
 	}
 
 	async fn run_track_stream(&mut self, stream: &mut Stream, msg: message::Subscribe) -> Result<(), Error> {
-		stream.writer.encode(&msg).await?;
+		stream.writer.encode_version(&msg, self.version).await?;
 
 		// TODO use the response correctly populate the track info
 		let _info: message::SubscribeOk = stream.reader.decode().await?;
@@ -208,23 +246,36 @@ impl Subscriber {
 	}
 
 	pub async fn recv_group(&mut self, stream: &mut Reader) -> Result<(), Error> {
-		let group: message::Group = stream.decode().await?;
+		let group: message::Group = stream.decode_version(self.version).await?;
 
 		tracing::trace!(group = %group.sequence, "received group");
 
-		let group = {
+		let (group, frames) = {
 			let mut subs = self.subscribes.lock();
 			let track = subs.get_mut(&group.subscribe).ok_or(Error::Cancel)?;
 
+			// The original version doesn't send the filter, so skip any groups and frames ourselves.
+			let filter = match self.version {
+				message::Version::LITE_00 => track.info.filter.clone(),
+				_ => Default::default(),
+			};
+
+			if !filter.group(group.sequence, None) {
+				tracing::trace!(group = %group.sequence, "filtered group");
+				return Ok(());
+			}
+
 			let group = Group {
 				sequence: group.sequence,
+				priority: group.priority,
+				frame_offset: group.frame_offset,
 			};
-			track.create_group(group).ok_or(Error::Old)?
+			(track.create_group(group).ok_or(Error::Old)?, filter.frames())
 		};
 
 		let res = tokio::select! {
 			_ = group.unused() => Err(Error::Cancel),
-			res = self.run_group(stream, group.clone()) => res,
+			res = self.run_group(stream, group.clone(), frames) => res,
 		};
 
 		match res {
@@ -245,9 +296,27 @@ impl Subscriber {
 		Ok(())
 	}
 
-	async fn run_group(&mut self, stream: &mut Reader, mut group: GroupProducer) -> Result<(), Error> {
+	async fn run_group(
+		&mut self,
+		stream: &mut Reader,
+		mut group: GroupProducer,
+		frames: Option<usize>,
+	) -> Result<(), Error> {
+		let mut count = 0;
+
 		while let Some(frame) = stream.decode_maybe::<message::Frame>().await? {
-			let frame = group.create_frame(Frame { size: frame.size });
+			// Ignore the rest of the group once we've received enough frames.
+			if frames.is_some_and(|frames| count >= frames) {
+				break;
+			}
+
+			count += 1;
+			if count > stream.limits().max_group_frames {
+				return Err(DecodeError::TooManyFrames.into());
+			}
+
+			// Stop reading if the group expired while it was still being received.
+			let frame = group.create_frame(Frame { size: frame.size })?;
 
 			let res = tokio::select! {
 				_ = frame.unused() => Err(Error::Cancel),
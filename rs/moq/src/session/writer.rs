@@ -1,25 +1,33 @@
 use std::fmt;
 
+use super::{CaptureStream, SessionConfig};
 use crate::{coding::*, message, Error};
 
 // A wrapper around a web_transport::SendStream that will reset on Drop
 pub(super) struct Writer {
 	stream: web_transport::SendStream,
 	buffer: bytes::BytesMut,
+	capture: Option<CaptureStream>,
 }
 
 impl Writer {
-	pub fn new(stream: web_transport::SendStream) -> Self {
+	pub fn new(stream: web_transport::SendStream, capture: Option<CaptureStream>) -> Self {
 		Self {
 			stream,
 			buffer: Default::default(),
+			capture,
 		}
 	}
 
-	pub async fn open(session: &mut web_transport::Session, typ: message::DataType) -> Result<Self, Error> {
+	pub async fn open(
+		session: &mut web_transport::Session,
+		typ: message::DataType,
+		config: &SessionConfig,
+	) -> Result<Self, Error> {
 		let send = session.open_uni().await?;
+		let capture = config.capture.as_ref().map(|capture| capture.stream(true));
 
-		let mut writer = Self::new(send);
+		let mut writer = Self::new(send, capture);
 		writer.encode(&typ).await?;
 
 		Ok(writer)
@@ -28,6 +36,24 @@ impl Writer {
 	pub async fn encode<T: Encode + fmt::Debug>(&mut self, msg: &T) -> Result<(), Error> {
 		self.buffer.clear();
 		msg.encode(&mut self.buffer);
+		self.flush().await
+	}
+
+	/// Encode a message using the layout of the negotiated version.
+	pub async fn encode_version<T: message::Versioned + fmt::Debug>(
+		&mut self,
+		msg: &T,
+		version: message::Version,
+	) -> Result<(), Error> {
+		self.buffer.clear();
+		msg.encode_version(&mut self.buffer, version);
+		self.flush().await
+	}
+
+	async fn flush(&mut self) -> Result<(), Error> {
+		if let Some(capture) = &self.capture {
+			capture.send(&self.buffer);
+		}
 
 		while !self.buffer.is_empty() {
 			self.stream.write_buf(&mut self.buffer).await?;
@@ -37,6 +63,10 @@ impl Writer {
 	}
 
 	pub async fn write(&mut self, buf: &[u8]) -> Result<(), Error> {
+		if let Some(capture) = &self.capture {
+			capture.send(buf);
+		}
+
 		self.stream.write(buf).await?; // convert the error type
 		Ok(())
 	}